@@ -15,6 +15,9 @@ fn bench_parser(c: &mut Criterion) {
     });
     let (module, _, _) = Module::from_file(WASM_TEST_INPUT_LARGE).unwrap();
     group.bench_function("encode", |b| b.iter(|| module.to_bytes()));
+    group.bench_function("parse lazy and encode", |b| {
+        b.iter(|| Module::from_file_lazy(WASM_TEST_INPUT_LARGE).unwrap().0.to_bytes())
+    });
     group.sample_size(20);
 }
 
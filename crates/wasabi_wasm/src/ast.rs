@@ -11,10 +11,14 @@
 
 use core::fmt;
 use std::hash;
+use std::io;
 use std::marker::PhantomData;
+use std::ops::Range;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 
+use once_cell::sync::OnceCell;
 use ordered_float::OrderedFloat;
 use serde::Serialize;
 use smallvec::SmallVec;
@@ -37,6 +41,14 @@ pub enum Val {
     // to make it possible, e.g., to put instructions in HashSets etc.
     F32(OrderedFloat<f32>),
     F64(OrderedFloat<f64>),
+    // Reference types extension. What a non-null reference points to (a function or a host
+    // value) is opaque, so only keep whether it is null.
+    // Not produced by the parser, which has `Instr::RefNull` and `Instr::RefFunc` for constant
+    // references instead, but, e.g., by runtimes when passing references to analysis hooks.
+    Ref { type_: ValType, is_null: bool },
+    // SIMD extension. As little-endian bytes, like in the binary format, which also keeps the
+    // alignment (and thus size) of `Val` small, unlike a `u128`.
+    V128([u8; 16]),
 }
 
 impl Val {
@@ -47,6 +59,8 @@ impl Val {
             Val::I64(_) => ValType::I64,
             Val::F32(_) => ValType::F32,
             Val::F64(_) => ValType::F64,
+            Val::Ref { type_, .. } => type_,
+            Val::V128(_) => ValType::V128,
         }
     }
 
@@ -59,6 +73,29 @@ impl Val {
             ValType::I64 => Val::I64(str.parse().map_err(|_| ())?),
             ValType::F32 => Val::F32(str.parse().map_err(|_| ())?),
             ValType::F64 => Val::F64(str.parse().map_err(|_| ())?),
+            // Only the null reference has a textual representation.
+            ValType::FuncRef | ValType::ExternRef => match str {
+                "null" => Val::Ref { type_: ty, is_null: true },
+                _ => return Err(()),
+            },
+            // Only the `i32x4` shape, in which `Display` prints vectors.
+            ValType::V128 => {
+                let lanes = str.trim().strip_prefix("i32x4").ok_or(())?;
+                let mut bytes = [0u8; 16];
+                let mut lanes = lanes.split_whitespace();
+                for lane_bytes in bytes.chunks_exact_mut(4) {
+                    let lane = lanes.next().ok_or(())?;
+                    let lane = match lane.strip_prefix("0x") {
+                        Some(hex) => u32::from_str_radix(hex, 16).map_err(|_| ())?,
+                        None => lane.parse::<i32>().map_err(|_| ())? as u32,
+                    };
+                    lane_bytes.copy_from_slice(&lane.to_le_bytes());
+                }
+                if lanes.next().is_some() {
+                    return Err(());
+                }
+                Val::V128(bytes)
+            }
         })
     }
 }
@@ -70,6 +107,15 @@ impl fmt::Display for Val {
             Val::I64(v) => v.fmt(f),
             Val::F32(v) => v.into_inner().fmt(f),
             Val::F64(v) => v.into_inner().fmt(f),
+            Val::Ref { is_null: true, .. } => f.write_str("null"),
+            Val::Ref { is_null: false, .. } => f.write_str("ref"),
+            Val::V128(bytes) => {
+                f.write_str("i32x4")?;
+                for lane in bytes.chunks_exact(4) {
+                    write!(f, " 0x{:08x}", u32::from_le_bytes(lane.try_into().unwrap()))?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -82,6 +128,11 @@ pub enum ValType {
     I64,
     F32,
     F64,
+    // Reference types extension.
+    FuncRef,
+    ExternRef,
+    // SIMD extension.
+    V128,
 }
 
 #[test]
@@ -90,16 +141,24 @@ fn val_type_is_small() {
 }
 
 impl ValType {
-    /// Produce a zero value (e.g., 0 or 0.0) for this type.
+    /// Produce a zero value (e.g., 0 or 0.0, or the null reference) for this type.
     pub fn zero(self) -> Val {
         match self {
             ValType::I32 => Val::I32(0),
             ValType::I64 => Val::I64(0),
             ValType::F32 => Val::F32(OrderedFloat(0.0)),
             ValType::F64 => Val::F64(OrderedFloat(0.0)),
+            ValType::FuncRef | ValType::ExternRef => Val::Ref { type_: self, is_null: true },
+            ValType::V128 => Val::V128([0; 16]),
         }
     }
 
+    /// Whether this is one of the reference types, which cannot be stored in memory, only in
+    /// tables, locals, and globals.
+    pub fn is_ref(self) -> bool {
+        matches!(self, ValType::FuncRef | ValType::ExternRef)
+    }
+
     /// Convert to the standard string representation, as in the WebAssembly
     /// specification and text format.
     pub fn to_str(self) -> &'static str {
@@ -108,18 +167,26 @@ impl ValType {
             ValType::I64 => "i64",
             ValType::F32 => "f32",
             ValType::F64 => "f64",
+            ValType::FuncRef => "funcref",
+            ValType::ExternRef => "externref",
+            ValType::V128 => "v128",
         }
     }
 
     /// Convert to a single character, e.g., as used by Emscripten.
     /// Lowercase is for 32 bit, uppercase is for 64 bit;
     /// `i` for integers, `f` for floats.
+    /// Reference types (not in Emscripten) are `r` for `funcref` and `e` for `externref`,
+    /// and `v` is for the SIMD type `v128`.
     pub fn to_char(self) -> char {
         match self {
             ValType::I32 => 'i',
             ValType::I64 => 'I',
             ValType::F32 => 'f',
             ValType::F64 => 'F',
+            ValType::FuncRef => 'r',
+            ValType::ExternRef => 'e',
+            ValType::V128 => 'v',
         }
     }
 
@@ -130,6 +197,9 @@ impl ValType {
             'I' => Some(ValType::I64),
             'f' => Some(ValType::F32),
             'F' => Some(ValType::F64),
+            'r' => Some(ValType::FuncRef),
+            'e' => Some(ValType::ExternRef),
+            'v' => Some(ValType::V128),
             _ => None,
         }
     }
@@ -150,16 +220,21 @@ impl FromStr for ValType {
             "i64" => ValType::I64,
             "f32" => ValType::F32,
             "f64" => ValType::F64,
+            "funcref" => ValType::FuncRef,
+            "externref" => ValType::ExternRef,
+            "v128" => ValType::V128,
             _ => return Err(()),
         })
     }
 }
 
 /// Limits for tables and memories.
+/// 64-bit wide for 64-bit memories (memory64 extension), table limits must fit in 32 bits
+/// (otherwise encoding fails).
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct Limits {
-    pub initial_size: u32,
-    pub max_size: Option<u32>,
+    pub initial_size: u64,
+    pub max_size: Option<u64>,
 }
 
 /// Type of global (scalar) variables.
@@ -320,8 +395,16 @@ pub struct Module {
     pub tables: Vec<Table>,
     pub memories: Vec<Memory>,
 
+    // Exception handling extension. Tags are the "types" of exceptions that can be thrown and caught.
+    pub tags: Vec<Tag>,
+
     pub start: Option<Idx<Function>>,
 
+    // Element and data segments are not nested inside their table or memory, because passive
+    // segments do not belong to any and instructions (e.g., `memory.init`) refer to them by index.
+    pub elements: Vec<Element>,
+    pub data: Vec<Data>,
+
     pub custom_sections: Vec<RawCustomSection>,
 
     pub metadata: ModuleMetadata,
@@ -330,6 +413,7 @@ pub struct Module {
 // TODO Crazy idea: make Module (and Function, Global) generic over InstrRepr, which
 // could even store the original WebAssembly bytes as a vector and only parse on demand,
 // which is more compact, requires less allocations, and is (obviously) faster on module creation.
+// (`LazyCode` does the latter for function bodies, but without being generic.)
 // Alternatively, the InstrRepr could also be a tree of blocks, or flat, or even a completely
 // diferent language, such as Wimpl or Stackl.
 
@@ -338,16 +422,54 @@ impl Module {
         Self::default()
     }
 
-    // TODO Generify this to work for any R: io::Read.
     pub fn from_bytes(bytes: &[u8]) -> Result<(Self, Offsets, ParseWarnings), ParseError> {
         crate::parse::parse_module(bytes)
     }
 
+    /// Parses a module in the WebAssembly text format (`.wat`). Errors in the text carry the
+    /// line and column (see `ParseIssue::Text`).
+    pub fn from_wat(text: &str) -> Result<(Self, ParseWarnings), ParseError> {
+        crate::parse::parse_module_text(text)
+    }
+
+    /// Like `from_bytes`, but reads the binary incrementally, e.g., from stdin, such that at most
+    /// one section (and not the whole binary) is held in memory in addition to the parsed AST.
+    pub fn from_reader(reader: impl io::Read) -> Result<(Self, Offsets, ParseWarnings), ParseError> {
+        crate::parse::parse_module_from_reader(reader)
+    }
+
     pub fn from_file(path: impl AsRef<Path>) -> Result<(Self, Offsets, ParseWarnings), ParseError> {
         let bytes = std::fs::read(path)?;
         Self::from_bytes(&bytes)
     }
 
+    /// Like `from_bytes`, but function bodies are only parsed on first access, see `LazyCode`.
+    /// Bodies that are never modified are copied verbatim when encoding the module again.
+    ///
+    /// Note that errors in function bodies are thus only found on access (which then panics) or
+    /// when encoding a body that cannot be copied verbatim (which returns an error), and that
+    /// extensions used only in unparsed bodies are missing from `metadata`.
+    pub fn from_bytes_lazy(bytes: &[u8]) -> Result<(Self, Offsets, ParseWarnings), ParseError> {
+        crate::parse::parse_module_lazy(bytes)
+    }
+
+    pub fn from_file_lazy(path: impl AsRef<Path>) -> Result<(Self, Offsets, ParseWarnings), ParseError> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes_lazy(&bytes)
+    }
+
+    /// Like `from_bytes`, but additionally records the offset of every instruction, e.g., for
+    /// mapping addresses in crash reports or stack traces back to instructions, see
+    /// `Offsets::instr_offset()` and `Offsets::offset_to_instr()`.
+    pub fn from_bytes_with_instr_offsets(bytes: &[u8]) -> Result<(Self, Offsets, ParseWarnings), ParseError> {
+        crate::parse::parse_module_with_instr_offsets(bytes)
+    }
+
+    pub fn from_file_with_instr_offsets(path: impl AsRef<Path>) -> Result<(Self, Offsets, ParseWarnings), ParseError> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes_with_instr_offsets(&bytes)
+    }
+
     // TODO Some standard version that prints warnings?
     // pub fn from_file_with_offsets_wasmparser(path: impl AsRef<Path>) -> Result<(Self, Offsets), Box<dyn std::error::Error>> {
     //     let bytes = std::fs::read(path)?;
@@ -362,19 +484,125 @@ impl Module {
     //     Ok((module, offsets))
     // }
 
-    // TODO Generify this to work for any W: io::Write.
-    // Unfortunately, wasm-encode only offers its `Encode` trait for `Vec<u8>`,
-    // so it is not quite so easy.
     pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
         crate::encode::encode_module(self)
     }
 
+    /// Writes each section as soon as it is encoded, instead of first building the whole binary.
+    /// Returns the number of bytes written.
+    // Unfortunately, wasm-encoder only offers its `Encode` trait for `Vec<u8>`, so each section
+    // is still encoded into a buffer first.
+    pub fn write_to(&self, writer: impl io::Write) -> Result<usize, EncodeError> {
+        crate::encode::encode_module_to(self, writer)
+    }
+
+    /// Prints the module in the text format, annotating each instruction with its `Idx<Instr>`.
+    /// Unlike external tools, this also works for invalid modules, e.g., for debugging passes.
+    pub fn to_wat(&self) -> String {
+        crate::print::print_module(self, None)
+    }
+
+    /// Like `to_wat`, but additionally marks which instructions were inserted (e.g., by
+    /// instrumentation) compared to the `original` module and the index of the others in it.
+    /// Functions are matched by their index, so `original` must not have more functions.
+    pub fn to_wat_marking_inserted(&self, original: &Module) -> String {
+        crate::print::print_module(self, Some(original))
+    }
+
+    /// Checks that the module is valid according to the WebAssembly specification (including the
+    /// extensions supported by the AST), without external tools. Returns the first error found.
+    /// This includes type checking all function bodies and global initializers.
+    pub fn validate(&self) -> Result<(), crate::validate::ValidationError> {
+        crate::validate::validate_module(self)
+    }
+
+    pub fn to_file(&self, path: impl AsRef<Path>) -> Result<usize, EncodeError> {
+        let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+        let len = self.write_to(&mut writer)?;
+        io::Write::flush(&mut writer)?;
+        Ok(len)
+    }
+}
+
+/// A component (component model extension), which we only support as a container of core modules.
+/// The core modules (also those of nested components) are parsed into `Module`s, which can be
+/// analyzed or instrumented, all other (component-level) sections are passed through unmodified.
+/// Note that adding imports to a core module (e.g., for hooks) requires adapting the core instances
+/// of the component, which we do not do.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Component {
+    // The component binary format is still evolving, so keep whatever version was in the preamble.
+    pub version: u16,
+    pub sections: Vec<ComponentSection>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ComponentSection {
+    // Boxed, because modules are much larger than the other variants.
+    Module(Box<Module>),
+    Component(Component),
+    /// Any other section, with its contents (excluding id and size) as raw bytes.
+    Raw { id: u8, content: Vec<u8> },
+}
+
+impl Component {
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, ParseWarnings), ParseError> {
+        crate::parse::parse_component(bytes)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<(Self, ParseWarnings), ParseError> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        crate::encode::encode_component(self)
+    }
+
     pub fn to_file(&self, path: impl AsRef<Path>) -> Result<usize, EncodeError> {
         let bytes = self.to_bytes()?;
         let len = bytes.len();
         std::fs::write(path, bytes)?;
         Ok(len)
     }
+
+    /// All core modules in the component, including those of nested components, in binary order.
+    pub fn modules(&self) -> Vec<&Module> {
+        let mut modules = Vec::new();
+        for section in &self.sections {
+            match section {
+                ComponentSection::Module(module) => modules.push(module.as_ref()),
+                ComponentSection::Component(component) => modules.extend(component.modules()),
+                ComponentSection::Raw { .. } => {}
+            }
+        }
+        modules
+    }
+
+    pub fn modules_mut(&mut self) -> Vec<&mut Module> {
+        let mut modules = Vec::new();
+        for section in &mut self.sections {
+            match section {
+                ComponentSection::Module(module) => modules.push(module.as_mut()),
+                ComponentSection::Component(component) => modules.extend(component.modules_mut()),
+                ComponentSection::Raw { .. } => {}
+            }
+        }
+        modules
+    }
+}
+
+impl ComponentSection {
+    pub const MODULE_ID: u8 = 1;
+    pub const COMPONENT_ID: u8 = 4;
+
+    pub fn id(&self) -> u8 {
+        match self {
+            ComponentSection::Module(_) => Self::MODULE_ID,
+            ComponentSection::Component(_) => Self::COMPONENT_ID,
+            ComponentSection::Raw { id, .. } => *id,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
@@ -406,7 +634,7 @@ pub enum ImportOrPresent<T> {
 pub struct Function {
     // Type is inlined here compared to low-level/binary/spec representation.
     pub type_: FunctionType,
-    pub code: ImportOrPresent<Code>,
+    pub code: ImportOrPresent<LazyCode>,
     // Functions/globals/memories/tables can be exported multiple times under different names.
     // But the export names must be unique (not ensured in this representation!).
     pub export: Vec<String>,
@@ -425,24 +653,45 @@ pub struct Global {
     pub type_: GlobalType,
     pub init: ImportOrPresent<Expr>,
     pub export: Vec<String>,
-    // TODO name from name section.
+    // From the name section, if present, e.g., `__stack_pointer` emitted by LLVM.
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct Table {
+    /// `funcref` in the MVP, can also be `externref` with the reference types extension.
+    pub elem_type: ValType,
     pub limits: Limits,
-    // Unlike functions and globals, an imported table can still be initialized with elements.
+    // Unlike functions and globals, an imported table can still be initialized with
+    // (active) elements, see `Module::elements`.
     pub import: Option<(String, String)>,
-    pub elements: Vec<Element>,
     pub export: Vec<String>,
+    // From the name section, if present (extended name section proposal).
+    pub name: Option<String>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct Memory {
     pub limits: Limits,
-    // Unlike functions and globals, an imported memory can still be initialized with data elements.
+    // Threads and atomics extension. Shared memories can be accessed by multiple agents (threads)
+    // at the same time and must have a maximum size.
+    pub shared: bool,
+    // Memory64 extension. 64-bit memories are addressed with i64 instead of i32, see `index_type()`.
+    pub memory64: bool,
+    // Unlike functions and globals, an imported memory can still be initialized with
+    // (active) data segments, see `Module::data`.
+    pub import: Option<(String, String)>,
+    pub export: Vec<String>,
+    // From the name section, if present (extended name section proposal).
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Tag {
+    /// The parameters are the values carried by an exception with this tag.
+    /// The results are always empty (for now, this might change with stack switching).
+    pub type_: FunctionType,
     pub import: Option<(String, String)>,
-    pub data: Vec<Data>,
     pub export: Vec<String>,
 }
 
@@ -452,6 +701,27 @@ pub struct Code {
     pub locals: Vec<Local>,
     // TODO rename to instrs
     pub body: Expr,
+    // From the name section, if present (extended name section proposal).
+    // Indexed by the "label index", i.e., the n-th block, loop, if, or try instruction in the body
+    // (not by the instruction index, such that inserting non-block instructions keeps the names).
+    // May be shorter than the number of blocks, if the last blocks have no name.
+    pub label_names: Vec<Option<String>>,
+}
+
+/// Function body that is parsed on first access, if the module was parsed with
+/// `Module::from_bytes_lazy`, otherwise it is just a `Code` that is already parsed.
+#[derive(Clone)]
+pub struct LazyCode {
+    // The original bytes of the body, as long as the body cannot have been modified, i.e., until
+    // the first mutable access, such that the encoder can copy them verbatim.
+    raw: Option<RawCode>,
+    parsed: OnceCell<Code>,
+}
+
+#[derive(Clone)]
+pub(crate) struct RawCode {
+    pub context: Arc<crate::parse::LazyContext>,
+    pub range: Range<usize>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -483,18 +753,56 @@ pub struct ParamRef<'a> {
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct Element {
-    pub offset: Expr,
-    pub functions: Vec<Idx<Function>>,
+    pub mode: ElementMode,
+    pub items: ElementItems,
+    // From the name section, if present (extended name section proposal).
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum ElementMode {
+    /// Copied into the table at `offset` during instantiation (the only mode in the MVP).
+    Active { table: Idx<Table>, offset: Expr },
+    /// Only copied into a table at runtime by `table.init` (bulk memory extension).
+    Passive,
+    /// Never copied into a table, only forward-declares the functions that are referenced by
+    /// `ref.func` instructions in the code (reference types extension).
+    Declared,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum ElementItems {
+    /// References to the given functions (the only kind of items in the MVP).
+    Functions(Vec<Idx<Function>>),
+    /// One constant expression per item that produces a reference of the given type, e.g.,
+    /// `ref.null` or `ref.func` (reference types extension).
+    Expressions(ValType, Vec<Expr>),
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
 pub struct Data {
-    pub offset: Expr,
+    pub mode: DataMode,
     pub bytes: Vec<u8>,
+    // From the name section, if present (extended name section proposal).
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum DataMode {
+    /// Copied into the memory at `offset` during instantiation (the only mode in the MVP).
+    Active { memory: Idx<Memory>, offset: Expr },
+    /// Only copied into a memory at runtime by `memory.init` (bulk memory extension).
+    Passive,
 }
 
-/// Metainformation how low-level sections and function bodies map to byte offsets in the binary.
-// TODO Attach either directly to functions/sections or to the module (but rather the former, otherwise it can get easily lost).
+/// Metainformation how low-level sections, function bodies, and (optionally) instructions map to
+/// byte offsets in the binary. All offsets are relative to the beginning of the module.
+///
+/// The offsets are kept separate from the AST on purpose: Otherwise modules parsed from the binary
+/// and the text format (or constructed by hand) would no longer compare equal, and instrumentation
+/// would have to decide what the offset of an inserted or moved instruction is.
+/// Since functions are only ever appended to a module (see `Module::add_function()`), function
+/// indices in here stay valid after instrumentation, instruction indices of course not.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Offsets {
     /// Section offsets point to the beginning of the content of a section, i.e., after the size.
@@ -502,6 +810,10 @@ pub struct Offsets {
     /// Code offsets are only present for non-imported function, and also point to after the size
     /// in the code element (similar to section offsets).
     pub functions_code: Vec<(Idx<Function>, usize)>,
+    /// Offset of every instruction in the body of non-imported functions, in the order of the code
+    /// section. Only present if requested, see `Module::from_bytes_with_instr_offsets()`, since
+    /// it is larger than the instructions themselves.
+    pub instrs: Option<Vec<(Idx<Function>, Vec<usize>)>>,
 }
 
 impl Offsets {
@@ -532,6 +844,46 @@ impl Offsets {
             .find_map(|(func, offset)|
                 if func == idx { Some(offset) } else { None })
     }
+
+    /// Returns the offset of the instruction `instr` in the (original) function `func`.
+    /// `None` if instruction offsets were not recorded or the indices are out of bounds.
+    pub fn instr_offset(&self, func: Idx<Function>, instr: Idx<Instr>) -> Option<usize> {
+        let instrs = self.instrs.as_ref()?;
+        // Code section entries are ordered by function index, so we can use binary search.
+        let func_pos = instrs.binary_search_by_key(&func, |(func, _)| *func).ok()?;
+        instrs[func_pos].1.get(instr.to_usize()).copied()
+    }
+
+    /// Returns the number of instructions in the (original) function `func`.
+    /// `None` if instruction offsets were not recorded or `func` has no body.
+    pub fn instr_count(&self, func: Idx<Function>) -> Option<usize> {
+        let instrs = self.instrs.as_ref()?;
+        let func_pos = instrs.binary_search_by_key(&func, |(func, _)| *func).ok()?;
+        Some(instrs[func_pos].1.len())
+    }
+
+    /// Returns the (original) function and instruction at the given offset.
+    /// The offset does not need to point to the beginning of the instruction, e.g., it can also
+    /// be inside its immediates. `None` if instruction offsets were not recorded or the offset is
+    /// not inside any instruction (e.g., it points to the locals of a function).
+    ///
+    /// Note that DWARF addresses are relative to the code section, so add the offset of the code
+    /// section (see `section_offsets()`) before looking them up here.
+    pub fn offset_to_instr(&self, offset: usize) -> Option<(Idx<Function>, Idx<Instr>)> {
+        let instrs = self.instrs.as_ref()?;
+        // Both functions and their instructions are ordered by offset, so use binary search twice.
+        // Valid bodies have at least one instruction (the final `end`), but truncated ones may have none.
+        let func_pos = instrs
+            .partition_point(|(_, instr_offsets)| instr_offsets.first().is_some_and(|&first| first <= offset))
+            .checked_sub(1)?;
+        let (func, instr_offsets) = &instrs[func_pos];
+        // The final `end` is one byte, anything after it belongs to the next code entry.
+        if offset > *instr_offsets.last()? {
+            return None;
+        }
+        let instr_pos = instr_offsets.partition_point(|&instr_offset| instr_offset <= offset).checked_sub(1)?;
+        Some((*func, instr_pos.into()))
+    }
 }
 
 /// A not-yet-parsed custom section.
@@ -557,10 +909,12 @@ pub enum SectionId {
     Function,
     Table,
     Memory,
+    Tag,
     Global,
     Export,
     Start,
     Element,
+    DataCount,
     Code,
     Data,
     Custom(String),
@@ -590,7 +944,11 @@ pub struct Memarg {
     /// and https://webassembly.github.io/spec/core/text/instructions.html#memory-instructions.
     pub alignment_exp: u8,
 
-    pub offset: u32,
+    /// Can only exceed 32 bits for 64-bit memories (memory64 extension).
+    pub offset: u64,
+
+    /// Multi-memory extension: the memory that is accessed, always 0 in the Wasm MVP.
+    pub memory: Idx<Memory>,
 }
 
 impl Memarg {
@@ -598,6 +956,7 @@ impl Memarg {
         Self {
             offset: 0,
             alignment_exp: op.natural_alignment_exp(),
+            memory: Idx::from(0u32),
         }
     }
 
@@ -611,6 +970,13 @@ impl Memarg {
 
     /// Formats non-default fields, depends on natural alignment of `op`.
     pub fn fmt(&self, f: &mut fmt::Formatter<'_>, op: impl MemoryOp) -> fmt::Result {
+        // As in the text format, a non-zero memory index comes before offset and alignment.
+        if self.memory != Idx::from(0u32) {
+            write!(f, "{}", self.memory.to_u32())?;
+            if self.offset != 0 || self.alignment_exp != op.natural_alignment_exp() {
+                f.write_str(" ")?;
+            }
+        }
         let is_natural_alignment = self.alignment_exp == op.natural_alignment_exp();
         match (self.offset, is_natural_alignment) {
             (0, true) => Ok(()),
@@ -644,6 +1010,9 @@ impl Memarg {
                 // TODO: Use usize::log2() once stabilized and TryInto.
                 let align_exp = (align as f64).log2() as u8;
                 result.alignment_exp = align_exp
+            } else if let Ok(memory) = field.parse::<u32>() {
+                // FIXME: Also allows the memory index after offset and alignment.
+                result.memory = memory.into();
             } else {
                 // Invalid Memarg field.
                 return Err(());
@@ -670,11 +1039,14 @@ fn instr_size_should_not_be_too_large() {
     assert_eq!(std::mem::size_of::<Idx<Function>>(), 4);
     assert_eq!(std::mem::size_of::<Label>(), 4);
 
-    assert_eq!(std::mem::size_of::<Memarg>(), 8);
+    // The memory index (multi-memory extension) and 64-bit offsets (memory64 extension) add
+    // 8 bytes to what used to fit in 8.
+    assert_eq!(std::mem::size_of::<Memarg>(), 16);
 
     // These are pretty large, but the only way to get it smaller is to store things out-of-line.
-    assert_eq!(std::mem::size_of::<Val>(), 16);
-    assert_eq!(std::mem::size_of::<Instr>(), 24);
+    // The 16 bytes of `Val::V128` (SIMD) add another 8 bytes to both.
+    assert_eq!(std::mem::size_of::<Val>(), 24);
+    assert_eq!(std::mem::size_of::<Instr>(), 32);
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -687,6 +1059,7 @@ pub enum Instr {
     // TODO: Make highlevel::Instr nesting, i.e., Block(FunctionType, Vec<Instr>)
     // see, e.g., the reference interpreter: https://github.com/WebAssembly/spec/blob/master/interpreter/valid/valid.ml
     // This would get rid of else and end.
+    // `nested::Body` implements this (and the terminators below) as an alternative representation.
     // TODO: One could also remove dead code by construction, by having optional
     // terminator instructions, which move control-flow unconditionally somewhere else:
     // Unreachable, Br, BrTable, Return.
@@ -711,8 +1084,23 @@ pub enum Instr {
     // TODO: Replace with Br(toplevel)
     Return,
     Call(Idx<Function>),
-    // TODO: remove Idx<Table>, always 0 in MVP.
+    // The table index is always 0 in the MVP, but not with the reference types extension.
     CallIndirect(FunctionType, Idx<Table>),
+    // Tail call extension: call and return from the current function at the same time, so the
+    // callee must have the same results as the current function.
+    ReturnCall(Idx<Function>),
+    ReturnCallIndirect(FunctionType, Idx<Table>),
+
+    // Exception handling extension. `Try` begins a block like `Block`, which is continued by
+    // zero or more `Catch` handlers, at most one `CatchAll` handler, and finally `End`.
+    // Alternatively, `Delegate` ends the try block and forwards exceptions to an outer handler.
+    Try(FunctionType),
+    Catch(Idx<Tag>),
+    CatchAll,
+    Delegate(Label),
+    Throw(Idx<Tag>),
+    // Re-throws the exception caught by the (enclosing) handler with the given label.
+    Rethrow(Label),
 
     // TODO: Include the type explicitly in the instruction to remove
     // value-polymorphism.
@@ -720,6 +1108,9 @@ pub enum Instr {
     Drop,
     // TODO: Replace with `If([ty, ty] -> [ty], ...)
     Select,
+    // Reference types extension: like `Select`, but with an explicit type, which is required
+    // for selecting references.
+    TypedSelect(ValType),
 
     // TODO: Get rid of all locals by using block params and results only + a pick or copy
     // instruction, that copies the nth value on the stack to the top.
@@ -734,6 +1125,43 @@ pub enum Instr {
     MemorySize(Idx<Memory>),
     MemoryGrow(Idx<Memory>),
 
+    // Bulk memory extension.
+    MemoryInit(Idx<Data>, Idx<Memory>),
+    DataDrop(Idx<Data>),
+    MemoryCopy { dst: Idx<Memory>, src: Idx<Memory> },
+    MemoryFill(Idx<Memory>),
+    TableInit(Idx<Element>, Idx<Table>),
+    ElemDrop(Idx<Element>),
+    TableCopy { dst: Idx<Table>, src: Idx<Table> },
+
+    // Reference types extension.
+    RefNull(ValType),
+    RefIsNull,
+    RefFunc(Idx<Function>),
+    TableGet(Idx<Table>),
+    TableSet(Idx<Table>),
+    TableSize(Idx<Table>),
+    TableGrow(Idx<Table>),
+    TableFill(Idx<Table>),
+
+    // SIMD extension. Vector constants, loads, stores, and the lane-wise unary and binary
+    // operations are grouped with their scalar counterparts, only the instructions with a lane
+    // index (or a lane permutation) and the single ternary instruction are separate.
+    LoadLane(LoadLaneOp, Memarg, u8),
+    StoreLane(StoreLaneOp, Memarg, u8),
+    ExtractLane(ExtractLaneOp, u8),
+    ReplaceLane(ReplaceLaneOp, u8),
+    I8x16Shuffle([u8; 16]),
+    V128Bitselect,
+
+    // Threads and atomics extension. All atomic accesses are sequentially consistent and require
+    // the memarg to have exactly the natural alignment.
+    AtomicLoad(AtomicLoadOp, Memarg),
+    AtomicStore(AtomicStoreOp, Memarg),
+    /// Read-modify-write operations (including compare-exchange) and `memory.atomic.wait/notify`.
+    Atomic(AtomicOp, Memarg),
+    AtomicFence,
+
     Const(Val),
     Unary(UnaryOp),
     Binary(BinaryOp),
@@ -789,6 +1217,21 @@ pub enum LoadOp {
     I64Load16U,
     I64Load32S,
     I64Load32U,
+
+    // SIMD extension.
+    V128Load,
+    V128Load8x8S,
+    V128Load8x8U,
+    V128Load16x4S,
+    V128Load16x4U,
+    V128Load32x2S,
+    V128Load32x2U,
+    V128Load8Splat,
+    V128Load16Splat,
+    V128Load32Splat,
+    V128Load64Splat,
+    V128Load32Zero,
+    V128Load64Zero,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -804,6 +1247,9 @@ pub enum StoreOp {
     I64Store8,
     I64Store16,
     I64Store32,
+
+    // SIMD extension.
+    V128Store,
 }
 
 /// Common trait for `LoadOp` and `StoreOp`.
@@ -838,6 +1284,20 @@ impl MemoryOp for LoadOp {
             I64Load16U => "i64.load16_u",
             I64Load32S => "i64.load32_s",
             I64Load32U => "i64.load32_u",
+
+            V128Load => "v128.load",
+            V128Load8x8S => "v128.load8x8_s",
+            V128Load8x8U => "v128.load8x8_u",
+            V128Load16x4S => "v128.load16x4_s",
+            V128Load16x4U => "v128.load16x4_u",
+            V128Load32x2S => "v128.load32x2_s",
+            V128Load32x2U => "v128.load32x2_u",
+            V128Load8Splat => "v128.load8_splat",
+            V128Load16Splat => "v128.load16_splat",
+            V128Load32Splat => "v128.load32_splat",
+            V128Load64Splat => "v128.load64_splat",
+            V128Load32Zero => "v128.load32_zero",
+            V128Load64Zero => "v128.load64_zero",
         }
     }
 
@@ -860,6 +1320,11 @@ impl MemoryOp for LoadOp {
             I64Load16U => FunctionType::new(&[I32], &[I64]),
             I64Load32S => FunctionType::new(&[I32], &[I64]),
             I64Load32U => FunctionType::new(&[I32], &[I64]),
+
+            V128Load
+            | V128Load8x8S | V128Load8x8U | V128Load16x4S | V128Load16x4U | V128Load32x2S | V128Load32x2U
+            | V128Load8Splat | V128Load16Splat | V128Load32Splat | V128Load64Splat
+            | V128Load32Zero | V128Load64Zero => FunctionType::new(&[I32], &[V128]),
         }
     }
 
@@ -881,6 +1346,20 @@ impl MemoryOp for LoadOp {
             I64Load16U => 1,
             I64Load32S => 2,
             I64Load32U => 2,
+
+            V128Load => 4,
+            V128Load8x8S => 3,
+            V128Load8x8U => 3,
+            V128Load16x4S => 3,
+            V128Load16x4U => 3,
+            V128Load32x2S => 3,
+            V128Load32x2U => 3,
+            V128Load8Splat => 0,
+            V128Load16Splat => 1,
+            V128Load32Splat => 2,
+            V128Load64Splat => 3,
+            V128Load32Zero => 2,
+            V128Load64Zero => 3,
         }
     }
 }
@@ -899,95 +1378,785 @@ impl MemoryOp for StoreOp {
             I64Store8 => "i64.store8",
             I64Store16 => "i64.store16",
             I64Store32 => "i64.store32",
+
+            V128Store => "v128.store",
+        }
+    }
+
+    fn to_type(self) -> FunctionType {
+        use StoreOp::*;
+        use ValType::*;
+        match self {
+            I32Store => FunctionType::new(&[I32, I32], &[]),
+            I64Store => FunctionType::new(&[I32, I64], &[]),
+            F32Store => FunctionType::new(&[I32, F32], &[]),
+            F64Store => FunctionType::new(&[I32, F64], &[]),
+
+            I32Store8 => FunctionType::new(&[I32, I32], &[]),
+            I32Store16 => FunctionType::new(&[I32, I32], &[]),
+            I64Store8 => FunctionType::new(&[I32, I64], &[]),
+            I64Store16 => FunctionType::new(&[I32, I64], &[]),
+            I64Store32 => FunctionType::new(&[I32, I64], &[]),
+
+            V128Store => FunctionType::new(&[I32, V128], &[]),
+        }
+    }
+
+    fn natural_alignment_exp(self) -> u8 {
+        use StoreOp::*;
+        match self {
+            I32Store => 2,
+            I64Store => 3,
+            F32Store => 2,
+            F64Store => 3,
+
+            I32Store8 => 0,
+            I32Store16 => 1,
+            I64Store8 => 0,
+            I64Store16 => 1,
+            I64Store32 => 2,
+
+            V128Store => 4,
+        }
+    }
+}
+
+impl fmt::Display for LoadOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl fmt::Display for StoreOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl FromStr for LoadOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use LoadOp::*;
+        Ok(match s {
+            "i32.load" => I32Load,
+            "i64.load" => I64Load,
+            "f32.load" => F32Load,
+            "f64.load" => F64Load,
+            "i32.load8_s" => I32Load8S,
+            "i32.load8_u" => I32Load8U,
+            "i32.load16_s" => I32Load16S,
+            "i32.load16_u" => I32Load16U,
+            "i64.load8_s" => I64Load8S,
+            "i64.load8_u" => I64Load8U,
+            "i64.load16_s" => I64Load16S,
+            "i64.load16_u" => I64Load16U,
+            "i64.load32_s" => I64Load32S,
+            "i64.load32_u" => I64Load32U,
+            "v128.load" => V128Load,
+            "v128.load8x8_s" => V128Load8x8S,
+            "v128.load8x8_u" => V128Load8x8U,
+            "v128.load16x4_s" => V128Load16x4S,
+            "v128.load16x4_u" => V128Load16x4U,
+            "v128.load32x2_s" => V128Load32x2S,
+            "v128.load32x2_u" => V128Load32x2U,
+            "v128.load8_splat" => V128Load8Splat,
+            "v128.load16_splat" => V128Load16Splat,
+            "v128.load32_splat" => V128Load32Splat,
+            "v128.load64_splat" => V128Load64Splat,
+            "v128.load32_zero" => V128Load32Zero,
+            "v128.load64_zero" => V128Load64Zero,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl FromStr for StoreOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use StoreOp::*;
+        Ok(match s {
+            "i32.store" => I32Store,
+            "i64.store" => I64Store,
+            "f32.store" => F32Store,
+            "f64.store" => F64Store,
+            "i32.store8" => I32Store8,
+            "i32.store16" => I32Store16,
+            "i64.store8" => I64Store8,
+            "i64.store16" => I64Store16,
+            "i64.store32" => I64Store32,
+            "v128.store" => V128Store,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// SIMD loads that replace a single lane of a vector, e.g., `v128.load8_lane`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum LoadLaneOp {
+    V128Load8Lane,
+    V128Load16Lane,
+    V128Load32Lane,
+    V128Load64Lane,
+}
+
+/// SIMD stores of a single lane of a vector, e.g., `v128.store8_lane`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum StoreLaneOp {
+    V128Store8Lane,
+    V128Store16Lane,
+    V128Store32Lane,
+    V128Store64Lane,
+}
+
+impl MemoryOp for LoadLaneOp {
+    fn to_name(self) -> &'static str {
+        use LoadLaneOp::*;
+        match self {
+            V128Load8Lane => "v128.load8_lane",
+            V128Load16Lane => "v128.load16_lane",
+            V128Load32Lane => "v128.load32_lane",
+            V128Load64Lane => "v128.load64_lane",
+        }
+    }
+
+    fn to_type(self) -> FunctionType {
+        use ValType::*;
+        // Address and the vector, in which one lane is replaced by the loaded value.
+        FunctionType::new(&[I32, V128], &[V128])
+    }
+
+    fn natural_alignment_exp(self) -> u8 {
+        use LoadLaneOp::*;
+        match self {
+            V128Load8Lane => 0,
+            V128Load16Lane => 1,
+            V128Load32Lane => 2,
+            V128Load64Lane => 3,
+        }
+    }
+}
+
+impl fmt::Display for LoadLaneOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl FromStr for LoadLaneOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use LoadLaneOp::*;
+        Ok(match s {
+            "v128.load8_lane" => V128Load8Lane,
+            "v128.load16_lane" => V128Load16Lane,
+            "v128.load32_lane" => V128Load32Lane,
+            "v128.load64_lane" => V128Load64Lane,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl MemoryOp for StoreLaneOp {
+    fn to_name(self) -> &'static str {
+        use StoreLaneOp::*;
+        match self {
+            V128Store8Lane => "v128.store8_lane",
+            V128Store16Lane => "v128.store16_lane",
+            V128Store32Lane => "v128.store32_lane",
+            V128Store64Lane => "v128.store64_lane",
+        }
+    }
+
+    fn to_type(self) -> FunctionType {
+        use ValType::*;
+        FunctionType::new(&[I32, V128], &[])
+    }
+
+    fn natural_alignment_exp(self) -> u8 {
+        use StoreLaneOp::*;
+        match self {
+            V128Store8Lane => 0,
+            V128Store16Lane => 1,
+            V128Store32Lane => 2,
+            V128Store64Lane => 3,
+        }
+    }
+}
+
+impl fmt::Display for StoreLaneOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl FromStr for StoreLaneOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use StoreLaneOp::*;
+        Ok(match s {
+            "v128.store8_lane" => V128Store8Lane,
+            "v128.store16_lane" => V128Store16Lane,
+            "v128.store32_lane" => V128Store32Lane,
+            "v128.store64_lane" => V128Store64Lane,
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum AtomicLoadOp {
+    I32AtomicLoad,
+    I64AtomicLoad,
+    I32AtomicLoad8U,
+    I32AtomicLoad16U,
+    I64AtomicLoad8U,
+    I64AtomicLoad16U,
+    I64AtomicLoad32U,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum AtomicStoreOp {
+    I32AtomicStore,
+    I64AtomicStore,
+    I32AtomicStore8,
+    I32AtomicStore16,
+    I64AtomicStore8,
+    I64AtomicStore16,
+    I64AtomicStore32,
+}
+
+/// Atomic memory instructions other than loads and stores, see `Instr::Atomic`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum AtomicOp {
+    I32AtomicRmwAdd,
+    I64AtomicRmwAdd,
+    I32AtomicRmw8AddU,
+    I32AtomicRmw16AddU,
+    I64AtomicRmw8AddU,
+    I64AtomicRmw16AddU,
+    I64AtomicRmw32AddU,
+    I32AtomicRmwSub,
+    I64AtomicRmwSub,
+    I32AtomicRmw8SubU,
+    I32AtomicRmw16SubU,
+    I64AtomicRmw8SubU,
+    I64AtomicRmw16SubU,
+    I64AtomicRmw32SubU,
+    I32AtomicRmwAnd,
+    I64AtomicRmwAnd,
+    I32AtomicRmw8AndU,
+    I32AtomicRmw16AndU,
+    I64AtomicRmw8AndU,
+    I64AtomicRmw16AndU,
+    I64AtomicRmw32AndU,
+    I32AtomicRmwOr,
+    I64AtomicRmwOr,
+    I32AtomicRmw8OrU,
+    I32AtomicRmw16OrU,
+    I64AtomicRmw8OrU,
+    I64AtomicRmw16OrU,
+    I64AtomicRmw32OrU,
+    I32AtomicRmwXor,
+    I64AtomicRmwXor,
+    I32AtomicRmw8XorU,
+    I32AtomicRmw16XorU,
+    I64AtomicRmw8XorU,
+    I64AtomicRmw16XorU,
+    I64AtomicRmw32XorU,
+    I32AtomicRmwXchg,
+    I64AtomicRmwXchg,
+    I32AtomicRmw8XchgU,
+    I32AtomicRmw16XchgU,
+    I64AtomicRmw8XchgU,
+    I64AtomicRmw16XchgU,
+    I64AtomicRmw32XchgU,
+    I32AtomicRmwCmpxchg,
+    I64AtomicRmwCmpxchg,
+    I32AtomicRmw8CmpxchgU,
+    I32AtomicRmw16CmpxchgU,
+    I64AtomicRmw8CmpxchgU,
+    I64AtomicRmw16CmpxchgU,
+    I64AtomicRmw32CmpxchgU,
+    MemoryAtomicNotify,
+    MemoryAtomicWait32,
+    MemoryAtomicWait64,
+}
+
+impl MemoryOp for AtomicLoadOp {
+    fn to_name(self) -> &'static str {
+        use AtomicLoadOp::*;
+        match self {
+            I32AtomicLoad => "i32.atomic.load",
+            I64AtomicLoad => "i64.atomic.load",
+            I32AtomicLoad8U => "i32.atomic.load8_u",
+            I32AtomicLoad16U => "i32.atomic.load16_u",
+            I64AtomicLoad8U => "i64.atomic.load8_u",
+            I64AtomicLoad16U => "i64.atomic.load16_u",
+            I64AtomicLoad32U => "i64.atomic.load32_u",
+        }
+    }
+
+    fn to_type(self) -> FunctionType {
+        use AtomicLoadOp::*;
+        use ValType::*;
+        match self {
+            I32AtomicLoad | I32AtomicLoad8U | I32AtomicLoad16U => FunctionType::new(&[I32], &[I32]),
+            I64AtomicLoad | I64AtomicLoad8U | I64AtomicLoad16U | I64AtomicLoad32U => FunctionType::new(&[I32], &[I64]),
+        }
+    }
+
+    fn natural_alignment_exp(self) -> u8 {
+        use AtomicLoadOp::*;
+        match self {
+            I32AtomicLoad => 2,
+            I64AtomicLoad => 3,
+            I32AtomicLoad8U => 0,
+            I32AtomicLoad16U => 1,
+            I64AtomicLoad8U => 0,
+            I64AtomicLoad16U => 1,
+            I64AtomicLoad32U => 2,
+        }
+    }
+}
+
+impl fmt::Display for AtomicLoadOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl FromStr for AtomicLoadOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use AtomicLoadOp::*;
+        Ok(match s {
+            "i32.atomic.load" => I32AtomicLoad,
+            "i64.atomic.load" => I64AtomicLoad,
+            "i32.atomic.load8_u" => I32AtomicLoad8U,
+            "i32.atomic.load16_u" => I32AtomicLoad16U,
+            "i64.atomic.load8_u" => I64AtomicLoad8U,
+            "i64.atomic.load16_u" => I64AtomicLoad16U,
+            "i64.atomic.load32_u" => I64AtomicLoad32U,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl MemoryOp for AtomicStoreOp {
+    fn to_name(self) -> &'static str {
+        use AtomicStoreOp::*;
+        match self {
+            I32AtomicStore => "i32.atomic.store",
+            I64AtomicStore => "i64.atomic.store",
+            I32AtomicStore8 => "i32.atomic.store8",
+            I32AtomicStore16 => "i32.atomic.store16",
+            I64AtomicStore8 => "i64.atomic.store8",
+            I64AtomicStore16 => "i64.atomic.store16",
+            I64AtomicStore32 => "i64.atomic.store32",
+        }
+    }
+
+    fn to_type(self) -> FunctionType {
+        use AtomicStoreOp::*;
+        use ValType::*;
+        match self {
+            I32AtomicStore | I32AtomicStore8 | I32AtomicStore16 => FunctionType::new(&[I32, I32], &[]),
+            I64AtomicStore | I64AtomicStore8 | I64AtomicStore16 | I64AtomicStore32 => FunctionType::new(&[I32, I64], &[]),
+        }
+    }
+
+    fn natural_alignment_exp(self) -> u8 {
+        use AtomicStoreOp::*;
+        match self {
+            I32AtomicStore => 2,
+            I64AtomicStore => 3,
+            I32AtomicStore8 => 0,
+            I32AtomicStore16 => 1,
+            I64AtomicStore8 => 0,
+            I64AtomicStore16 => 1,
+            I64AtomicStore32 => 2,
+        }
+    }
+}
+
+impl fmt::Display for AtomicStoreOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl FromStr for AtomicStoreOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use AtomicStoreOp::*;
+        Ok(match s {
+            "i32.atomic.store" => I32AtomicStore,
+            "i64.atomic.store" => I64AtomicStore,
+            "i32.atomic.store8" => I32AtomicStore8,
+            "i32.atomic.store16" => I32AtomicStore16,
+            "i64.atomic.store8" => I64AtomicStore8,
+            "i64.atomic.store16" => I64AtomicStore16,
+            "i64.atomic.store32" => I64AtomicStore32,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl MemoryOp for AtomicOp {
+    fn to_name(self) -> &'static str {
+        use AtomicOp::*;
+        match self {
+            I32AtomicRmwAdd => "i32.atomic.rmw.add",
+            I64AtomicRmwAdd => "i64.atomic.rmw.add",
+            I32AtomicRmw8AddU => "i32.atomic.rmw8.add_u",
+            I32AtomicRmw16AddU => "i32.atomic.rmw16.add_u",
+            I64AtomicRmw8AddU => "i64.atomic.rmw8.add_u",
+            I64AtomicRmw16AddU => "i64.atomic.rmw16.add_u",
+            I64AtomicRmw32AddU => "i64.atomic.rmw32.add_u",
+            I32AtomicRmwSub => "i32.atomic.rmw.sub",
+            I64AtomicRmwSub => "i64.atomic.rmw.sub",
+            I32AtomicRmw8SubU => "i32.atomic.rmw8.sub_u",
+            I32AtomicRmw16SubU => "i32.atomic.rmw16.sub_u",
+            I64AtomicRmw8SubU => "i64.atomic.rmw8.sub_u",
+            I64AtomicRmw16SubU => "i64.atomic.rmw16.sub_u",
+            I64AtomicRmw32SubU => "i64.atomic.rmw32.sub_u",
+            I32AtomicRmwAnd => "i32.atomic.rmw.and",
+            I64AtomicRmwAnd => "i64.atomic.rmw.and",
+            I32AtomicRmw8AndU => "i32.atomic.rmw8.and_u",
+            I32AtomicRmw16AndU => "i32.atomic.rmw16.and_u",
+            I64AtomicRmw8AndU => "i64.atomic.rmw8.and_u",
+            I64AtomicRmw16AndU => "i64.atomic.rmw16.and_u",
+            I64AtomicRmw32AndU => "i64.atomic.rmw32.and_u",
+            I32AtomicRmwOr => "i32.atomic.rmw.or",
+            I64AtomicRmwOr => "i64.atomic.rmw.or",
+            I32AtomicRmw8OrU => "i32.atomic.rmw8.or_u",
+            I32AtomicRmw16OrU => "i32.atomic.rmw16.or_u",
+            I64AtomicRmw8OrU => "i64.atomic.rmw8.or_u",
+            I64AtomicRmw16OrU => "i64.atomic.rmw16.or_u",
+            I64AtomicRmw32OrU => "i64.atomic.rmw32.or_u",
+            I32AtomicRmwXor => "i32.atomic.rmw.xor",
+            I64AtomicRmwXor => "i64.atomic.rmw.xor",
+            I32AtomicRmw8XorU => "i32.atomic.rmw8.xor_u",
+            I32AtomicRmw16XorU => "i32.atomic.rmw16.xor_u",
+            I64AtomicRmw8XorU => "i64.atomic.rmw8.xor_u",
+            I64AtomicRmw16XorU => "i64.atomic.rmw16.xor_u",
+            I64AtomicRmw32XorU => "i64.atomic.rmw32.xor_u",
+            I32AtomicRmwXchg => "i32.atomic.rmw.xchg",
+            I64AtomicRmwXchg => "i64.atomic.rmw.xchg",
+            I32AtomicRmw8XchgU => "i32.atomic.rmw8.xchg_u",
+            I32AtomicRmw16XchgU => "i32.atomic.rmw16.xchg_u",
+            I64AtomicRmw8XchgU => "i64.atomic.rmw8.xchg_u",
+            I64AtomicRmw16XchgU => "i64.atomic.rmw16.xchg_u",
+            I64AtomicRmw32XchgU => "i64.atomic.rmw32.xchg_u",
+            I32AtomicRmwCmpxchg => "i32.atomic.rmw.cmpxchg",
+            I64AtomicRmwCmpxchg => "i64.atomic.rmw.cmpxchg",
+            I32AtomicRmw8CmpxchgU => "i32.atomic.rmw8.cmpxchg_u",
+            I32AtomicRmw16CmpxchgU => "i32.atomic.rmw16.cmpxchg_u",
+            I64AtomicRmw8CmpxchgU => "i64.atomic.rmw8.cmpxchg_u",
+            I64AtomicRmw16CmpxchgU => "i64.atomic.rmw16.cmpxchg_u",
+            I64AtomicRmw32CmpxchgU => "i64.atomic.rmw32.cmpxchg_u",
+            MemoryAtomicNotify => "memory.atomic.notify",
+            MemoryAtomicWait32 => "memory.atomic.wait32",
+            MemoryAtomicWait64 => "memory.atomic.wait64",
+        }
+    }
+
+    fn to_type(self) -> FunctionType {
+        use AtomicOp::*;
+        use ValType::*;
+        match self {
+            // Address and operand, result is the previous value in memory.
+            I32AtomicRmwAdd | I32AtomicRmw8AddU | I32AtomicRmw16AddU
+            | I32AtomicRmwSub | I32AtomicRmw8SubU | I32AtomicRmw16SubU
+            | I32AtomicRmwAnd | I32AtomicRmw8AndU | I32AtomicRmw16AndU
+            | I32AtomicRmwOr | I32AtomicRmw8OrU | I32AtomicRmw16OrU
+            | I32AtomicRmwXor | I32AtomicRmw8XorU | I32AtomicRmw16XorU
+            | I32AtomicRmwXchg | I32AtomicRmw8XchgU | I32AtomicRmw16XchgU => FunctionType::new(&[I32, I32], &[I32]),
+            I64AtomicRmwAdd | I64AtomicRmw8AddU | I64AtomicRmw16AddU | I64AtomicRmw32AddU
+            | I64AtomicRmwSub | I64AtomicRmw8SubU | I64AtomicRmw16SubU | I64AtomicRmw32SubU
+            | I64AtomicRmwAnd | I64AtomicRmw8AndU | I64AtomicRmw16AndU | I64AtomicRmw32AndU
+            | I64AtomicRmwOr | I64AtomicRmw8OrU | I64AtomicRmw16OrU | I64AtomicRmw32OrU
+            | I64AtomicRmwXor | I64AtomicRmw8XorU | I64AtomicRmw16XorU | I64AtomicRmw32XorU
+            | I64AtomicRmwXchg | I64AtomicRmw8XchgU | I64AtomicRmw16XchgU | I64AtomicRmw32XchgU => FunctionType::new(&[I32, I64], &[I64]),
+            // Address, expected, and replacement value, result is the previous value in memory.
+            I32AtomicRmwCmpxchg | I32AtomicRmw8CmpxchgU | I32AtomicRmw16CmpxchgU => FunctionType::new(&[I32, I32, I32], &[I32]),
+            I64AtomicRmwCmpxchg | I64AtomicRmw8CmpxchgU | I64AtomicRmw16CmpxchgU | I64AtomicRmw32CmpxchgU => FunctionType::new(&[I32, I64, I64], &[I64]),
+            // Address and number of waiters to wake up, result is the number of woken waiters.
+            MemoryAtomicNotify => FunctionType::new(&[I32, I32], &[I32]),
+            // Address, expected value, and timeout in nanoseconds (negative for infinite), result
+            // is 0 for "ok", 1 for "not-equal", and 2 for "timed-out".
+            MemoryAtomicWait32 => FunctionType::new(&[I32, I32, I64], &[I32]),
+            MemoryAtomicWait64 => FunctionType::new(&[I32, I64, I64], &[I32]),
         }
     }
 
-    fn to_type(self) -> FunctionType {
-        use StoreOp::*;
-        use ValType::*;
-        match self {
-            I32Store => FunctionType::new(&[I32, I32], &[]),
-            I64Store => FunctionType::new(&[I32, I64], &[]),
-            F32Store => FunctionType::new(&[I32, F32], &[]),
-            F64Store => FunctionType::new(&[I32, F64], &[]),
+    fn natural_alignment_exp(self) -> u8 {
+        use AtomicOp::*;
+        match self {
+            I32AtomicRmwAdd => 2,
+            I64AtomicRmwAdd => 3,
+            I32AtomicRmw8AddU => 0,
+            I32AtomicRmw16AddU => 1,
+            I64AtomicRmw8AddU => 0,
+            I64AtomicRmw16AddU => 1,
+            I64AtomicRmw32AddU => 2,
+            I32AtomicRmwSub => 2,
+            I64AtomicRmwSub => 3,
+            I32AtomicRmw8SubU => 0,
+            I32AtomicRmw16SubU => 1,
+            I64AtomicRmw8SubU => 0,
+            I64AtomicRmw16SubU => 1,
+            I64AtomicRmw32SubU => 2,
+            I32AtomicRmwAnd => 2,
+            I64AtomicRmwAnd => 3,
+            I32AtomicRmw8AndU => 0,
+            I32AtomicRmw16AndU => 1,
+            I64AtomicRmw8AndU => 0,
+            I64AtomicRmw16AndU => 1,
+            I64AtomicRmw32AndU => 2,
+            I32AtomicRmwOr => 2,
+            I64AtomicRmwOr => 3,
+            I32AtomicRmw8OrU => 0,
+            I32AtomicRmw16OrU => 1,
+            I64AtomicRmw8OrU => 0,
+            I64AtomicRmw16OrU => 1,
+            I64AtomicRmw32OrU => 2,
+            I32AtomicRmwXor => 2,
+            I64AtomicRmwXor => 3,
+            I32AtomicRmw8XorU => 0,
+            I32AtomicRmw16XorU => 1,
+            I64AtomicRmw8XorU => 0,
+            I64AtomicRmw16XorU => 1,
+            I64AtomicRmw32XorU => 2,
+            I32AtomicRmwXchg => 2,
+            I64AtomicRmwXchg => 3,
+            I32AtomicRmw8XchgU => 0,
+            I32AtomicRmw16XchgU => 1,
+            I64AtomicRmw8XchgU => 0,
+            I64AtomicRmw16XchgU => 1,
+            I64AtomicRmw32XchgU => 2,
+            I32AtomicRmwCmpxchg => 2,
+            I64AtomicRmwCmpxchg => 3,
+            I32AtomicRmw8CmpxchgU => 0,
+            I32AtomicRmw16CmpxchgU => 1,
+            I64AtomicRmw8CmpxchgU => 0,
+            I64AtomicRmw16CmpxchgU => 1,
+            I64AtomicRmw32CmpxchgU => 2,
+            MemoryAtomicNotify => 2,
+            MemoryAtomicWait32 => 2,
+            MemoryAtomicWait64 => 3,
+        }
+    }
+}
+
+impl fmt::Display for AtomicOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl FromStr for AtomicOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use AtomicOp::*;
+        Ok(match s {
+            "i32.atomic.rmw.add" => I32AtomicRmwAdd,
+            "i64.atomic.rmw.add" => I64AtomicRmwAdd,
+            "i32.atomic.rmw8.add_u" => I32AtomicRmw8AddU,
+            "i32.atomic.rmw16.add_u" => I32AtomicRmw16AddU,
+            "i64.atomic.rmw8.add_u" => I64AtomicRmw8AddU,
+            "i64.atomic.rmw16.add_u" => I64AtomicRmw16AddU,
+            "i64.atomic.rmw32.add_u" => I64AtomicRmw32AddU,
+            "i32.atomic.rmw.sub" => I32AtomicRmwSub,
+            "i64.atomic.rmw.sub" => I64AtomicRmwSub,
+            "i32.atomic.rmw8.sub_u" => I32AtomicRmw8SubU,
+            "i32.atomic.rmw16.sub_u" => I32AtomicRmw16SubU,
+            "i64.atomic.rmw8.sub_u" => I64AtomicRmw8SubU,
+            "i64.atomic.rmw16.sub_u" => I64AtomicRmw16SubU,
+            "i64.atomic.rmw32.sub_u" => I64AtomicRmw32SubU,
+            "i32.atomic.rmw.and" => I32AtomicRmwAnd,
+            "i64.atomic.rmw.and" => I64AtomicRmwAnd,
+            "i32.atomic.rmw8.and_u" => I32AtomicRmw8AndU,
+            "i32.atomic.rmw16.and_u" => I32AtomicRmw16AndU,
+            "i64.atomic.rmw8.and_u" => I64AtomicRmw8AndU,
+            "i64.atomic.rmw16.and_u" => I64AtomicRmw16AndU,
+            "i64.atomic.rmw32.and_u" => I64AtomicRmw32AndU,
+            "i32.atomic.rmw.or" => I32AtomicRmwOr,
+            "i64.atomic.rmw.or" => I64AtomicRmwOr,
+            "i32.atomic.rmw8.or_u" => I32AtomicRmw8OrU,
+            "i32.atomic.rmw16.or_u" => I32AtomicRmw16OrU,
+            "i64.atomic.rmw8.or_u" => I64AtomicRmw8OrU,
+            "i64.atomic.rmw16.or_u" => I64AtomicRmw16OrU,
+            "i64.atomic.rmw32.or_u" => I64AtomicRmw32OrU,
+            "i32.atomic.rmw.xor" => I32AtomicRmwXor,
+            "i64.atomic.rmw.xor" => I64AtomicRmwXor,
+            "i32.atomic.rmw8.xor_u" => I32AtomicRmw8XorU,
+            "i32.atomic.rmw16.xor_u" => I32AtomicRmw16XorU,
+            "i64.atomic.rmw8.xor_u" => I64AtomicRmw8XorU,
+            "i64.atomic.rmw16.xor_u" => I64AtomicRmw16XorU,
+            "i64.atomic.rmw32.xor_u" => I64AtomicRmw32XorU,
+            "i32.atomic.rmw.xchg" => I32AtomicRmwXchg,
+            "i64.atomic.rmw.xchg" => I64AtomicRmwXchg,
+            "i32.atomic.rmw8.xchg_u" => I32AtomicRmw8XchgU,
+            "i32.atomic.rmw16.xchg_u" => I32AtomicRmw16XchgU,
+            "i64.atomic.rmw8.xchg_u" => I64AtomicRmw8XchgU,
+            "i64.atomic.rmw16.xchg_u" => I64AtomicRmw16XchgU,
+            "i64.atomic.rmw32.xchg_u" => I64AtomicRmw32XchgU,
+            "i32.atomic.rmw.cmpxchg" => I32AtomicRmwCmpxchg,
+            "i64.atomic.rmw.cmpxchg" => I64AtomicRmwCmpxchg,
+            "i32.atomic.rmw8.cmpxchg_u" => I32AtomicRmw8CmpxchgU,
+            "i32.atomic.rmw16.cmpxchg_u" => I32AtomicRmw16CmpxchgU,
+            "i64.atomic.rmw8.cmpxchg_u" => I64AtomicRmw8CmpxchgU,
+            "i64.atomic.rmw16.cmpxchg_u" => I64AtomicRmw16CmpxchgU,
+            "i64.atomic.rmw32.cmpxchg_u" => I64AtomicRmw32CmpxchgU,
+            "memory.atomic.notify" => MemoryAtomicNotify,
+            "memory.atomic.wait32" => MemoryAtomicWait32,
+            "memory.atomic.wait64" => MemoryAtomicWait64,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl AtomicOp {
+    /// Compare-exchange operations only write their replacement operand if the value read from
+    /// memory equals their expected operand.
+    pub fn is_cmpxchg(self) -> bool {
+        use AtomicOp::*;
+        matches!(self, I32AtomicRmwCmpxchg | I64AtomicRmwCmpxchg | I32AtomicRmw8CmpxchgU | I32AtomicRmw16CmpxchgU | I64AtomicRmw8CmpxchgU | I64AtomicRmw16CmpxchgU | I64AtomicRmw32CmpxchgU)
+    }
+
+    /// `memory.atomic.wait32/64` and `memory.atomic.notify` do not write to memory, unlike all
+    /// other (read-modify-write) atomic operations.
+    pub fn is_wait_or_notify(self) -> bool {
+        use AtomicOp::*;
+        matches!(self, MemoryAtomicNotify | MemoryAtomicWait32 | MemoryAtomicWait64)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum ExtractLaneOp {
+    I8x16ExtractLaneS,
+    I8x16ExtractLaneU,
+    I16x8ExtractLaneS,
+    I16x8ExtractLaneU,
+    I32x4ExtractLane,
+    I64x2ExtractLane,
+    F32x4ExtractLane,
+    F64x2ExtractLane,
+}
 
-            I32Store8 => FunctionType::new(&[I32, I32], &[]),
-            I32Store16 => FunctionType::new(&[I32, I32], &[]),
-            I64Store8 => FunctionType::new(&[I32, I64], &[]),
-            I64Store16 => FunctionType::new(&[I32, I64], &[]),
-            I64Store32 => FunctionType::new(&[I32, I64], &[]),
-        }
-    }
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum ReplaceLaneOp {
+    I8x16ReplaceLane,
+    I16x8ReplaceLane,
+    I32x4ReplaceLane,
+    I64x2ReplaceLane,
+    F32x4ReplaceLane,
+    F64x2ReplaceLane,
+}
 
-    fn natural_alignment_exp(self) -> u8 {
-        use StoreOp::*;
+impl ExtractLaneOp {
+    pub fn to_name(self) -> &'static str {
+        use ExtractLaneOp::*;
         match self {
-            I32Store => 2,
-            I64Store => 3,
-            F32Store => 2,
-            F64Store => 3,
-
-            I32Store8 => 0,
-            I32Store16 => 1,
-            I64Store8 => 0,
-            I64Store16 => 1,
-            I64Store32 => 2,
+            I8x16ExtractLaneS => "i8x16.extract_lane_s",
+            I8x16ExtractLaneU => "i8x16.extract_lane_u",
+            I16x8ExtractLaneS => "i16x8.extract_lane_s",
+            I16x8ExtractLaneU => "i16x8.extract_lane_u",
+            I32x4ExtractLane => "i32x4.extract_lane",
+            I64x2ExtractLane => "i64x2.extract_lane",
+            F32x4ExtractLane => "f32x4.extract_lane",
+            F64x2ExtractLane => "f64x2.extract_lane",
         }
     }
-}
 
-impl fmt::Display for LoadOp {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.to_name())
+    pub fn to_type(self) -> FunctionType {
+        use ExtractLaneOp::*;
+        use ValType::*;
+        match self {
+            I8x16ExtractLaneS | I8x16ExtractLaneU | I16x8ExtractLaneS | I16x8ExtractLaneU | I32x4ExtractLane => FunctionType::new(&[V128], &[I32]),
+            I64x2ExtractLane => FunctionType::new(&[V128], &[I64]),
+            F32x4ExtractLane => FunctionType::new(&[V128], &[F32]),
+            F64x2ExtractLane => FunctionType::new(&[V128], &[F64]),
+        }
     }
 }
 
-impl fmt::Display for StoreOp {
+impl fmt::Display for ExtractLaneOp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(self.to_name())
     }
 }
 
-impl FromStr for LoadOp {
+impl FromStr for ExtractLaneOp {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use LoadOp::*;
+        use ExtractLaneOp::*;
         Ok(match s {
-            "i32.load" => I32Load,
-            "i64.load" => I64Load,
-            "f32.load" => F32Load,
-            "f64.load" => F64Load,
-            "i32.load8_s" => I32Load8S,
-            "i32.load8_u" => I32Load8U,
-            "i32.load16_s" => I32Load16S,
-            "i32.load16_u" => I32Load16U,
-            "i64.load8_s" => I64Load8S,
-            "i64.load8_u" => I64Load8U,
-            "i64.load16_s" => I64Load16S,
-            "i64.load16_u" => I64Load16U,
-            "i64.load32_s" => I64Load32S,
-            "i64.load32_u" => I64Load32U,
+            "i8x16.extract_lane_s" => I8x16ExtractLaneS,
+            "i8x16.extract_lane_u" => I8x16ExtractLaneU,
+            "i16x8.extract_lane_s" => I16x8ExtractLaneS,
+            "i16x8.extract_lane_u" => I16x8ExtractLaneU,
+            "i32x4.extract_lane" => I32x4ExtractLane,
+            "i64x2.extract_lane" => I64x2ExtractLane,
+            "f32x4.extract_lane" => F32x4ExtractLane,
+            "f64x2.extract_lane" => F64x2ExtractLane,
             _ => return Err(()),
         })
     }
 }
 
-impl FromStr for StoreOp {
+impl ReplaceLaneOp {
+    pub fn to_name(self) -> &'static str {
+        use ReplaceLaneOp::*;
+        match self {
+            I8x16ReplaceLane => "i8x16.replace_lane",
+            I16x8ReplaceLane => "i16x8.replace_lane",
+            I32x4ReplaceLane => "i32x4.replace_lane",
+            I64x2ReplaceLane => "i64x2.replace_lane",
+            F32x4ReplaceLane => "f32x4.replace_lane",
+            F64x2ReplaceLane => "f64x2.replace_lane",
+        }
+    }
+
+    pub fn to_type(self) -> FunctionType {
+        use ReplaceLaneOp::*;
+        use ValType::*;
+        match self {
+            I8x16ReplaceLane | I16x8ReplaceLane | I32x4ReplaceLane => FunctionType::new(&[V128, I32], &[V128]),
+            I64x2ReplaceLane => FunctionType::new(&[V128, I64], &[V128]),
+            F32x4ReplaceLane => FunctionType::new(&[V128, F32], &[V128]),
+            F64x2ReplaceLane => FunctionType::new(&[V128, F64], &[V128]),
+        }
+    }
+}
+
+impl fmt::Display for ReplaceLaneOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_name())
+    }
+}
+
+impl FromStr for ReplaceLaneOp {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        use StoreOp::*;
+        use ReplaceLaneOp::*;
         Ok(match s {
-            "i32.store" => I32Store,
-            "i64.store" => I64Store,
-            "f32.store" => F32Store,
-            "f64.store" => F64Store,
-            "i32.store8" => I32Store8,
-            "i32.store16" => I32Store16,
-            "i64.store8" => I64Store8,
-            "i64.store16" => I64Store16,
-            "i64.store32" => I64Store32,
+            "i8x16.replace_lane" => I8x16ReplaceLane,
+            "i16x8.replace_lane" => I16x8ReplaceLane,
+            "i32x4.replace_lane" => I32x4ReplaceLane,
+            "i64x2.replace_lane" => I64x2ReplaceLane,
+            "f32x4.replace_lane" => F32x4ReplaceLane,
+            "f64x2.replace_lane" => F64x2ReplaceLane,
             _ => return Err(()),
         })
     }
@@ -1051,6 +2220,90 @@ pub enum UnaryOp {
     I64ReinterpretF64,
     F32ReinterpretI32,
     F64ReinterpretI64,
+
+    // Sign-extension operators extension.
+    I32Extend8S,
+    I32Extend16S,
+    I64Extend8S,
+    I64Extend16S,
+    I64Extend32S,
+
+    // Non-trapping float-to-int conversions extension.
+    I32TruncSatF32S,
+    I32TruncSatF32U,
+    I32TruncSatF64S,
+    I32TruncSatF64U,
+    I64TruncSatF32S,
+    I64TruncSatF32U,
+    I64TruncSatF64S,
+    I64TruncSatF64U,
+
+    // SIMD extension.
+    I8x16Splat,
+    I16x8Splat,
+    I32x4Splat,
+    I64x2Splat,
+    F32x4Splat,
+    F64x2Splat,
+    V128AnyTrue,
+    I8x16AllTrue,
+    I8x16Bitmask,
+    I16x8AllTrue,
+    I16x8Bitmask,
+    I32x4AllTrue,
+    I32x4Bitmask,
+    I64x2AllTrue,
+    I64x2Bitmask,
+    V128Not,
+    I8x16Abs,
+    I8x16Neg,
+    I8x16Popcnt,
+    I16x8Abs,
+    I16x8Neg,
+    I32x4Abs,
+    I32x4Neg,
+    I64x2Abs,
+    I64x2Neg,
+    F32x4Ceil,
+    F32x4Floor,
+    F32x4Trunc,
+    F32x4Nearest,
+    F32x4Abs,
+    F32x4Neg,
+    F32x4Sqrt,
+    F64x2Ceil,
+    F64x2Floor,
+    F64x2Trunc,
+    F64x2Nearest,
+    F64x2Abs,
+    F64x2Neg,
+    F64x2Sqrt,
+    I16x8ExtAddPairwiseI8x16S,
+    I16x8ExtAddPairwiseI8x16U,
+    I32x4ExtAddPairwiseI16x8S,
+    I32x4ExtAddPairwiseI16x8U,
+    I16x8ExtendLowI8x16S,
+    I16x8ExtendHighI8x16S,
+    I16x8ExtendLowI8x16U,
+    I16x8ExtendHighI8x16U,
+    I32x4ExtendLowI16x8S,
+    I32x4ExtendHighI16x8S,
+    I32x4ExtendLowI16x8U,
+    I32x4ExtendHighI16x8U,
+    I64x2ExtendLowI32x4S,
+    I64x2ExtendHighI32x4S,
+    I64x2ExtendLowI32x4U,
+    I64x2ExtendHighI32x4U,
+    I32x4TruncSatF32x4S,
+    I32x4TruncSatF32x4U,
+    F32x4ConvertI32x4S,
+    F32x4ConvertI32x4U,
+    I32x4TruncSatF64x2SZero,
+    I32x4TruncSatF64x2UZero,
+    F64x2ConvertLowI32x4S,
+    F64x2ConvertLowI32x4U,
+    F32x4DemoteF64x2Zero,
+    F64x2PromoteLowF32x4,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -1138,6 +2391,140 @@ pub enum BinaryOp {
     F64Min,
     F64Max,
     F64Copysign,
+
+    // SIMD extension.
+    I8x16Eq,
+    I8x16Ne,
+    I8x16LtS,
+    I8x16LtU,
+    I8x16GtS,
+    I8x16GtU,
+    I8x16LeS,
+    I8x16LeU,
+    I8x16GeS,
+    I8x16GeU,
+    I16x8Eq,
+    I16x8Ne,
+    I16x8LtS,
+    I16x8LtU,
+    I16x8GtS,
+    I16x8GtU,
+    I16x8LeS,
+    I16x8LeU,
+    I16x8GeS,
+    I16x8GeU,
+    I32x4Eq,
+    I32x4Ne,
+    I32x4LtS,
+    I32x4LtU,
+    I32x4GtS,
+    I32x4GtU,
+    I32x4LeS,
+    I32x4LeU,
+    I32x4GeS,
+    I32x4GeU,
+    I64x2Eq,
+    I64x2Ne,
+    I64x2LtS,
+    I64x2GtS,
+    I64x2LeS,
+    I64x2GeS,
+    F32x4Eq,
+    F32x4Ne,
+    F32x4Lt,
+    F32x4Gt,
+    F32x4Le,
+    F32x4Ge,
+    F64x2Eq,
+    F64x2Ne,
+    F64x2Lt,
+    F64x2Gt,
+    F64x2Le,
+    F64x2Ge,
+    V128And,
+    V128AndNot,
+    V128Or,
+    V128Xor,
+    I8x16Swizzle,
+    I8x16NarrowI16x8S,
+    I8x16NarrowI16x8U,
+    I8x16Add,
+    I8x16AddSatS,
+    I8x16AddSatU,
+    I8x16Sub,
+    I8x16SubSatS,
+    I8x16SubSatU,
+    I8x16MinS,
+    I8x16MinU,
+    I8x16MaxS,
+    I8x16MaxU,
+    I8x16AvgrU,
+    I16x8Q15MulrSatS,
+    I16x8NarrowI32x4S,
+    I16x8NarrowI32x4U,
+    I16x8Add,
+    I16x8AddSatS,
+    I16x8AddSatU,
+    I16x8Sub,
+    I16x8SubSatS,
+    I16x8SubSatU,
+    I16x8Mul,
+    I16x8MinS,
+    I16x8MinU,
+    I16x8MaxS,
+    I16x8MaxU,
+    I16x8AvgrU,
+    I16x8ExtMulLowI8x16S,
+    I16x8ExtMulHighI8x16S,
+    I16x8ExtMulLowI8x16U,
+    I16x8ExtMulHighI8x16U,
+    I32x4Add,
+    I32x4Sub,
+    I32x4Mul,
+    I32x4MinS,
+    I32x4MinU,
+    I32x4MaxS,
+    I32x4MaxU,
+    I32x4DotI16x8S,
+    I32x4ExtMulLowI16x8S,
+    I32x4ExtMulHighI16x8S,
+    I32x4ExtMulLowI16x8U,
+    I32x4ExtMulHighI16x8U,
+    I64x2Add,
+    I64x2Sub,
+    I64x2Mul,
+    I64x2ExtMulLowI32x4S,
+    I64x2ExtMulHighI32x4S,
+    I64x2ExtMulLowI32x4U,
+    I64x2ExtMulHighI32x4U,
+    F32x4Add,
+    F32x4Sub,
+    F32x4Mul,
+    F32x4Div,
+    F32x4Min,
+    F32x4Max,
+    F32x4PMin,
+    F32x4PMax,
+    F64x2Add,
+    F64x2Sub,
+    F64x2Mul,
+    F64x2Div,
+    F64x2Min,
+    F64x2Max,
+    F64x2PMin,
+    F64x2PMax,
+    I8x16Shl,
+    I8x16ShrS,
+    I8x16ShrU,
+    I16x8Shl,
+    I16x8ShrS,
+    I16x8ShrU,
+    I32x4Shl,
+    I32x4ShrS,
+    I32x4ShrU,
+    I64x2Shl,
+    I64x2ShrS,
+    I64x2ShrU,
 }
 
 impl fmt::Display for UnaryOp {
@@ -1203,6 +2590,84 @@ impl UnaryOp {
             I64ReinterpretF64 => "i64.reinterpret_f64",
             F32ReinterpretI32 => "f32.reinterpret_i32",
             F64ReinterpretI64 => "f64.reinterpret_i64",
+            I32Extend8S => "i32.extend8_s",
+            I32Extend16S => "i32.extend16_s",
+            I64Extend8S => "i64.extend8_s",
+            I64Extend16S => "i64.extend16_s",
+            I64Extend32S => "i64.extend32_s",
+            I32TruncSatF32S => "i32.trunc_sat_f32_s",
+            I32TruncSatF32U => "i32.trunc_sat_f32_u",
+            I32TruncSatF64S => "i32.trunc_sat_f64_s",
+            I32TruncSatF64U => "i32.trunc_sat_f64_u",
+            I64TruncSatF32S => "i64.trunc_sat_f32_s",
+            I64TruncSatF32U => "i64.trunc_sat_f32_u",
+            I64TruncSatF64S => "i64.trunc_sat_f64_s",
+            I64TruncSatF64U => "i64.trunc_sat_f64_u",
+            I8x16Splat => "i8x16.splat",
+            I16x8Splat => "i16x8.splat",
+            I32x4Splat => "i32x4.splat",
+            I64x2Splat => "i64x2.splat",
+            F32x4Splat => "f32x4.splat",
+            F64x2Splat => "f64x2.splat",
+            V128AnyTrue => "v128.any_true",
+            I8x16AllTrue => "i8x16.all_true",
+            I8x16Bitmask => "i8x16.bitmask",
+            I16x8AllTrue => "i16x8.all_true",
+            I16x8Bitmask => "i16x8.bitmask",
+            I32x4AllTrue => "i32x4.all_true",
+            I32x4Bitmask => "i32x4.bitmask",
+            I64x2AllTrue => "i64x2.all_true",
+            I64x2Bitmask => "i64x2.bitmask",
+            V128Not => "v128.not",
+            I8x16Abs => "i8x16.abs",
+            I8x16Neg => "i8x16.neg",
+            I8x16Popcnt => "i8x16.popcnt",
+            I16x8Abs => "i16x8.abs",
+            I16x8Neg => "i16x8.neg",
+            I32x4Abs => "i32x4.abs",
+            I32x4Neg => "i32x4.neg",
+            I64x2Abs => "i64x2.abs",
+            I64x2Neg => "i64x2.neg",
+            F32x4Ceil => "f32x4.ceil",
+            F32x4Floor => "f32x4.floor",
+            F32x4Trunc => "f32x4.trunc",
+            F32x4Nearest => "f32x4.nearest",
+            F32x4Abs => "f32x4.abs",
+            F32x4Neg => "f32x4.neg",
+            F32x4Sqrt => "f32x4.sqrt",
+            F64x2Ceil => "f64x2.ceil",
+            F64x2Floor => "f64x2.floor",
+            F64x2Trunc => "f64x2.trunc",
+            F64x2Nearest => "f64x2.nearest",
+            F64x2Abs => "f64x2.abs",
+            F64x2Neg => "f64x2.neg",
+            F64x2Sqrt => "f64x2.sqrt",
+            I16x8ExtAddPairwiseI8x16S => "i16x8.extadd_pairwise_i8x16_s",
+            I16x8ExtAddPairwiseI8x16U => "i16x8.extadd_pairwise_i8x16_u",
+            I32x4ExtAddPairwiseI16x8S => "i32x4.extadd_pairwise_i16x8_s",
+            I32x4ExtAddPairwiseI16x8U => "i32x4.extadd_pairwise_i16x8_u",
+            I16x8ExtendLowI8x16S => "i16x8.extend_low_i8x16_s",
+            I16x8ExtendHighI8x16S => "i16x8.extend_high_i8x16_s",
+            I16x8ExtendLowI8x16U => "i16x8.extend_low_i8x16_u",
+            I16x8ExtendHighI8x16U => "i16x8.extend_high_i8x16_u",
+            I32x4ExtendLowI16x8S => "i32x4.extend_low_i16x8_s",
+            I32x4ExtendHighI16x8S => "i32x4.extend_high_i16x8_s",
+            I32x4ExtendLowI16x8U => "i32x4.extend_low_i16x8_u",
+            I32x4ExtendHighI16x8U => "i32x4.extend_high_i16x8_u",
+            I64x2ExtendLowI32x4S => "i64x2.extend_low_i32x4_s",
+            I64x2ExtendHighI32x4S => "i64x2.extend_high_i32x4_s",
+            I64x2ExtendLowI32x4U => "i64x2.extend_low_i32x4_u",
+            I64x2ExtendHighI32x4U => "i64x2.extend_high_i32x4_u",
+            I32x4TruncSatF32x4S => "i32x4.trunc_sat_f32x4_s",
+            I32x4TruncSatF32x4U => "i32x4.trunc_sat_f32x4_u",
+            F32x4ConvertI32x4S => "f32x4.convert_i32x4_s",
+            F32x4ConvertI32x4U => "f32x4.convert_i32x4_u",
+            I32x4TruncSatF64x2SZero => "i32x4.trunc_sat_f64x2_s_zero",
+            I32x4TruncSatF64x2UZero => "i32x4.trunc_sat_f64x2_u_zero",
+            F64x2ConvertLowI32x4S => "f64x2.convert_low_i32x4_s",
+            F64x2ConvertLowI32x4U => "f64x2.convert_low_i32x4_u",
+            F32x4DemoteF64x2Zero => "f32x4.demote_f64x2_zero",
+            F64x2PromoteLowF32x4 => "f64x2.promote_low_f32x4",
         }
     }
 
@@ -1236,6 +2701,45 @@ impl UnaryOp {
             I64ReinterpretF64 => FunctionType::new(&[F64], &[I64]),
             F32ReinterpretI32 => FunctionType::new(&[I32], &[F32]),
             F64ReinterpretI64 => FunctionType::new(&[I64], &[F64]),
+
+            // sign-extension
+            I32Extend8S | I32Extend16S => FunctionType::new(&[I32], &[I32]),
+            I64Extend8S | I64Extend16S | I64Extend32S => FunctionType::new(&[I64], &[I64]),
+
+            // non-trapping float-to-int
+            I32TruncSatF32S | I32TruncSatF32U => FunctionType::new(&[F32], &[I32]),
+            I32TruncSatF64S | I32TruncSatF64U => FunctionType::new(&[F64], &[I32]),
+            I64TruncSatF32S | I64TruncSatF32U => FunctionType::new(&[F32], &[I64]),
+            I64TruncSatF64S | I64TruncSatF64U => FunctionType::new(&[F64], &[I64]),
+
+            // SIMD splats, tests, and lane-wise operations
+            I8x16Splat | I16x8Splat | I32x4Splat => FunctionType::new(&[I32], &[V128]),
+            I64x2Splat => FunctionType::new(&[I64], &[V128]),
+            F32x4Splat => FunctionType::new(&[F32], &[V128]),
+            F64x2Splat => FunctionType::new(&[F64], &[V128]),
+            V128AnyTrue
+            | I8x16AllTrue | I8x16Bitmask
+            | I16x8AllTrue | I16x8Bitmask
+            | I32x4AllTrue | I32x4Bitmask
+            | I64x2AllTrue | I64x2Bitmask => FunctionType::new(&[V128], &[I32]),
+            V128Not
+            | I8x16Abs | I8x16Neg | I8x16Popcnt
+            | I16x8Abs | I16x8Neg
+            | I32x4Abs | I32x4Neg
+            | I64x2Abs | I64x2Neg
+            | F32x4Ceil | F32x4Floor | F32x4Trunc | F32x4Nearest | F32x4Abs | F32x4Neg | F32x4Sqrt
+            | F64x2Ceil | F64x2Floor | F64x2Trunc | F64x2Nearest | F64x2Abs | F64x2Neg | F64x2Sqrt
+            | I16x8ExtAddPairwiseI8x16S | I16x8ExtAddPairwiseI8x16U
+            | I32x4ExtAddPairwiseI16x8S | I32x4ExtAddPairwiseI16x8U
+            | I16x8ExtendLowI8x16S | I16x8ExtendHighI8x16S | I16x8ExtendLowI8x16U | I16x8ExtendHighI8x16U
+            | I32x4ExtendLowI16x8S | I32x4ExtendHighI16x8S | I32x4ExtendLowI16x8U | I32x4ExtendHighI16x8U
+            | I64x2ExtendLowI32x4S | I64x2ExtendHighI32x4S | I64x2ExtendLowI32x4U | I64x2ExtendHighI32x4U
+            | I32x4TruncSatF32x4S | I32x4TruncSatF32x4U
+            | F32x4ConvertI32x4S | F32x4ConvertI32x4U
+            | I32x4TruncSatF64x2SZero | I32x4TruncSatF64x2UZero
+            | F64x2ConvertLowI32x4S | F64x2ConvertLowI32x4U
+            | F32x4DemoteF64x2Zero
+            | F64x2PromoteLowF32x4 => FunctionType::new(&[V128], &[V128]),
         }
     }
 }
@@ -1293,6 +2797,84 @@ impl FromStr for UnaryOp {
             "i64.reinterpret_f64" => I64ReinterpretF64,
             "f32.reinterpret_i32" => F32ReinterpretI32,
             "f64.reinterpret_i64" => F64ReinterpretI64,
+            "i32.extend8_s" => I32Extend8S,
+            "i32.extend16_s" => I32Extend16S,
+            "i64.extend8_s" => I64Extend8S,
+            "i64.extend16_s" => I64Extend16S,
+            "i64.extend32_s" => I64Extend32S,
+            "i32.trunc_sat_f32_s" => I32TruncSatF32S,
+            "i32.trunc_sat_f32_u" => I32TruncSatF32U,
+            "i32.trunc_sat_f64_s" => I32TruncSatF64S,
+            "i32.trunc_sat_f64_u" => I32TruncSatF64U,
+            "i64.trunc_sat_f32_s" => I64TruncSatF32S,
+            "i64.trunc_sat_f32_u" => I64TruncSatF32U,
+            "i64.trunc_sat_f64_s" => I64TruncSatF64S,
+            "i64.trunc_sat_f64_u" => I64TruncSatF64U,
+            "i8x16.splat" => I8x16Splat,
+            "i16x8.splat" => I16x8Splat,
+            "i32x4.splat" => I32x4Splat,
+            "i64x2.splat" => I64x2Splat,
+            "f32x4.splat" => F32x4Splat,
+            "f64x2.splat" => F64x2Splat,
+            "v128.any_true" => V128AnyTrue,
+            "i8x16.all_true" => I8x16AllTrue,
+            "i8x16.bitmask" => I8x16Bitmask,
+            "i16x8.all_true" => I16x8AllTrue,
+            "i16x8.bitmask" => I16x8Bitmask,
+            "i32x4.all_true" => I32x4AllTrue,
+            "i32x4.bitmask" => I32x4Bitmask,
+            "i64x2.all_true" => I64x2AllTrue,
+            "i64x2.bitmask" => I64x2Bitmask,
+            "v128.not" => V128Not,
+            "i8x16.abs" => I8x16Abs,
+            "i8x16.neg" => I8x16Neg,
+            "i8x16.popcnt" => I8x16Popcnt,
+            "i16x8.abs" => I16x8Abs,
+            "i16x8.neg" => I16x8Neg,
+            "i32x4.abs" => I32x4Abs,
+            "i32x4.neg" => I32x4Neg,
+            "i64x2.abs" => I64x2Abs,
+            "i64x2.neg" => I64x2Neg,
+            "f32x4.ceil" => F32x4Ceil,
+            "f32x4.floor" => F32x4Floor,
+            "f32x4.trunc" => F32x4Trunc,
+            "f32x4.nearest" => F32x4Nearest,
+            "f32x4.abs" => F32x4Abs,
+            "f32x4.neg" => F32x4Neg,
+            "f32x4.sqrt" => F32x4Sqrt,
+            "f64x2.ceil" => F64x2Ceil,
+            "f64x2.floor" => F64x2Floor,
+            "f64x2.trunc" => F64x2Trunc,
+            "f64x2.nearest" => F64x2Nearest,
+            "f64x2.abs" => F64x2Abs,
+            "f64x2.neg" => F64x2Neg,
+            "f64x2.sqrt" => F64x2Sqrt,
+            "i16x8.extadd_pairwise_i8x16_s" => I16x8ExtAddPairwiseI8x16S,
+            "i16x8.extadd_pairwise_i8x16_u" => I16x8ExtAddPairwiseI8x16U,
+            "i32x4.extadd_pairwise_i16x8_s" => I32x4ExtAddPairwiseI16x8S,
+            "i32x4.extadd_pairwise_i16x8_u" => I32x4ExtAddPairwiseI16x8U,
+            "i16x8.extend_low_i8x16_s" => I16x8ExtendLowI8x16S,
+            "i16x8.extend_high_i8x16_s" => I16x8ExtendHighI8x16S,
+            "i16x8.extend_low_i8x16_u" => I16x8ExtendLowI8x16U,
+            "i16x8.extend_high_i8x16_u" => I16x8ExtendHighI8x16U,
+            "i32x4.extend_low_i16x8_s" => I32x4ExtendLowI16x8S,
+            "i32x4.extend_high_i16x8_s" => I32x4ExtendHighI16x8S,
+            "i32x4.extend_low_i16x8_u" => I32x4ExtendLowI16x8U,
+            "i32x4.extend_high_i16x8_u" => I32x4ExtendHighI16x8U,
+            "i64x2.extend_low_i32x4_s" => I64x2ExtendLowI32x4S,
+            "i64x2.extend_high_i32x4_s" => I64x2ExtendHighI32x4S,
+            "i64x2.extend_low_i32x4_u" => I64x2ExtendLowI32x4U,
+            "i64x2.extend_high_i32x4_u" => I64x2ExtendHighI32x4U,
+            "i32x4.trunc_sat_f32x4_s" => I32x4TruncSatF32x4S,
+            "i32x4.trunc_sat_f32x4_u" => I32x4TruncSatF32x4U,
+            "f32x4.convert_i32x4_s" => F32x4ConvertI32x4S,
+            "f32x4.convert_i32x4_u" => F32x4ConvertI32x4U,
+            "i32x4.trunc_sat_f64x2_s_zero" => I32x4TruncSatF64x2SZero,
+            "i32x4.trunc_sat_f64x2_u_zero" => I32x4TruncSatF64x2UZero,
+            "f64x2.convert_low_i32x4_s" => F64x2ConvertLowI32x4S,
+            "f64x2.convert_low_i32x4_u" => F64x2ConvertLowI32x4U,
+            "f32x4.demote_f64x2_zero" => F32x4DemoteF64x2Zero,
+            "f64x2.promote_low_f32x4" => F64x2PromoteLowF32x4,
             _ => return Err(()),
         })
     }
@@ -1378,6 +2960,138 @@ impl BinaryOp {
             F64Min => "f64.min",
             F64Max => "f64.max",
             F64Copysign => "f64.copysign",
+            I8x16Eq => "i8x16.eq",
+            I8x16Ne => "i8x16.ne",
+            I8x16LtS => "i8x16.lt_s",
+            I8x16LtU => "i8x16.lt_u",
+            I8x16GtS => "i8x16.gt_s",
+            I8x16GtU => "i8x16.gt_u",
+            I8x16LeS => "i8x16.le_s",
+            I8x16LeU => "i8x16.le_u",
+            I8x16GeS => "i8x16.ge_s",
+            I8x16GeU => "i8x16.ge_u",
+            I16x8Eq => "i16x8.eq",
+            I16x8Ne => "i16x8.ne",
+            I16x8LtS => "i16x8.lt_s",
+            I16x8LtU => "i16x8.lt_u",
+            I16x8GtS => "i16x8.gt_s",
+            I16x8GtU => "i16x8.gt_u",
+            I16x8LeS => "i16x8.le_s",
+            I16x8LeU => "i16x8.le_u",
+            I16x8GeS => "i16x8.ge_s",
+            I16x8GeU => "i16x8.ge_u",
+            I32x4Eq => "i32x4.eq",
+            I32x4Ne => "i32x4.ne",
+            I32x4LtS => "i32x4.lt_s",
+            I32x4LtU => "i32x4.lt_u",
+            I32x4GtS => "i32x4.gt_s",
+            I32x4GtU => "i32x4.gt_u",
+            I32x4LeS => "i32x4.le_s",
+            I32x4LeU => "i32x4.le_u",
+            I32x4GeS => "i32x4.ge_s",
+            I32x4GeU => "i32x4.ge_u",
+            I64x2Eq => "i64x2.eq",
+            I64x2Ne => "i64x2.ne",
+            I64x2LtS => "i64x2.lt_s",
+            I64x2GtS => "i64x2.gt_s",
+            I64x2LeS => "i64x2.le_s",
+            I64x2GeS => "i64x2.ge_s",
+            F32x4Eq => "f32x4.eq",
+            F32x4Ne => "f32x4.ne",
+            F32x4Lt => "f32x4.lt",
+            F32x4Gt => "f32x4.gt",
+            F32x4Le => "f32x4.le",
+            F32x4Ge => "f32x4.ge",
+            F64x2Eq => "f64x2.eq",
+            F64x2Ne => "f64x2.ne",
+            F64x2Lt => "f64x2.lt",
+            F64x2Gt => "f64x2.gt",
+            F64x2Le => "f64x2.le",
+            F64x2Ge => "f64x2.ge",
+            V128And => "v128.and",
+            V128AndNot => "v128.andnot",
+            V128Or => "v128.or",
+            V128Xor => "v128.xor",
+            I8x16Swizzle => "i8x16.swizzle",
+            I8x16NarrowI16x8S => "i8x16.narrow_i16x8_s",
+            I8x16NarrowI16x8U => "i8x16.narrow_i16x8_u",
+            I8x16Add => "i8x16.add",
+            I8x16AddSatS => "i8x16.add_sat_s",
+            I8x16AddSatU => "i8x16.add_sat_u",
+            I8x16Sub => "i8x16.sub",
+            I8x16SubSatS => "i8x16.sub_sat_s",
+            I8x16SubSatU => "i8x16.sub_sat_u",
+            I8x16MinS => "i8x16.min_s",
+            I8x16MinU => "i8x16.min_u",
+            I8x16MaxS => "i8x16.max_s",
+            I8x16MaxU => "i8x16.max_u",
+            I8x16AvgrU => "i8x16.avgr_u",
+            I16x8Q15MulrSatS => "i16x8.q15mulr_sat_s",
+            I16x8NarrowI32x4S => "i16x8.narrow_i32x4_s",
+            I16x8NarrowI32x4U => "i16x8.narrow_i32x4_u",
+            I16x8Add => "i16x8.add",
+            I16x8AddSatS => "i16x8.add_sat_s",
+            I16x8AddSatU => "i16x8.add_sat_u",
+            I16x8Sub => "i16x8.sub",
+            I16x8SubSatS => "i16x8.sub_sat_s",
+            I16x8SubSatU => "i16x8.sub_sat_u",
+            I16x8Mul => "i16x8.mul",
+            I16x8MinS => "i16x8.min_s",
+            I16x8MinU => "i16x8.min_u",
+            I16x8MaxS => "i16x8.max_s",
+            I16x8MaxU => "i16x8.max_u",
+            I16x8AvgrU => "i16x8.avgr_u",
+            I16x8ExtMulLowI8x16S => "i16x8.extmul_low_i8x16_s",
+            I16x8ExtMulHighI8x16S => "i16x8.extmul_high_i8x16_s",
+            I16x8ExtMulLowI8x16U => "i16x8.extmul_low_i8x16_u",
+            I16x8ExtMulHighI8x16U => "i16x8.extmul_high_i8x16_u",
+            I32x4Add => "i32x4.add",
+            I32x4Sub => "i32x4.sub",
+            I32x4Mul => "i32x4.mul",
+            I32x4MinS => "i32x4.min_s",
+            I32x4MinU => "i32x4.min_u",
+            I32x4MaxS => "i32x4.max_s",
+            I32x4MaxU => "i32x4.max_u",
+            I32x4DotI16x8S => "i32x4.dot_i16x8_s",
+            I32x4ExtMulLowI16x8S => "i32x4.extmul_low_i16x8_s",
+            I32x4ExtMulHighI16x8S => "i32x4.extmul_high_i16x8_s",
+            I32x4ExtMulLowI16x8U => "i32x4.extmul_low_i16x8_u",
+            I32x4ExtMulHighI16x8U => "i32x4.extmul_high_i16x8_u",
+            I64x2Add => "i64x2.add",
+            I64x2Sub => "i64x2.sub",
+            I64x2Mul => "i64x2.mul",
+            I64x2ExtMulLowI32x4S => "i64x2.extmul_low_i32x4_s",
+            I64x2ExtMulHighI32x4S => "i64x2.extmul_high_i32x4_s",
+            I64x2ExtMulLowI32x4U => "i64x2.extmul_low_i32x4_u",
+            I64x2ExtMulHighI32x4U => "i64x2.extmul_high_i32x4_u",
+            F32x4Add => "f32x4.add",
+            F32x4Sub => "f32x4.sub",
+            F32x4Mul => "f32x4.mul",
+            F32x4Div => "f32x4.div",
+            F32x4Min => "f32x4.min",
+            F32x4Max => "f32x4.max",
+            F32x4PMin => "f32x4.pmin",
+            F32x4PMax => "f32x4.pmax",
+            F64x2Add => "f64x2.add",
+            F64x2Sub => "f64x2.sub",
+            F64x2Mul => "f64x2.mul",
+            F64x2Div => "f64x2.div",
+            F64x2Min => "f64x2.min",
+            F64x2Max => "f64x2.max",
+            F64x2PMin => "f64x2.pmin",
+            F64x2PMax => "f64x2.pmax",
+            I8x16Shl => "i8x16.shl",
+            I8x16ShrS => "i8x16.shr_s",
+            I8x16ShrU => "i8x16.shr_u",
+            I16x8Shl => "i16x8.shl",
+            I16x8ShrS => "i16x8.shr_s",
+            I16x8ShrU => "i16x8.shr_u",
+            I32x4Shl => "i32x4.shl",
+            I32x4ShrS => "i32x4.shr_s",
+            I32x4ShrU => "i32x4.shr_u",
+            I64x2Shl => "i64x2.shl",
+            I64x2ShrS => "i64x2.shr_s",
+            I64x2ShrU => "i64x2.shr_u",
         }
     }
 
@@ -1395,6 +3109,25 @@ impl BinaryOp {
             I64Add | I64Sub | I64Mul | I64DivS | I64DivU | I64RemS | I64RemU | I64And | I64Or | I64Xor | I64Shl | I64ShrS | I64ShrU | I64Rotl | I64Rotr => FunctionType::new(&[I64, I64], &[I64]),
             F32Add | F32Sub | F32Mul | F32Div | F32Min | F32Max | F32Copysign => FunctionType::new(&[F32, F32], &[F32]),
             F64Add | F64Sub | F64Mul | F64Div | F64Min | F64Max | F64Copysign => FunctionType::new(&[F64, F64], &[F64]),
+
+            // SIMD comparisons, lane-wise operations, and shifts (by a scalar)
+            I8x16Eq | I8x16Ne | I8x16LtS | I8x16LtU | I8x16GtS | I8x16GtU | I8x16LeS | I8x16LeU | I8x16GeS | I8x16GeU
+            | I16x8Eq | I16x8Ne | I16x8LtS | I16x8LtU | I16x8GtS | I16x8GtU | I16x8LeS | I16x8LeU | I16x8GeS | I16x8GeU
+            | I32x4Eq | I32x4Ne | I32x4LtS | I32x4LtU | I32x4GtS | I32x4GtU | I32x4LeS | I32x4LeU | I32x4GeS | I32x4GeU
+            | I64x2Eq | I64x2Ne | I64x2LtS | I64x2GtS | I64x2LeS | I64x2GeS
+            | F32x4Eq | F32x4Ne | F32x4Lt | F32x4Gt | F32x4Le | F32x4Ge
+            | F64x2Eq | F64x2Ne | F64x2Lt | F64x2Gt | F64x2Le | F64x2Ge => FunctionType::new(&[V128, V128], &[V128]),
+            V128And | V128AndNot | V128Or | V128Xor
+            | I8x16Swizzle | I8x16NarrowI16x8S | I8x16NarrowI16x8U | I8x16Add | I8x16AddSatS | I8x16AddSatU | I8x16Sub | I8x16SubSatS | I8x16SubSatU | I8x16MinS | I8x16MinU | I8x16MaxS | I8x16MaxU | I8x16AvgrU
+            | I16x8Q15MulrSatS | I16x8NarrowI32x4S | I16x8NarrowI32x4U | I16x8Add | I16x8AddSatS | I16x8AddSatU | I16x8Sub | I16x8SubSatS | I16x8SubSatU | I16x8Mul | I16x8MinS | I16x8MinU | I16x8MaxS | I16x8MaxU | I16x8AvgrU | I16x8ExtMulLowI8x16S | I16x8ExtMulHighI8x16S | I16x8ExtMulLowI8x16U | I16x8ExtMulHighI8x16U
+            | I32x4Add | I32x4Sub | I32x4Mul | I32x4MinS | I32x4MinU | I32x4MaxS | I32x4MaxU | I32x4DotI16x8S | I32x4ExtMulLowI16x8S | I32x4ExtMulHighI16x8S | I32x4ExtMulLowI16x8U | I32x4ExtMulHighI16x8U
+            | I64x2Add | I64x2Sub | I64x2Mul | I64x2ExtMulLowI32x4S | I64x2ExtMulHighI32x4S | I64x2ExtMulLowI32x4U | I64x2ExtMulHighI32x4U
+            | F32x4Add | F32x4Sub | F32x4Mul | F32x4Div | F32x4Min | F32x4Max | F32x4PMin | F32x4PMax
+            | F64x2Add | F64x2Sub | F64x2Mul | F64x2Div | F64x2Min | F64x2Max | F64x2PMin | F64x2PMax => FunctionType::new(&[V128, V128], &[V128]),
+            I8x16Shl | I8x16ShrS | I8x16ShrU
+            | I16x8Shl | I16x8ShrS | I16x8ShrU
+            | I32x4Shl | I32x4ShrS | I32x4ShrU
+            | I64x2Shl | I64x2ShrS | I64x2ShrU => FunctionType::new(&[V128, I32], &[V128]),
         }
     }
 }
@@ -1481,6 +3214,138 @@ impl FromStr for BinaryOp {
             "f64.min" => F64Min,
             "f64.max" => F64Max,
             "f64.copysign" => F64Copysign,
+            "i8x16.eq" => I8x16Eq,
+            "i8x16.ne" => I8x16Ne,
+            "i8x16.lt_s" => I8x16LtS,
+            "i8x16.lt_u" => I8x16LtU,
+            "i8x16.gt_s" => I8x16GtS,
+            "i8x16.gt_u" => I8x16GtU,
+            "i8x16.le_s" => I8x16LeS,
+            "i8x16.le_u" => I8x16LeU,
+            "i8x16.ge_s" => I8x16GeS,
+            "i8x16.ge_u" => I8x16GeU,
+            "i16x8.eq" => I16x8Eq,
+            "i16x8.ne" => I16x8Ne,
+            "i16x8.lt_s" => I16x8LtS,
+            "i16x8.lt_u" => I16x8LtU,
+            "i16x8.gt_s" => I16x8GtS,
+            "i16x8.gt_u" => I16x8GtU,
+            "i16x8.le_s" => I16x8LeS,
+            "i16x8.le_u" => I16x8LeU,
+            "i16x8.ge_s" => I16x8GeS,
+            "i16x8.ge_u" => I16x8GeU,
+            "i32x4.eq" => I32x4Eq,
+            "i32x4.ne" => I32x4Ne,
+            "i32x4.lt_s" => I32x4LtS,
+            "i32x4.lt_u" => I32x4LtU,
+            "i32x4.gt_s" => I32x4GtS,
+            "i32x4.gt_u" => I32x4GtU,
+            "i32x4.le_s" => I32x4LeS,
+            "i32x4.le_u" => I32x4LeU,
+            "i32x4.ge_s" => I32x4GeS,
+            "i32x4.ge_u" => I32x4GeU,
+            "i64x2.eq" => I64x2Eq,
+            "i64x2.ne" => I64x2Ne,
+            "i64x2.lt_s" => I64x2LtS,
+            "i64x2.gt_s" => I64x2GtS,
+            "i64x2.le_s" => I64x2LeS,
+            "i64x2.ge_s" => I64x2GeS,
+            "f32x4.eq" => F32x4Eq,
+            "f32x4.ne" => F32x4Ne,
+            "f32x4.lt" => F32x4Lt,
+            "f32x4.gt" => F32x4Gt,
+            "f32x4.le" => F32x4Le,
+            "f32x4.ge" => F32x4Ge,
+            "f64x2.eq" => F64x2Eq,
+            "f64x2.ne" => F64x2Ne,
+            "f64x2.lt" => F64x2Lt,
+            "f64x2.gt" => F64x2Gt,
+            "f64x2.le" => F64x2Le,
+            "f64x2.ge" => F64x2Ge,
+            "v128.and" => V128And,
+            "v128.andnot" => V128AndNot,
+            "v128.or" => V128Or,
+            "v128.xor" => V128Xor,
+            "i8x16.swizzle" => I8x16Swizzle,
+            "i8x16.narrow_i16x8_s" => I8x16NarrowI16x8S,
+            "i8x16.narrow_i16x8_u" => I8x16NarrowI16x8U,
+            "i8x16.add" => I8x16Add,
+            "i8x16.add_sat_s" => I8x16AddSatS,
+            "i8x16.add_sat_u" => I8x16AddSatU,
+            "i8x16.sub" => I8x16Sub,
+            "i8x16.sub_sat_s" => I8x16SubSatS,
+            "i8x16.sub_sat_u" => I8x16SubSatU,
+            "i8x16.min_s" => I8x16MinS,
+            "i8x16.min_u" => I8x16MinU,
+            "i8x16.max_s" => I8x16MaxS,
+            "i8x16.max_u" => I8x16MaxU,
+            "i8x16.avgr_u" => I8x16AvgrU,
+            "i16x8.q15mulr_sat_s" => I16x8Q15MulrSatS,
+            "i16x8.narrow_i32x4_s" => I16x8NarrowI32x4S,
+            "i16x8.narrow_i32x4_u" => I16x8NarrowI32x4U,
+            "i16x8.add" => I16x8Add,
+            "i16x8.add_sat_s" => I16x8AddSatS,
+            "i16x8.add_sat_u" => I16x8AddSatU,
+            "i16x8.sub" => I16x8Sub,
+            "i16x8.sub_sat_s" => I16x8SubSatS,
+            "i16x8.sub_sat_u" => I16x8SubSatU,
+            "i16x8.mul" => I16x8Mul,
+            "i16x8.min_s" => I16x8MinS,
+            "i16x8.min_u" => I16x8MinU,
+            "i16x8.max_s" => I16x8MaxS,
+            "i16x8.max_u" => I16x8MaxU,
+            "i16x8.avgr_u" => I16x8AvgrU,
+            "i16x8.extmul_low_i8x16_s" => I16x8ExtMulLowI8x16S,
+            "i16x8.extmul_high_i8x16_s" => I16x8ExtMulHighI8x16S,
+            "i16x8.extmul_low_i8x16_u" => I16x8ExtMulLowI8x16U,
+            "i16x8.extmul_high_i8x16_u" => I16x8ExtMulHighI8x16U,
+            "i32x4.add" => I32x4Add,
+            "i32x4.sub" => I32x4Sub,
+            "i32x4.mul" => I32x4Mul,
+            "i32x4.min_s" => I32x4MinS,
+            "i32x4.min_u" => I32x4MinU,
+            "i32x4.max_s" => I32x4MaxS,
+            "i32x4.max_u" => I32x4MaxU,
+            "i32x4.dot_i16x8_s" => I32x4DotI16x8S,
+            "i32x4.extmul_low_i16x8_s" => I32x4ExtMulLowI16x8S,
+            "i32x4.extmul_high_i16x8_s" => I32x4ExtMulHighI16x8S,
+            "i32x4.extmul_low_i16x8_u" => I32x4ExtMulLowI16x8U,
+            "i32x4.extmul_high_i16x8_u" => I32x4ExtMulHighI16x8U,
+            "i64x2.add" => I64x2Add,
+            "i64x2.sub" => I64x2Sub,
+            "i64x2.mul" => I64x2Mul,
+            "i64x2.extmul_low_i32x4_s" => I64x2ExtMulLowI32x4S,
+            "i64x2.extmul_high_i32x4_s" => I64x2ExtMulHighI32x4S,
+            "i64x2.extmul_low_i32x4_u" => I64x2ExtMulLowI32x4U,
+            "i64x2.extmul_high_i32x4_u" => I64x2ExtMulHighI32x4U,
+            "f32x4.add" => F32x4Add,
+            "f32x4.sub" => F32x4Sub,
+            "f32x4.mul" => F32x4Mul,
+            "f32x4.div" => F32x4Div,
+            "f32x4.min" => F32x4Min,
+            "f32x4.max" => F32x4Max,
+            "f32x4.pmin" => F32x4PMin,
+            "f32x4.pmax" => F32x4PMax,
+            "f64x2.add" => F64x2Add,
+            "f64x2.sub" => F64x2Sub,
+            "f64x2.mul" => F64x2Mul,
+            "f64x2.div" => F64x2Div,
+            "f64x2.min" => F64x2Min,
+            "f64x2.max" => F64x2Max,
+            "f64x2.pmin" => F64x2PMin,
+            "f64x2.pmax" => F64x2PMax,
+            "i8x16.shl" => I8x16Shl,
+            "i8x16.shr_s" => I8x16ShrS,
+            "i8x16.shr_u" => I8x16ShrU,
+            "i16x8.shl" => I16x8Shl,
+            "i16x8.shr_s" => I16x8ShrS,
+            "i16x8.shr_u" => I16x8ShrU,
+            "i32x4.shl" => I32x4Shl,
+            "i32x4.shr_s" => I32x4ShrS,
+            "i32x4.shr_u" => I32x4ShrU,
+            "i64x2.shl" => I64x2Shl,
+            "i64x2.shr_s" => I64x2ShrS,
+            "i64x2.shr_u" => I64x2ShrU,
             _ => return Err(()),
         })
     }
@@ -1509,9 +3374,18 @@ impl Instr {
             Return => "return",
             Call(_) => "call",
             CallIndirect(_, _) => "call_indirect",
+            ReturnCall(_) => "return_call",
+            ReturnCallIndirect(_, _) => "return_call_indirect",
+
+            Try(_) => "try",
+            Catch(_) => "catch",
+            CatchAll => "catch_all",
+            Delegate(_) => "delegate",
+            Throw(_) => "throw",
+            Rethrow(_) => "rethrow",
 
             Drop => "drop",
-            Select => "select",
+            Select | TypedSelect(_) => "select",
 
             Local(LocalOp::Get, _) => "local.get",
             Local(LocalOp::Set, _) => "local.set",
@@ -1522,10 +3396,42 @@ impl Instr {
             MemorySize(_) => "memory.size",
             MemoryGrow(_) => "memory.grow",
 
+            MemoryInit(_, _) => "memory.init",
+            DataDrop(_) => "data.drop",
+            MemoryCopy { .. } => "memory.copy",
+            MemoryFill(_) => "memory.fill",
+            TableInit(_, _) => "table.init",
+            ElemDrop(_) => "elem.drop",
+            TableCopy { .. } => "table.copy",
+
+            RefNull(_) => "ref.null",
+            RefIsNull => "ref.is_null",
+            RefFunc(_) => "ref.func",
+            TableGet(_) => "table.get",
+            TableSet(_) => "table.set",
+            TableSize(_) => "table.size",
+            TableGrow(_) => "table.grow",
+            TableFill(_) => "table.fill",
+
             Const(Val::I32(_)) => "i32.const",
             Const(Val::I64(_)) => "i64.const",
             Const(Val::F32(_)) => "f32.const",
             Const(Val::F64(_)) => "f64.const",
+            // See `Val::Ref`, only the null reference can be a constant.
+            Const(Val::Ref { .. }) => "ref.null",
+            Const(Val::V128(_)) => "v128.const",
+
+            LoadLane(op, _, _) => op.to_name(),
+            StoreLane(op, _, _) => op.to_name(),
+            ExtractLane(op, _) => op.to_name(),
+            ReplaceLane(op, _) => op.to_name(),
+            I8x16Shuffle(_) => "i8x16.shuffle",
+            V128Bitselect => "v128.bitselect",
+
+            AtomicLoad(op, _) => op.to_name(),
+            AtomicStore(op, _) => op.to_name(),
+            Atomic(op, _) => op.to_name(),
+            AtomicFence => "atomic.fence",
 
             Load(op, _) => op.to_name(),
             Store(op, _) => op.to_name(),
@@ -1538,6 +3444,9 @@ impl Instr {
     /// by just looking at the instruction, and which does neither need to
     /// additional context information like the function or module, nor type
     /// inference (for stack-polymorphic and value-polymorphic instructions).
+    ///
+    /// Memory instructions are typed as if they access a 32-bit memory, see
+    /// `simple_type_with_memories()` for the correct type with 64-bit memories.
     pub fn simple_type(&self) -> Option<FunctionType> {
         use Instr::*;
         use ValType::*;
@@ -1547,6 +3456,25 @@ impl Instr {
             Store(ref op, _) => Some(op.to_type()),
             MemorySize(_) => Some(FunctionType::new(&[], &[I32])),
             MemoryGrow(_) => Some(FunctionType::new(&[I32], &[I32])),
+            // Destination address/index, source address/index (or value for fill), and length.
+            MemoryInit(_, _) | MemoryCopy { .. } | MemoryFill(_)
+            | TableInit(_, _) | TableCopy { .. } => Some(FunctionType::new(&[I32, I32, I32], &[])),
+            DataDrop(_) | ElemDrop(_) => Some(FunctionType::new(&[], &[])),
+            TypedSelect(ty) => Some(FunctionType::new(&[ty, ty, I32], &[ty])),
+            RefNull(ty) => Some(FunctionType::new(&[], &[ty])),
+            RefFunc(_) => Some(FunctionType::new(&[], &[FuncRef])),
+            TableSize(_) => Some(FunctionType::new(&[], &[I32])),
+            LoadLane(ref op, _, _) => Some(op.to_type()),
+            StoreLane(ref op, _, _) => Some(op.to_type()),
+            ExtractLane(ref op, _) => Some(op.to_type()),
+            ReplaceLane(ref op, _) => Some(op.to_type()),
+            I8x16Shuffle(_) => Some(FunctionType::new(&[V128, V128], &[V128])),
+            // The third input is the mask that selects bits from either the first or second input.
+            V128Bitselect => Some(FunctionType::new(&[V128, V128, V128], &[V128])),
+            AtomicLoad(ref op, _) => Some(op.to_type()),
+            AtomicStore(ref op, _) => Some(op.to_type()),
+            Atomic(ref op, _) => Some(op.to_type()),
+            AtomicFence => Some(FunctionType::empty()),
             Const(ref val) => Some(FunctionType::new(&[], &[val.to_type()])),
             Unary(ref op) => Some(op.to_type()),
             Binary(ref op) => Some(op.to_type()),
@@ -1561,14 +3489,65 @@ impl Instr {
             Br(_) | BrIf(_) | BrTable { .. } => None,
             // Need to inspect the current/called function type.
             Return | Call(_) => None,
-            // Need lookup in locals/globals
+            // Stack-polymorphic, i.e., the rest of the block is unreachable, like after `Return`.
+            ReturnCall(_) | ReturnCallIndirect(_, _) => None,
+            // Like other blocks, plus need lookup of the tag type.
+            Try(_) | Catch(_) | CatchAll | Delegate(_) => None,
+            // Stack-polymorphic, and `Throw` needs lookup of the tag type.
+            Throw(_) | Rethrow(_) => None,
+            // Need lookup in locals/globals/tables.
             Local(_, _) | Global(_, _) => None,
+            TableGet(_) | TableSet(_) | TableGrow(_) | TableFill(_) => None,
             // Value-polymorphic, need abstract type stack.
-            Drop | Select => None,
+            Drop | Select | RefIsNull => None,
             // Stack-polymorphic, needs type inference (br* above as well).
             Unreachable => None,
         }
     }
+
+    /// Like `simple_type()`, but addresses and sizes of memory instructions have the index type
+    /// of the accessed memory, i.e., i64 for 64-bit memories (memory64 extension).
+    pub fn simple_type_with_memories(&self, memories: &[Memory]) -> Option<FunctionType> {
+        use Instr::*;
+        use ValType::*;
+        let ty = self.simple_type()?;
+        // Fast path for the (very common) case of only 32-bit memories.
+        if !memories.iter().any(|memory| memory.memory64) {
+            return Some(ty);
+        }
+
+        let index_type = |memory: Idx<Memory>| memories[memory.to_usize()].index_type();
+        let mut inputs = ty.inputs().to_vec();
+        let mut results = ty.results().to_vec();
+        match *self {
+            Load(_, memarg) | Store(_, memarg) | LoadLane(_, memarg, _) | StoreLane(_, memarg, _)
+            | AtomicLoad(_, memarg) | AtomicStore(_, memarg) | Atomic(_, memarg) => {
+                inputs[0] = index_type(memarg.memory);
+            }
+            MemorySize(memory) => results[0] = index_type(memory),
+            MemoryGrow(memory) => {
+                inputs[0] = index_type(memory);
+                results[0] = index_type(memory);
+            }
+            // Destination address, fill value, and length.
+            MemoryFill(memory) => {
+                inputs[0] = index_type(memory);
+                inputs[2] = index_type(memory);
+            }
+            // The length must fit into both memories, so it is only i64 if both are 64-bit.
+            MemoryCopy { dst, src } => {
+                inputs[0] = index_type(dst);
+                inputs[1] = index_type(src);
+                if index_type(dst) == I64 && index_type(src) == I64 {
+                    inputs[2] = I64;
+                }
+            }
+            // Source offset and length are within the data segment, so only the address changes.
+            MemoryInit(_, memory) => inputs[0] = index_type(memory),
+            _ => return Some(ty),
+        }
+        Some(FunctionType::new(&inputs, &results))
+    }
 }
 
 impl FromStr for Instr {
@@ -1586,6 +3565,13 @@ impl FromStr for Instr {
             let u: usize = str.parse().map_err(|_| ())?;
             Ok(u.into())
         }
+        // Multi-memory extension: a missing memory index means memory 0, as in the text format.
+        fn parse_memory_idx(str: &str) -> Result<Idx<Memory>, ()> {
+            match str.trim() {
+                "" => Ok(Idx::from(0u32)),
+                idx => parse_idx(idx),
+            }
+        }
 
         let (operator, rest) = str.split_once(char::is_whitespace).ok_or(())?;
         Ok(match operator {
@@ -1626,9 +3612,29 @@ impl FromStr for Instr {
                 let table_idx = Idx::from(0u32);
                 CallIndirect(ty, table_idx)
             }
+            "return_call" => {
+                let func_idx = parse_idx(rest)?;
+                ReturnCall(func_idx)
+            }
+            "return_call_indirect" => {
+                let ty = FunctionType::from_str(rest)?;
+                // Table index not printed, see `call_indirect` above.
+                let table_idx = Idx::from(0u32);
+                ReturnCallIndirect(ty, table_idx)
+            }
+
+            "try" => Try(FunctionType::from_str(rest)?),
+            "catch" => Catch(parse_idx(rest)?),
+            "catch_all" => CatchAll,
+            "delegate" => Delegate(parse_label(rest)?),
+            "throw" => Throw(parse_idx(rest)?),
+            "rethrow" => Rethrow(parse_label(rest)?),
 
             "drop" => Drop,
-            "select" => Select,
+            "select" => match rest.trim() {
+                "" => Select,
+                ty => TypedSelect(ValType::from_str(ty)?),
+            },
 
             "local.get" => Local(LocalOp::Get, parse_idx(rest)?),
             "local.set" => Local(LocalOp::Set, parse_idx(rest)?),
@@ -1636,15 +3642,52 @@ impl FromStr for Instr {
             "global.get" => Global(GlobalOp::Get, parse_idx(rest)?),
             "global.set" => Global(GlobalOp::Set, parse_idx(rest)?),
 
-            // For the WebAssembly MVP there is only a single memory, so the
-            // memory index was not printed. Instead assume 0.
-            "memory.size" => MemorySize(Idx::from(0u32)),
-            "memory.grow" => MemoryGrow(Idx::from(0u32)),
+            "memory.size" => MemorySize(parse_memory_idx(rest)?),
+            "memory.grow" => MemoryGrow(parse_memory_idx(rest)?),
+
+            "memory.init" => match *rest.split_whitespace().collect::<Vec<_>>() {
+                [data_idx] => MemoryInit(parse_idx(data_idx)?, Idx::from(0u32)),
+                [memory_idx, data_idx] => MemoryInit(parse_idx(data_idx)?, parse_idx(memory_idx)?),
+                _ => return Err(()),
+            },
+            "data.drop" => DataDrop(parse_idx(rest)?),
+            "memory.copy" => match *rest.split_whitespace().collect::<Vec<_>>() {
+                [] => MemoryCopy { dst: Idx::from(0u32), src: Idx::from(0u32) },
+                [dst, src] => MemoryCopy { dst: parse_idx(dst)?, src: parse_idx(src)? },
+                _ => return Err(()),
+            },
+            "memory.fill" => MemoryFill(parse_memory_idx(rest)?),
+
+            // For the table indices of bulk memory instructions, assume 0, see also `Display`.
+            "table.init" => TableInit(parse_idx(rest)?, Idx::from(0u32)),
+            "elem.drop" => ElemDrop(parse_idx(rest)?),
+            "table.copy" => TableCopy { dst: Idx::from(0u32), src: Idx::from(0u32) },
+
+            "ref.null" => RefNull(ValType::from_str(rest)?),
+            "ref.is_null" => RefIsNull,
+            "ref.func" => RefFunc(parse_idx(rest)?),
+            "table.get" => TableGet(parse_idx(rest)?),
+            "table.set" => TableSet(parse_idx(rest)?),
+            "table.size" => TableSize(parse_idx(rest)?),
+            "table.grow" => TableGrow(parse_idx(rest)?),
+            "table.fill" => TableFill(parse_idx(rest)?),
 
             "i32.const" => Const(Val::from_str(rest, ValType::I32)?),
             "i64.const" => Const(Val::from_str(rest, ValType::I64)?),
             "f32.const" => Const(Val::from_str(rest, ValType::F32)?),
             "f64.const" => Const(Val::from_str(rest, ValType::F64)?),
+            "v128.const" => Const(Val::from_str(rest, ValType::V128)?),
+
+            "i8x16.shuffle" => {
+                let mut lanes = [0u8; 16];
+                let mut lane_strs = rest.split_whitespace();
+                for lane in &mut lanes {
+                    *lane = lane_strs.next().ok_or(())?.parse().map_err(|_| ())?;
+                }
+                I8x16Shuffle(lanes)
+            }
+            "v128.bitselect" => V128Bitselect,
+            "atomic.fence" => AtomicFence,
 
             op if LoadOp::from_str(op).is_ok() => {
                 let op = LoadOp::from_str(op).unwrap();
@@ -1654,6 +3697,32 @@ impl FromStr for Instr {
                 let op = StoreOp::from_str(op).unwrap();
                 Store(op, Memarg::from_str(rest, op)?)
             }
+            op if AtomicLoadOp::from_str(op).is_ok() => {
+                let op = AtomicLoadOp::from_str(op).unwrap();
+                AtomicLoad(op, Memarg::from_str(rest, op)?)
+            }
+            op if AtomicStoreOp::from_str(op).is_ok() => {
+                let op = AtomicStoreOp::from_str(op).unwrap();
+                AtomicStore(op, Memarg::from_str(rest, op)?)
+            }
+            op if AtomicOp::from_str(op).is_ok() => {
+                let op = AtomicOp::from_str(op).unwrap();
+                Atomic(op, Memarg::from_str(rest, op)?)
+            }
+
+            // The lane index comes after the optional memarg.
+            op if LoadLaneOp::from_str(op).is_ok() => {
+                let op = LoadLaneOp::from_str(op).unwrap();
+                let (memarg, lane) = rest.trim().rsplit_once(' ').unwrap_or(("", rest));
+                LoadLane(op, Memarg::from_str(memarg, op)?, lane.trim().parse().map_err(|_| ())?)
+            }
+            op if StoreLaneOp::from_str(op).is_ok() => {
+                let op = StoreLaneOp::from_str(op).unwrap();
+                let (memarg, lane) = rest.trim().rsplit_once(' ').unwrap_or(("", rest));
+                StoreLane(op, Memarg::from_str(memarg, op)?, lane.trim().parse().map_err(|_| ())?)
+            }
+            op if ExtractLaneOp::from_str(op).is_ok() => ExtractLane(ExtractLaneOp::from_str(op).unwrap(), rest.trim().parse().map_err(|_| ())?),
+            op if ReplaceLaneOp::from_str(op).is_ok() => ReplaceLane(ReplaceLaneOp::from_str(op).unwrap(), rest.trim().parse().map_err(|_| ())?),
 
             op if UnaryOp::from_str(op).is_ok() => UnaryOp::from_str(op).map(Unary)?,
             op if BinaryOp::from_str(op).is_ok() => BinaryOp::from_str(op).map(Binary)?,
@@ -1672,13 +3741,14 @@ impl fmt::Display for Instr {
         use self::Instr::*;
         match self {
             // instructions without arguments
-            Unreachable | Nop | Drop | Select | Return | Else | End | MemorySize(_)
-            | MemoryGrow(_) | Unary(_) | Binary(_) => Ok(()),
+            Unreachable | Nop | Drop | Select | Return | Else | End | CatchAll | TableCopy { .. } | RefIsNull
+            | V128Bitselect | AtomicFence | Unary(_) | Binary(_) => Ok(()),
 
-            Block(ty) | Loop(ty) | If(ty) => write!(f, " {ty}"),
+            TypedSelect(ty) | RefNull(ty) => write!(f, " {ty}"),
 
-            Br(label) => write!(f, " {}", label.to_u32()),
-            BrIf(label) => write!(f, " {}", label.to_u32()),
+            Block(ty) | Loop(ty) | If(ty) | Try(ty) => write!(f, " {ty}"),
+
+            Br(label) | BrIf(label) | Delegate(label) | Rethrow(label) => write!(f, " {}", label.to_u32()),
             BrTable { table, default } => {
                 for label in table.iter() {
                     write!(f, " {}", label.to_u32())?;
@@ -1686,14 +3756,44 @@ impl fmt::Display for Instr {
                 write!(f, " {}", default.to_u32())
             }
 
-            Call(func_idx) => write!(f, " {}", func_idx.to_u32()),
-            // We don't print the table index, because we also don't for memory.size and memory.grow,
-            // and because in the MVP the table index is going to be 0 anyway.
-            CallIndirect(func_ty, _table_idx) => write!(f, " {func_ty}"),
+            Call(func_idx) | ReturnCall(func_idx) => write!(f, " {}", func_idx.to_u32()),
+            Catch(tag_idx) | Throw(tag_idx) => write!(f, " {}", tag_idx.to_u32()),
+            // We don't print the table index, because in the MVP it is going to be 0 anyway.
+            CallIndirect(func_ty, _table_idx) | ReturnCallIndirect(func_ty, _table_idx) => write!(f, " {func_ty}"),
 
             Local(_, local_idx) => write!(f, " {}", local_idx.to_u32()),
             Global(_, global_idx) => write!(f, " {}", global_idx.to_u32()),
 
+            // Multi-memory extension: as in the text format, memory index 0 is omitted.
+            MemorySize(memory_idx) | MemoryGrow(memory_idx) | MemoryFill(memory_idx) => {
+                if *memory_idx != Idx::from(0u32) {
+                    write!(f, " {}", memory_idx.to_u32())?;
+                }
+                Ok(())
+            }
+            MemoryCopy { dst, src } => {
+                if *dst != Idx::from(0u32) || *src != Idx::from(0u32) {
+                    write!(f, " {} {}", dst.to_u32(), src.to_u32())?;
+                }
+                Ok(())
+            }
+            MemoryInit(data_idx, memory_idx) => {
+                if *memory_idx != Idx::from(0u32) {
+                    write!(f, " {}", memory_idx.to_u32())?;
+                }
+                write!(f, " {}", data_idx.to_u32())
+            }
+            DataDrop(data_idx) => write!(f, " {}", data_idx.to_u32()),
+            // Table indices are not printed, see `CallIndirect` above.
+            TableInit(element_idx, _table_idx) => write!(f, " {}", element_idx.to_u32()),
+            ElemDrop(element_idx) => write!(f, " {}", element_idx.to_u32()),
+
+            RefFunc(func_idx) => write!(f, " {}", func_idx.to_u32()),
+            // Unlike for the MVP instructions above, tables other than 0 are common here, so
+            // print the index.
+            TableGet(table_idx) | TableSet(table_idx) | TableSize(table_idx) | TableGrow(table_idx)
+            | TableFill(table_idx) => write!(f, " {}", table_idx.to_u32()),
+
             Load(op, memarg) => {
                 if !memarg.is_default(*op) {
                     f.write_str(" ")?;
@@ -1706,6 +3806,45 @@ impl fmt::Display for Instr {
                 }
                 memarg.fmt(f, *op)
             }
+            AtomicLoad(op, memarg) => {
+                if !memarg.is_default(*op) {
+                    f.write_str(" ")?;
+                }
+                memarg.fmt(f, *op)
+            }
+            AtomicStore(op, memarg) => {
+                if !memarg.is_default(*op) {
+                    f.write_str(" ")?;
+                }
+                memarg.fmt(f, *op)
+            }
+            Atomic(op, memarg) => {
+                if !memarg.is_default(*op) {
+                    f.write_str(" ")?;
+                }
+                memarg.fmt(f, *op)
+            }
+            LoadLane(op, memarg, lane) => {
+                if !memarg.is_default(*op) {
+                    f.write_str(" ")?;
+                    memarg.fmt(f, *op)?;
+                }
+                write!(f, " {lane}")
+            }
+            StoreLane(op, memarg, lane) => {
+                if !memarg.is_default(*op) {
+                    f.write_str(" ")?;
+                    memarg.fmt(f, *op)?;
+                }
+                write!(f, " {lane}")
+            }
+            ExtractLane(_, lane) | ReplaceLane(_, lane) => write!(f, " {lane}"),
+            I8x16Shuffle(lanes) => {
+                for lane in lanes {
+                    write!(f, " {lane}")?;
+                }
+                Ok(())
+            }
 
             Const(val) => write!(f, " {val}"),
         }
@@ -1745,8 +3884,20 @@ impl Module {
         self.memories.iter().enumerate().map(|(i, m)| (i.into(), m))
     }
 
+    pub fn tags(&self) -> impl Iterator<Item = (Idx<Tag>, &Tag)> {
+        self.tags.iter().enumerate().map(|(i, t)| (i.into(), t))
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = (Idx<Element>, &Element)> {
+        self.elements.iter().enumerate().map(|(i, e)| (i.into(), e))
+    }
+
+    pub fn data(&self) -> impl Iterator<Item = (Idx<Data>, &Data)> {
+        self.data.iter().enumerate().map(|(i, d)| (i.into(), d))
+    }
+
     // Convenient accessors of functions for the typed, high-level index.
-    // TODO Add the same for globals, tables, and memories, if needed.
+    // TODO Add the same for memories, if needed.
 
     pub fn function(&self, idx: Idx<Function>) -> &Function {
         &self.functions[idx.to_usize()]
@@ -1764,6 +3915,18 @@ impl Module {
         &mut self.globals[idx.to_usize()]
     }
 
+    pub fn table(&self, idx: Idx<Table>) -> &Table {
+        &self.tables[idx.to_usize()]
+    }
+
+    pub fn memory(&self, idx: Idx<Memory>) -> &Memory {
+        &self.memories[idx.to_usize()]
+    }
+
+    pub fn tag(&self, idx: Idx<Tag>) -> &Tag {
+        &self.tags[idx.to_usize()]
+    }
+
     pub fn add_function(
         &mut self,
         type_: FunctionType,
@@ -1775,6 +3938,7 @@ impl Module {
             Code {
                 locals: locals.into_iter().map(Local::new).collect(),
                 body,
+                label_names: Vec::new(),
             },
             Vec::new(),
         ));
@@ -1802,6 +3966,7 @@ impl Module {
             type_: GlobalType(type_, mut_),
             init: ImportOrPresent::Present(init),
             export: Vec::new(),
+            name: None,
         });
         (self.globals.len() - 1).into()
     }
@@ -1811,7 +3976,7 @@ impl Function {
     pub fn new(type_: FunctionType, code: Code, export: Vec<String>) -> Self {
         Function {
             type_,
-            code: ImportOrPresent::Present(code),
+            code: ImportOrPresent::Present(code.into()),
             export,
             name: None,
             param_names: Vec::new(),
@@ -1841,17 +4006,20 @@ impl Function {
         }
     }
 
+    /// Parses the body on first access, if the module was parsed lazily.
     pub fn code(&self) -> Option<&Code> {
         if let ImportOrPresent::Present(t) = &self.code {
-            Some(t)
+            Some(t.get())
         } else {
             None
         }
     }
 
+    /// Parses the body on first access, if the module was parsed lazily. Afterwards, the body is
+    /// always encoded from the AST, i.e., no longer copied verbatim from the original binary.
     pub fn code_mut(&mut self) -> Option<&mut Code> {
         if let ImportOrPresent::Present(t) = &mut self.code {
-            Some(t)
+            Some(t.get_mut())
         } else {
             None
         }
@@ -1859,7 +4027,7 @@ impl Function {
 
     pub fn into_code(self) -> Option<Code> {
         if let ImportOrPresent::Present(t) = self.code {
-            Some(t)
+            Some(t.into_inner())
         } else {
             None
         }
@@ -1869,6 +4037,13 @@ impl Function {
         self.code().map(|code| code.body.as_slice()).unwrap_or(&[])
     }
 
+    /// Type checks the body and yields every instruction (including dead code) with its index,
+    /// its inferred type, and the type stack of the current block after it.
+    /// The `module` is needed for the types of called functions, globals, etc.
+    pub fn instrs_with_types<'a>(&'a self, module: &'a Module) -> crate::types::InstrsWithTypes<'a> {
+        crate::types::InstrsWithTypes::new(self, module)
+    }
+
     pub fn instrs_mut(&mut self) -> Option<&mut Vec<Instr>> {
         self.code_mut().map(|code| &mut code.body)
     }
@@ -2087,7 +4262,23 @@ impl Code {
         Code {
             locals: Vec::new(),
             body: Vec::new(),
+            label_names: Vec::new(),
+        }
+    }
+
+    /// The name of the `label_idx`-th block, loop, if, or try instruction in the body, if any.
+    pub fn label_name(&self, label_idx: usize) -> Option<&str> {
+        self.label_names
+            .get(label_idx)
+            .and_then(|name| name.as_deref())
+    }
+
+    pub fn label_name_mut(&mut self, label_idx: usize) -> &mut Option<String> {
+        // Ensure the label_names vec is long enough.
+        if label_idx >= self.label_names.len() {
+            self.label_names.resize(label_idx + 1, None);
         }
+        &mut self.label_names[label_idx]
     }
 }
 
@@ -2097,6 +4288,103 @@ impl Default for Code {
     }
 }
 
+impl LazyCode {
+    pub(crate) fn new_raw(context: Arc<crate::parse::LazyContext>, range: Range<usize>) -> Self {
+        LazyCode {
+            raw: Some(RawCode { context, range }),
+            parsed: OnceCell::new(),
+        }
+    }
+
+    /// Parses the body, if that was not done before.
+    pub fn try_get(&self) -> Result<&Code, ParseError> {
+        self.parsed.get_or_try_init(|| {
+            let raw = self.raw.as_ref().expect("body should be either parsed or raw");
+            crate::parse::parse_lazy_body(&raw.context, raw.range.clone())
+        })
+    }
+
+    /// Like `try_get`, but panics if the body cannot be parsed.
+    pub fn get(&self) -> &Code {
+        self.try_get()
+            .unwrap_or_else(|err| panic!("could not parse function body on demand: {err}"))
+    }
+
+    /// Parses the body, if that was not done before, and discards the original bytes, i.e., the
+    /// body is encoded from the AST afterwards, even if it was not actually modified.
+    pub fn get_mut(&mut self) -> &mut Code {
+        self.get();
+        self.raw = None;
+        self.parsed.get_mut().expect("body was just parsed")
+    }
+
+    pub fn into_inner(mut self) -> Code {
+        self.get();
+        self.parsed.take().expect("body was just parsed")
+    }
+
+    pub fn is_parsed(&self) -> bool {
+        self.parsed.get().is_some()
+    }
+
+    /// The body, but only if it is already parsed, i.e., without parsing it.
+    pub(crate) fn get_if_parsed(&self) -> Option<&Code> {
+        self.parsed.get()
+    }
+
+    /// The original bytes of the body, if it was not (potentially) modified.
+    pub(crate) fn raw(&self) -> Option<&RawCode> {
+        self.raw.as_ref()
+    }
+}
+
+impl From<Code> for LazyCode {
+    fn from(code: Code) -> Self {
+        LazyCode {
+            raw: None,
+            parsed: OnceCell::with_value(code),
+        }
+    }
+}
+
+// Comparing, hashing, and ordering needs the parsed body, so they parse it on demand.
+// Debug output doesn't, to not panic on invalid bodies.
+impl fmt::Debug for LazyCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.parsed.get(), &self.raw) {
+            (Some(code), _) => code.fmt(f),
+            (None, Some(raw)) => write!(f, "LazyCode(<{} bytes, not parsed yet>)", raw.range.len()),
+            (None, None) => unreachable!("body should be either parsed or raw"),
+        }
+    }
+}
+
+impl PartialEq for LazyCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl Eq for LazyCode {}
+
+impl hash::Hash for LazyCode {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.get().hash(state)
+    }
+}
+
+impl PartialOrd for LazyCode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LazyCode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.get().cmp(other.get())
+    }
+}
+
 // See description on enum type above.
 impl<'a> ParamOrLocalRef<'a> {
     pub fn type_(self) -> ValType {
@@ -2119,6 +4407,7 @@ impl Global {
             type_,
             init: ImportOrPresent::Present(init),
             export: Vec::new(),
+            name: None,
         }
     }
 
@@ -2127,6 +4416,7 @@ impl Global {
             type_,
             init: ImportOrPresent::Import(import_module, import_name),
             export: Vec::new(),
+            name: None,
         }
     }
 
@@ -2148,21 +4438,23 @@ impl Global {
 }
 
 impl Table {
-    pub fn new(limits: Limits) -> Table {
+    pub fn new(elem_type: ValType, limits: Limits) -> Table {
         Table {
+            elem_type,
             limits,
             import: None,
-            elements: Vec::new(),
             export: Vec::new(),
+            name: None,
         }
     }
 
-    pub fn new_imported(limits: Limits, import_module: String, import_name: String) -> Table {
+    pub fn new_imported(elem_type: ValType, limits: Limits, import_module: String, import_name: String) -> Table {
         Table {
+            elem_type,
             limits,
             import: Some((import_module, import_name)),
-            elements: Vec::new(),
             export: Vec::new(),
+            name: None,
         }
     }
 
@@ -2174,20 +4466,57 @@ impl Table {
 }
 
 impl Memory {
-    pub fn new(limits: Limits) -> Memory {
+    pub fn new(limits: Limits, shared: bool, memory64: bool) -> Memory {
         Memory {
             limits,
+            shared,
+            memory64,
             import: None,
-            data: Vec::new(),
             export: Vec::new(),
+            name: None,
         }
     }
 
-    pub fn new_imported(limits: Limits, import_module: String, import_name: String) -> Memory {
+    pub fn new_imported(limits: Limits, shared: bool, memory64: bool, import_module: String, import_name: String) -> Memory {
         Memory {
             limits,
+            shared,
+            memory64,
+            import: Some((import_module, import_name)),
+            export: Vec::new(),
+            name: None,
+        }
+    }
+
+    /// The type of addresses and sizes (in pages) of this memory.
+    pub fn index_type(&self) -> ValType {
+        if self.memory64 {
+            ValType::I64
+        } else {
+            ValType::I32
+        }
+    }
+
+    pub fn import(&self) -> Option<(&str, &str)> {
+        self.import
+            .as_ref()
+            .map(|(module, name)| (module.as_str(), name.as_str()))
+    }
+}
+
+impl Tag {
+    pub fn new(type_: FunctionType) -> Tag {
+        Tag {
+            type_,
+            import: None,
+            export: Vec::new(),
+        }
+    }
+
+    pub fn new_imported(type_: FunctionType, import_module: String, import_name: String) -> Tag {
+        Tag {
+            type_,
             import: Some((import_module, import_name)),
-            data: Vec::new(),
             export: Vec::new(),
         }
     }
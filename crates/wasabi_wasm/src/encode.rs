@@ -2,6 +2,8 @@
 //! Uses `wasm-encoder` for the actual low-level work.
 
 use std::convert::TryInto;
+use std::io;
+use std::sync::Arc;
 use std::sync::RwLock;
 
 use nohash_hasher::IntMap;
@@ -23,6 +25,7 @@ mod marker {
         pub struct Global;
         pub struct Table;
         pub struct Memory;
+        pub struct Tag;
     }
 }
 
@@ -39,6 +42,11 @@ struct EncodeState {
     global_idx: IntMap<Idx<Global>, Idx<marker::we::Global>>,
     table_idx: IntMap<Idx<Table>, Idx<marker::we::Table>>,
     memory_idx: IntMap<Idx<Memory>, Idx<marker::we::Memory>>,
+    tag_idx: IntMap<Idx<Tag>, Idx<marker::we::Tag>>,
+
+    // Set if unmodified, lazily parsed function bodies from this original binary can be copied
+    // verbatim, because all indices in them still refer to the same types, functions, etc.
+    raw_bodies_context: Option<Arc<crate::parse::LazyContext>>,
 
     last_encoded_section: Option<SectionId>,
     custom_sections_encoded: usize,
@@ -84,10 +92,30 @@ impl EncodeState {
     encode_state_idx_fns!(insert_table_idx, map_table_idx, table_idx, Table, "table");
     encode_state_idx_fns!(insert_memory_idx, map_memory_idx, memory_idx, Memory, "memory");
     encode_state_idx_fns!(insert_global_idx, map_global_idx, global_idx, Global, "global");
+    encode_state_idx_fns!(insert_tag_idx, map_tag_idx, tag_idx, Tag, "tag");
+
+    /// Whether the low-level indices are the same as the high-level ones, i.e., nothing was
+    /// re-indexed, e.g., because an import was added after the non-imported functions.
+    fn indices_unchanged(&self) -> bool {
+        fn is_identity<T, U>(idx_map: &IntMap<Idx<T>, Idx<U>>) -> bool {
+            idx_map.iter().all(|(hl_idx, ll_idx)| hl_idx.to_u32() == ll_idx.to_u32())
+        }
+        is_identity(&self.function_idx)
+            && is_identity(&self.global_idx)
+            && is_identity(&self.table_idx)
+            && is_identity(&self.memory_idx)
+            && is_identity(&self.tag_idx)
+    }
 }
 
 pub fn encode_module(module: &Module) -> Result<Vec<u8>, EncodeError> {
-    let mut encoder = wasm_encoder::Module::new();
+    let mut bytes = Vec::new();
+    encode_module_to(module, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Returns the number of bytes written.
+pub fn encode_module_to(module: &Module, writer: impl io::Write) -> Result<usize, EncodeError> {
     let mut state = EncodeState::default();
 
     // Note that the order in which the high-level AST is traversed is not equal to the order
@@ -100,23 +128,46 @@ pub fn encode_module(module: &Module) -> Result<Vec<u8>, EncodeError> {
     // module. This would make the lifetime of the allocated sections a bit shorter.
     // In principle, however, all sections must be fully resident in memory before one can write
     // them out anyway, because the section size in bytes is prepended to its contents.
+    // So the best we can do is write each section out as soon as it is complete, see `SectionWriter`.
+
+    // Function bodies that were parsed lazily and not modified can be copied verbatim from the
+    // original binary, if the indices in them still refer to the same things.
+    // For types, ensure this by adding the original types first and in their original order.
+    let lazy_context = module.functions.iter().find_map(|function| match &function.code {
+        ImportOrPresent::Present(code) => code.raw().map(|raw| raw.context.clone()),
+        ImportOrPresent::Import(_, _) => None,
+    });
+    let mut types_unchanged = false;
+    if let Some(lazy_context) = &lazy_context {
+        for type_ in lazy_context.types() {
+            state.get_or_insert_type(*type_);
+        }
+        // Duplicate types in the original type section are merged, which shifts the indices after.
+        types_unchanged = state.types_idx.read().unwrap().len() == lazy_context.types().len();
+    }
 
     // First, traverse all imported functions, globals, etc., such that they are at the
     // beginning of all index spaces.
-    let import_section = encode_imports(module, &mut state);
+    let import_section = encode_imports(module, &mut state)?;
 
     // Then traverse all non-imported functions, globals, etc., such that their indices and
     // types are in `state`.
     let function_section = encode_functions(module, &mut state);
-    let (table_section, element_section) = encode_tables(module, &mut state)?;
-    let (memory_section, data_section) = encode_memories(module, &mut state)?;
+    let table_section = encode_tables(module, &mut state)?;
+    let memory_section = encode_memories(module, &mut state);
+    let tag_section = encode_tags(module, &mut state);
     let global_section = encode_globals(module, &mut state)?;
+    let element_section = encode_elements(module, &mut state)?;
+    let data_section = encode_data(module, &mut state)?;
 
     // The code section can also contain types we haven't seen so far (e.g., in `call_indirect`),
     // so it must be processed before encoding the type section.
     // However the functions, globals, tables, etc. referred to in instructions should all
     // already be known from processing the sections above. If NOT, this is an error in the
     // input highlevel module and we report it.
+    if types_unchanged && state.indices_unchanged() {
+        state.raw_bodies_context = lazy_context;
+    }
     let code_section = encode_code(module, &mut state)?;
 
     // Now, `state` contains all types that appear in the module, so we are ready encode the
@@ -124,81 +175,124 @@ pub fn encode_module(module: &Module) -> Result<Vec<u8>, EncodeError> {
     let type_section = encode_types(&state);
 
     // Then, write all sections in the correct order into the binary.
+    let mut encoder = SectionWriter::new(writer)?;
     // For the section order, see https://webassembly.github.io/spec/core/binary/modules.html#binary-module
     // Intersperse the correct custom sections in between as well.
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !type_section.is_empty() {
-        encoder.section(&type_section);
+        encoder.section(&type_section)?;
     }
     state.last_encoded_section = Some(SectionId::Type);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !import_section.is_empty() {
-        encoder.section(&import_section);
+        encoder.section(&import_section)?;
     }
     state.last_encoded_section = Some(SectionId::Import);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !function_section.is_empty() {
-        encoder.section(&function_section);
+        encoder.section(&function_section)?;
     }
     state.last_encoded_section = Some(SectionId::Function);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !table_section.is_empty() {
-        encoder.section(&table_section);
+        encoder.section(&table_section)?;
     }
     state.last_encoded_section = Some(SectionId::Table);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !memory_section.is_empty() {
-        encoder.section(&memory_section);
+        encoder.section(&memory_section)?;
     }
     state.last_encoded_section = Some(SectionId::Memory);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
+    if !tag_section.is_empty() {
+        encoder.section(&tag_section)?;
+    }
+    state.last_encoded_section = Some(SectionId::Tag);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !global_section.is_empty() {
-        encoder.section(&global_section);
+        encoder.section(&global_section)?;
     }
     state.last_encoded_section = Some(SectionId::Global);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     let export_section = encode_exports(module, &mut state)?;
     if !export_section.is_empty() {
-        encoder.section(&export_section);
+        encoder.section(&export_section)?;
     }
     state.last_encoded_section = Some(SectionId::Export);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if let Some(function_idx) = module.start {
         let start_section = we::StartSection {
             function_index: state.map_function_idx(function_idx)?.to_u32(),
         };
-        encoder.section(&start_section);
+        encoder.section(&start_section)?;
     }
     state.last_encoded_section = Some(SectionId::Start);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !element_section.is_empty() {
-        encoder.section(&element_section);
+        encoder.section(&element_section)?;
     }
     state.last_encoded_section = Some(SectionId::Element);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
+    // The data count section is only required (and only allowed without the bulk memory
+    // extension) if data segments are referenced from code, so don't add it unconditionally.
+    if uses_data_idx_in_code(module) {
+        encoder.section(&we::DataCountSection {
+            count: module.data.len().try_into().expect("data segment count must fit in u32"),
+        })?;
+    }
+    state.last_encoded_section = Some(SectionId::DataCount);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !code_section.is_empty() {
-        encoder.section(&code_section);
+        encoder.section(&code_section)?;
     }
     state.last_encoded_section = Some(SectionId::Code);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     if !data_section.is_empty() {
-        encoder.section(&data_section);
+        encoder.section(&data_section)?;
     }
     state.last_encoded_section = Some(SectionId::Data);
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
     // Custom name section is only valid after data section, see
     // https://webassembly.github.io/spec/core/appendix/custom.html#name-section
     let name_section = encode_names(module, &state)?;
     if let Some(name_section) = name_section {
-        encoder.section(&name_section);
+        encoder.section(&name_section)?;
         state.last_encoded_section = Some(SectionId::Custom("name".to_string()));
     }
-    encode_and_insert_custom(&mut encoder, &mut state, module);
+    encode_and_insert_custom(&mut encoder, &mut state, module)?;
+
+    Ok(encoder.bytes_written)
+}
+
+pub fn encode_component(component: &Component) -> Result<Vec<u8>, EncodeError> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"\0asm");
+    bytes.extend_from_slice(&component.version.to_le_bytes());
+    // Layer 1 distinguishes components from core modules.
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+
+    for section in &component.sections {
+        let encoded_content;
+        let content = match section {
+            ComponentSection::Module(module) => {
+                encoded_content = module.to_bytes()?;
+                &encoded_content
+            }
+            ComponentSection::Component(component) => {
+                encoded_content = encode_component(component)?;
+                &encoded_content
+            }
+            ComponentSection::Raw { id: _, content } => content,
+        };
+        bytes.push(section.id());
+        content.len().encode(&mut bytes);
+        bytes.extend_from_slice(content);
+    }
 
-    Ok(encoder.finish())
+    Ok(bytes)
 }
 
-fn encode_imports(module: &Module, state: &mut EncodeState) -> we::ImportSection {
+fn encode_imports(module: &Module, state: &mut EncodeState) -> Result<we::ImportSection, EncodeError> {
     let mut import_section = we::ImportSection::new();
 
     macro_rules! add_imports {
@@ -206,22 +300,24 @@ fn encode_imports(module: &Module, state: &mut EncodeState) -> we::ImportSection
             for (hl_idx, elem) in module.$elem_iter() {
                 if let Some((module_name, name)) = elem.import() {
                     state.$state_insert_fn(hl_idx);
+                    let ll_type: Result<_, EncodeError> = $hl_to_ll_closure(elem);
                     import_section.import(
                         module_name,
                         name,
-                        we::EntityType::$ll_import_type($hl_to_ll_closure(elem)),
+                        we::EntityType::$ll_import_type(ll_type?),
                     );
                 }
             }
         }
     }
 
-    add_imports!(functions, insert_function_idx, Function, |f: &Function| state.get_or_insert_type(f.type_).to_u32());
-    add_imports!(tables, insert_table_idx, Table, |t: &Table| we::TableType::from(t.limits));
-    add_imports!(memories, insert_memory_idx, Memory, |m: &Memory| we::MemoryType::from(m.limits));
-    add_imports!(globals, insert_global_idx, Global, |g: &Global| we::GlobalType::from(g.type_));
+    add_imports!(functions, insert_function_idx, Function, |f: &Function| Ok(state.get_or_insert_type(f.type_).to_u32()));
+    add_imports!(tables, insert_table_idx, Table, we::TableType::try_from);
+    add_imports!(memories, insert_memory_idx, Memory, |m: &Memory| Ok(we::MemoryType::from(m)));
+    add_imports!(globals, insert_global_idx, Global, |g: &Global| Ok(we::GlobalType::from(g.type_)));
+    add_imports!(tags, insert_tag_idx, Tag, |t: &Tag| Ok(encode_tag_type(t, state)));
 
-    import_section
+    Ok(import_section)
 }
 
 fn encode_exports(
@@ -248,6 +344,7 @@ fn encode_exports(
     add_exports!(tables, Table, map_table_idx);
     add_exports!(memories, Memory, map_memory_idx);
     add_exports!(globals, Global, map_global_idx);
+    add_exports!(tags, Tag, map_tag_idx);
 
     Ok(export_section)
 }
@@ -282,7 +379,8 @@ fn encode_functions(module: &Module, state: &mut EncodeState) -> we::FunctionSec
     let mut function_section = we::FunctionSection::new();
 
     for (function_idx, function) in module.functions() {
-        if let Some(_code) = function.code() {
+        // Don't use `function.code()`, which would parse lazily parsed bodies.
+        if let ImportOrPresent::Present(_code) = &function.code {
             state.insert_function_idx(function_idx);
 
             let ll_type_idx = state.get_or_insert_type(function.type_);
@@ -293,66 +391,157 @@ fn encode_functions(module: &Module, state: &mut EncodeState) -> we::FunctionSec
     function_section
 }
 
-fn encode_tables(
-    module: &Module,
-    state: &mut EncodeState,
-) -> Result<(we::TableSection, we::ElementSection), EncodeError> {
+fn encode_tables(module: &Module, state: &mut EncodeState) -> Result<we::TableSection, EncodeError> {
     let mut table_section = we::TableSection::new();
-    let mut element_section = we::ElementSection::new();
 
     for (hl_table_idx, table) in module.tables() {
-        let ll_table_idx = if table.import.is_none() {
-            table_section.table(we::TableType::from(table.limits));
-            state.insert_table_idx(hl_table_idx)
-        } else {
-            state.map_table_idx(hl_table_idx)?
-        };
+        if table.import.is_none() {
+            table_section.table(we::TableType::try_from(table)?);
+            state.insert_table_idx(hl_table_idx);
+        }
+    }
 
-        for hl_element in &table.elements {
-            // `wasm-encoder` uses None as the table index to signify the MVP binary format.
-            // Use that whenever possible, to avoid producing a binary using extensions.
-            let ll_table_idx = if ll_table_idx.to_u32() == 0 {
-                None
-            } else {
-                Some(ll_table_idx.to_u32())
-            };
-            let ll_offset = encode_single_instruction_with_end(&hl_element.offset, state)?;
-            let ll_elements = hl_element
-                .functions
-                .iter()
-                .map(|function_idx| state.map_function_idx(*function_idx).map(Idx::to_u32))
-                .collect::<Result<Vec<u32>, _>>()?;
-            let ll_elements = we::Elements::Functions(ll_elements.as_slice());
-            element_section.active(ll_table_idx, &ll_offset, we::ValType::FuncRef, ll_elements);
+    Ok(table_section)
+}
+
+fn encode_memories(module: &Module, state: &mut EncodeState) -> we::MemorySection {
+    let mut memory_section = we::MemorySection::new();
+
+    for (hl_memory_idx, memory) in module.memories() {
+        if memory.import.is_none() {
+            memory_section.memory(we::MemoryType::from(memory));
+            state.insert_memory_idx(hl_memory_idx);
         }
     }
 
-    Ok((table_section, element_section))
+    memory_section
 }
 
-fn encode_memories(
+fn encode_tags(module: &Module, state: &mut EncodeState) -> we::TagSection {
+    let mut tag_section = we::TagSection::new();
+
+    for (hl_tag_idx, tag) in module.tags() {
+        if tag.import.is_none() {
+            tag_section.tag(encode_tag_type(tag, state));
+            state.insert_tag_idx(hl_tag_idx);
+        }
+    }
+
+    tag_section
+}
+
+fn encode_tag_type(tag: &Tag, state: &EncodeState) -> we::TagType {
+    we::TagType {
+        kind: we::TagKind::Exception,
+        func_type_idx: state.get_or_insert_type(tag.type_).to_u32(),
+    }
+}
+
+fn encode_elements(
     module: &Module,
     state: &mut EncodeState,
-) -> Result<(we::MemorySection, we::DataSection), EncodeError> {
-    let mut memory_section = we::MemorySection::new();
-    let mut data_section = we::DataSection::new();
+) -> Result<we::ElementSection, EncodeError> {
+    let mut element_section = we::ElementSection::new();
 
-    for (hl_memory_idx, memory) in module.memories() {
-        let ll_memory_idx = if memory.import.is_none() {
-            memory_section.memory(we::MemoryType::from(memory.limits));
-            state.insert_memory_idx(hl_memory_idx)
-        } else {
-            state.map_memory_idx(hl_memory_idx)?
+    for hl_element in &module.elements {
+        // Only one of the two is used, but both need to outlive `ll_elements`.
+        let ll_functions;
+        let ll_exprs;
+        let (ll_element_type, ll_elements) = match &hl_element.items {
+            ElementItems::Functions(functions) => {
+                ll_functions = functions
+                    .iter()
+                    .map(|function_idx| state.map_function_idx(*function_idx).map(Idx::to_u32))
+                    .collect::<Result<Vec<u32>, _>>()?;
+                (we::ValType::FuncRef, we::Elements::Functions(ll_functions.as_slice()))
+            }
+            ElementItems::Expressions(type_, exprs) => {
+                ll_exprs = exprs
+                    .iter()
+                    .map(|expr| encode_single_instruction_with_end(expr, state))
+                    .collect::<Result<Vec<we::ConstExpr>, _>>()?;
+                ((*type_).into(), we::Elements::Expressions(ll_exprs.as_slice()))
+            }
+        };
+        let ll_mode = match &hl_element.mode {
+            ElementMode::Active { table, offset } => {
+                // `wasm-encoder` uses None as the table index to signify the MVP binary format
+                // (which implies `funcref` elements).
+                // Use that whenever possible, to avoid producing a binary using extensions.
+                let ll_table_idx = match (state.map_table_idx(*table)?.to_u32(), ll_element_type) {
+                    (0, we::ValType::FuncRef) => None,
+                    (ll_table_idx, _) => Some(ll_table_idx),
+                };
+                we::ElementMode::Active {
+                    table: ll_table_idx,
+                    offset: &encode_single_instruction_with_end(offset, state)?,
+                }
+            }
+            ElementMode::Passive => we::ElementMode::Passive,
+            ElementMode::Declared => we::ElementMode::Declared,
         };
+        element_section.segment(we::ElementSegment {
+            mode: ll_mode,
+            element_type: ll_element_type,
+            elements: ll_elements,
+        });
+    }
+
+    Ok(element_section)
+}
+
+fn encode_data(
+    module: &Module,
+    state: &mut EncodeState,
+) -> Result<we::DataSection, EncodeError> {
+    let mut data_section = we::DataSection::new();
 
-        for data in &memory.data {
-            let ll_offset = encode_single_instruction_with_end(&data.offset, state)?;
-            let ll_data = data.bytes.iter().copied();
-            data_section.active(ll_memory_idx.to_u32(), &ll_offset, ll_data);
+    for data in &module.data {
+        let ll_data = data.bytes.iter().copied();
+        match &data.mode {
+            DataMode::Active { memory, offset } => {
+                let ll_memory_idx = state.map_memory_idx(*memory)?;
+                let ll_offset = encode_single_instruction_with_end(offset, state)?;
+                data_section.active(ll_memory_idx.to_u32(), &ll_offset, ll_data);
+            }
+            DataMode::Passive => {
+                data_section.passive(ll_data);
+            }
         }
     }
 
-    Ok((memory_section, data_section))
+    Ok(data_section)
+}
+
+/// Whether any function body refers to a data segment by index, which requires a data count
+/// section in the binary.
+fn uses_data_idx_in_code(module: &Module) -> bool {
+    module
+        .functions
+        .par_iter()
+        .any(|function| match &function.code {
+            ImportOrPresent::Import(_, _) => false,
+            ImportOrPresent::Present(code) => match (code.get_if_parsed(), code.raw()) {
+                (Some(code), _) => code
+                    .body
+                    .iter()
+                    .any(|instr| matches!(instr, Instr::MemoryInit(_, _) | Instr::DataDrop(_))),
+                // Don't parse the body just for this, but conservatively assume it refers to data
+                // segments if the original binary allowed it to.
+                (None, Some(raw)) => raw.context.has_data_count_section,
+                (None, None) => unreachable!("body should be either parsed or raw"),
+            },
+        })
+}
+
+/// The body of a non-imported function, but only if it does not need to be parsed first.
+// Bodies that were not parsed yet have no local and label names, because all bodies with names
+// are parsed when parsing the name section.
+fn code_if_parsed(function: &Function) -> Option<&Code> {
+    match &function.code {
+        ImportOrPresent::Present(code) => code.get_if_parsed(),
+        ImportOrPresent::Import(_, _) => None,
+    }
 }
 
 fn encode_globals(
@@ -375,12 +564,28 @@ fn encode_globals(
 fn encode_code(module: &Module, state: &mut EncodeState) -> Result<we::CodeSection, EncodeError> {
     let mut code_section = we::CodeSection::new();
 
+    enum LowLevelBody<'a> {
+        Verbatim(&'a [u8]),
+        Encoded(we::Function),
+    }
+
     // Encode function bodies in parallel.
     let ll_functions = module
         .functions
         .par_iter()
-        .filter_map(Function::code)
-        .map(|code| -> Result<we::Function, EncodeError> {
+        .filter_map(|function| match &function.code {
+            ImportOrPresent::Present(code) => Some(code),
+            ImportOrPresent::Import(_, _) => None,
+        })
+        .map(|code| -> Result<LowLevelBody, EncodeError> {
+            if let (Some(raw), Some(raw_bodies_context)) = (code.raw(), &state.raw_bodies_context) {
+                if Arc::ptr_eq(&raw.context, raw_bodies_context) {
+                    return Ok(LowLevelBody::Verbatim(raw.context.bytes(raw.range.clone())));
+                }
+            }
+
+            // The body has to be re-encoded, so an unparsed body is parsed now, which can fail.
+            let code = code.try_get()?;
             let ll_locals_iter = code
                 .locals
                 .iter()
@@ -389,11 +594,14 @@ fn encode_code(module: &Module, state: &mut EncodeState) -> Result<we::CodeSecti
             for instr in &code.body {
                 ll_function.instruction(&encode_instruction(instr, state)?);
             }
-            Ok(ll_function)
+            Ok(LowLevelBody::Encoded(ll_function))
         })
-        .collect::<Result<Vec<we::Function>, _>>()?;
+        .collect::<Result<Vec<LowLevelBody>, _>>()?;
     for ll_function in ll_functions {
-        code_section.function(&ll_function);
+        match ll_function {
+            LowLevelBody::Verbatim(bytes) => code_section.raw(bytes),
+            LowLevelBody::Encoded(ll_function) => code_section.function(&ll_function),
+        };
     }
 
     Ok(code_section)
@@ -403,10 +611,10 @@ fn encode_code(module: &Module, state: &mut EncodeState) -> Result<we::CodeSecti
 // fn insert_section<T>(encoder: &mut wasm_encoder::Module, state: &mut EncodeState, section: T, module: &Module, previous_section: Option<SectionId>)
 //     where T: wasm_encoder::Section {
 fn encode_and_insert_custom(
-    encoder: &mut wasm_encoder::Module,
+    encoder: &mut SectionWriter<impl io::Write>,
     state: &mut EncodeState,
     module: &Module,
-) {
+) -> io::Result<()> {
     for custom in module
         .custom_sections
         .iter()
@@ -418,11 +626,50 @@ fn encode_and_insert_custom(
             encoder.section(&wasm_encoder::CustomSection {
                 name: &custom.name,
                 data: &custom.content[..],
-            });
+            })?;
             state.custom_sections_encoded += 1;
             state.last_encoded_section = Some(SectionId::Custom(custom.name.clone()));
         }
     }
+    Ok(())
+}
+
+/// Like `wasm_encoder::Module`, but writes each section out immediately instead of appending it
+/// to a single buffer for the whole binary.
+struct SectionWriter<W> {
+    writer: W,
+    // Re-used between sections to avoid allocations.
+    buffer: Vec<u8>,
+    bytes_written: usize,
+}
+
+impl<W: io::Write> SectionWriter<W> {
+    /// Writes the preamble (magic number and version) already.
+    fn new(writer: W) -> io::Result<Self> {
+        let mut encoder = SectionWriter {
+            writer,
+            buffer: Vec::new(),
+            bytes_written: 0,
+        };
+        encoder.write(we::Module::new().as_slice())?;
+        Ok(encoder)
+    }
+
+    fn section(&mut self, section: &impl we::Section) -> io::Result<()> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.clear();
+        buffer.push(section.id());
+        section.encode(&mut buffer);
+        self.write(&buffer)?;
+        self.buffer = buffer;
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.bytes_written += bytes.len();
+        Ok(())
+    }
 }
 
 fn encode_single_instruction_with_end(
@@ -466,9 +713,22 @@ fn encode_instruction(
             ty: state.get_or_insert_type(*function_type).to_u32(),
             table: state.map_table_idx(table_idx)?.to_u32(),
         },
+        Instr::ReturnCall(function_idx) => we::Instruction::ReturnCall(state.map_function_idx(function_idx)?.to_u32()),
+        Instr::ReturnCallIndirect(ref function_type, table_idx) => we::Instruction::ReturnCallIndirect {
+            ty: state.get_or_insert_type(*function_type).to_u32(),
+            table: state.map_table_idx(table_idx)?.to_u32(),
+        },
+
+        Instr::Try(block_type) => we::Instruction::Try(encode_block_type(block_type, state)),
+        Instr::Catch(tag_idx) => we::Instruction::Catch(state.map_tag_idx(tag_idx)?.to_u32()),
+        Instr::CatchAll => we::Instruction::CatchAll,
+        Instr::Delegate(label) => we::Instruction::Delegate(label.to_u32()),
+        Instr::Throw(tag_idx) => we::Instruction::Throw(state.map_tag_idx(tag_idx)?.to_u32()),
+        Instr::Rethrow(label) => we::Instruction::Rethrow(label.to_u32()),
 
         Instr::Drop => we::Instruction::Drop,
         Instr::Select => we::Instruction::Select,
+        Instr::TypedSelect(type_) => we::Instruction::TypedSelect(type_.into()),
 
         Instr::Local(LocalOp::Get, local_idx) => we::Instruction::LocalGet(local_idx.to_u32()),
         Instr::Local(LocalOp::Set, local_idx) => we::Instruction::LocalSet(local_idx.to_u32()),
@@ -476,38 +736,181 @@ fn encode_instruction(
         Instr::Global(GlobalOp::Get, global_idx) => we::Instruction::GlobalGet(state.map_global_idx(global_idx)?.to_u32()),
         Instr::Global(GlobalOp::Set, global_idx) => we::Instruction::GlobalSet(state.map_global_idx(global_idx)?.to_u32()),
 
-        Instr::Load(LoadOp::I32Load, memarg) => we::Instruction::I32Load(memarg.into()),
-        Instr::Load(LoadOp::I64Load, memarg) => we::Instruction::I64Load(memarg.into()),
-        Instr::Load(LoadOp::F32Load, memarg) => we::Instruction::F32Load(memarg.into()),
-        Instr::Load(LoadOp::F64Load, memarg) => we::Instruction::F64Load(memarg.into()),
-        Instr::Load(LoadOp::I32Load8S, memarg) => we::Instruction::I32Load8S(memarg.into()),
-        Instr::Load(LoadOp::I32Load8U, memarg) => we::Instruction::I32Load8U(memarg.into()),
-        Instr::Load(LoadOp::I32Load16S, memarg) => we::Instruction::I32Load16S(memarg.into()),
-        Instr::Load(LoadOp::I32Load16U, memarg) => we::Instruction::I32Load16U(memarg.into()),
-        Instr::Load(LoadOp::I64Load8S, memarg) => we::Instruction::I64Load8S(memarg.into()),
-        Instr::Load(LoadOp::I64Load8U, memarg) => we::Instruction::I64Load8U(memarg.into()),
-        Instr::Load(LoadOp::I64Load16S, memarg) => we::Instruction::I64Load16S(memarg.into()),
-        Instr::Load(LoadOp::I64Load16U, memarg) => we::Instruction::I64Load16U(memarg.into()),
-        Instr::Load(LoadOp::I64Load32S, memarg) => we::Instruction::I64Load32S(memarg.into()),
-        Instr::Load(LoadOp::I64Load32U, memarg) => we::Instruction::I64Load32U(memarg.into()),
-
-        Instr::Store(StoreOp::I32Store, memarg) => we::Instruction::I32Store(memarg.into()),
-        Instr::Store(StoreOp::I64Store, memarg) => we::Instruction::I64Store(memarg.into()),
-        Instr::Store(StoreOp::F32Store, memarg) => we::Instruction::F32Store(memarg.into()),
-        Instr::Store(StoreOp::F64Store, memarg) => we::Instruction::F64Store(memarg.into()),
-        Instr::Store(StoreOp::I32Store8, memarg) => we::Instruction::I32Store8(memarg.into()),
-        Instr::Store(StoreOp::I32Store16, memarg) => we::Instruction::I32Store16(memarg.into()),
-        Instr::Store(StoreOp::I64Store8, memarg) => we::Instruction::I64Store8(memarg.into()),
-        Instr::Store(StoreOp::I64Store16, memarg) => we::Instruction::I64Store16(memarg.into()),
-        Instr::Store(StoreOp::I64Store32, memarg) => we::Instruction::I64Store32(memarg.into()),
+        Instr::Load(LoadOp::I32Load, memarg) => we::Instruction::I32Load(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I64Load, memarg) => we::Instruction::I64Load(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::F32Load, memarg) => we::Instruction::F32Load(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::F64Load, memarg) => we::Instruction::F64Load(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I32Load8S, memarg) => we::Instruction::I32Load8S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I32Load8U, memarg) => we::Instruction::I32Load8U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I32Load16S, memarg) => we::Instruction::I32Load16S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I32Load16U, memarg) => we::Instruction::I32Load16U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I64Load8S, memarg) => we::Instruction::I64Load8S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I64Load8U, memarg) => we::Instruction::I64Load8U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I64Load16S, memarg) => we::Instruction::I64Load16S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I64Load16U, memarg) => we::Instruction::I64Load16U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I64Load32S, memarg) => we::Instruction::I64Load32S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::I64Load32U, memarg) => we::Instruction::I64Load32U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load, memarg) => we::Instruction::V128Load(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load8x8S, memarg) => we::Instruction::V128Load8x8S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load8x8U, memarg) => we::Instruction::V128Load8x8U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load16x4S, memarg) => we::Instruction::V128Load16x4S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load16x4U, memarg) => we::Instruction::V128Load16x4U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load32x2S, memarg) => we::Instruction::V128Load32x2S(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load32x2U, memarg) => we::Instruction::V128Load32x2U(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load8Splat, memarg) => we::Instruction::V128Load8Splat(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load16Splat, memarg) => we::Instruction::V128Load16Splat(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load32Splat, memarg) => we::Instruction::V128Load32Splat(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load64Splat, memarg) => we::Instruction::V128Load64Splat(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load32Zero, memarg) => we::Instruction::V128Load32Zero(encode_memarg(memarg, state)?),
+        Instr::Load(LoadOp::V128Load64Zero, memarg) => we::Instruction::V128Load64Zero(encode_memarg(memarg, state)?),
+
+        Instr::Store(StoreOp::I32Store, memarg) => we::Instruction::I32Store(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::I64Store, memarg) => we::Instruction::I64Store(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::F32Store, memarg) => we::Instruction::F32Store(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::F64Store, memarg) => we::Instruction::F64Store(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::I32Store8, memarg) => we::Instruction::I32Store8(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::I32Store16, memarg) => we::Instruction::I32Store16(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::I64Store8, memarg) => we::Instruction::I64Store8(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::I64Store16, memarg) => we::Instruction::I64Store16(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::I64Store32, memarg) => we::Instruction::I64Store32(encode_memarg(memarg, state)?),
+        Instr::Store(StoreOp::V128Store, memarg) => we::Instruction::V128Store(encode_memarg(memarg, state)?),
+
+        Instr::LoadLane(LoadLaneOp::V128Load8Lane, memarg, lane) => we::Instruction::V128Load8Lane { memarg: encode_memarg(memarg, state)?, lane },
+        Instr::LoadLane(LoadLaneOp::V128Load16Lane, memarg, lane) => we::Instruction::V128Load16Lane { memarg: encode_memarg(memarg, state)?, lane },
+        Instr::LoadLane(LoadLaneOp::V128Load32Lane, memarg, lane) => we::Instruction::V128Load32Lane { memarg: encode_memarg(memarg, state)?, lane },
+        Instr::LoadLane(LoadLaneOp::V128Load64Lane, memarg, lane) => we::Instruction::V128Load64Lane { memarg: encode_memarg(memarg, state)?, lane },
+        Instr::StoreLane(StoreLaneOp::V128Store8Lane, memarg, lane) => we::Instruction::V128Store8Lane { memarg: encode_memarg(memarg, state)?, lane },
+        Instr::StoreLane(StoreLaneOp::V128Store16Lane, memarg, lane) => we::Instruction::V128Store16Lane { memarg: encode_memarg(memarg, state)?, lane },
+        Instr::StoreLane(StoreLaneOp::V128Store32Lane, memarg, lane) => we::Instruction::V128Store32Lane { memarg: encode_memarg(memarg, state)?, lane },
+        Instr::StoreLane(StoreLaneOp::V128Store64Lane, memarg, lane) => we::Instruction::V128Store64Lane { memarg: encode_memarg(memarg, state)?, lane },
+
+        Instr::AtomicLoad(AtomicLoadOp::I32AtomicLoad, memarg) => we::Instruction::I32AtomicLoad(encode_memarg(memarg, state)?),
+        Instr::AtomicLoad(AtomicLoadOp::I64AtomicLoad, memarg) => we::Instruction::I64AtomicLoad(encode_memarg(memarg, state)?),
+        Instr::AtomicLoad(AtomicLoadOp::I32AtomicLoad8U, memarg) => we::Instruction::I32AtomicLoad8U(encode_memarg(memarg, state)?),
+        Instr::AtomicLoad(AtomicLoadOp::I32AtomicLoad16U, memarg) => we::Instruction::I32AtomicLoad16U(encode_memarg(memarg, state)?),
+        Instr::AtomicLoad(AtomicLoadOp::I64AtomicLoad8U, memarg) => we::Instruction::I64AtomicLoad8U(encode_memarg(memarg, state)?),
+        Instr::AtomicLoad(AtomicLoadOp::I64AtomicLoad16U, memarg) => we::Instruction::I64AtomicLoad16U(encode_memarg(memarg, state)?),
+        Instr::AtomicLoad(AtomicLoadOp::I64AtomicLoad32U, memarg) => we::Instruction::I64AtomicLoad32U(encode_memarg(memarg, state)?),
+        Instr::AtomicStore(AtomicStoreOp::I32AtomicStore, memarg) => we::Instruction::I32AtomicStore(encode_memarg(memarg, state)?),
+        Instr::AtomicStore(AtomicStoreOp::I64AtomicStore, memarg) => we::Instruction::I64AtomicStore(encode_memarg(memarg, state)?),
+        Instr::AtomicStore(AtomicStoreOp::I32AtomicStore8, memarg) => we::Instruction::I32AtomicStore8(encode_memarg(memarg, state)?),
+        Instr::AtomicStore(AtomicStoreOp::I32AtomicStore16, memarg) => we::Instruction::I32AtomicStore16(encode_memarg(memarg, state)?),
+        Instr::AtomicStore(AtomicStoreOp::I64AtomicStore8, memarg) => we::Instruction::I64AtomicStore8(encode_memarg(memarg, state)?),
+        Instr::AtomicStore(AtomicStoreOp::I64AtomicStore16, memarg) => we::Instruction::I64AtomicStore16(encode_memarg(memarg, state)?),
+        Instr::AtomicStore(AtomicStoreOp::I64AtomicStore32, memarg) => we::Instruction::I64AtomicStore32(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmwAdd, memarg) => we::Instruction::I32AtomicRmwAdd(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmwAdd, memarg) => we::Instruction::I64AtomicRmwAdd(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw8AddU, memarg) => we::Instruction::I32AtomicRmw8AddU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw16AddU, memarg) => we::Instruction::I32AtomicRmw16AddU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw8AddU, memarg) => we::Instruction::I64AtomicRmw8AddU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw16AddU, memarg) => we::Instruction::I64AtomicRmw16AddU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw32AddU, memarg) => we::Instruction::I64AtomicRmw32AddU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmwSub, memarg) => we::Instruction::I32AtomicRmwSub(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmwSub, memarg) => we::Instruction::I64AtomicRmwSub(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw8SubU, memarg) => we::Instruction::I32AtomicRmw8SubU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw16SubU, memarg) => we::Instruction::I32AtomicRmw16SubU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw8SubU, memarg) => we::Instruction::I64AtomicRmw8SubU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw16SubU, memarg) => we::Instruction::I64AtomicRmw16SubU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw32SubU, memarg) => we::Instruction::I64AtomicRmw32SubU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmwAnd, memarg) => we::Instruction::I32AtomicRmwAnd(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmwAnd, memarg) => we::Instruction::I64AtomicRmwAnd(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw8AndU, memarg) => we::Instruction::I32AtomicRmw8AndU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw16AndU, memarg) => we::Instruction::I32AtomicRmw16AndU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw8AndU, memarg) => we::Instruction::I64AtomicRmw8AndU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw16AndU, memarg) => we::Instruction::I64AtomicRmw16AndU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw32AndU, memarg) => we::Instruction::I64AtomicRmw32AndU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmwOr, memarg) => we::Instruction::I32AtomicRmwOr(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmwOr, memarg) => we::Instruction::I64AtomicRmwOr(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw8OrU, memarg) => we::Instruction::I32AtomicRmw8OrU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw16OrU, memarg) => we::Instruction::I32AtomicRmw16OrU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw8OrU, memarg) => we::Instruction::I64AtomicRmw8OrU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw16OrU, memarg) => we::Instruction::I64AtomicRmw16OrU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw32OrU, memarg) => we::Instruction::I64AtomicRmw32OrU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmwXor, memarg) => we::Instruction::I32AtomicRmwXor(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmwXor, memarg) => we::Instruction::I64AtomicRmwXor(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw8XorU, memarg) => we::Instruction::I32AtomicRmw8XorU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw16XorU, memarg) => we::Instruction::I32AtomicRmw16XorU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw8XorU, memarg) => we::Instruction::I64AtomicRmw8XorU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw16XorU, memarg) => we::Instruction::I64AtomicRmw16XorU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw32XorU, memarg) => we::Instruction::I64AtomicRmw32XorU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmwXchg, memarg) => we::Instruction::I32AtomicRmwXchg(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmwXchg, memarg) => we::Instruction::I64AtomicRmwXchg(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw8XchgU, memarg) => we::Instruction::I32AtomicRmw8XchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw16XchgU, memarg) => we::Instruction::I32AtomicRmw16XchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw8XchgU, memarg) => we::Instruction::I64AtomicRmw8XchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw16XchgU, memarg) => we::Instruction::I64AtomicRmw16XchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw32XchgU, memarg) => we::Instruction::I64AtomicRmw32XchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmwCmpxchg, memarg) => we::Instruction::I32AtomicRmwCmpxchg(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmwCmpxchg, memarg) => we::Instruction::I64AtomicRmwCmpxchg(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw8CmpxchgU, memarg) => we::Instruction::I32AtomicRmw8CmpxchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I32AtomicRmw16CmpxchgU, memarg) => we::Instruction::I32AtomicRmw16CmpxchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw8CmpxchgU, memarg) => we::Instruction::I64AtomicRmw8CmpxchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw16CmpxchgU, memarg) => we::Instruction::I64AtomicRmw16CmpxchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::I64AtomicRmw32CmpxchgU, memarg) => we::Instruction::I64AtomicRmw32CmpxchgU(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::MemoryAtomicNotify, memarg) => we::Instruction::MemoryAtomicNotify(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::MemoryAtomicWait32, memarg) => we::Instruction::MemoryAtomicWait32(encode_memarg(memarg, state)?),
+        Instr::Atomic(AtomicOp::MemoryAtomicWait64, memarg) => we::Instruction::MemoryAtomicWait64(encode_memarg(memarg, state)?),
+        Instr::AtomicFence => we::Instruction::AtomicFence,
 
         Instr::MemorySize(memory_idx) => we::Instruction::MemorySize(state.map_memory_idx(memory_idx)?.to_u32()),
         Instr::MemoryGrow(memory_idx) => we::Instruction::MemoryGrow(state.map_memory_idx(memory_idx)?.to_u32()),
 
+        // Data and element segments cannot be imported, so their indices need no re-mapping.
+        Instr::MemoryInit(data_idx, memory_idx) => we::Instruction::MemoryInit {
+            mem: state.map_memory_idx(memory_idx)?.to_u32(),
+            data_index: data_idx.to_u32(),
+        },
+        Instr::DataDrop(data_idx) => we::Instruction::DataDrop(data_idx.to_u32()),
+        Instr::MemoryCopy { dst, src } => we::Instruction::MemoryCopy {
+            src_mem: state.map_memory_idx(src)?.to_u32(),
+            dst_mem: state.map_memory_idx(dst)?.to_u32(),
+        },
+        Instr::MemoryFill(memory_idx) => we::Instruction::MemoryFill(state.map_memory_idx(memory_idx)?.to_u32()),
+        Instr::TableInit(element_idx, table_idx) => we::Instruction::TableInit {
+            elem_index: element_idx.to_u32(),
+            table: state.map_table_idx(table_idx)?.to_u32(),
+        },
+        Instr::ElemDrop(element_idx) => we::Instruction::ElemDrop(element_idx.to_u32()),
+        Instr::TableCopy { dst, src } => we::Instruction::TableCopy {
+            src_table: state.map_table_idx(src)?.to_u32(),
+            dst_table: state.map_table_idx(dst)?.to_u32(),
+        },
+
+        Instr::RefNull(type_) => we::Instruction::RefNull(type_.into()),
+        Instr::RefIsNull => we::Instruction::RefIsNull,
+        Instr::RefFunc(function_idx) => we::Instruction::RefFunc(state.map_function_idx(function_idx)?.to_u32()),
+        Instr::TableGet(table_idx) => we::Instruction::TableGet(state.map_table_idx(table_idx)?.to_u32()),
+        Instr::TableSet(table_idx) => we::Instruction::TableSet(state.map_table_idx(table_idx)?.to_u32()),
+        Instr::TableSize(table_idx) => we::Instruction::TableSize(state.map_table_idx(table_idx)?.to_u32()),
+        Instr::TableGrow(table_idx) => we::Instruction::TableGrow(state.map_table_idx(table_idx)?.to_u32()),
+        Instr::TableFill(table_idx) => we::Instruction::TableFill(state.map_table_idx(table_idx)?.to_u32()),
+
         Instr::Const(Val::I32(value)) => we::Instruction::I32Const(value),
         Instr::Const(Val::I64(value)) => we::Instruction::I64Const(value),
         Instr::Const(Val::F32(value)) => we::Instruction::F32Const(value.into_inner()),
         Instr::Const(Val::F64(value)) => we::Instruction::F64Const(value.into_inner()),
+        Instr::Const(Val::Ref { type_, is_null: true }) => we::Instruction::RefNull(type_.into()),
+        Instr::Const(Val::V128(bytes)) => we::Instruction::V128Const(i128::from_le_bytes(bytes)),
+
+        Instr::I8x16Shuffle(lanes) => we::Instruction::I8x16Shuffle(lanes),
+        Instr::V128Bitselect => we::Instruction::V128Bitselect,
+        Instr::ExtractLane(ExtractLaneOp::I8x16ExtractLaneS, lane) => we::Instruction::I8x16ExtractLaneS(lane),
+        Instr::ExtractLane(ExtractLaneOp::I8x16ExtractLaneU, lane) => we::Instruction::I8x16ExtractLaneU(lane),
+        Instr::ExtractLane(ExtractLaneOp::I16x8ExtractLaneS, lane) => we::Instruction::I16x8ExtractLaneS(lane),
+        Instr::ExtractLane(ExtractLaneOp::I16x8ExtractLaneU, lane) => we::Instruction::I16x8ExtractLaneU(lane),
+        Instr::ExtractLane(ExtractLaneOp::I32x4ExtractLane, lane) => we::Instruction::I32x4ExtractLane(lane),
+        Instr::ExtractLane(ExtractLaneOp::I64x2ExtractLane, lane) => we::Instruction::I64x2ExtractLane(lane),
+        Instr::ExtractLane(ExtractLaneOp::F32x4ExtractLane, lane) => we::Instruction::F32x4ExtractLane(lane),
+        Instr::ExtractLane(ExtractLaneOp::F64x2ExtractLane, lane) => we::Instruction::F64x2ExtractLane(lane),
+        Instr::ReplaceLane(ReplaceLaneOp::I8x16ReplaceLane, lane) => we::Instruction::I8x16ReplaceLane(lane),
+        Instr::ReplaceLane(ReplaceLaneOp::I16x8ReplaceLane, lane) => we::Instruction::I16x8ReplaceLane(lane),
+        Instr::ReplaceLane(ReplaceLaneOp::I32x4ReplaceLane, lane) => we::Instruction::I32x4ReplaceLane(lane),
+        Instr::ReplaceLane(ReplaceLaneOp::I64x2ReplaceLane, lane) => we::Instruction::I64x2ReplaceLane(lane),
+        Instr::ReplaceLane(ReplaceLaneOp::F32x4ReplaceLane, lane) => we::Instruction::F32x4ReplaceLane(lane),
+        Instr::ReplaceLane(ReplaceLaneOp::F64x2ReplaceLane, lane) => we::Instruction::F64x2ReplaceLane(lane),
+        Instr::Const(val @ Val::Ref { is_null: false, .. }) => {
+            return Err(EncodeError::message(format!("non-null reference constant {val:?} cannot be encoded, only ref.null and ref.func are constant references")))
+        }
 
         Instr::Unary(UnaryOp::I32Eqz) => we::Instruction::I32Eqz,
         Instr::Unary(UnaryOp::I64Eqz) => we::Instruction::I64Eqz,
@@ -556,6 +959,84 @@ fn encode_instruction(
         Instr::Unary(UnaryOp::I64ReinterpretF64) => we::Instruction::I64ReinterpretF64,
         Instr::Unary(UnaryOp::F32ReinterpretI32) => we::Instruction::F32ReinterpretI32,
         Instr::Unary(UnaryOp::F64ReinterpretI64) => we::Instruction::F64ReinterpretI64,
+        Instr::Unary(UnaryOp::I32Extend8S) => we::Instruction::I32Extend8S,
+        Instr::Unary(UnaryOp::I32Extend16S) => we::Instruction::I32Extend16S,
+        Instr::Unary(UnaryOp::I64Extend8S) => we::Instruction::I64Extend8S,
+        Instr::Unary(UnaryOp::I64Extend16S) => we::Instruction::I64Extend16S,
+        Instr::Unary(UnaryOp::I64Extend32S) => we::Instruction::I64Extend32S,
+        Instr::Unary(UnaryOp::I32TruncSatF32S) => we::Instruction::I32TruncSatF32S,
+        Instr::Unary(UnaryOp::I32TruncSatF32U) => we::Instruction::I32TruncSatF32U,
+        Instr::Unary(UnaryOp::I32TruncSatF64S) => we::Instruction::I32TruncSatF64S,
+        Instr::Unary(UnaryOp::I32TruncSatF64U) => we::Instruction::I32TruncSatF64U,
+        Instr::Unary(UnaryOp::I64TruncSatF32S) => we::Instruction::I64TruncSatF32S,
+        Instr::Unary(UnaryOp::I64TruncSatF32U) => we::Instruction::I64TruncSatF32U,
+        Instr::Unary(UnaryOp::I64TruncSatF64S) => we::Instruction::I64TruncSatF64S,
+        Instr::Unary(UnaryOp::I64TruncSatF64U) => we::Instruction::I64TruncSatF64U,
+        Instr::Unary(UnaryOp::I8x16Splat) => we::Instruction::I8x16Splat,
+        Instr::Unary(UnaryOp::I16x8Splat) => we::Instruction::I16x8Splat,
+        Instr::Unary(UnaryOp::I32x4Splat) => we::Instruction::I32x4Splat,
+        Instr::Unary(UnaryOp::I64x2Splat) => we::Instruction::I64x2Splat,
+        Instr::Unary(UnaryOp::F32x4Splat) => we::Instruction::F32x4Splat,
+        Instr::Unary(UnaryOp::F64x2Splat) => we::Instruction::F64x2Splat,
+        Instr::Unary(UnaryOp::V128AnyTrue) => we::Instruction::V128AnyTrue,
+        Instr::Unary(UnaryOp::I8x16AllTrue) => we::Instruction::I8x16AllTrue,
+        Instr::Unary(UnaryOp::I8x16Bitmask) => we::Instruction::I8x16Bitmask,
+        Instr::Unary(UnaryOp::I16x8AllTrue) => we::Instruction::I16x8AllTrue,
+        Instr::Unary(UnaryOp::I16x8Bitmask) => we::Instruction::I16x8Bitmask,
+        Instr::Unary(UnaryOp::I32x4AllTrue) => we::Instruction::I32x4AllTrue,
+        Instr::Unary(UnaryOp::I32x4Bitmask) => we::Instruction::I32x4Bitmask,
+        Instr::Unary(UnaryOp::I64x2AllTrue) => we::Instruction::I64x2AllTrue,
+        Instr::Unary(UnaryOp::I64x2Bitmask) => we::Instruction::I64x2Bitmask,
+        Instr::Unary(UnaryOp::V128Not) => we::Instruction::V128Not,
+        Instr::Unary(UnaryOp::I8x16Abs) => we::Instruction::I8x16Abs,
+        Instr::Unary(UnaryOp::I8x16Neg) => we::Instruction::I8x16Neg,
+        Instr::Unary(UnaryOp::I8x16Popcnt) => we::Instruction::I8x16Popcnt,
+        Instr::Unary(UnaryOp::I16x8Abs) => we::Instruction::I16x8Abs,
+        Instr::Unary(UnaryOp::I16x8Neg) => we::Instruction::I16x8Neg,
+        Instr::Unary(UnaryOp::I32x4Abs) => we::Instruction::I32x4Abs,
+        Instr::Unary(UnaryOp::I32x4Neg) => we::Instruction::I32x4Neg,
+        Instr::Unary(UnaryOp::I64x2Abs) => we::Instruction::I64x2Abs,
+        Instr::Unary(UnaryOp::I64x2Neg) => we::Instruction::I64x2Neg,
+        Instr::Unary(UnaryOp::F32x4Ceil) => we::Instruction::F32x4Ceil,
+        Instr::Unary(UnaryOp::F32x4Floor) => we::Instruction::F32x4Floor,
+        Instr::Unary(UnaryOp::F32x4Trunc) => we::Instruction::F32x4Trunc,
+        Instr::Unary(UnaryOp::F32x4Nearest) => we::Instruction::F32x4Nearest,
+        Instr::Unary(UnaryOp::F32x4Abs) => we::Instruction::F32x4Abs,
+        Instr::Unary(UnaryOp::F32x4Neg) => we::Instruction::F32x4Neg,
+        Instr::Unary(UnaryOp::F32x4Sqrt) => we::Instruction::F32x4Sqrt,
+        Instr::Unary(UnaryOp::F64x2Ceil) => we::Instruction::F64x2Ceil,
+        Instr::Unary(UnaryOp::F64x2Floor) => we::Instruction::F64x2Floor,
+        Instr::Unary(UnaryOp::F64x2Trunc) => we::Instruction::F64x2Trunc,
+        Instr::Unary(UnaryOp::F64x2Nearest) => we::Instruction::F64x2Nearest,
+        Instr::Unary(UnaryOp::F64x2Abs) => we::Instruction::F64x2Abs,
+        Instr::Unary(UnaryOp::F64x2Neg) => we::Instruction::F64x2Neg,
+        Instr::Unary(UnaryOp::F64x2Sqrt) => we::Instruction::F64x2Sqrt,
+        Instr::Unary(UnaryOp::I16x8ExtAddPairwiseI8x16S) => we::Instruction::I16x8ExtAddPairwiseI8x16S,
+        Instr::Unary(UnaryOp::I16x8ExtAddPairwiseI8x16U) => we::Instruction::I16x8ExtAddPairwiseI8x16U,
+        Instr::Unary(UnaryOp::I32x4ExtAddPairwiseI16x8S) => we::Instruction::I32x4ExtAddPairwiseI16x8S,
+        Instr::Unary(UnaryOp::I32x4ExtAddPairwiseI16x8U) => we::Instruction::I32x4ExtAddPairwiseI16x8U,
+        Instr::Unary(UnaryOp::I16x8ExtendLowI8x16S) => we::Instruction::I16x8ExtendLowI8x16S,
+        Instr::Unary(UnaryOp::I16x8ExtendHighI8x16S) => we::Instruction::I16x8ExtendHighI8x16S,
+        Instr::Unary(UnaryOp::I16x8ExtendLowI8x16U) => we::Instruction::I16x8ExtendLowI8x16U,
+        Instr::Unary(UnaryOp::I16x8ExtendHighI8x16U) => we::Instruction::I16x8ExtendHighI8x16U,
+        Instr::Unary(UnaryOp::I32x4ExtendLowI16x8S) => we::Instruction::I32x4ExtendLowI16x8S,
+        Instr::Unary(UnaryOp::I32x4ExtendHighI16x8S) => we::Instruction::I32x4ExtendHighI16x8S,
+        Instr::Unary(UnaryOp::I32x4ExtendLowI16x8U) => we::Instruction::I32x4ExtendLowI16x8U,
+        Instr::Unary(UnaryOp::I32x4ExtendHighI16x8U) => we::Instruction::I32x4ExtendHighI16x8U,
+        Instr::Unary(UnaryOp::I64x2ExtendLowI32x4S) => we::Instruction::I64x2ExtendLowI32x4S,
+        Instr::Unary(UnaryOp::I64x2ExtendHighI32x4S) => we::Instruction::I64x2ExtendHighI32x4S,
+        Instr::Unary(UnaryOp::I64x2ExtendLowI32x4U) => we::Instruction::I64x2ExtendLowI32x4U,
+        Instr::Unary(UnaryOp::I64x2ExtendHighI32x4U) => we::Instruction::I64x2ExtendHighI32x4U,
+        Instr::Unary(UnaryOp::I32x4TruncSatF32x4S) => we::Instruction::I32x4TruncSatF32x4S,
+        Instr::Unary(UnaryOp::I32x4TruncSatF32x4U) => we::Instruction::I32x4TruncSatF32x4U,
+        Instr::Unary(UnaryOp::F32x4ConvertI32x4S) => we::Instruction::F32x4ConvertI32x4S,
+        Instr::Unary(UnaryOp::F32x4ConvertI32x4U) => we::Instruction::F32x4ConvertI32x4U,
+        Instr::Unary(UnaryOp::I32x4TruncSatF64x2SZero) => we::Instruction::I32x4TruncSatF64x2SZero,
+        Instr::Unary(UnaryOp::I32x4TruncSatF64x2UZero) => we::Instruction::I32x4TruncSatF64x2UZero,
+        Instr::Unary(UnaryOp::F64x2ConvertLowI32x4S) => we::Instruction::F64x2ConvertLowI32x4S,
+        Instr::Unary(UnaryOp::F64x2ConvertLowI32x4U) => we::Instruction::F64x2ConvertLowI32x4U,
+        Instr::Unary(UnaryOp::F32x4DemoteF64x2Zero) => we::Instruction::F32x4DemoteF64x2Zero,
+        Instr::Unary(UnaryOp::F64x2PromoteLowF32x4) => we::Instruction::F64x2PromoteLowF32x4,
 
         Instr::Binary(BinaryOp::I32Eq) => we::Instruction::I32Eq,
         Instr::Binary(BinaryOp::I32Ne) => we::Instruction::I32Ne,
@@ -633,6 +1114,138 @@ fn encode_instruction(
         Instr::Binary(BinaryOp::F64Min) => we::Instruction::F64Min,
         Instr::Binary(BinaryOp::F64Max) => we::Instruction::F64Max,
         Instr::Binary(BinaryOp::F64Copysign) => we::Instruction::F64Copysign,
+        Instr::Binary(BinaryOp::I8x16Eq) => we::Instruction::I8x16Eq,
+        Instr::Binary(BinaryOp::I8x16Ne) => we::Instruction::I8x16Ne,
+        Instr::Binary(BinaryOp::I8x16LtS) => we::Instruction::I8x16LtS,
+        Instr::Binary(BinaryOp::I8x16LtU) => we::Instruction::I8x16LtU,
+        Instr::Binary(BinaryOp::I8x16GtS) => we::Instruction::I8x16GtS,
+        Instr::Binary(BinaryOp::I8x16GtU) => we::Instruction::I8x16GtU,
+        Instr::Binary(BinaryOp::I8x16LeS) => we::Instruction::I8x16LeS,
+        Instr::Binary(BinaryOp::I8x16LeU) => we::Instruction::I8x16LeU,
+        Instr::Binary(BinaryOp::I8x16GeS) => we::Instruction::I8x16GeS,
+        Instr::Binary(BinaryOp::I8x16GeU) => we::Instruction::I8x16GeU,
+        Instr::Binary(BinaryOp::I16x8Eq) => we::Instruction::I16x8Eq,
+        Instr::Binary(BinaryOp::I16x8Ne) => we::Instruction::I16x8Ne,
+        Instr::Binary(BinaryOp::I16x8LtS) => we::Instruction::I16x8LtS,
+        Instr::Binary(BinaryOp::I16x8LtU) => we::Instruction::I16x8LtU,
+        Instr::Binary(BinaryOp::I16x8GtS) => we::Instruction::I16x8GtS,
+        Instr::Binary(BinaryOp::I16x8GtU) => we::Instruction::I16x8GtU,
+        Instr::Binary(BinaryOp::I16x8LeS) => we::Instruction::I16x8LeS,
+        Instr::Binary(BinaryOp::I16x8LeU) => we::Instruction::I16x8LeU,
+        Instr::Binary(BinaryOp::I16x8GeS) => we::Instruction::I16x8GeS,
+        Instr::Binary(BinaryOp::I16x8GeU) => we::Instruction::I16x8GeU,
+        Instr::Binary(BinaryOp::I32x4Eq) => we::Instruction::I32x4Eq,
+        Instr::Binary(BinaryOp::I32x4Ne) => we::Instruction::I32x4Ne,
+        Instr::Binary(BinaryOp::I32x4LtS) => we::Instruction::I32x4LtS,
+        Instr::Binary(BinaryOp::I32x4LtU) => we::Instruction::I32x4LtU,
+        Instr::Binary(BinaryOp::I32x4GtS) => we::Instruction::I32x4GtS,
+        Instr::Binary(BinaryOp::I32x4GtU) => we::Instruction::I32x4GtU,
+        Instr::Binary(BinaryOp::I32x4LeS) => we::Instruction::I32x4LeS,
+        Instr::Binary(BinaryOp::I32x4LeU) => we::Instruction::I32x4LeU,
+        Instr::Binary(BinaryOp::I32x4GeS) => we::Instruction::I32x4GeS,
+        Instr::Binary(BinaryOp::I32x4GeU) => we::Instruction::I32x4GeU,
+        Instr::Binary(BinaryOp::I64x2Eq) => we::Instruction::I64x2Eq,
+        Instr::Binary(BinaryOp::I64x2Ne) => we::Instruction::I64x2Ne,
+        Instr::Binary(BinaryOp::I64x2LtS) => we::Instruction::I64x2LtS,
+        Instr::Binary(BinaryOp::I64x2GtS) => we::Instruction::I64x2GtS,
+        Instr::Binary(BinaryOp::I64x2LeS) => we::Instruction::I64x2LeS,
+        Instr::Binary(BinaryOp::I64x2GeS) => we::Instruction::I64x2GeS,
+        Instr::Binary(BinaryOp::F32x4Eq) => we::Instruction::F32x4Eq,
+        Instr::Binary(BinaryOp::F32x4Ne) => we::Instruction::F32x4Ne,
+        Instr::Binary(BinaryOp::F32x4Lt) => we::Instruction::F32x4Lt,
+        Instr::Binary(BinaryOp::F32x4Gt) => we::Instruction::F32x4Gt,
+        Instr::Binary(BinaryOp::F32x4Le) => we::Instruction::F32x4Le,
+        Instr::Binary(BinaryOp::F32x4Ge) => we::Instruction::F32x4Ge,
+        Instr::Binary(BinaryOp::F64x2Eq) => we::Instruction::F64x2Eq,
+        Instr::Binary(BinaryOp::F64x2Ne) => we::Instruction::F64x2Ne,
+        Instr::Binary(BinaryOp::F64x2Lt) => we::Instruction::F64x2Lt,
+        Instr::Binary(BinaryOp::F64x2Gt) => we::Instruction::F64x2Gt,
+        Instr::Binary(BinaryOp::F64x2Le) => we::Instruction::F64x2Le,
+        Instr::Binary(BinaryOp::F64x2Ge) => we::Instruction::F64x2Ge,
+        Instr::Binary(BinaryOp::V128And) => we::Instruction::V128And,
+        Instr::Binary(BinaryOp::V128AndNot) => we::Instruction::V128AndNot,
+        Instr::Binary(BinaryOp::V128Or) => we::Instruction::V128Or,
+        Instr::Binary(BinaryOp::V128Xor) => we::Instruction::V128Xor,
+        Instr::Binary(BinaryOp::I8x16Swizzle) => we::Instruction::I8x16Swizzle,
+        Instr::Binary(BinaryOp::I8x16NarrowI16x8S) => we::Instruction::I8x16NarrowI16x8S,
+        Instr::Binary(BinaryOp::I8x16NarrowI16x8U) => we::Instruction::I8x16NarrowI16x8U,
+        Instr::Binary(BinaryOp::I8x16Add) => we::Instruction::I8x16Add,
+        Instr::Binary(BinaryOp::I8x16AddSatS) => we::Instruction::I8x16AddSatS,
+        Instr::Binary(BinaryOp::I8x16AddSatU) => we::Instruction::I8x16AddSatU,
+        Instr::Binary(BinaryOp::I8x16Sub) => we::Instruction::I8x16Sub,
+        Instr::Binary(BinaryOp::I8x16SubSatS) => we::Instruction::I8x16SubSatS,
+        Instr::Binary(BinaryOp::I8x16SubSatU) => we::Instruction::I8x16SubSatU,
+        Instr::Binary(BinaryOp::I8x16MinS) => we::Instruction::I8x16MinS,
+        Instr::Binary(BinaryOp::I8x16MinU) => we::Instruction::I8x16MinU,
+        Instr::Binary(BinaryOp::I8x16MaxS) => we::Instruction::I8x16MaxS,
+        Instr::Binary(BinaryOp::I8x16MaxU) => we::Instruction::I8x16MaxU,
+        Instr::Binary(BinaryOp::I8x16AvgrU) => we::Instruction::I8x16AvgrU,
+        Instr::Binary(BinaryOp::I16x8Q15MulrSatS) => we::Instruction::I16x8Q15MulrSatS,
+        Instr::Binary(BinaryOp::I16x8NarrowI32x4S) => we::Instruction::I16x8NarrowI32x4S,
+        Instr::Binary(BinaryOp::I16x8NarrowI32x4U) => we::Instruction::I16x8NarrowI32x4U,
+        Instr::Binary(BinaryOp::I16x8Add) => we::Instruction::I16x8Add,
+        Instr::Binary(BinaryOp::I16x8AddSatS) => we::Instruction::I16x8AddSatS,
+        Instr::Binary(BinaryOp::I16x8AddSatU) => we::Instruction::I16x8AddSatU,
+        Instr::Binary(BinaryOp::I16x8Sub) => we::Instruction::I16x8Sub,
+        Instr::Binary(BinaryOp::I16x8SubSatS) => we::Instruction::I16x8SubSatS,
+        Instr::Binary(BinaryOp::I16x8SubSatU) => we::Instruction::I16x8SubSatU,
+        Instr::Binary(BinaryOp::I16x8Mul) => we::Instruction::I16x8Mul,
+        Instr::Binary(BinaryOp::I16x8MinS) => we::Instruction::I16x8MinS,
+        Instr::Binary(BinaryOp::I16x8MinU) => we::Instruction::I16x8MinU,
+        Instr::Binary(BinaryOp::I16x8MaxS) => we::Instruction::I16x8MaxS,
+        Instr::Binary(BinaryOp::I16x8MaxU) => we::Instruction::I16x8MaxU,
+        Instr::Binary(BinaryOp::I16x8AvgrU) => we::Instruction::I16x8AvgrU,
+        Instr::Binary(BinaryOp::I16x8ExtMulLowI8x16S) => we::Instruction::I16x8ExtMulLowI8x16S,
+        Instr::Binary(BinaryOp::I16x8ExtMulHighI8x16S) => we::Instruction::I16x8ExtMulHighI8x16S,
+        Instr::Binary(BinaryOp::I16x8ExtMulLowI8x16U) => we::Instruction::I16x8ExtMulLowI8x16U,
+        Instr::Binary(BinaryOp::I16x8ExtMulHighI8x16U) => we::Instruction::I16x8ExtMulHighI8x16U,
+        Instr::Binary(BinaryOp::I32x4Add) => we::Instruction::I32x4Add,
+        Instr::Binary(BinaryOp::I32x4Sub) => we::Instruction::I32x4Sub,
+        Instr::Binary(BinaryOp::I32x4Mul) => we::Instruction::I32x4Mul,
+        Instr::Binary(BinaryOp::I32x4MinS) => we::Instruction::I32x4MinS,
+        Instr::Binary(BinaryOp::I32x4MinU) => we::Instruction::I32x4MinU,
+        Instr::Binary(BinaryOp::I32x4MaxS) => we::Instruction::I32x4MaxS,
+        Instr::Binary(BinaryOp::I32x4MaxU) => we::Instruction::I32x4MaxU,
+        Instr::Binary(BinaryOp::I32x4DotI16x8S) => we::Instruction::I32x4DotI16x8S,
+        Instr::Binary(BinaryOp::I32x4ExtMulLowI16x8S) => we::Instruction::I32x4ExtMulLowI16x8S,
+        Instr::Binary(BinaryOp::I32x4ExtMulHighI16x8S) => we::Instruction::I32x4ExtMulHighI16x8S,
+        Instr::Binary(BinaryOp::I32x4ExtMulLowI16x8U) => we::Instruction::I32x4ExtMulLowI16x8U,
+        Instr::Binary(BinaryOp::I32x4ExtMulHighI16x8U) => we::Instruction::I32x4ExtMulHighI16x8U,
+        Instr::Binary(BinaryOp::I64x2Add) => we::Instruction::I64x2Add,
+        Instr::Binary(BinaryOp::I64x2Sub) => we::Instruction::I64x2Sub,
+        Instr::Binary(BinaryOp::I64x2Mul) => we::Instruction::I64x2Mul,
+        Instr::Binary(BinaryOp::I64x2ExtMulLowI32x4S) => we::Instruction::I64x2ExtMulLowI32x4S,
+        Instr::Binary(BinaryOp::I64x2ExtMulHighI32x4S) => we::Instruction::I64x2ExtMulHighI32x4S,
+        Instr::Binary(BinaryOp::I64x2ExtMulLowI32x4U) => we::Instruction::I64x2ExtMulLowI32x4U,
+        Instr::Binary(BinaryOp::I64x2ExtMulHighI32x4U) => we::Instruction::I64x2ExtMulHighI32x4U,
+        Instr::Binary(BinaryOp::F32x4Add) => we::Instruction::F32x4Add,
+        Instr::Binary(BinaryOp::F32x4Sub) => we::Instruction::F32x4Sub,
+        Instr::Binary(BinaryOp::F32x4Mul) => we::Instruction::F32x4Mul,
+        Instr::Binary(BinaryOp::F32x4Div) => we::Instruction::F32x4Div,
+        Instr::Binary(BinaryOp::F32x4Min) => we::Instruction::F32x4Min,
+        Instr::Binary(BinaryOp::F32x4Max) => we::Instruction::F32x4Max,
+        Instr::Binary(BinaryOp::F32x4PMin) => we::Instruction::F32x4PMin,
+        Instr::Binary(BinaryOp::F32x4PMax) => we::Instruction::F32x4PMax,
+        Instr::Binary(BinaryOp::F64x2Add) => we::Instruction::F64x2Add,
+        Instr::Binary(BinaryOp::F64x2Sub) => we::Instruction::F64x2Sub,
+        Instr::Binary(BinaryOp::F64x2Mul) => we::Instruction::F64x2Mul,
+        Instr::Binary(BinaryOp::F64x2Div) => we::Instruction::F64x2Div,
+        Instr::Binary(BinaryOp::F64x2Min) => we::Instruction::F64x2Min,
+        Instr::Binary(BinaryOp::F64x2Max) => we::Instruction::F64x2Max,
+        Instr::Binary(BinaryOp::F64x2PMin) => we::Instruction::F64x2PMin,
+        Instr::Binary(BinaryOp::F64x2PMax) => we::Instruction::F64x2PMax,
+        Instr::Binary(BinaryOp::I8x16Shl) => we::Instruction::I8x16Shl,
+        Instr::Binary(BinaryOp::I8x16ShrS) => we::Instruction::I8x16ShrS,
+        Instr::Binary(BinaryOp::I8x16ShrU) => we::Instruction::I8x16ShrU,
+        Instr::Binary(BinaryOp::I16x8Shl) => we::Instruction::I16x8Shl,
+        Instr::Binary(BinaryOp::I16x8ShrS) => we::Instruction::I16x8ShrS,
+        Instr::Binary(BinaryOp::I16x8ShrU) => we::Instruction::I16x8ShrU,
+        Instr::Binary(BinaryOp::I32x4Shl) => we::Instruction::I32x4Shl,
+        Instr::Binary(BinaryOp::I32x4ShrS) => we::Instruction::I32x4ShrS,
+        Instr::Binary(BinaryOp::I32x4ShrU) => we::Instruction::I32x4ShrU,
+        Instr::Binary(BinaryOp::I64x2Shl) => we::Instruction::I64x2Shl,
+        Instr::Binary(BinaryOp::I64x2ShrS) => we::Instruction::I64x2ShrS,
+        Instr::Binary(BinaryOp::I64x2ShrU) => we::Instruction::I64x2ShrU,
     })
 }
 
@@ -645,6 +1258,7 @@ fn encode_names(
     // lazily initialize on access. Then, write them only if they are not `None`.
     let mut functions_subsection: Option<we::NameMap> = None;
     let mut locals_subsection: Option<we::IndirectNameMap> = None;
+    let mut labels_subsection: Option<we::IndirectNameMap> = None;
     for (hl_function_idx, function) in module.functions() {
         let ll_function_idx = state.map_function_idx(hl_function_idx)?.to_u32();
 
@@ -655,11 +1269,20 @@ fn encode_names(
         }
 
         let mut local_names: Option<we::NameMap> = None;
-        for (local_idx, local) in function.param_or_locals() {
-            if let Some(name) = local.name() {
+        for (param_idx, param) in function.params() {
+            if let Some(name) = param.name {
                 local_names
                     .get_or_insert_with(Default::default)
-                    .append(local_idx.to_u32(), name);
+                    .append(param_idx.to_u32(), name);
+            }
+        }
+        if let Some(code) = code_if_parsed(function) {
+            for (local_idx, local) in code.locals.iter().enumerate() {
+                if let Some(name) = &local.name {
+                    local_names
+                        .get_or_insert_with(Default::default)
+                        .append((function.param_count() + local_idx) as u32, name);
+                }
             }
         }
         if let Some(local_names) = local_names {
@@ -667,8 +1290,46 @@ fn encode_names(
                 .get_or_insert_with(Default::default)
                 .append(ll_function_idx, &local_names);
         }
+
+        let mut label_names: Option<we::NameMap> = None;
+        if let Some(code) = code_if_parsed(function) {
+            for (label_idx, name) in code.label_names.iter().enumerate() {
+                if let Some(name) = name {
+                    label_names
+                        .get_or_insert_with(Default::default)
+                        .append(label_idx as u32, name);
+                }
+            }
+        }
+        if let Some(label_names) = label_names {
+            labels_subsection
+                .get_or_insert_with(Default::default)
+                .append(ll_function_idx, &label_names);
+        }
     }
 
+    // Names of the other index spaces (extended name section proposal), which all map a
+    // (low-level) index to a name.
+    macro_rules! name_map {
+        ($elem_iter: ident, $map_idx: expr) => {{
+            let mut name_map: Option<we::NameMap> = None;
+            for (hl_idx, elem) in module.$elem_iter() {
+                if let Some(name) = &elem.name {
+                    name_map
+                        .get_or_insert_with(Default::default)
+                        .append(($map_idx)(hl_idx)?, name);
+                }
+            }
+            name_map
+        }}
+    }
+    let tables_subsection = name_map!(tables, |idx| state.map_table_idx(idx).map(Idx::to_u32));
+    let memories_subsection = name_map!(memories, |idx| state.map_memory_idx(idx).map(Idx::to_u32));
+    let globals_subsection = name_map!(globals, |idx| state.map_global_idx(idx).map(Idx::to_u32));
+    // Element and data segments are encoded in order, so their indices do not change.
+    let elements_subsection = name_map!(elements, |idx: Idx<Element>| Ok::<_, EncodeError>(idx.to_u32()));
+    let data_subsection = name_map!(data, |idx: Idx<Data>| Ok::<_, EncodeError>(idx.to_u32()));
+
     let mut name_section: Option<we::NameSection> = None;
     if let Some(module_name) = &module.name {
         name_section
@@ -685,10 +1346,48 @@ fn encode_names(
             .get_or_insert_with(Default::default)
             .locals(locals_subsection);
     }
+    if let Some(labels_subsection) = &labels_subsection {
+        name_section
+            .get_or_insert_with(Default::default)
+            .labels(labels_subsection);
+    }
+    if let Some(tables_subsection) = &tables_subsection {
+        name_section
+            .get_or_insert_with(Default::default)
+            .tables(tables_subsection);
+    }
+    if let Some(memories_subsection) = &memories_subsection {
+        name_section
+            .get_or_insert_with(Default::default)
+            .memories(memories_subsection);
+    }
+    if let Some(globals_subsection) = &globals_subsection {
+        name_section
+            .get_or_insert_with(Default::default)
+            .globals(globals_subsection);
+    }
+    if let Some(elements_subsection) = &elements_subsection {
+        name_section
+            .get_or_insert_with(Default::default)
+            .elements(elements_subsection);
+    }
+    if let Some(data_subsection) = &data_subsection {
+        name_section
+            .get_or_insert_with(Default::default)
+            .data(data_subsection);
+    }
 
     Ok(name_section)
 }
 
+fn encode_memarg(memarg: Memarg, state: &EncodeState) -> Result<we::MemArg, EncodeError> {
+    Ok(we::MemArg {
+        offset: memarg.offset,
+        align: memarg.alignment_exp.into(),
+        memory_index: state.map_memory_idx(memarg.memory)?.to_u32(),
+    })
+}
+
 fn encode_block_type(func_or_block_ty: FunctionType, state: &EncodeState) -> we::BlockType {
     match (func_or_block_ty.inputs(), func_or_block_ty.results()) {
         // Prefer the more compact inline encoding for Wasm MVP block types.
@@ -713,28 +1412,28 @@ impl From<GlobalType> for we::GlobalType {
     }
 }
 
-impl From<Limits> for we::TableType {
-    fn from(limits: Limits) -> Self {
-        Self {
-            element_type: we::ValType::FuncRef,
-            minimum: limits.initial_size,
-            maximum: limits.max_size,
-        }
+impl TryFrom<&Table> for we::TableType {
+    type Error = EncodeError;
+
+    fn try_from(table: &Table) -> Result<Self, Self::Error> {
+        // Only memories can be 64-bit, table limits must fit in u32.
+        let limit_u32 = |limit: u64| u32::try_from(limit)
+            .map_err(|_| EncodeError::message(format!("table limit {limit} does not fit in u32")));
+        Ok(Self {
+            element_type: table.elem_type.into(),
+            minimum: limit_u32(table.limits.initial_size)?,
+            maximum: table.limits.max_size.map(limit_u32).transpose()?,
+        })
     }
 }
 
-impl From<Limits> for we::MemoryType {
-    fn from(limits: Limits) -> Self {
+impl From<&Memory> for we::MemoryType {
+    fn from(memory: &Memory) -> Self {
         Self {
-            minimum: limits
-                .initial_size
-                .try_into()
-                .expect("u32 to u64 should always succeed"),
-            maximum: limits
-                .max_size
-                .map(|u32| u32.try_into().expect("u32 to u64 should always succeed")),
-            memory64: false,
-            shared: false,
+            minimum: memory.limits.initial_size,
+            maximum: memory.limits.max_size,
+            memory64: memory.memory64,
+            shared: memory.shared,
         }
     }
 }
@@ -747,19 +1446,11 @@ impl From<ValType> for we::ValType {
             I64 => we::ValType::I64,
             F32 => we::ValType::F32,
             F64 => we::ValType::F64,
+            FuncRef => we::ValType::FuncRef,
+            ExternRef => we::ValType::ExternRef,
+            V128 => we::ValType::V128,
         }
     }
 }
 
-impl From<Memarg> for we::MemArg {
-    fn from(hl_memarg: Memarg) -> Self {
-        Self {
-            offset: hl_memarg
-                .offset
-                .try_into()
-                .expect("u32 to u64 should always succeed"),
-            align: hl_memarg.alignment_exp.into(),
-            memory_index: 0,
-        }
-    }
-}
+
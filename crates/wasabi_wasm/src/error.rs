@@ -40,6 +40,14 @@ pub enum ParseIssue {
         extension: WasmExtension,
     },
 
+    #[error("error parsing WebAssembly text format at line {}, column {}: {}", line, column, message)]
+    Text {
+        // Both 1-based, as in editors.
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
@@ -64,6 +72,8 @@ impl ParseIssue {
             ParseIssue::Message { offset, .. } => Some(*offset),
             ParseIssue::Index { offset, .. } => Some(*offset),
             ParseIssue::Unsupported { offset, .. } => Some(*offset),
+            // Offsets are into the binary, which does not exist (yet) when parsing the text format.
+            ParseIssue::Text { .. } => None,
             ParseIssue::Io(_) => None,
         }
     }
@@ -77,6 +87,9 @@ impl ParseError {
     pub fn offset(&self) -> Option<usize> {
         self.0.offset()
     }
+    pub(crate) fn into_issue(self) -> Box<ParseIssue> {
+        self.0
+    }
 }
 
 // Allow conversion of everything that can be converted into a `ParseIssue`
@@ -108,6 +121,9 @@ pub enum EncodeErrorInner {
         index_space: &'static str,
     },
 
+    #[error("could not parse function body on demand: {}", .0)]
+    LazyBody(#[from] ParseError),
+
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
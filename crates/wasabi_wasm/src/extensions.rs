@@ -14,6 +14,7 @@ pub enum WasmExtension {
     ThreadsAtomics,
     RelaxedSimd,
     Memory64,
+    ExtendedConst,
     ExceptionHandling,
     ExtendedNameSection,
     TailCalls,
@@ -36,6 +37,7 @@ impl WasmExtension {
             ThreadsAtomics => "threads and atomics",
             RelaxedSimd => "relaxed SIMD",
             Memory64 => "64-bit memory",
+            ExtendedConst => "extended constant expressions",
             ExceptionHandling => "exception handling",
             ExtendedNameSection => "extended name section",
             TailCalls => "tail calls",
@@ -59,6 +61,7 @@ impl WasmExtension {
             ThreadsAtomics => r"https://github.com/WebAssembly/threads",
             RelaxedSimd => r"https://github.com/WebAssembly/relaxed-simd",
             Memory64 => r"https://github.com/WebAssembly/memory64",
+            ExtendedConst => r"https://github.com/WebAssembly/extended-const",
             ExceptionHandling => r"https://github.com/WebAssembly/exception-handling",
             ExtendedNameSection => r"https://github.com/WebAssembly/extended-name-section",
             TailCalls => r"https://github.com/WebAssembly/tail-call",
@@ -209,6 +209,9 @@ const fn val_type_to_goedel_number(val_type: ValType) -> usize {
         ValType::I64 => 1,
         ValType::F32 => 2,
         ValType::F64 => 3,
+        ValType::FuncRef => 4,
+        ValType::ExternRef => 5,
+        ValType::V128 => 6,
     }
 }
 
@@ -218,12 +221,15 @@ const fn goedel_number_to_val_type(goedel_number: usize) -> Option<ValType> {
         1 => Some(ValType::I64),
         2 => Some(ValType::F32),
         3 => Some(ValType::F64),
+        4 => Some(ValType::FuncRef),
+        5 => Some(ValType::ExternRef),
+        6 => Some(ValType::V128),
         _ => None,
     }
 }
 
 // Determined by the number of variants of `ValType`.
-const VAL_TYPE_MAX_GOEDEL_NUMBER: usize = 3;
+const VAL_TYPE_MAX_GOEDEL_NUMBER: usize = 6;
 
 #[allow(unused)]
 const fn val_type_seq_max_goedel_number(max_seq_len: u32) -> usize {
@@ -239,12 +245,12 @@ const fn val_type_seq_max_goedel_number(max_seq_len: u32) -> usize {
 #[test]
 fn test_goedel_number_constants() {
     assert_eq!(val_type_to_goedel_number(ValType::I32), 0);
-    assert_eq!(val_type_to_goedel_number(ValType::F64), 3);
+    assert_eq!(val_type_to_goedel_number(ValType::V128), 6);
     assert_eq!(val_type_seq_max_goedel_number(0), 0);
-    assert_eq!(val_type_seq_max_goedel_number(1), 4);
-    assert_eq!(val_type_seq_max_goedel_number(2), 20);
-    assert_eq!(val_type_seq_max_goedel_number(3), 84);
-    assert_eq!(val_type_seq_max_goedel_number(4), 340);
+    assert_eq!(val_type_seq_max_goedel_number(1), 7);
+    assert_eq!(val_type_seq_max_goedel_number(2), 56);
+    assert_eq!(val_type_seq_max_goedel_number(3), 399);
+    assert_eq!(val_type_seq_max_goedel_number(4), 2800);
 }
 
 fn val_type_seq_to_goedel_number(seq: impl IntoIterator<Item=ValType>) -> Option<usize> {
@@ -262,7 +268,7 @@ fn val_type_seq_to_goedel_number(seq: impl IntoIterator<Item=ValType>) -> Option
 fn test_val_type_seq_to_goedel_number() {
     assert_eq!(val_type_seq_to_goedel_number([]), Some(0));
     assert_eq!(val_type_seq_to_goedel_number([ValType::I32]), Some(1));
-    assert_eq!(val_type_seq_to_goedel_number([ValType::I32, ValType::I32]), Some(5));
+    assert_eq!(val_type_seq_to_goedel_number([ValType::I32, ValType::I32]), Some(8));
 }
 
 // Reverse direction: Gödel number to slice.
@@ -318,7 +324,8 @@ fn goedel_number_to_val_type_seq(mut goedel_number: usize) -> Vec<ValType> {
 fn test_goedel_number_to_val_type_seq() {
     assert_eq!(goedel_number_to_val_type_seq(0), vec![]);
     assert_eq!(goedel_number_to_val_type_seq(1), vec![ValType::I32]);
-    assert_eq!(goedel_number_to_val_type_seq(5), vec![ValType::I32, ValType::I32]);
+    assert_eq!(goedel_number_to_val_type_seq(7), vec![ValType::V128]);
+    assert_eq!(goedel_number_to_val_type_seq(8), vec![ValType::I32, ValType::I32]);
 }
 
 #[test]
@@ -9,9 +9,17 @@ pub use crate::error::*;
 
 pub mod types;
 
+// Validation of whole modules beyond type checking, see `Module::validate()`.
+pub mod validate;
+
+// Alternative representation of function bodies with nested blocks.
+pub mod nested;
+
 mod encode;
 mod extensions;
+pub use crate::extensions::WasmExtension;
 mod parse;
+mod print;
 
 #[cfg(test)]
 mod tests;
@@ -25,6 +33,5 @@ static GLOBAL: MiMalloc = MiMalloc;
 
 /*
  TODO WHEN CONTINUING
- - support multi-table, multi-memory because they are anyway pretty much supported (and make for less special cases)
- - make AST blocks nested, remove end/else opcodes
+ - make AST blocks nested by default (see `nested`), remove end/else opcodes
 */
@@ -0,0 +1,286 @@
+//! Alternative, nested representation of function bodies, where blocks contain their instructions.
+//!
+//! In the flat representation (`Vec<ast::Instr>`, as in the binary format), the extent of blocks
+//! is only given implicitly by matching `Block`/`Loop`/`If`/`Try` with `Else`/`Catch`/`End`, so
+//! passes that need to know the enclosing blocks must track them, e.g., with a stack of blocks.
+//! Here, blocks directly contain their instructions as a `Body`, and `Else`, `End`, etc. are gone.
+//!
+//! Each body ends with an optional `Terminator`, i.e., an instruction that unconditionally
+//! transfers control somewhere else (e.g., `br` or `return`). Because no instructions can follow
+//! it, dead code after a terminator cannot be represented and is dropped when converting from the
+//! flat representation. Otherwise, the conversion to and from the flat representation is lossless.
+//!
+//! Labels of branches are still relative to the enclosing blocks, as in the flat representation.
+
+use crate::ast;
+use crate::FunctionType;
+use crate::Idx;
+use crate::Label;
+
+/// Instructions of a block (or the whole function body), without the final `End` or `Else`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Body {
+    pub instrs: Vec<Instr>,
+    pub terminator: Option<Terminator>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Instr {
+    Block(FunctionType, Body),
+    Loop(FunctionType, Body),
+    /// The else branch is `None` if there is no `Else` instruction in the flat representation,
+    /// which is different from an empty else branch.
+    If(FunctionType, Body, Option<Body>),
+    /// Exception handling extension: try block with `Catch` handlers and an optional `CatchAll`
+    /// handler.
+    Try {
+        type_: FunctionType,
+        body: Body,
+        catches: Vec<(Idx<ast::Tag>, Body)>,
+        catch_all: Option<Body>,
+    },
+    /// Exception handling extension: try block, which forwards exceptions to the block with the
+    /// given label (relative to the try block, i.e., the `Delegate` instruction is outside of it).
+    TryDelegate(FunctionType, Body, Label),
+    /// Any other instruction, which does neither begin or end a block, nor is a terminator.
+    Simple(ast::Instr),
+}
+
+/// Instructions that transfer control unconditionally, such that following code is dead.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Terminator {
+    Unreachable,
+    Br(Label),
+    BrTable { table: Box<[Label]>, default: Label },
+    Return,
+    // Tail call extension.
+    ReturnCall(Idx<ast::Function>),
+    ReturnCallIndirect(FunctionType, Idx<ast::Table>),
+    // Exception handling extension.
+    Throw(Idx<ast::Tag>),
+    Rethrow(Label),
+}
+
+/// Error when converting an unbalanced flat instruction sequence, e.g., with a missing `End`.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid block nesting at instruction #{}: {}", .instruction_idx.to_usize(), .message)]
+pub struct NestingError {
+    pub message: &'static str,
+    pub instruction_idx: Idx<ast::Instr>,
+}
+
+impl Body {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Converts a flat function body (including the final `End`) to the nested representation.
+    pub fn from_flat(instrs: &[ast::Instr]) -> Result<Self, NestingError> {
+        let mut parser = FlatParser { instrs, position: 0 };
+        let body = match parser.body()? {
+            (body, Some(ast::Instr::End)) => body,
+            (_, _) => return Err(parser.error("function body must end with `end`")),
+        };
+        if parser.position != instrs.len() {
+            return Err(parser.error("instructions after the final `end` of the function body"));
+        }
+        Ok(body)
+    }
+
+    /// Converts to a flat function body, including the final `End`.
+    pub fn to_flat(&self) -> Vec<ast::Instr> {
+        let mut instrs = Vec::new();
+        self.append_flat(&mut instrs);
+        instrs.push(ast::Instr::End);
+        instrs
+    }
+
+    // Does not add an `End`, since the caller decides how the body is closed.
+    fn append_flat(&self, instrs: &mut Vec<ast::Instr>) {
+        use ast::Instr as Flat;
+        for instr in &self.instrs {
+            match instr {
+                Instr::Block(type_, body) => {
+                    instrs.push(Flat::Block(*type_));
+                    body.append_flat(instrs);
+                    instrs.push(Flat::End);
+                }
+                Instr::Loop(type_, body) => {
+                    instrs.push(Flat::Loop(*type_));
+                    body.append_flat(instrs);
+                    instrs.push(Flat::End);
+                }
+                Instr::If(type_, then, else_) => {
+                    instrs.push(Flat::If(*type_));
+                    then.append_flat(instrs);
+                    if let Some(else_) = else_ {
+                        instrs.push(Flat::Else);
+                        else_.append_flat(instrs);
+                    }
+                    instrs.push(Flat::End);
+                }
+                Instr::Try { type_, body, catches, catch_all } => {
+                    instrs.push(Flat::Try(*type_));
+                    body.append_flat(instrs);
+                    for (tag, handler) in catches {
+                        instrs.push(Flat::Catch(*tag));
+                        handler.append_flat(instrs);
+                    }
+                    if let Some(handler) = catch_all {
+                        instrs.push(Flat::CatchAll);
+                        handler.append_flat(instrs);
+                    }
+                    instrs.push(Flat::End);
+                }
+                Instr::TryDelegate(type_, body, label) => {
+                    instrs.push(Flat::Try(*type_));
+                    body.append_flat(instrs);
+                    instrs.push(Flat::Delegate(*label));
+                }
+                Instr::Simple(instr) => instrs.push(instr.clone()),
+            }
+        }
+        if let Some(terminator) = &self.terminator {
+            instrs.push(terminator.clone().into());
+        }
+    }
+}
+
+impl From<Terminator> for ast::Instr {
+    fn from(terminator: Terminator) -> Self {
+        match terminator {
+            Terminator::Unreachable => ast::Instr::Unreachable,
+            Terminator::Br(label) => ast::Instr::Br(label),
+            Terminator::BrTable { table, default } => ast::Instr::BrTable { table, default },
+            Terminator::Return => ast::Instr::Return,
+            Terminator::ReturnCall(function) => ast::Instr::ReturnCall(function),
+            Terminator::ReturnCallIndirect(type_, table) => ast::Instr::ReturnCallIndirect(type_, table),
+            Terminator::Throw(tag) => ast::Instr::Throw(tag),
+            Terminator::Rethrow(label) => ast::Instr::Rethrow(label),
+        }
+    }
+}
+
+impl TryFrom<ast::Instr> for Terminator {
+    type Error = ast::Instr;
+
+    /// Returns the instruction unchanged as the error, if it is not a terminator.
+    fn try_from(instr: ast::Instr) -> Result<Self, Self::Error> {
+        Ok(match instr {
+            ast::Instr::Unreachable => Terminator::Unreachable,
+            ast::Instr::Br(label) => Terminator::Br(label),
+            ast::Instr::BrTable { table, default } => Terminator::BrTable { table, default },
+            ast::Instr::Return => Terminator::Return,
+            ast::Instr::ReturnCall(function) => Terminator::ReturnCall(function),
+            ast::Instr::ReturnCallIndirect(type_, table) => Terminator::ReturnCallIndirect(type_, table),
+            ast::Instr::Throw(tag) => Terminator::Throw(tag),
+            ast::Instr::Rethrow(label) => Terminator::Rethrow(label),
+            instr => return Err(instr),
+        })
+    }
+}
+
+/// Recursive descent over the flat instructions, one call of `body()` per (nested) block.
+struct FlatParser<'a> {
+    instrs: &'a [ast::Instr],
+    position: usize,
+}
+
+impl<'a> FlatParser<'a> {
+    fn error(&self, message: &'static str) -> NestingError {
+        NestingError {
+            message,
+            // Point to the last consumed instruction, which caused the error.
+            instruction_idx: self.position.saturating_sub(1).into(),
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a ast::Instr> {
+        let instr = self.instrs.get(self.position)?;
+        self.position += 1;
+        Some(instr)
+    }
+
+    /// Parses instructions until (and including) the instruction that closes the current block,
+    /// i.e., `End`, `Else`, `Catch`, `CatchAll`, or `Delegate`, which is returned alongside.
+    /// Returns `None` as the closing instruction if the instructions end before.
+    fn body(&mut self) -> Result<(Body, Option<&'a ast::Instr>), NestingError> {
+        use ast::Instr as Flat;
+        let mut body = Body::new();
+        while let Some(instr) = self.next() {
+            let nested_instr = match instr {
+                Flat::End | Flat::Else | Flat::Catch(_) | Flat::CatchAll | Flat::Delegate(_) => {
+                    return Ok((body, Some(instr)))
+                }
+                Flat::Block(type_) => Instr::Block(*type_, self.block_until_end()?),
+                Flat::Loop(type_) => Instr::Loop(*type_, self.block_until_end()?),
+                Flat::If(type_) => match self.body()? {
+                    (then, Some(Flat::End)) => Instr::If(*type_, then, None),
+                    (then, Some(Flat::Else)) => Instr::If(*type_, then, Some(self.block_until_end()?)),
+                    (_, _) => return Err(self.error("`if` must be closed by `else` or `end`")),
+                },
+                Flat::Try(type_) => self.try_(*type_)?,
+                instr => match Terminator::try_from(instr.clone()) {
+                    Ok(terminator) => {
+                        body.terminator = Some(terminator);
+                        return Ok((body, self.skip_dead_code()));
+                    }
+                    Err(instr) => Instr::Simple(instr),
+                },
+            };
+            body.instrs.push(nested_instr);
+        }
+        Ok((body, None))
+    }
+
+    fn block_until_end(&mut self) -> Result<Body, NestingError> {
+        match self.body()? {
+            (body, Some(ast::Instr::End)) => Ok(body),
+            (_, _) => Err(self.error("block must be closed by `end`")),
+        }
+    }
+
+    fn try_(&mut self, type_: FunctionType) -> Result<Instr, NestingError> {
+        use ast::Instr as Flat;
+        let (body, mut closing) = self.body()?;
+        if let Some(Flat::Delegate(label)) = closing {
+            return Ok(Instr::TryDelegate(type_, body, *label));
+        }
+
+        let mut catches = Vec::new();
+        while let Some(Flat::Catch(tag)) = closing {
+            let (handler, next_closing) = self.body()?;
+            catches.push((*tag, handler));
+            closing = next_closing;
+        }
+        let mut catch_all = None;
+        if let Some(Flat::CatchAll) = closing {
+            let (handler, next_closing) = self.body()?;
+            catch_all = Some(handler);
+            closing = next_closing;
+        }
+
+        match closing {
+            Some(Flat::End) => Ok(Instr::Try { type_, body, catches, catch_all }),
+            _ => Err(self.error("`try` must be closed by `catch`, `catch_all`, `end`, or `delegate`")),
+        }
+    }
+
+    /// Skips over the instructions after a terminator and returns the instruction that closes the
+    /// current block (see `body()`), ignoring the closing instructions of nested dead blocks.
+    fn skip_dead_code(&mut self) -> Option<&'a ast::Instr> {
+        use ast::Instr as Flat;
+        let mut depth = 0usize;
+        while let Some(instr) = self.next() {
+            match instr {
+                Flat::Block(_) | Flat::Loop(_) | Flat::If(_) | Flat::Try(_) => depth += 1,
+                Flat::End | Flat::Else | Flat::Catch(_) | Flat::CatchAll | Flat::Delegate(_) if depth == 0 => {
+                    return Some(instr)
+                }
+                Flat::End | Flat::Delegate(_) => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+}
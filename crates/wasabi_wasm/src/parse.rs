@@ -1,7 +1,12 @@
 //! Code for parsing the WebAssembly binary format to our AST.
 //! Uses `wasmparser` crate for the actual low-level work.
 
+use std::cell::Cell;
 use std::convert::TryInto;
+use std::io;
+use std::io::Read;
+use std::ops::Range;
+use std::sync::Arc;
 use std::sync::RwLock;
 
 use ordered_float::OrderedFloat;
@@ -13,11 +18,27 @@ use wasmparser as wp;
 use crate::extensions::WasmExtension;
 use crate::*;
 
-// The streaming API of wasmparser is a bit cumbersome, so implement reading
-// from bytes fully resident in memory first.
-// TODO Add a second API from streaming sources, i.e., `io::Read` like here:
-// https://docs.rs/wasmparser/latest/wasmparser/struct.Parser.html#examples
 pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), ParseError> {
+    parse_module_with(bytes, false, false)
+}
+
+/// Like `parse_module`, but only stores the raw function bodies, see `LazyCode`.
+pub fn parse_module_lazy(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), ParseError> {
+    parse_module_with(bytes, true, false)
+}
+
+/// Like `parse_module`, but reads the binary incrementally, see `StreamingInput`.
+pub fn parse_module_from_reader(reader: impl io::Read) -> Result<(Module, Offsets, ParseWarnings), ParseError> {
+    parse_module_with(reader, false, false)
+}
+
+/// Like `parse_module`, but also records the offset of every instruction, see `Offsets::instrs`.
+pub fn parse_module_with_instr_offsets(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), ParseError> {
+    parse_module_with(bytes, false, true)
+}
+
+/// With `lazy_bodies`, instructions are not parsed, so `instr_offsets` is ignored in that case.
+fn parse_module_with(reader: impl io::Read, lazy_bodies: bool, instr_offsets: bool) -> Result<(Module, Offsets, ParseWarnings), ParseError> {
     let mut warnings = Vec::new();
 
     // The final module to return.
@@ -25,18 +46,26 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
 
     // State during module parsing.
     let mut types = Types::none();
-    let mut imported_function_count = 0;
-    let mut current_code_index = 0;
+    let mut imported_function_count = 0u32;
     let mut section_offsets = Vec::with_capacity(16);
     let mut function_offsets = Vec::new();
-    // Put the function bodies in their own vector, such that parallel processing of the
-    // code section doesn't require synchronization on the shared `module` variable.
-    let mut function_bodies = Vec::new();
-    let mut code_entries_count = 0;
+    let mut instr_offsets = if instr_offsets && !lazy_bodies { Some(Vec::new()) } else { None };
     let metadata = RwLock::new(ModuleMetadata::default());
 
-    for payload in wp::Parser::new(0).parse_all(bytes) {
-        match payload? {
+    let mut input = StreamingInput::new(reader);
+    let mut parser = wp::Parser::new(0);
+    loop {
+        let payload = match parser.parse(input.buffered(), input.eof)? {
+            wp::Chunk::NeedMoreData(hint) => {
+                input.read_more(hint)?;
+                continue;
+            }
+            wp::Chunk::Parsed { consumed, payload } => {
+                input.consume(consumed);
+                payload
+            }
+        };
+        match payload {
             wp::Payload::Version { num: _, encoding, range: _ } => {
                 // The version number is checked by wasmparser to always be 1.
                 match encoding {
@@ -57,6 +86,8 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                 for elem in reader.into_iter_with_offsets() {
                     let (offset, wp::Type::Func(type_)) = elem?;
                     let type_ = parse_func_ty(type_, offset)?;
+                    add_val_ty_extensions(type_.inputs(), &metadata);
+                    add_val_ty_extensions(type_.results(), &metadata);
                     types.add(type_);
                 }
             }
@@ -82,25 +113,29 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                                 Vec::new(),
                             ))
                         }
-                        wp::TypeRef::Global(ty) => module.globals.push(
+                        wp::TypeRef::Global(ty) => {
                             // Same issue regarding `import_offset`.
-                            Global::new_imported(parse_global_ty(ty, import_offset)?, import_module, import_name),
-                        ),
-                        wp::TypeRef::Table(ty) => module.tables.push(
+                            let type_ = parse_global_ty(ty, import_offset)?;
+                            add_val_ty_extensions(&[type_.0], &metadata);
+                            module.globals.push(Global::new_imported(type_, import_module, import_name))
+                        }
+                        wp::TypeRef::Table(ty) => {
                             // Same issue regarding `import_offset`.
-                            Table::new_imported(parse_table_ty(ty, import_offset)?, import_module, import_name),
-                        ),
+                            let (elem_type, limits) = parse_table_ty(ty, import_offset)?;
+                            module.tables.push(Table::new_imported(elem_type, limits, import_module, import_name));
+                            add_table_extensions(&module.tables, &metadata);
+                        }
                         wp::TypeRef::Memory(ty) => {
                             // Same issue regarding `import_offset`.
-                            module.memories.push(Memory::new_imported(
-                                parse_memory_ty(ty, import_offset)?,
-                                import_module,
-                                import_name,
-                            ))
+                            let (limits, shared, memory64) = parse_memory_ty(ty);
+                            module.memories.push(Memory::new_imported(limits, shared, memory64, import_module, import_name));
+                            add_memory_extensions(&module.memories, &metadata);
                         }
-                        wp::TypeRef::Tag(_) => {
+                        wp::TypeRef::Tag(ty) => {
                             // Same issue regarding `import_offset`.
-                            Err(ParseIssue::unsupported(import_offset, WasmExtension::ExceptionHandling))?
+                            let type_ = parse_tag_ty(ty, import_offset, &types)?;
+                            add_used_extension(&metadata, WasmExtension::ExceptionHandling);
+                            module.tags.push(Tag::new_imported(type_, import_module, import_name))
                         }
                     }
                 }
@@ -126,10 +161,10 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
 
                 for elem in reader.into_iter_with_offsets() {
                     let (offset, table_ty) = elem?;
-                    let table_ty = parse_table_ty(table_ty, offset)?;
-                    // Fill in the elements of the table later with the element section.
-                    module.tables.push(Table::new(table_ty));
+                    let (elem_type, limits) = parse_table_ty(table_ty, offset)?;
+                    module.tables.push(Table::new(elem_type, limits));
                 }
+                add_table_extensions(&module.tables, &metadata);
             }
             wp::Payload::MemorySection(reader) => {
                 section_offsets.push((SectionId::Memory, reader.range().start));
@@ -137,14 +172,26 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                 let memory_count = reader.count();
                 module.memories.reserve(u32_to_usize(memory_count));
 
-                for elem in reader.into_iter_with_offsets() {
-                    let (offset, memory_ty) = elem?;
-                    let memory_ty = parse_memory_ty(memory_ty, offset)?;
+                for memory_ty in reader {
+                    let (limits, shared, memory64) = parse_memory_ty(memory_ty?);
                     // Fill in the data of the memory later with the data section.
-                    module.memories.push(Memory::new(memory_ty));
+                    module.memories.push(Memory::new(limits, shared, memory64));
+                }
+                add_memory_extensions(&module.memories, &metadata);
+            }
+            wp::Payload::TagSection(reader) => {
+                section_offsets.push((SectionId::Tag, reader.range().start));
+
+                let tag_count = reader.count();
+                module.tags.reserve(u32_to_usize(tag_count));
+
+                for elem in reader.into_iter_with_offsets() {
+                    let (offset, tag_ty) = elem?;
+                    let type_ = parse_tag_ty(tag_ty, offset, &types)?;
+                    add_used_extension(&metadata, WasmExtension::ExceptionHandling);
+                    module.tags.push(Tag::new(type_));
                 }
             }
-            wp::Payload::TagSection(reader) => Err(ParseIssue::unsupported(reader.range().start, WasmExtension::ExceptionHandling))?,
             wp::Payload::GlobalSection(reader) => {
                 section_offsets.push((SectionId::Global, reader.range().start));
 
@@ -154,6 +201,7 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                 for elem in reader.into_iter_with_offsets() {
                     let (offset, global) = elem?;
                     let type_ = parse_global_ty(global.ty, offset)?;
+                    add_val_ty_extensions(&[type_.0], &metadata);
 
                     // Most initialization expressions have just a constant and the end instruction.
                     let mut init = Vec::with_capacity(2);
@@ -162,6 +210,7 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                         // whole global entry, not the initialization expression.
                         init.push(parse_instr(op?, offset, &types, &metadata)?)
                     }
+                    add_const_expr_extensions(&init, &metadata);
 
                     module.globals.push(Global::new(type_, init));
                 }
@@ -208,10 +257,13 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                             .ok_or_else(|| ParseIssue::index(export_offset, index_u32, "global"))?
                             .export
                             .push(name),
-                        ExternalKind::Tag => {
+                        ExternalKind::Tag => module
+                            .tags
+                            .get_mut(index)
                             // Same issue regarding `export_offset`.
-                            Err(ParseIssue::unsupported(export_offset, WasmExtension::ExceptionHandling))?
-                        }
+                            .ok_or_else(|| ParseIssue::index(export_offset, index_u32, "tag"))?
+                            .export
+                            .push(name),
                     };
                 }
             }
@@ -228,27 +280,43 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
 
                 for elem in reader.into_iter_with_offsets() {
                     let (element_offset, element) = elem?;
-                    parse_elem_ty(element.ty, element_offset)?;
+                    let elem_type = parse_elem_ty(element.ty, element_offset)?;
 
                     let items = match element.items {
                         wp::ElementItems::Functions(items_reader) => {
-                            items_reader.into_iter()
+                            ElementItems::Functions(items_reader.into_iter()
                                 .map(|func_idx| func_idx.map(
                                     |func_idx| u32_to_usize(func_idx).into()))
-                                .collect::<Result<Vec<Idx<Function>>, _>>()?
+                                .collect::<Result<Vec<Idx<Function>>, _>>()?)
+                        },
+                        wp::ElementItems::Expressions(items_reader) => {
+                            let mut items = Vec::with_capacity(u32_to_usize(items_reader.count()));
+                            for const_expr in items_reader {
+                                // Most item expressions are just a `ref.func` or `ref.null` and the end instruction.
+                                let mut item_instrs = Vec::with_capacity(2);
+                                for op_offset in const_expr?.get_operators_reader().into_iter_with_offsets() {
+                                    let (op, offset) = op_offset?;
+                                    item_instrs.push(parse_instr(op, offset, &types, &metadata)?)
+                                }
+                                add_const_expr_extensions(&item_instrs, &metadata);
+                                items.push(item_instrs);
+                            }
+                            add_used_extension(&metadata, WasmExtension::ReferenceTypes);
+                            ElementItems::Expressions(elem_type, items)
                         },
-                        wp::ElementItems::Expressions(reader) => Err(ParseIssue::unsupported(reader.original_position(), WasmExtension::ReferenceTypes))?,
                     };
 
-                    match element.kind {
+                    let mode = match element.kind {
                         wp::ElementKind::Active {
                             table_index,
                             offset_expr,
                         } => {
-                            let table = module
-                                .tables
-                                .get_mut(u32_to_usize(table_index))
-                                .ok_or_else(|| ParseIssue::index(element_offset, table_index, "table"))?;
+                            if u32_to_usize(table_index) >= module.tables.len() {
+                                Err(ParseIssue::index(element_offset, table_index, "table"))?
+                            }
+                            if table_index != 0 {
+                                add_used_extension(&metadata, WasmExtension::ReferenceTypes);
+                            }
 
                             // Most offset expressions are just a constant and the end instruction.
                             let mut offset_instrs = Vec::with_capacity(2);
@@ -256,23 +324,35 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                                 let (op, offset) = op_offset?;
                                 offset_instrs.push(parse_instr(op, offset, &types, &metadata)?)
                             }
+                            add_const_expr_extensions(&offset_instrs, &metadata);
 
-                            table.elements.push(Element {
+                            ElementMode::Active {
+                                table: table_index.into(),
                                 offset: offset_instrs,
-                                functions: items,
-                            })
+                            }
                         }
                         wp::ElementKind::Passive => {
-                            Err(ParseIssue::unsupported(element_offset, WasmExtension::BulkMemoryOperations))?
+                            add_used_extension(&metadata, WasmExtension::BulkMemoryOperations);
+                            ElementMode::Passive
                         }
                         wp::ElementKind::Declared => {
-                            Err(ParseIssue::unsupported(element_offset, WasmExtension::ReferenceTypes))?
+                            add_used_extension(&metadata, WasmExtension::ReferenceTypes);
+                            ElementMode::Declared
                         }
-                    }
+                    };
+
+                    module.elements.push(Element {
+                        mode,
+                        items,
+                        name: None,
+                    });
                 }
             }
             wp::Payload::DataCountSection { count: _, range } => {
-                Err(ParseIssue::unsupported(range.start, WasmExtension::BulkMemoryOperations))?
+                // The count is redundant with the number of segments in the data section, so we
+                // don't store it, the encoder re-computes it instead.
+                section_offsets.push((SectionId::DataCount, range.start));
+                add_used_extension(&metadata, WasmExtension::BulkMemoryOperations);
             }
             wp::Payload::DataSection(reader) => {
                 section_offsets.push((SectionId::Data, reader.range().start));
@@ -280,15 +360,17 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                 for elem in reader.into_iter_with_offsets() {
                     let (data_offset, data) = elem?;
 
-                    match data.kind {
+                    let mode = match data.kind {
                         wp::DataKind::Active {
                             memory_index,
                             offset_expr,
                         } => {
-                            let memory = module
-                                .memories
-                                .get_mut(u32_to_usize(memory_index))
-                                .ok_or_else(|| ParseIssue::index(data_offset, memory_index, "memory"))?;
+                            if u32_to_usize(memory_index) >= module.memories.len() {
+                                Err(ParseIssue::index(data_offset, memory_index, "memory"))?
+                            }
+                            if memory_index != 0 {
+                                add_used_extension(&metadata, WasmExtension::MultiMemory);
+                            }
 
                             // Most offset expressions are just a constant and the end instruction.
                             let mut offset_instrs = Vec::with_capacity(2);
@@ -296,57 +378,89 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                                 let (op, offset) = op_offset?;
                                 offset_instrs.push(parse_instr(op, offset, &types, &metadata)?)
                             }
+                            add_const_expr_extensions(&offset_instrs, &metadata);
 
-                            memory.data.push(Data {
+                            DataMode::Active {
+                                memory: memory_index.into(),
                                 offset: offset_instrs,
-                                bytes: data.data.to_vec(),
-                            })
+                            }
                         }
                         wp::DataKind::Passive => {
-                            Err(ParseIssue::unsupported(data_offset, WasmExtension::BulkMemoryOperations))?
+                            add_used_extension(&metadata, WasmExtension::BulkMemoryOperations);
+                            DataMode::Passive
                         }
-                    }
+                    };
+
+                    module.data.push(Data {
+                        mode,
+                        bytes: data.data.to_vec(),
+                        name: None,
+                    });
                 }
             }
-            wp::Payload::CodeSectionStart {
-                count,
-                range,
-                size: _,
-            } => {
+            wp::Payload::CodeSectionStart { count, range, size } => {
                 section_offsets.push((SectionId::Code, range.start));
 
-                function_offsets.reserve_exact(u32_to_usize(count));
-                function_bodies.reserve_exact(u32_to_usize(count));
-
-                code_entries_count = count;
-            }
-            wp::Payload::CodeSectionEntry(body) => {
-                let func_index = imported_function_count + current_code_index;
-
-                function_offsets.push((func_index.into(), body.range().start));
-                function_bodies.push((func_index, body));
+                // Function bodies borrow from the code section, so take it out of the input as a
+                // whole. This also allows to parse the bodies in parallel.
+                let code_section = input.take(u32_to_usize(size))
+                    .map_err(|err| ParseIssue::message(range.start, "could not read code section", Some(Box::new(err.into()))))?;
+                let code_section_offset = range.end - code_section.len();
 
-                current_code_index += 1;
+                function_offsets.reserve_exact(u32_to_usize(count));
+                let code_entries = (imported_function_count..).map(Idx::<Function>::from);
+
+                if lazy_bodies {
+                    let lazy_context = Arc::new(LazyContext {
+                        code_section,
+                        code_section_offset,
+                        types: types.clone(),
+                        metadata: RwLock::new(ModuleMetadata::default()),
+                        has_data_count_section: section_offsets
+                            .iter()
+                            .any(|(section, _)| *section == SectionId::DataCount),
+                    });
+                    let bodies = parse_code_section_entries(&mut parser, &lazy_context.code_section, count)?;
+                    for (func_idx, body) in code_entries.zip(bodies) {
+                        function_offsets.push((func_idx, body.range().start));
+                        let function = module
+                            .functions
+                            .get_mut(func_idx.to_usize())
+                            .ok_or_else(|| ParseIssue::index(body.range().start, func_idx.to_u32(), "function"))?;
+                        function.code = ImportOrPresent::Present(LazyCode::new_raw(lazy_context.clone(), body.range()));
+                    }
+                } else {
+                    let bodies = parse_code_section_entries(&mut parser, &code_section, count)?;
+                    let function_bodies = code_entries.zip(bodies).collect::<Vec<_>>();
+                    function_offsets.extend(function_bodies.iter().map(|(func_idx, body)| (*func_idx, body.range().start)));
 
-                let last_code_entry = current_code_index == code_entries_count;
-                if last_code_entry {
                     // Parse and convert to high-level instructions in parallel.
+                    let record_instr_offsets = instr_offsets.is_some();
                     let function_bodies = function_bodies
-                        .par_drain(..)
+                        .into_par_iter()
                         .map(|(func_idx, body)| {
-                            (func_idx, body.range().start, parse_body(body, &types, &metadata))
+                            let offset = body.range().start;
+                            let mut body_instr_offsets = Vec::new();
+                            let code = parse_body(body, &types, &metadata, record_instr_offsets.then_some(&mut body_instr_offsets));
+                            (func_idx, offset, code, body_instr_offsets)
                         })
                         .collect::<Vec<_>>();
                     // Attach the converted function bodies to the function definitions (not parallel).
-                    for (func_idx, offset, code) in function_bodies {
+                    for (func_idx, offset, code, body_instr_offsets) in function_bodies {
                         let function = module
                             .functions
-                            .get_mut(u32_to_usize(func_idx))
-                            .ok_or_else(|| ParseIssue::index(offset, func_idx, "function"))?;
-                        function.code = ImportOrPresent::Present(code?);
+                            .get_mut(func_idx.to_usize())
+                            .ok_or_else(|| ParseIssue::index(offset, func_idx.to_u32(), "function"))?;
+                        function.code = ImportOrPresent::Present(code?.into());
+                        if let Some(instr_offsets) = &mut instr_offsets {
+                            instr_offsets.push((func_idx, body_instr_offsets));
+                        }
                     }
                 }
             }
+            wp::Payload::CodeSectionEntry(body) => {
+                unreachable!("code section entry at offset 0x{:x} should have been parsed together with the code section start", body.range().start)
+            }
             wp::Payload::CustomSection(reader) => {
                 let name = reader.name().to_string();
                 let previous_section_id = section_offsets
@@ -400,9 +514,8 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
                 range,
             } => Err(ParseIssue::message(range.start, "unknown section", None))?,
             wp::Payload::End(_offset_bytes) => {
-                // I don't understand what this end marker is for?
-                // If the module ended (i.e., the input buffer is exhausted),
-                // there is just no more payload following, isn't there?
+                // When reading from a stream, this is how we know that the module ended.
+                break;
             }
         }
     }
@@ -410,6 +523,7 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
     let offsets = Offsets {
         sections: section_offsets,
         functions_code: function_offsets,
+        instrs: instr_offsets,
     };
 
     module.metadata = metadata.into_inner().unwrap();
@@ -417,10 +531,90 @@ pub fn parse_module(bytes: &[u8]) -> Result<(Module, Offsets, ParseWarnings), Pa
     Ok((module, offsets, warnings))
 }
 
+/// Splits the contents of the code section into the function bodies, without parsing them yet.
+/// The `parser` must be right after the `CodeSectionStart` payload.
+fn parse_code_section_entries<'a>(
+    parser: &mut wp::Parser,
+    mut code_section: &'a [u8],
+    count: u32,
+) -> Result<Vec<wp::FunctionBody<'a>>, ParseError> {
+    let mut bodies = Vec::with_capacity(u32_to_usize(count));
+    for _ in 0..count {
+        match parser.parse(code_section, true)? {
+            wp::Chunk::Parsed { consumed, payload: wp::Payload::CodeSectionEntry(mut body) } => {
+                // Memarg offsets of 64-bit memories can exceed u32, so always read them as u64.
+                body.allow_memarg64(true);
+                bodies.push(body);
+                code_section = &code_section[consumed..];
+            }
+            _ => unreachable!("parser should produce exactly `count` code section entries"),
+        }
+    }
+    Ok(bodies)
+}
+
+/// Input for the parser, which buffers only the bytes `wasmparser` currently needs, i.e., usually
+/// a single section, instead of the whole binary.
+struct StreamingInput<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    // Start of the not yet consumed bytes in `buffer`. A `Cell`, such that bytes can be consumed
+    // while the payload parsed from them still borrows the buffer.
+    position: Cell<usize>,
+    eof: bool,
+}
+
+impl<R: io::Read> StreamingInput<R> {
+    fn new(reader: R) -> Self {
+        StreamingInput {
+            reader,
+            buffer: Vec::new(),
+            position: Cell::new(0),
+            eof: false,
+        }
+    }
+
+    fn buffered(&self) -> &[u8] {
+        &self.buffer[self.position.get()..]
+    }
+
+    fn consume(&self, count: usize) {
+        self.position.set(self.position.get() + count);
+    }
+
+    /// Reads (at most) `count` more bytes into the buffer, and sets `eof` if there are less.
+    fn read_more(&mut self, count: u64) -> io::Result<()> {
+        self.buffer.drain(..self.position.replace(0));
+        let read = (&mut self.reader).take(count).read_to_end(&mut self.buffer)?;
+        if (read as u64) < count {
+            self.eof = true;
+        }
+        Ok(())
+    }
+
+    /// Removes the next `count` bytes from the input, reading them first if necessary.
+    fn take(&mut self, count: usize) -> io::Result<Vec<u8>> {
+        let buffered = self.buffered().len();
+        if buffered < count {
+            self.read_more((count - buffered) as u64)?;
+            if self.eof {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        let position = self.position.replace(0);
+        let rest = self.buffer.split_off(position + count);
+        let mut taken = std::mem::replace(&mut self.buffer, rest);
+        taken.drain(..position);
+        Ok(taken)
+    }
+}
+
+/// If given, the offset of each instruction is pushed to `instr_offsets`.
 fn parse_body(
     body: wp::FunctionBody,
     types: &Types,
     metadata: &RwLock<ModuleMetadata>,
+    mut instr_offsets: Option<&mut Vec<usize>>,
 ) -> Result<Code, ParseError> {
     let mut locals_reader = body.get_locals_reader()?;
     let mut offset = locals_reader.original_position();
@@ -430,6 +624,7 @@ fn parse_body(
         let (count, type_) = locals_reader.read()?;
         let count = u32_to_usize(count);
         let type_ = parse_val_ty(type_, offset)?;
+        add_val_ty_extensions(&[type_], metadata);
         locals.extend(std::iter::repeat(Local::new(type_)).take(count));
         offset = locals_reader.original_position();
     }
@@ -470,11 +665,15 @@ fn parse_body(
     for op_offset in body.get_operators_reader()?.into_iter_with_offsets() {
         let (op, offset) = op_offset?;
         instrs.push(parse_instr(op, offset, types, metadata)?);
+        if let Some(instr_offsets) = &mut instr_offsets {
+            instr_offsets.push(offset);
+        }
     }
 
     Ok(Code {
         locals,
         body: instrs,
+        label_names: Vec::new(),
     })
 }
 
@@ -486,7 +685,7 @@ fn parse_instr(
 ) -> Result<Instr, ParseError> {
     use crate::Instr::*;
     use wp::Operator as wp;
-    Ok(match op {
+    let instr = match op {
         wp::Unreachable => Unreachable,
         wp::Nop => Nop,
 
@@ -496,14 +695,12 @@ fn parse_instr(
         wp::Else => Else,
         wp::End => End,
 
-        wp::Try { blockty: _ }
-        | wp::Catch { tag_index: _ }
-        | wp::CatchAll
-        | wp::Throw { tag_index: _ }
-        | wp::Rethrow { relative_depth: _ }
-        | wp::Delegate { relative_depth: _ } => {
-            Err(ParseIssue::unsupported(offset, WasmExtension::ExceptionHandling))?
-        }
+        wp::Try { blockty } => Try(parse_block_ty(blockty, offset + 1, types, metadata)?),
+        wp::Catch { tag_index } => Catch(tag_index.into()),
+        wp::CatchAll => CatchAll,
+        wp::Delegate { relative_depth } => Delegate(Label::from(relative_depth)),
+        wp::Throw { tag_index } => Throw(tag_index.into()),
+        wp::Rethrow { relative_depth } => Rethrow(Label::from(relative_depth)),
 
         wp::Br { relative_depth } => Br(Label::from(relative_depth)),
         wp::BrIf { relative_depth } => BrIf(Label::from(relative_depth)),
@@ -521,24 +718,21 @@ fn parse_instr(
 
         wp::Return => Return,
         wp::Call { function_index } => Call(function_index.into()),
-        wp::CallIndirect { type_index, table_index, table_byte } => {
-            if table_index != 0 {
-                Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?
-            }
-            assert!(table_byte == 0, "not sure which extension this is");
-            CallIndirect(types.get(type_index, offset + 1)?, 0usize.into())
+        // `table_byte` is the same as `table_index`, but the original (single) byte, see the
+        // comment on `mem_byte` for `memory.size` below.
+        wp::CallIndirect { type_index, table_index, table_byte: _ } => {
+            CallIndirect(types.get(type_index, offset + 1)?, table_index.into())
         }
 
-        wp::ReturnCall { function_index: _ }
-        | wp::ReturnCallIndirect {
-            type_index: _,
-            table_index: _,
-        } => Err(ParseIssue::unsupported(offset, WasmExtension::TailCalls))?,
+        wp::ReturnCall { function_index } => ReturnCall(function_index.into()),
+        wp::ReturnCallIndirect { type_index, table_index } => {
+            ReturnCallIndirect(types.get(type_index, offset + 1)?, table_index.into())
+        }
 
         wp::Drop => Drop,
         wp::Select => Select,
 
-        wp::TypedSelect { ty: _ } => Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?,
+        wp::TypedSelect { ty } => TypedSelect(parse_val_ty(ty, offset + 1)?),
 
         wp::LocalGet { local_index } => Local(LocalOp::Get, local_index.into()),
         wp::LocalSet { local_index } => Local(LocalOp::Set, local_index.into()),
@@ -546,57 +740,47 @@ fn parse_instr(
         wp::GlobalGet { global_index } => Global(GlobalOp::Get, global_index.into()),
         wp::GlobalSet { global_index } => Global(GlobalOp::Set, global_index.into()),
 
-        wp::I32Load { memarg } => Load(LoadOp::I32Load, parse_memarg(memarg, offset + 1)?),
-        wp::I64Load { memarg } => Load(LoadOp::I64Load, parse_memarg(memarg, offset + 1)?),
-        wp::F32Load { memarg } => Load(LoadOp::F32Load, parse_memarg(memarg, offset + 1)?),
-        wp::F64Load { memarg } => Load(LoadOp::F64Load, parse_memarg(memarg, offset + 1)?),
-        wp::I32Load8S { memarg } => Load(LoadOp::I32Load8S, parse_memarg(memarg, offset + 1)?),
-        wp::I32Load8U { memarg } => Load(LoadOp::I32Load8U, parse_memarg(memarg, offset + 1)?),
-        wp::I32Load16S { memarg } => Load(LoadOp::I32Load16S, parse_memarg(memarg, offset + 1)?),
-        wp::I32Load16U { memarg } => Load(LoadOp::I32Load16U, parse_memarg(memarg, offset + 1)?),
-        wp::I64Load8S { memarg } => Load(LoadOp::I64Load8S, parse_memarg(memarg, offset + 1)?),
-        wp::I64Load8U { memarg } => Load(LoadOp::I64Load8U, parse_memarg(memarg, offset + 1)?),
-        wp::I64Load16S { memarg } => Load(LoadOp::I64Load16S, parse_memarg(memarg, offset + 1)?),
-        wp::I64Load16U { memarg } => Load(LoadOp::I64Load16U, parse_memarg(memarg, offset + 1)?),
-        wp::I64Load32S { memarg } => Load(LoadOp::I64Load32S, parse_memarg(memarg, offset + 1)?),
-        wp::I64Load32U { memarg } => Load(LoadOp::I64Load32U, parse_memarg(memarg, offset + 1)?),
-
-        wp::I32Store { memarg } => Store(StoreOp::I32Store, parse_memarg(memarg, offset + 1)?),
-        wp::I64Store { memarg } => Store(StoreOp::I64Store, parse_memarg(memarg, offset + 1)?),
-        wp::F32Store { memarg } => Store(StoreOp::F32Store, parse_memarg(memarg, offset + 1)?),
-        wp::F64Store { memarg } => Store(StoreOp::F64Store, parse_memarg(memarg, offset + 1)?),
-        wp::I32Store8 { memarg } => Store(StoreOp::I32Store8, parse_memarg(memarg, offset + 1)?),
-        wp::I32Store16 { memarg } => Store(StoreOp::I32Store16, parse_memarg(memarg, offset + 1)?),
-        wp::I64Store8 { memarg } => Store(StoreOp::I64Store8, parse_memarg(memarg, offset + 1)?),
-        wp::I64Store16 { memarg } => Store(StoreOp::I64Store16, parse_memarg(memarg, offset + 1)?),
-        wp::I64Store32 { memarg } => Store(StoreOp::I64Store32, parse_memarg(memarg, offset + 1)?),
+        wp::I32Load { memarg } => Load(LoadOp::I32Load, parse_memarg(memarg)),
+        wp::I64Load { memarg } => Load(LoadOp::I64Load, parse_memarg(memarg)),
+        wp::F32Load { memarg } => Load(LoadOp::F32Load, parse_memarg(memarg)),
+        wp::F64Load { memarg } => Load(LoadOp::F64Load, parse_memarg(memarg)),
+        wp::I32Load8S { memarg } => Load(LoadOp::I32Load8S, parse_memarg(memarg)),
+        wp::I32Load8U { memarg } => Load(LoadOp::I32Load8U, parse_memarg(memarg)),
+        wp::I32Load16S { memarg } => Load(LoadOp::I32Load16S, parse_memarg(memarg)),
+        wp::I32Load16U { memarg } => Load(LoadOp::I32Load16U, parse_memarg(memarg)),
+        wp::I64Load8S { memarg } => Load(LoadOp::I64Load8S, parse_memarg(memarg)),
+        wp::I64Load8U { memarg } => Load(LoadOp::I64Load8U, parse_memarg(memarg)),
+        wp::I64Load16S { memarg } => Load(LoadOp::I64Load16S, parse_memarg(memarg)),
+        wp::I64Load16U { memarg } => Load(LoadOp::I64Load16U, parse_memarg(memarg)),
+        wp::I64Load32S { memarg } => Load(LoadOp::I64Load32S, parse_memarg(memarg)),
+        wp::I64Load32U { memarg } => Load(LoadOp::I64Load32U, parse_memarg(memarg)),
+
+        wp::I32Store { memarg } => Store(StoreOp::I32Store, parse_memarg(memarg)),
+        wp::I64Store { memarg } => Store(StoreOp::I64Store, parse_memarg(memarg)),
+        wp::F32Store { memarg } => Store(StoreOp::F32Store, parse_memarg(memarg)),
+        wp::F64Store { memarg } => Store(StoreOp::F64Store, parse_memarg(memarg)),
+        wp::I32Store8 { memarg } => Store(StoreOp::I32Store8, parse_memarg(memarg)),
+        wp::I32Store16 { memarg } => Store(StoreOp::I32Store16, parse_memarg(memarg)),
+        wp::I64Store8 { memarg } => Store(StoreOp::I64Store8, parse_memarg(memarg)),
+        wp::I64Store16 { memarg } => Store(StoreOp::I64Store16, parse_memarg(memarg)),
+        wp::I64Store32 { memarg } => Store(StoreOp::I64Store32, parse_memarg(memarg)),
 
         // This is not well documented in wasmparser: `mem_byte` and `mem` essentially contain
         // the same information, it's just that mem_byte is the original (single) byte that was
         // read from the instruction stream, and mem is it if parsed as a LEB128.
         // I think the variable-length parser is more robust, as it can handle memory indices
         // above 255, so ignore `mem_byte` here.
-        wp::MemorySize { mem, mem_byte: _ } => {
-            if mem != 0 {
-                Err(ParseIssue::unsupported(offset, WasmExtension::MultiMemory))?
-            }
-            MemorySize(0u32.into())
-        }
-        wp::MemoryGrow { mem, mem_byte: _ } => {
-            if mem != 0 {
-                Err(ParseIssue::unsupported(offset, WasmExtension::MultiMemory))?
-            }
-            MemoryGrow(0u32.into())
-        }
+        wp::MemorySize { mem, mem_byte: _ } => MemorySize(mem.into()),
+        wp::MemoryGrow { mem, mem_byte: _ } => MemoryGrow(mem.into()),
 
         wp::I32Const { value } => Const(Val::I32(value)),
         wp::I64Const { value } => Const(Val::I64(value)),
         wp::F32Const { value } => Const(Val::F32(OrderedFloat(f32::from_bits(value.bits())))),
         wp::F64Const { value } => Const(Val::F64(OrderedFloat(f64::from_bits(value.bits())))),
 
-        wp::RefNull { ty: _ } | wp::RefIsNull | wp::RefFunc { function_index: _ } => {
-            Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?
-        }
+        wp::RefNull { ty } => RefNull(parse_elem_ty(ty, offset + 1)?),
+        wp::RefIsNull => RefIsNull,
+        wp::RefFunc { function_index } => RefFunc(function_index.into()),
 
         wp::I32Eqz => Unary(UnaryOp::I32Eqz),
         wp::I64Eqz => Unary(UnaryOp::I64Eqz),
@@ -645,6 +829,19 @@ fn parse_instr(
         wp::I64ReinterpretF64 => Unary(UnaryOp::I64ReinterpretF64),
         wp::F32ReinterpretI32 => Unary(UnaryOp::F32ReinterpretI32),
         wp::F64ReinterpretI64 => Unary(UnaryOp::F64ReinterpretI64),
+        wp::I32Extend8S => Unary(UnaryOp::I32Extend8S),
+        wp::I32Extend16S => Unary(UnaryOp::I32Extend16S),
+        wp::I64Extend8S => Unary(UnaryOp::I64Extend8S),
+        wp::I64Extend16S => Unary(UnaryOp::I64Extend16S),
+        wp::I64Extend32S => Unary(UnaryOp::I64Extend32S),
+        wp::I32TruncSatF32S => Unary(UnaryOp::I32TruncSatF32S),
+        wp::I32TruncSatF32U => Unary(UnaryOp::I32TruncSatF32U),
+        wp::I32TruncSatF64S => Unary(UnaryOp::I32TruncSatF64S),
+        wp::I32TruncSatF64U => Unary(UnaryOp::I32TruncSatF64U),
+        wp::I64TruncSatF32S => Unary(UnaryOp::I64TruncSatF32S),
+        wp::I64TruncSatF32U => Unary(UnaryOp::I64TruncSatF32U),
+        wp::I64TruncSatF64S => Unary(UnaryOp::I64TruncSatF64S),
+        wp::I64TruncSatF64U => Unary(UnaryOp::I64TruncSatF64U),
 
         wp::I32Eq => Binary(BinaryOp::I32Eq),
         wp::I32Ne => Binary(BinaryOp::I32Ne),
@@ -723,342 +920,327 @@ fn parse_instr(
         wp::F64Max => Binary(BinaryOp::F64Max),
         wp::F64Copysign => Binary(BinaryOp::F64Copysign),
 
-        wp::I32Extend8S
-        | wp::I32Extend16S
-        | wp::I64Extend8S
-        | wp::I64Extend16S
-        | wp::I64Extend32S => Err(ParseIssue::unsupported(offset, WasmExtension::SignExtensionOps))?,
-
-        wp::I32TruncSatF32S
-        | wp::I32TruncSatF32U
-        | wp::I32TruncSatF64S
-        | wp::I32TruncSatF64U
-        | wp::I64TruncSatF32S
-        | wp::I64TruncSatF32U
-        | wp::I64TruncSatF64S
-        | wp::I64TruncSatF64U => Err(ParseIssue::unsupported(offset, WasmExtension::NontrappingFloatToInt))?,
-
-        wp::MemoryInit { data_index: _, mem: _ }
-        | wp::DataDrop { data_index: _ }
-        | wp::MemoryCopy { dst_mem: _, src_mem: _ }
-        | wp::MemoryFill { mem: _ }
-        | wp::TableInit { elem_index: _, table: _ }
-        | wp::ElemDrop { elem_index: _ }
-        | wp::TableCopy { dst_table: _, src_table: _ } => Err(ParseIssue::unsupported(offset, WasmExtension::BulkMemoryOperations))?,
-
-        wp::TableFill { table: _ } => Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?,
-
-        wp::TableGet { table: _ }
-        | wp::TableSet { table: _ }
-        | wp::TableGrow { table: _ }
-        | wp::TableSize { table: _ } => Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?,
-
-        wp::MemoryAtomicNotify { memarg: _ }
-        | wp::MemoryAtomicWait32 { memarg: _ }
-        | wp::MemoryAtomicWait64 { memarg: _ }
-        | wp::AtomicFence
-        | wp::I32AtomicLoad { memarg: _ }
-        | wp::I64AtomicLoad { memarg: _ }
-        | wp::I32AtomicLoad8U { memarg: _ }
-        | wp::I32AtomicLoad16U { memarg: _ }
-        | wp::I64AtomicLoad8U { memarg: _ }
-        | wp::I64AtomicLoad16U { memarg: _ }
-        | wp::I64AtomicLoad32U { memarg: _ }
-        | wp::I32AtomicStore { memarg: _ }
-        | wp::I64AtomicStore { memarg: _ }
-        | wp::I32AtomicStore8 { memarg: _ }
-        | wp::I32AtomicStore16 { memarg: _ }
-        | wp::I64AtomicStore8 { memarg: _ }
-        | wp::I64AtomicStore16 { memarg: _ }
-        | wp::I64AtomicStore32 { memarg: _ }
-        | wp::I32AtomicRmwAdd { memarg: _ }
-        | wp::I64AtomicRmwAdd { memarg: _ }
-        | wp::I32AtomicRmw8AddU { memarg: _ }
-        | wp::I32AtomicRmw16AddU { memarg: _ }
-        | wp::I64AtomicRmw8AddU { memarg: _ }
-        | wp::I64AtomicRmw16AddU { memarg: _ }
-        | wp::I64AtomicRmw32AddU { memarg: _ }
-        | wp::I32AtomicRmwSub { memarg: _ }
-        | wp::I64AtomicRmwSub { memarg: _ }
-        | wp::I32AtomicRmw8SubU { memarg: _ }
-        | wp::I32AtomicRmw16SubU { memarg: _ }
-        | wp::I64AtomicRmw8SubU { memarg: _ }
-        | wp::I64AtomicRmw16SubU { memarg: _ }
-        | wp::I64AtomicRmw32SubU { memarg: _ }
-        | wp::I32AtomicRmwAnd { memarg: _ }
-        | wp::I64AtomicRmwAnd { memarg: _ }
-        | wp::I32AtomicRmw8AndU { memarg: _ }
-        | wp::I32AtomicRmw16AndU { memarg: _ }
-        | wp::I64AtomicRmw8AndU { memarg: _ }
-        | wp::I64AtomicRmw16AndU { memarg: _ }
-        | wp::I64AtomicRmw32AndU { memarg: _ }
-        | wp::I32AtomicRmwOr { memarg: _ }
-        | wp::I64AtomicRmwOr { memarg: _ }
-        | wp::I32AtomicRmw8OrU { memarg: _ }
-        | wp::I32AtomicRmw16OrU { memarg: _ }
-        | wp::I64AtomicRmw8OrU { memarg: _ }
-        | wp::I64AtomicRmw16OrU { memarg: _ }
-        | wp::I64AtomicRmw32OrU { memarg: _ }
-        | wp::I32AtomicRmwXor { memarg: _ }
-        | wp::I64AtomicRmwXor { memarg: _ }
-        | wp::I32AtomicRmw8XorU { memarg: _ }
-        | wp::I32AtomicRmw16XorU { memarg: _ }
-        | wp::I64AtomicRmw8XorU { memarg: _ }
-        | wp::I64AtomicRmw16XorU { memarg: _ }
-        | wp::I64AtomicRmw32XorU { memarg: _ }
-        | wp::I32AtomicRmwXchg { memarg: _ }
-        | wp::I64AtomicRmwXchg { memarg: _ }
-        | wp::I32AtomicRmw8XchgU { memarg: _ }
-        | wp::I32AtomicRmw16XchgU { memarg: _ }
-        | wp::I64AtomicRmw8XchgU { memarg: _ }
-        | wp::I64AtomicRmw16XchgU { memarg: _ }
-        | wp::I64AtomicRmw32XchgU { memarg: _ }
-        | wp::I32AtomicRmwCmpxchg { memarg: _ }
-        | wp::I64AtomicRmwCmpxchg { memarg: _ }
-        | wp::I32AtomicRmw8CmpxchgU { memarg: _ }
-        | wp::I32AtomicRmw16CmpxchgU { memarg: _ }
-        | wp::I64AtomicRmw8CmpxchgU { memarg: _ }
-        | wp::I64AtomicRmw16CmpxchgU { memarg: _ }
-        | wp::I64AtomicRmw32CmpxchgU { memarg: _ } => {
-            Err(ParseIssue::unsupported(offset, WasmExtension::ThreadsAtomics))?
-        }
-
-        wp::V128Load { memarg: _ }
-        | wp::V128Load8x8S { memarg: _ }
-        | wp::V128Load8x8U { memarg: _ }
-        | wp::V128Load16x4S { memarg: _ }
-        | wp::V128Load16x4U { memarg: _ }
-        | wp::V128Load32x2S { memarg: _ }
-        | wp::V128Load32x2U { memarg: _ }
-        | wp::V128Load8Splat { memarg: _ }
-        | wp::V128Load16Splat { memarg: _ }
-        | wp::V128Load32Splat { memarg: _ }
-        | wp::V128Load64Splat { memarg: _ }
-        | wp::V128Load32Zero { memarg: _ }
-        | wp::V128Load64Zero { memarg: _ }
-        | wp::V128Store { memarg: _ }
-        | wp::V128Load8Lane { memarg: _, lane: _ }
-        | wp::V128Load16Lane { memarg: _, lane: _ }
-        | wp::V128Load32Lane { memarg: _, lane: _ }
-        | wp::V128Load64Lane { memarg: _, lane: _ }
-        | wp::V128Store8Lane { memarg: _, lane: _ }
-        | wp::V128Store16Lane { memarg: _, lane: _ }
-        | wp::V128Store32Lane { memarg: _, lane: _ }
-        | wp::V128Store64Lane { memarg: _, lane: _ }
-        | wp::V128Const { value: _ }
-        | wp::I8x16Shuffle { lanes: _ }
-        | wp::I8x16ExtractLaneS { lane: _ }
-        | wp::I8x16ExtractLaneU { lane: _ }
-        | wp::I8x16ReplaceLane { lane: _ }
-        | wp::I16x8ExtractLaneS { lane: _ }
-        | wp::I16x8ExtractLaneU { lane: _ }
-        | wp::I16x8ReplaceLane { lane: _ }
-        | wp::I32x4ExtractLane { lane: _ }
-        | wp::I32x4ReplaceLane { lane: _ }
-        | wp::I64x2ExtractLane { lane: _ }
-        | wp::I64x2ReplaceLane { lane: _ }
-        | wp::F32x4ExtractLane { lane: _ }
-        | wp::F32x4ReplaceLane { lane: _ }
-        | wp::F64x2ExtractLane { lane: _ }
-        | wp::F64x2ReplaceLane { lane: _ }
-        | wp::I8x16Swizzle
-        | wp::I8x16Splat
-        | wp::I16x8Splat
-        | wp::I32x4Splat
-        | wp::I64x2Splat
-        | wp::F32x4Splat
-        | wp::F64x2Splat
-        | wp::I8x16Eq
-        | wp::I8x16Ne
-        | wp::I8x16LtS
-        | wp::I8x16LtU
-        | wp::I8x16GtS
-        | wp::I8x16GtU
-        | wp::I8x16LeS
-        | wp::I8x16LeU
-        | wp::I8x16GeS
-        | wp::I8x16GeU
-        | wp::I16x8Eq
-        | wp::I16x8Ne
-        | wp::I16x8LtS
-        | wp::I16x8LtU
-        | wp::I16x8GtS
-        | wp::I16x8GtU
-        | wp::I16x8LeS
-        | wp::I16x8LeU
-        | wp::I16x8GeS
-        | wp::I16x8GeU
-        | wp::I32x4Eq
-        | wp::I32x4Ne
-        | wp::I32x4LtS
-        | wp::I32x4LtU
-        | wp::I32x4GtS
-        | wp::I32x4GtU
-        | wp::I32x4LeS
-        | wp::I32x4LeU
-        | wp::I32x4GeS
-        | wp::I32x4GeU
-        | wp::I64x2Eq
-        | wp::I64x2Ne
-        | wp::I64x2LtS
-        | wp::I64x2GtS
-        | wp::I64x2LeS
-        | wp::I64x2GeS
-        | wp::F32x4Eq
-        | wp::F32x4Ne
-        | wp::F32x4Lt
-        | wp::F32x4Gt
-        | wp::F32x4Le
-        | wp::F32x4Ge
-        | wp::F64x2Eq
-        | wp::F64x2Ne
-        | wp::F64x2Lt
-        | wp::F64x2Gt
-        | wp::F64x2Le
-        | wp::F64x2Ge
-        | wp::V128Not
-        | wp::V128And
-        | wp::V128AndNot
-        | wp::V128Or
-        | wp::V128Xor
-        | wp::V128Bitselect
-        | wp::V128AnyTrue
-        | wp::I8x16Abs
-        | wp::I8x16Neg
-        | wp::I8x16Popcnt
-        | wp::I8x16AllTrue
-        | wp::I8x16Bitmask
-        | wp::I8x16NarrowI16x8S
-        | wp::I8x16NarrowI16x8U
-        | wp::I8x16Shl
-        | wp::I8x16ShrS
-        | wp::I8x16ShrU
-        | wp::I8x16Add
-        | wp::I8x16AddSatS
-        | wp::I8x16AddSatU
-        | wp::I8x16Sub
-        | wp::I8x16SubSatS
-        | wp::I8x16SubSatU
-        | wp::I8x16MinS
-        | wp::I8x16MinU
-        | wp::I8x16MaxS
-        | wp::I8x16MaxU
-        | wp::I8x16AvgrU
-        | wp::I16x8ExtAddPairwiseI8x16S
-        | wp::I16x8ExtAddPairwiseI8x16U
-        | wp::I16x8Abs
-        | wp::I16x8Neg
-        | wp::I16x8Q15MulrSatS
-        | wp::I16x8AllTrue
-        | wp::I16x8Bitmask
-        | wp::I16x8NarrowI32x4S
-        | wp::I16x8NarrowI32x4U
-        | wp::I16x8ExtendLowI8x16S
-        | wp::I16x8ExtendHighI8x16S
-        | wp::I16x8ExtendLowI8x16U
-        | wp::I16x8ExtendHighI8x16U
-        | wp::I16x8Shl
-        | wp::I16x8ShrS
-        | wp::I16x8ShrU
-        | wp::I16x8Add
-        | wp::I16x8AddSatS
-        | wp::I16x8AddSatU
-        | wp::I16x8Sub
-        | wp::I16x8SubSatS
-        | wp::I16x8SubSatU
-        | wp::I16x8Mul
-        | wp::I16x8MinS
-        | wp::I16x8MinU
-        | wp::I16x8MaxS
-        | wp::I16x8MaxU
-        | wp::I16x8AvgrU
-        | wp::I16x8ExtMulLowI8x16S
-        | wp::I16x8ExtMulHighI8x16S
-        | wp::I16x8ExtMulLowI8x16U
-        | wp::I16x8ExtMulHighI8x16U
-        | wp::I32x4ExtAddPairwiseI16x8S
-        | wp::I32x4ExtAddPairwiseI16x8U
-        | wp::I32x4Abs
-        | wp::I32x4Neg
-        | wp::I32x4AllTrue
-        | wp::I32x4Bitmask
-        | wp::I32x4ExtendLowI16x8S
-        | wp::I32x4ExtendHighI16x8S
-        | wp::I32x4ExtendLowI16x8U
-        | wp::I32x4ExtendHighI16x8U
-        | wp::I32x4Shl
-        | wp::I32x4ShrS
-        | wp::I32x4ShrU
-        | wp::I32x4Add
-        | wp::I32x4Sub
-        | wp::I32x4Mul
-        | wp::I32x4MinS
-        | wp::I32x4MinU
-        | wp::I32x4MaxS
-        | wp::I32x4MaxU
-        | wp::I32x4DotI16x8S
-        | wp::I32x4ExtMulLowI16x8S
-        | wp::I32x4ExtMulHighI16x8S
-        | wp::I32x4ExtMulLowI16x8U
-        | wp::I32x4ExtMulHighI16x8U
-        | wp::I64x2Abs
-        | wp::I64x2Neg
-        | wp::I64x2AllTrue
-        | wp::I64x2Bitmask
-        | wp::I64x2ExtendLowI32x4S
-        | wp::I64x2ExtendHighI32x4S
-        | wp::I64x2ExtendLowI32x4U
-        | wp::I64x2ExtendHighI32x4U
-        | wp::I64x2Shl
-        | wp::I64x2ShrS
-        | wp::I64x2ShrU
-        | wp::I64x2Add
-        | wp::I64x2Sub
-        | wp::I64x2Mul
-        | wp::I64x2ExtMulLowI32x4S
-        | wp::I64x2ExtMulHighI32x4S
-        | wp::I64x2ExtMulLowI32x4U
-        | wp::I64x2ExtMulHighI32x4U
-        | wp::F32x4Ceil
-        | wp::F32x4Floor
-        | wp::F32x4Trunc
-        | wp::F32x4Nearest
-        | wp::F32x4Abs
-        | wp::F32x4Neg
-        | wp::F32x4Sqrt
-        | wp::F32x4Add
-        | wp::F32x4Sub
-        | wp::F32x4Mul
-        | wp::F32x4Div
-        | wp::F32x4Min
-        | wp::F32x4Max
-        | wp::F32x4PMin
-        | wp::F32x4PMax
-        | wp::F64x2Ceil
-        | wp::F64x2Floor
-        | wp::F64x2Trunc
-        | wp::F64x2Nearest
-        | wp::F64x2Abs
-        | wp::F64x2Neg
-        | wp::F64x2Sqrt
-        | wp::F64x2Add
-        | wp::F64x2Sub
-        | wp::F64x2Mul
-        | wp::F64x2Div
-        | wp::F64x2Min
-        | wp::F64x2Max
-        | wp::F64x2PMin
-        | wp::F64x2PMax
-        | wp::I32x4TruncSatF32x4S
-        | wp::I32x4TruncSatF32x4U
-        | wp::F32x4ConvertI32x4S
-        | wp::F32x4ConvertI32x4U
-        | wp::I32x4TruncSatF64x2SZero
-        | wp::I32x4TruncSatF64x2UZero
-        | wp::F64x2ConvertLowI32x4S
-        | wp::F64x2ConvertLowI32x4U
-        | wp::F32x4DemoteF64x2Zero
-        | wp::F64x2PromoteLowF32x4 => Err(ParseIssue::unsupported(offset, WasmExtension::Simd))?,
+        wp::MemoryInit { data_index, mem } => MemoryInit(data_index.into(), mem.into()),
+        wp::DataDrop { data_index } => DataDrop(data_index.into()),
+        wp::MemoryCopy { dst_mem, src_mem } => MemoryCopy { dst: dst_mem.into(), src: src_mem.into() },
+        wp::MemoryFill { mem } => MemoryFill(mem.into()),
+        wp::TableInit { elem_index, table } => TableInit(elem_index.into(), table.into()),
+        wp::ElemDrop { elem_index } => ElemDrop(elem_index.into()),
+        wp::TableCopy { dst_table, src_table } => TableCopy { dst: dst_table.into(), src: src_table.into() },
+
+        wp::TableGet { table } => TableGet(table.into()),
+        wp::TableSet { table } => TableSet(table.into()),
+        wp::TableSize { table } => TableSize(table.into()),
+        wp::TableGrow { table } => TableGrow(table.into()),
+        wp::TableFill { table } => TableFill(table.into()),
+
+        wp::I32AtomicLoad { memarg } => AtomicLoad(AtomicLoadOp::I32AtomicLoad, parse_memarg(memarg)),
+        wp::I64AtomicLoad { memarg } => AtomicLoad(AtomicLoadOp::I64AtomicLoad, parse_memarg(memarg)),
+        wp::I32AtomicLoad8U { memarg } => AtomicLoad(AtomicLoadOp::I32AtomicLoad8U, parse_memarg(memarg)),
+        wp::I32AtomicLoad16U { memarg } => AtomicLoad(AtomicLoadOp::I32AtomicLoad16U, parse_memarg(memarg)),
+        wp::I64AtomicLoad8U { memarg } => AtomicLoad(AtomicLoadOp::I64AtomicLoad8U, parse_memarg(memarg)),
+        wp::I64AtomicLoad16U { memarg } => AtomicLoad(AtomicLoadOp::I64AtomicLoad16U, parse_memarg(memarg)),
+        wp::I64AtomicLoad32U { memarg } => AtomicLoad(AtomicLoadOp::I64AtomicLoad32U, parse_memarg(memarg)),
+        wp::I32AtomicStore { memarg } => AtomicStore(AtomicStoreOp::I32AtomicStore, parse_memarg(memarg)),
+        wp::I64AtomicStore { memarg } => AtomicStore(AtomicStoreOp::I64AtomicStore, parse_memarg(memarg)),
+        wp::I32AtomicStore8 { memarg } => AtomicStore(AtomicStoreOp::I32AtomicStore8, parse_memarg(memarg)),
+        wp::I32AtomicStore16 { memarg } => AtomicStore(AtomicStoreOp::I32AtomicStore16, parse_memarg(memarg)),
+        wp::I64AtomicStore8 { memarg } => AtomicStore(AtomicStoreOp::I64AtomicStore8, parse_memarg(memarg)),
+        wp::I64AtomicStore16 { memarg } => AtomicStore(AtomicStoreOp::I64AtomicStore16, parse_memarg(memarg)),
+        wp::I64AtomicStore32 { memarg } => AtomicStore(AtomicStoreOp::I64AtomicStore32, parse_memarg(memarg)),
+        wp::I32AtomicRmwAdd { memarg } => Atomic(AtomicOp::I32AtomicRmwAdd, parse_memarg(memarg)),
+        wp::I64AtomicRmwAdd { memarg } => Atomic(AtomicOp::I64AtomicRmwAdd, parse_memarg(memarg)),
+        wp::I32AtomicRmw8AddU { memarg } => Atomic(AtomicOp::I32AtomicRmw8AddU, parse_memarg(memarg)),
+        wp::I32AtomicRmw16AddU { memarg } => Atomic(AtomicOp::I32AtomicRmw16AddU, parse_memarg(memarg)),
+        wp::I64AtomicRmw8AddU { memarg } => Atomic(AtomicOp::I64AtomicRmw8AddU, parse_memarg(memarg)),
+        wp::I64AtomicRmw16AddU { memarg } => Atomic(AtomicOp::I64AtomicRmw16AddU, parse_memarg(memarg)),
+        wp::I64AtomicRmw32AddU { memarg } => Atomic(AtomicOp::I64AtomicRmw32AddU, parse_memarg(memarg)),
+        wp::I32AtomicRmwSub { memarg } => Atomic(AtomicOp::I32AtomicRmwSub, parse_memarg(memarg)),
+        wp::I64AtomicRmwSub { memarg } => Atomic(AtomicOp::I64AtomicRmwSub, parse_memarg(memarg)),
+        wp::I32AtomicRmw8SubU { memarg } => Atomic(AtomicOp::I32AtomicRmw8SubU, parse_memarg(memarg)),
+        wp::I32AtomicRmw16SubU { memarg } => Atomic(AtomicOp::I32AtomicRmw16SubU, parse_memarg(memarg)),
+        wp::I64AtomicRmw8SubU { memarg } => Atomic(AtomicOp::I64AtomicRmw8SubU, parse_memarg(memarg)),
+        wp::I64AtomicRmw16SubU { memarg } => Atomic(AtomicOp::I64AtomicRmw16SubU, parse_memarg(memarg)),
+        wp::I64AtomicRmw32SubU { memarg } => Atomic(AtomicOp::I64AtomicRmw32SubU, parse_memarg(memarg)),
+        wp::I32AtomicRmwAnd { memarg } => Atomic(AtomicOp::I32AtomicRmwAnd, parse_memarg(memarg)),
+        wp::I64AtomicRmwAnd { memarg } => Atomic(AtomicOp::I64AtomicRmwAnd, parse_memarg(memarg)),
+        wp::I32AtomicRmw8AndU { memarg } => Atomic(AtomicOp::I32AtomicRmw8AndU, parse_memarg(memarg)),
+        wp::I32AtomicRmw16AndU { memarg } => Atomic(AtomicOp::I32AtomicRmw16AndU, parse_memarg(memarg)),
+        wp::I64AtomicRmw8AndU { memarg } => Atomic(AtomicOp::I64AtomicRmw8AndU, parse_memarg(memarg)),
+        wp::I64AtomicRmw16AndU { memarg } => Atomic(AtomicOp::I64AtomicRmw16AndU, parse_memarg(memarg)),
+        wp::I64AtomicRmw32AndU { memarg } => Atomic(AtomicOp::I64AtomicRmw32AndU, parse_memarg(memarg)),
+        wp::I32AtomicRmwOr { memarg } => Atomic(AtomicOp::I32AtomicRmwOr, parse_memarg(memarg)),
+        wp::I64AtomicRmwOr { memarg } => Atomic(AtomicOp::I64AtomicRmwOr, parse_memarg(memarg)),
+        wp::I32AtomicRmw8OrU { memarg } => Atomic(AtomicOp::I32AtomicRmw8OrU, parse_memarg(memarg)),
+        wp::I32AtomicRmw16OrU { memarg } => Atomic(AtomicOp::I32AtomicRmw16OrU, parse_memarg(memarg)),
+        wp::I64AtomicRmw8OrU { memarg } => Atomic(AtomicOp::I64AtomicRmw8OrU, parse_memarg(memarg)),
+        wp::I64AtomicRmw16OrU { memarg } => Atomic(AtomicOp::I64AtomicRmw16OrU, parse_memarg(memarg)),
+        wp::I64AtomicRmw32OrU { memarg } => Atomic(AtomicOp::I64AtomicRmw32OrU, parse_memarg(memarg)),
+        wp::I32AtomicRmwXor { memarg } => Atomic(AtomicOp::I32AtomicRmwXor, parse_memarg(memarg)),
+        wp::I64AtomicRmwXor { memarg } => Atomic(AtomicOp::I64AtomicRmwXor, parse_memarg(memarg)),
+        wp::I32AtomicRmw8XorU { memarg } => Atomic(AtomicOp::I32AtomicRmw8XorU, parse_memarg(memarg)),
+        wp::I32AtomicRmw16XorU { memarg } => Atomic(AtomicOp::I32AtomicRmw16XorU, parse_memarg(memarg)),
+        wp::I64AtomicRmw8XorU { memarg } => Atomic(AtomicOp::I64AtomicRmw8XorU, parse_memarg(memarg)),
+        wp::I64AtomicRmw16XorU { memarg } => Atomic(AtomicOp::I64AtomicRmw16XorU, parse_memarg(memarg)),
+        wp::I64AtomicRmw32XorU { memarg } => Atomic(AtomicOp::I64AtomicRmw32XorU, parse_memarg(memarg)),
+        wp::I32AtomicRmwXchg { memarg } => Atomic(AtomicOp::I32AtomicRmwXchg, parse_memarg(memarg)),
+        wp::I64AtomicRmwXchg { memarg } => Atomic(AtomicOp::I64AtomicRmwXchg, parse_memarg(memarg)),
+        wp::I32AtomicRmw8XchgU { memarg } => Atomic(AtomicOp::I32AtomicRmw8XchgU, parse_memarg(memarg)),
+        wp::I32AtomicRmw16XchgU { memarg } => Atomic(AtomicOp::I32AtomicRmw16XchgU, parse_memarg(memarg)),
+        wp::I64AtomicRmw8XchgU { memarg } => Atomic(AtomicOp::I64AtomicRmw8XchgU, parse_memarg(memarg)),
+        wp::I64AtomicRmw16XchgU { memarg } => Atomic(AtomicOp::I64AtomicRmw16XchgU, parse_memarg(memarg)),
+        wp::I64AtomicRmw32XchgU { memarg } => Atomic(AtomicOp::I64AtomicRmw32XchgU, parse_memarg(memarg)),
+        wp::I32AtomicRmwCmpxchg { memarg } => Atomic(AtomicOp::I32AtomicRmwCmpxchg, parse_memarg(memarg)),
+        wp::I64AtomicRmwCmpxchg { memarg } => Atomic(AtomicOp::I64AtomicRmwCmpxchg, parse_memarg(memarg)),
+        wp::I32AtomicRmw8CmpxchgU { memarg } => Atomic(AtomicOp::I32AtomicRmw8CmpxchgU, parse_memarg(memarg)),
+        wp::I32AtomicRmw16CmpxchgU { memarg } => Atomic(AtomicOp::I32AtomicRmw16CmpxchgU, parse_memarg(memarg)),
+        wp::I64AtomicRmw8CmpxchgU { memarg } => Atomic(AtomicOp::I64AtomicRmw8CmpxchgU, parse_memarg(memarg)),
+        wp::I64AtomicRmw16CmpxchgU { memarg } => Atomic(AtomicOp::I64AtomicRmw16CmpxchgU, parse_memarg(memarg)),
+        wp::I64AtomicRmw32CmpxchgU { memarg } => Atomic(AtomicOp::I64AtomicRmw32CmpxchgU, parse_memarg(memarg)),
+        wp::MemoryAtomicNotify { memarg } => Atomic(AtomicOp::MemoryAtomicNotify, parse_memarg(memarg)),
+        wp::MemoryAtomicWait32 { memarg } => Atomic(AtomicOp::MemoryAtomicWait32, parse_memarg(memarg)),
+        wp::MemoryAtomicWait64 { memarg } => Atomic(AtomicOp::MemoryAtomicWait64, parse_memarg(memarg)),
+        wp::AtomicFence => AtomicFence,
+
+        wp::V128Load { memarg } => Load(LoadOp::V128Load, parse_memarg(memarg)),
+        wp::V128Load8x8S { memarg } => Load(LoadOp::V128Load8x8S, parse_memarg(memarg)),
+        wp::V128Load8x8U { memarg } => Load(LoadOp::V128Load8x8U, parse_memarg(memarg)),
+        wp::V128Load16x4S { memarg } => Load(LoadOp::V128Load16x4S, parse_memarg(memarg)),
+        wp::V128Load16x4U { memarg } => Load(LoadOp::V128Load16x4U, parse_memarg(memarg)),
+        wp::V128Load32x2S { memarg } => Load(LoadOp::V128Load32x2S, parse_memarg(memarg)),
+        wp::V128Load32x2U { memarg } => Load(LoadOp::V128Load32x2U, parse_memarg(memarg)),
+        wp::V128Load8Splat { memarg } => Load(LoadOp::V128Load8Splat, parse_memarg(memarg)),
+        wp::V128Load16Splat { memarg } => Load(LoadOp::V128Load16Splat, parse_memarg(memarg)),
+        wp::V128Load32Splat { memarg } => Load(LoadOp::V128Load32Splat, parse_memarg(memarg)),
+        wp::V128Load64Splat { memarg } => Load(LoadOp::V128Load64Splat, parse_memarg(memarg)),
+        wp::V128Load32Zero { memarg } => Load(LoadOp::V128Load32Zero, parse_memarg(memarg)),
+        wp::V128Load64Zero { memarg } => Load(LoadOp::V128Load64Zero, parse_memarg(memarg)),
+        wp::V128Store { memarg } => Store(StoreOp::V128Store, parse_memarg(memarg)),
+        wp::V128Load8Lane { memarg, lane } => LoadLane(LoadLaneOp::V128Load8Lane, parse_memarg(memarg), lane),
+        wp::V128Load16Lane { memarg, lane } => LoadLane(LoadLaneOp::V128Load16Lane, parse_memarg(memarg), lane),
+        wp::V128Load32Lane { memarg, lane } => LoadLane(LoadLaneOp::V128Load32Lane, parse_memarg(memarg), lane),
+        wp::V128Load64Lane { memarg, lane } => LoadLane(LoadLaneOp::V128Load64Lane, parse_memarg(memarg), lane),
+        wp::V128Store8Lane { memarg, lane } => StoreLane(StoreLaneOp::V128Store8Lane, parse_memarg(memarg), lane),
+        wp::V128Store16Lane { memarg, lane } => StoreLane(StoreLaneOp::V128Store16Lane, parse_memarg(memarg), lane),
+        wp::V128Store32Lane { memarg, lane } => StoreLane(StoreLaneOp::V128Store32Lane, parse_memarg(memarg), lane),
+        wp::V128Store64Lane { memarg, lane } => StoreLane(StoreLaneOp::V128Store64Lane, parse_memarg(memarg), lane),
+
+        wp::V128Const { value } => Const(Val::V128(*value.bytes())),
+        wp::I8x16Shuffle { lanes } => I8x16Shuffle(lanes),
+        wp::V128Bitselect => V128Bitselect,
+        wp::I8x16ExtractLaneS { lane } => ExtractLane(ExtractLaneOp::I8x16ExtractLaneS, lane),
+        wp::I8x16ExtractLaneU { lane } => ExtractLane(ExtractLaneOp::I8x16ExtractLaneU, lane),
+        wp::I16x8ExtractLaneS { lane } => ExtractLane(ExtractLaneOp::I16x8ExtractLaneS, lane),
+        wp::I16x8ExtractLaneU { lane } => ExtractLane(ExtractLaneOp::I16x8ExtractLaneU, lane),
+        wp::I32x4ExtractLane { lane } => ExtractLane(ExtractLaneOp::I32x4ExtractLane, lane),
+        wp::I64x2ExtractLane { lane } => ExtractLane(ExtractLaneOp::I64x2ExtractLane, lane),
+        wp::F32x4ExtractLane { lane } => ExtractLane(ExtractLaneOp::F32x4ExtractLane, lane),
+        wp::F64x2ExtractLane { lane } => ExtractLane(ExtractLaneOp::F64x2ExtractLane, lane),
+        wp::I8x16ReplaceLane { lane } => ReplaceLane(ReplaceLaneOp::I8x16ReplaceLane, lane),
+        wp::I16x8ReplaceLane { lane } => ReplaceLane(ReplaceLaneOp::I16x8ReplaceLane, lane),
+        wp::I32x4ReplaceLane { lane } => ReplaceLane(ReplaceLaneOp::I32x4ReplaceLane, lane),
+        wp::I64x2ReplaceLane { lane } => ReplaceLane(ReplaceLaneOp::I64x2ReplaceLane, lane),
+        wp::F32x4ReplaceLane { lane } => ReplaceLane(ReplaceLaneOp::F32x4ReplaceLane, lane),
+        wp::F64x2ReplaceLane { lane } => ReplaceLane(ReplaceLaneOp::F64x2ReplaceLane, lane),
+
+        wp::I8x16Splat => Unary(UnaryOp::I8x16Splat),
+        wp::I16x8Splat => Unary(UnaryOp::I16x8Splat),
+        wp::I32x4Splat => Unary(UnaryOp::I32x4Splat),
+        wp::I64x2Splat => Unary(UnaryOp::I64x2Splat),
+        wp::F32x4Splat => Unary(UnaryOp::F32x4Splat),
+        wp::F64x2Splat => Unary(UnaryOp::F64x2Splat),
+        wp::V128AnyTrue => Unary(UnaryOp::V128AnyTrue),
+        wp::I8x16AllTrue => Unary(UnaryOp::I8x16AllTrue),
+        wp::I8x16Bitmask => Unary(UnaryOp::I8x16Bitmask),
+        wp::I16x8AllTrue => Unary(UnaryOp::I16x8AllTrue),
+        wp::I16x8Bitmask => Unary(UnaryOp::I16x8Bitmask),
+        wp::I32x4AllTrue => Unary(UnaryOp::I32x4AllTrue),
+        wp::I32x4Bitmask => Unary(UnaryOp::I32x4Bitmask),
+        wp::I64x2AllTrue => Unary(UnaryOp::I64x2AllTrue),
+        wp::I64x2Bitmask => Unary(UnaryOp::I64x2Bitmask),
+        wp::V128Not => Unary(UnaryOp::V128Not),
+        wp::I8x16Abs => Unary(UnaryOp::I8x16Abs),
+        wp::I8x16Neg => Unary(UnaryOp::I8x16Neg),
+        wp::I8x16Popcnt => Unary(UnaryOp::I8x16Popcnt),
+        wp::I16x8Abs => Unary(UnaryOp::I16x8Abs),
+        wp::I16x8Neg => Unary(UnaryOp::I16x8Neg),
+        wp::I32x4Abs => Unary(UnaryOp::I32x4Abs),
+        wp::I32x4Neg => Unary(UnaryOp::I32x4Neg),
+        wp::I64x2Abs => Unary(UnaryOp::I64x2Abs),
+        wp::I64x2Neg => Unary(UnaryOp::I64x2Neg),
+        wp::F32x4Ceil => Unary(UnaryOp::F32x4Ceil),
+        wp::F32x4Floor => Unary(UnaryOp::F32x4Floor),
+        wp::F32x4Trunc => Unary(UnaryOp::F32x4Trunc),
+        wp::F32x4Nearest => Unary(UnaryOp::F32x4Nearest),
+        wp::F32x4Abs => Unary(UnaryOp::F32x4Abs),
+        wp::F32x4Neg => Unary(UnaryOp::F32x4Neg),
+        wp::F32x4Sqrt => Unary(UnaryOp::F32x4Sqrt),
+        wp::F64x2Ceil => Unary(UnaryOp::F64x2Ceil),
+        wp::F64x2Floor => Unary(UnaryOp::F64x2Floor),
+        wp::F64x2Trunc => Unary(UnaryOp::F64x2Trunc),
+        wp::F64x2Nearest => Unary(UnaryOp::F64x2Nearest),
+        wp::F64x2Abs => Unary(UnaryOp::F64x2Abs),
+        wp::F64x2Neg => Unary(UnaryOp::F64x2Neg),
+        wp::F64x2Sqrt => Unary(UnaryOp::F64x2Sqrt),
+        wp::I16x8ExtAddPairwiseI8x16S => Unary(UnaryOp::I16x8ExtAddPairwiseI8x16S),
+        wp::I16x8ExtAddPairwiseI8x16U => Unary(UnaryOp::I16x8ExtAddPairwiseI8x16U),
+        wp::I32x4ExtAddPairwiseI16x8S => Unary(UnaryOp::I32x4ExtAddPairwiseI16x8S),
+        wp::I32x4ExtAddPairwiseI16x8U => Unary(UnaryOp::I32x4ExtAddPairwiseI16x8U),
+        wp::I16x8ExtendLowI8x16S => Unary(UnaryOp::I16x8ExtendLowI8x16S),
+        wp::I16x8ExtendHighI8x16S => Unary(UnaryOp::I16x8ExtendHighI8x16S),
+        wp::I16x8ExtendLowI8x16U => Unary(UnaryOp::I16x8ExtendLowI8x16U),
+        wp::I16x8ExtendHighI8x16U => Unary(UnaryOp::I16x8ExtendHighI8x16U),
+        wp::I32x4ExtendLowI16x8S => Unary(UnaryOp::I32x4ExtendLowI16x8S),
+        wp::I32x4ExtendHighI16x8S => Unary(UnaryOp::I32x4ExtendHighI16x8S),
+        wp::I32x4ExtendLowI16x8U => Unary(UnaryOp::I32x4ExtendLowI16x8U),
+        wp::I32x4ExtendHighI16x8U => Unary(UnaryOp::I32x4ExtendHighI16x8U),
+        wp::I64x2ExtendLowI32x4S => Unary(UnaryOp::I64x2ExtendLowI32x4S),
+        wp::I64x2ExtendHighI32x4S => Unary(UnaryOp::I64x2ExtendHighI32x4S),
+        wp::I64x2ExtendLowI32x4U => Unary(UnaryOp::I64x2ExtendLowI32x4U),
+        wp::I64x2ExtendHighI32x4U => Unary(UnaryOp::I64x2ExtendHighI32x4U),
+        wp::I32x4TruncSatF32x4S => Unary(UnaryOp::I32x4TruncSatF32x4S),
+        wp::I32x4TruncSatF32x4U => Unary(UnaryOp::I32x4TruncSatF32x4U),
+        wp::F32x4ConvertI32x4S => Unary(UnaryOp::F32x4ConvertI32x4S),
+        wp::F32x4ConvertI32x4U => Unary(UnaryOp::F32x4ConvertI32x4U),
+        wp::I32x4TruncSatF64x2SZero => Unary(UnaryOp::I32x4TruncSatF64x2SZero),
+        wp::I32x4TruncSatF64x2UZero => Unary(UnaryOp::I32x4TruncSatF64x2UZero),
+        wp::F64x2ConvertLowI32x4S => Unary(UnaryOp::F64x2ConvertLowI32x4S),
+        wp::F64x2ConvertLowI32x4U => Unary(UnaryOp::F64x2ConvertLowI32x4U),
+        wp::F32x4DemoteF64x2Zero => Unary(UnaryOp::F32x4DemoteF64x2Zero),
+        wp::F64x2PromoteLowF32x4 => Unary(UnaryOp::F64x2PromoteLowF32x4),
+
+        wp::I8x16Eq => Binary(BinaryOp::I8x16Eq),
+        wp::I8x16Ne => Binary(BinaryOp::I8x16Ne),
+        wp::I8x16LtS => Binary(BinaryOp::I8x16LtS),
+        wp::I8x16LtU => Binary(BinaryOp::I8x16LtU),
+        wp::I8x16GtS => Binary(BinaryOp::I8x16GtS),
+        wp::I8x16GtU => Binary(BinaryOp::I8x16GtU),
+        wp::I8x16LeS => Binary(BinaryOp::I8x16LeS),
+        wp::I8x16LeU => Binary(BinaryOp::I8x16LeU),
+        wp::I8x16GeS => Binary(BinaryOp::I8x16GeS),
+        wp::I8x16GeU => Binary(BinaryOp::I8x16GeU),
+        wp::I16x8Eq => Binary(BinaryOp::I16x8Eq),
+        wp::I16x8Ne => Binary(BinaryOp::I16x8Ne),
+        wp::I16x8LtS => Binary(BinaryOp::I16x8LtS),
+        wp::I16x8LtU => Binary(BinaryOp::I16x8LtU),
+        wp::I16x8GtS => Binary(BinaryOp::I16x8GtS),
+        wp::I16x8GtU => Binary(BinaryOp::I16x8GtU),
+        wp::I16x8LeS => Binary(BinaryOp::I16x8LeS),
+        wp::I16x8LeU => Binary(BinaryOp::I16x8LeU),
+        wp::I16x8GeS => Binary(BinaryOp::I16x8GeS),
+        wp::I16x8GeU => Binary(BinaryOp::I16x8GeU),
+        wp::I32x4Eq => Binary(BinaryOp::I32x4Eq),
+        wp::I32x4Ne => Binary(BinaryOp::I32x4Ne),
+        wp::I32x4LtS => Binary(BinaryOp::I32x4LtS),
+        wp::I32x4LtU => Binary(BinaryOp::I32x4LtU),
+        wp::I32x4GtS => Binary(BinaryOp::I32x4GtS),
+        wp::I32x4GtU => Binary(BinaryOp::I32x4GtU),
+        wp::I32x4LeS => Binary(BinaryOp::I32x4LeS),
+        wp::I32x4LeU => Binary(BinaryOp::I32x4LeU),
+        wp::I32x4GeS => Binary(BinaryOp::I32x4GeS),
+        wp::I32x4GeU => Binary(BinaryOp::I32x4GeU),
+        wp::I64x2Eq => Binary(BinaryOp::I64x2Eq),
+        wp::I64x2Ne => Binary(BinaryOp::I64x2Ne),
+        wp::I64x2LtS => Binary(BinaryOp::I64x2LtS),
+        wp::I64x2GtS => Binary(BinaryOp::I64x2GtS),
+        wp::I64x2LeS => Binary(BinaryOp::I64x2LeS),
+        wp::I64x2GeS => Binary(BinaryOp::I64x2GeS),
+        wp::F32x4Eq => Binary(BinaryOp::F32x4Eq),
+        wp::F32x4Ne => Binary(BinaryOp::F32x4Ne),
+        wp::F32x4Lt => Binary(BinaryOp::F32x4Lt),
+        wp::F32x4Gt => Binary(BinaryOp::F32x4Gt),
+        wp::F32x4Le => Binary(BinaryOp::F32x4Le),
+        wp::F32x4Ge => Binary(BinaryOp::F32x4Ge),
+        wp::F64x2Eq => Binary(BinaryOp::F64x2Eq),
+        wp::F64x2Ne => Binary(BinaryOp::F64x2Ne),
+        wp::F64x2Lt => Binary(BinaryOp::F64x2Lt),
+        wp::F64x2Gt => Binary(BinaryOp::F64x2Gt),
+        wp::F64x2Le => Binary(BinaryOp::F64x2Le),
+        wp::F64x2Ge => Binary(BinaryOp::F64x2Ge),
+        wp::V128And => Binary(BinaryOp::V128And),
+        wp::V128AndNot => Binary(BinaryOp::V128AndNot),
+        wp::V128Or => Binary(BinaryOp::V128Or),
+        wp::V128Xor => Binary(BinaryOp::V128Xor),
+        wp::I8x16Swizzle => Binary(BinaryOp::I8x16Swizzle),
+        wp::I8x16NarrowI16x8S => Binary(BinaryOp::I8x16NarrowI16x8S),
+        wp::I8x16NarrowI16x8U => Binary(BinaryOp::I8x16NarrowI16x8U),
+        wp::I8x16Add => Binary(BinaryOp::I8x16Add),
+        wp::I8x16AddSatS => Binary(BinaryOp::I8x16AddSatS),
+        wp::I8x16AddSatU => Binary(BinaryOp::I8x16AddSatU),
+        wp::I8x16Sub => Binary(BinaryOp::I8x16Sub),
+        wp::I8x16SubSatS => Binary(BinaryOp::I8x16SubSatS),
+        wp::I8x16SubSatU => Binary(BinaryOp::I8x16SubSatU),
+        wp::I8x16MinS => Binary(BinaryOp::I8x16MinS),
+        wp::I8x16MinU => Binary(BinaryOp::I8x16MinU),
+        wp::I8x16MaxS => Binary(BinaryOp::I8x16MaxS),
+        wp::I8x16MaxU => Binary(BinaryOp::I8x16MaxU),
+        wp::I8x16AvgrU => Binary(BinaryOp::I8x16AvgrU),
+        wp::I16x8Q15MulrSatS => Binary(BinaryOp::I16x8Q15MulrSatS),
+        wp::I16x8NarrowI32x4S => Binary(BinaryOp::I16x8NarrowI32x4S),
+        wp::I16x8NarrowI32x4U => Binary(BinaryOp::I16x8NarrowI32x4U),
+        wp::I16x8Add => Binary(BinaryOp::I16x8Add),
+        wp::I16x8AddSatS => Binary(BinaryOp::I16x8AddSatS),
+        wp::I16x8AddSatU => Binary(BinaryOp::I16x8AddSatU),
+        wp::I16x8Sub => Binary(BinaryOp::I16x8Sub),
+        wp::I16x8SubSatS => Binary(BinaryOp::I16x8SubSatS),
+        wp::I16x8SubSatU => Binary(BinaryOp::I16x8SubSatU),
+        wp::I16x8Mul => Binary(BinaryOp::I16x8Mul),
+        wp::I16x8MinS => Binary(BinaryOp::I16x8MinS),
+        wp::I16x8MinU => Binary(BinaryOp::I16x8MinU),
+        wp::I16x8MaxS => Binary(BinaryOp::I16x8MaxS),
+        wp::I16x8MaxU => Binary(BinaryOp::I16x8MaxU),
+        wp::I16x8AvgrU => Binary(BinaryOp::I16x8AvgrU),
+        wp::I16x8ExtMulLowI8x16S => Binary(BinaryOp::I16x8ExtMulLowI8x16S),
+        wp::I16x8ExtMulHighI8x16S => Binary(BinaryOp::I16x8ExtMulHighI8x16S),
+        wp::I16x8ExtMulLowI8x16U => Binary(BinaryOp::I16x8ExtMulLowI8x16U),
+        wp::I16x8ExtMulHighI8x16U => Binary(BinaryOp::I16x8ExtMulHighI8x16U),
+        wp::I32x4Add => Binary(BinaryOp::I32x4Add),
+        wp::I32x4Sub => Binary(BinaryOp::I32x4Sub),
+        wp::I32x4Mul => Binary(BinaryOp::I32x4Mul),
+        wp::I32x4MinS => Binary(BinaryOp::I32x4MinS),
+        wp::I32x4MinU => Binary(BinaryOp::I32x4MinU),
+        wp::I32x4MaxS => Binary(BinaryOp::I32x4MaxS),
+        wp::I32x4MaxU => Binary(BinaryOp::I32x4MaxU),
+        wp::I32x4DotI16x8S => Binary(BinaryOp::I32x4DotI16x8S),
+        wp::I32x4ExtMulLowI16x8S => Binary(BinaryOp::I32x4ExtMulLowI16x8S),
+        wp::I32x4ExtMulHighI16x8S => Binary(BinaryOp::I32x4ExtMulHighI16x8S),
+        wp::I32x4ExtMulLowI16x8U => Binary(BinaryOp::I32x4ExtMulLowI16x8U),
+        wp::I32x4ExtMulHighI16x8U => Binary(BinaryOp::I32x4ExtMulHighI16x8U),
+        wp::I64x2Add => Binary(BinaryOp::I64x2Add),
+        wp::I64x2Sub => Binary(BinaryOp::I64x2Sub),
+        wp::I64x2Mul => Binary(BinaryOp::I64x2Mul),
+        wp::I64x2ExtMulLowI32x4S => Binary(BinaryOp::I64x2ExtMulLowI32x4S),
+        wp::I64x2ExtMulHighI32x4S => Binary(BinaryOp::I64x2ExtMulHighI32x4S),
+        wp::I64x2ExtMulLowI32x4U => Binary(BinaryOp::I64x2ExtMulLowI32x4U),
+        wp::I64x2ExtMulHighI32x4U => Binary(BinaryOp::I64x2ExtMulHighI32x4U),
+        wp::F32x4Add => Binary(BinaryOp::F32x4Add),
+        wp::F32x4Sub => Binary(BinaryOp::F32x4Sub),
+        wp::F32x4Mul => Binary(BinaryOp::F32x4Mul),
+        wp::F32x4Div => Binary(BinaryOp::F32x4Div),
+        wp::F32x4Min => Binary(BinaryOp::F32x4Min),
+        wp::F32x4Max => Binary(BinaryOp::F32x4Max),
+        wp::F32x4PMin => Binary(BinaryOp::F32x4PMin),
+        wp::F32x4PMax => Binary(BinaryOp::F32x4PMax),
+        wp::F64x2Add => Binary(BinaryOp::F64x2Add),
+        wp::F64x2Sub => Binary(BinaryOp::F64x2Sub),
+        wp::F64x2Mul => Binary(BinaryOp::F64x2Mul),
+        wp::F64x2Div => Binary(BinaryOp::F64x2Div),
+        wp::F64x2Min => Binary(BinaryOp::F64x2Min),
+        wp::F64x2Max => Binary(BinaryOp::F64x2Max),
+        wp::F64x2PMin => Binary(BinaryOp::F64x2PMin),
+        wp::F64x2PMax => Binary(BinaryOp::F64x2PMax),
+        wp::I8x16Shl => Binary(BinaryOp::I8x16Shl),
+        wp::I8x16ShrS => Binary(BinaryOp::I8x16ShrS),
+        wp::I8x16ShrU => Binary(BinaryOp::I8x16ShrU),
+        wp::I16x8Shl => Binary(BinaryOp::I16x8Shl),
+        wp::I16x8ShrS => Binary(BinaryOp::I16x8ShrS),
+        wp::I16x8ShrU => Binary(BinaryOp::I16x8ShrU),
+        wp::I32x4Shl => Binary(BinaryOp::I32x4Shl),
+        wp::I32x4ShrS => Binary(BinaryOp::I32x4ShrS),
+        wp::I32x4ShrU => Binary(BinaryOp::I32x4ShrU),
+        wp::I64x2Shl => Binary(BinaryOp::I64x2Shl),
+        wp::I64x2ShrS => Binary(BinaryOp::I64x2ShrS),
+        wp::I64x2ShrU => Binary(BinaryOp::I64x2ShrU),
 
         | wp::I8x16RelaxedSwizzle
         | wp::I32x4RelaxedTruncSatF32x4S
@@ -1081,56 +1263,149 @@ fn parse_instr(
         | wp::I16x8DotI8x16I7x16S
         | wp::I32x4DotI8x16I7x16AddS
         | wp::F32x4RelaxedDotBf16x8AddF32x4 => Err(ParseIssue::unsupported(offset, WasmExtension::RelaxedSimd))?,
-    })
+    };
+    add_instr_extensions(&instr, metadata);
+    Ok(instr)
 }
 
-fn parse_memarg(memarg: wp::MemArg, parser_offset: usize) -> Result<Memarg, ParseError> {
-    if memarg.memory != 0 {
-        Err(ParseIssue::unsupported(parser_offset, WasmExtension::MultiMemory))?
+/// Records the extensions that introduced `instr` (if any) in the module metadata.
+fn add_instr_extensions(instr: &Instr, metadata: &RwLock<ModuleMetadata>) {
+    use crate::Instr::*;
+    use crate::UnaryOp::*;
+    use WasmExtension::*;
+    match instr {
+        Unary(I32Extend8S | I32Extend16S | I64Extend8S | I64Extend16S | I64Extend32S) => add_used_extension(metadata, SignExtensionOps),
+        Unary(I32TruncSatF32S | I32TruncSatF32U | I32TruncSatF64S | I32TruncSatF64U | I64TruncSatF32S | I64TruncSatF32U | I64TruncSatF64S | I64TruncSatF64U) => {
+            add_used_extension(metadata, NontrappingFloatToInt)
+        }
+        MemoryInit(..) | DataDrop(_) | MemoryCopy { .. } | MemoryFill(_) | TableInit(..) | ElemDrop(_) | TableCopy { .. } => {
+            add_used_extension(metadata, BulkMemoryOperations)
+        }
+        TypedSelect(_) | RefNull(_) | RefIsNull | RefFunc(_) | TableGet(_) | TableSet(_) | TableSize(_) | TableGrow(_) | TableFill(_) => {
+            add_used_extension(metadata, ReferenceTypes)
+        }
+        CallIndirect(_, table) if table.to_u32() != 0 => add_used_extension(metadata, ReferenceTypes),
+        ReturnCall(_) => add_used_extension(metadata, TailCalls),
+        Try(_) | Catch(_) | CatchAll | Delegate(_) | Throw(_) | Rethrow(_) => add_used_extension(metadata, ExceptionHandling),
+        ReturnCallIndirect(_, table) => {
+            add_used_extension(metadata, TailCalls);
+            if table.to_u32() != 0 {
+                add_used_extension(metadata, ReferenceTypes);
+            }
+        }
+        Const(Val::V128(_)) | LoadLane(..) | StoreLane(..) | ExtractLane(..) | ReplaceLane(..) | I8x16Shuffle(_) | V128Bitselect => {
+            add_used_extension(metadata, Simd)
+        }
+        AtomicLoad(..) | AtomicStore(..) | Atomic(..) | AtomicFence => add_used_extension(metadata, ThreadsAtomics),
+        Load(op, _) => add_val_ty_extensions(op.to_type().results(), metadata),
+        Store(op, _) => add_val_ty_extensions(op.to_type().inputs(), metadata),
+        Unary(op) => add_val_ty_extensions(op.to_type().inputs(), metadata),
+        Binary(op) => {
+            let type_ = op.to_type();
+            add_val_ty_extensions(type_.inputs(), metadata);
+            add_val_ty_extensions(type_.results(), metadata);
+        }
+        _ => {}
+    }
+
+    // Multi-memory has no instructions of its own, but allows to access memories other than the first.
+    let accesses_other_memory = match instr {
+        Load(_, memarg) | Store(_, memarg) | LoadLane(_, memarg, _) | StoreLane(_, memarg, _) | AtomicLoad(_, memarg) | AtomicStore(_, memarg) | Atomic(_, memarg) => {
+            memarg.memory.to_u32() != 0
+        }
+        MemorySize(memory) | MemoryGrow(memory) | MemoryInit(_, memory) | MemoryFill(memory) => memory.to_u32() != 0,
+        MemoryCopy { dst, src } => dst.to_u32() != 0 || src.to_u32() != 0,
+        _ => false,
+    };
+    if accesses_other_memory {
+        add_used_extension(metadata, MultiMemory);
     }
-    let offset: u32 = memarg
-        .offset
-        .try_into()
-        .map_err(|_| ParseIssue::unsupported(parser_offset, WasmExtension::Memory64))?;
-    Ok(Memarg {
-        alignment_exp: memarg.align,
-        offset,
-    })
 }
 
-fn parse_memory_ty(ty: wp::MemoryType, offset: usize) -> Result<Limits, ParseError> {
-    if ty.memory64 {
-        Err(ParseIssue::unsupported(offset, WasmExtension::Memory64))?
+/// Records the extensions that introduced the value types `tys` (if any) in the module metadata.
+fn add_val_ty_extensions(tys: &[ValType], metadata: &RwLock<ModuleMetadata>) {
+    for ty in tys {
+        if ty.is_ref() {
+            add_used_extension(metadata, WasmExtension::ReferenceTypes);
+        } else if *ty == ValType::V128 {
+            add_used_extension(metadata, WasmExtension::Simd);
+        }
     }
-    if ty.shared {
-        Err(ParseIssue::unsupported(offset, WasmExtension::ThreadsAtomics))?
+}
+
+/// Records the reference types extension if there is more than one table or a table of `externref`s.
+fn add_table_extensions(tables: &[Table], metadata: &RwLock<ModuleMetadata>) {
+    if tables.len() > 1 || tables.iter().any(|table| table.elem_type == ValType::ExternRef) {
+        add_used_extension(metadata, WasmExtension::ReferenceTypes);
     }
-    Ok(Limits {
-        initial_size: ty
-            .initial
-            .try_into()
-            .expect("guaranteed u32 by wasmparser if !memory64"),
-        max_size: ty
-            .maximum
-            .map(|u| u.try_into().expect("guaranteed u32 by wasmparser if !memory64")),
-    })
 }
 
-fn parse_table_ty(ty: wp::TableType, offset: usize) -> Result<Limits, ParseError> {
-    parse_elem_ty(ty.element_type, offset)?;
-    Ok(Limits {
+/// Records the extended constant expressions extension if the constant expression `expr` contains arithmetic.
+fn add_const_expr_extensions(expr: &[Instr], metadata: &RwLock<ModuleMetadata>) {
+    if expr.iter().any(|instr| matches!(instr, Instr::Binary(_))) {
+        add_used_extension(metadata, WasmExtension::ExtendedConst);
+    }
+}
+
+/// Records the extensions that introduced the memory types of `memories` (if any) in the module metadata.
+fn add_memory_extensions(memories: &[Memory], metadata: &RwLock<ModuleMetadata>) {
+    if memories.iter().any(|memory| memory.shared) {
+        add_used_extension(metadata, WasmExtension::ThreadsAtomics);
+    }
+    if memories.len() > 1 {
+        add_used_extension(metadata, WasmExtension::MultiMemory);
+    }
+    if memories.iter().any(|memory| memory.memory64) {
+        add_used_extension(metadata, WasmExtension::Memory64);
+    }
+}
+
+/// Like `ModuleMetadata::add_used_extension`, but takes the write lock only if the extension was not
+/// recorded yet, since this is called for many instructions while parsing function bodies in parallel.
+fn add_used_extension(metadata: &RwLock<ModuleMetadata>, extension: WasmExtension) {
+    if !metadata.read().unwrap().used_extensions().any(|used| used == extension) {
+        metadata.write().unwrap().add_used_extension(extension);
+    }
+}
+
+fn parse_memarg(memarg: wp::MemArg) -> Memarg {
+    Memarg {
+        alignment_exp: memarg.align,
+        offset: memarg.offset,
+        memory: memarg.memory.into(),
+    }
+}
+
+/// Returns the limits and whether the memory is shared and 64-bit.
+fn parse_memory_ty(ty: wp::MemoryType) -> (Limits, bool, bool) {
+    let limits = Limits {
         initial_size: ty.initial,
         max_size: ty.maximum,
-    })
+    };
+    (limits, ty.shared, ty.memory64)
+}
+
+fn parse_tag_ty(ty: wp::TagType, offset: usize, types: &Types) -> Result<FunctionType, ParseError> {
+    // `TagKind::Exception` is the only kind of tag so far, so the function type is all there is.
+    let wp::TagType { kind: wp::TagKind::Exception, func_type_idx } = ty;
+    types.get(func_type_idx, offset)
 }
 
-fn parse_elem_ty(ty: wp::ValType, offset: usize) -> Result<(), ParseError> {
+fn parse_table_ty(ty: wp::TableType, offset: usize) -> Result<(ValType, Limits), ParseError> {
+    let elem_type = parse_elem_ty(ty.element_type, offset)?;
+    Ok((elem_type, Limits {
+        initial_size: ty.initial.into(),
+        max_size: ty.maximum.map(Into::into),
+    }))
+}
+
+fn parse_elem_ty(ty: wp::ValType, offset: usize) -> Result<ValType, ParseError> {
     use wp::ValType::*;
     match ty {
         I32 | I64 | F32 | F64 => Err(ParseIssue::message(offset, "only reftypes, not value types are allowed as table elements", None))?,
         V128 => Err(ParseIssue::message(offset, "only reftypes, not value types are allowed as table elements", None))?,
-        FuncRef => Ok(()),
-        ExternRef => Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?,
+        FuncRef => Ok(ValType::FuncRef),
+        ExternRef => Ok(ValType::ExternRef),
     }
 }
 
@@ -1143,9 +1418,13 @@ fn parse_block_ty(
     use wp::BlockType::*;
     match ty {
         Empty => Ok(FunctionType::empty()),
-        Type(ty) => Ok(FunctionType::new(&[], &[parse_val_ty(ty, offset)?])),
+        Type(ty) => {
+            let ty = parse_val_ty(ty, offset)?;
+            add_val_ty_extensions(&[ty], metadata);
+            Ok(FunctionType::new(&[], &[ty]))
+        }
         FuncType(type_idx) => {
-            metadata.write().unwrap().add_used_extension(WasmExtension::MultiValue);
+            add_used_extension(metadata, WasmExtension::MultiValue);
             types.get(type_idx, offset)
         }
     }
@@ -1179,15 +1458,17 @@ fn parse_global_ty(ty: wp::GlobalType, offset: usize) -> Result<GlobalType, Pars
     ))
 }
 
-fn parse_val_ty(ty: wp::ValType, offset: usize) -> Result<ValType, ParseError> {
+// All value types of the parser are supported (i.e., the result is always `Ok`) since SIMD, but
+// keep the signature for consistency with the other `parse_*_ty` functions.
+fn parse_val_ty(ty: wp::ValType, _offset: usize) -> Result<ValType, ParseError> {
     match ty {
         wp::ValType::I32 => Ok(ValType::I32),
         wp::ValType::I64 => Ok(ValType::I64),
         wp::ValType::F32 => Ok(ValType::F32),
         wp::ValType::F64 => Ok(ValType::F64),
-        wp::ValType::V128 => Err(ParseIssue::unsupported(offset, WasmExtension::Simd))?,
-        wp::ValType::FuncRef => Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?,
-        wp::ValType::ExternRef => Err(ParseIssue::unsupported(offset, WasmExtension::ReferenceTypes))?,
+        wp::ValType::V128 => Ok(ValType::V128),
+        wp::ValType::FuncRef => Ok(ValType::FuncRef),
+        wp::ValType::ExternRef => Ok(ValType::ExternRef),
     }
 }
 
@@ -1225,6 +1506,10 @@ fn parse_name_custom_section(
                         .functions
                         .get_mut(u32_to_usize(function_index))
                         .ok_or_else(|| ParseIssue::index(offset, function_index, "function"))?;
+                    // Parse lazily parsed bodies here, to report errors instead of panicking below.
+                    if let ImportOrPresent::Present(code) = &function.code {
+                        code.try_get().map_err(|err| *err.into_issue())?;
+                    }
 
                     for elem in names.into_iter_with_offsets() {
                         let (offset, wp::Naming { index: local_index, name }) = elem?;
@@ -1240,15 +1525,92 @@ fn parse_name_custom_section(
                     }
                 }
             }
-            Name::Label(name_map) => {
-                warnings.push(ParseIssue::unsupported(name_map.range().start, WasmExtension::ExtendedNameSection))
+            Name::Label(indirect_name_map) => {
+                for elem in indirect_name_map.into_iter_with_offsets() {
+                    let (offset, wp::IndirectNaming { index: function_index, names }) = elem?;
+                    let code = match &mut module
+                        .functions
+                        .get_mut(u32_to_usize(function_index))
+                        .ok_or_else(|| ParseIssue::index(offset, function_index, "function"))?
+                        .code
+                    {
+                        ImportOrPresent::Present(code) => {
+                            // See local names above.
+                            code.try_get().map_err(|err| *err.into_issue())?;
+                            code.get_mut()
+                        }
+                        ImportOrPresent::Import(_, _) => Err(ParseIssue::message(offset, "name section: label names for imported function", None))?,
+                    };
+
+                    // Label indices count the block-introducing instructions in the body.
+                    let block_count = code.body
+                        .iter()
+                        .filter(|instr| matches!(instr, Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Try(_)))
+                        .count();
+
+                    for elem in names.into_iter_with_offsets() {
+                        let (offset, wp::Naming { index: label_index, name }) = elem?;
+                        if u32_to_usize(label_index) >= block_count {
+                            warnings.push(ParseIssue::index(offset, label_index, "label"));
+                        } else {
+                            *code.label_name_mut(u32_to_usize(label_index)) = Some(name.to_string());
+                        }
+                    }
+                }
             }
-            Name::Type(name_map)
-            | Name::Table(name_map)
-            | Name::Memory(name_map)
-            | Name::Global(name_map)
-            | Name::Element(name_map)
-            | Name::Data(name_map) => {
+            Name::Global(name_map) => {
+                for elem in name_map.into_iter_with_offsets() {
+                    let (offset, wp::Naming { index: global_index, name }) = elem?;
+                    module
+                        .globals
+                        .get_mut(u32_to_usize(global_index))
+                        .ok_or_else(|| ParseIssue::index(offset, global_index, "global"))?
+                        .name = Some(name.to_string());
+                }
+            }
+            Name::Table(name_map) => {
+                for elem in name_map.into_iter_with_offsets() {
+                    let (offset, wp::Naming { index: table_index, name }) = elem?;
+                    module
+                        .tables
+                        .get_mut(u32_to_usize(table_index))
+                        .ok_or_else(|| ParseIssue::index(offset, table_index, "table"))?
+                        .name = Some(name.to_string());
+                }
+            }
+            Name::Memory(name_map) => {
+                for elem in name_map.into_iter_with_offsets() {
+                    let (offset, wp::Naming { index: memory_index, name }) = elem?;
+                    module
+                        .memories
+                        .get_mut(u32_to_usize(memory_index))
+                        .ok_or_else(|| ParseIssue::index(offset, memory_index, "memory"))?
+                        .name = Some(name.to_string());
+                }
+            }
+            Name::Element(name_map) => {
+                for elem in name_map.into_iter_with_offsets() {
+                    let (offset, wp::Naming { index: element_index, name }) = elem?;
+                    module
+                        .elements
+                        .get_mut(u32_to_usize(element_index))
+                        .ok_or_else(|| ParseIssue::index(offset, element_index, "element"))?
+                        .name = Some(name.to_string());
+                }
+            }
+            Name::Data(name_map) => {
+                for elem in name_map.into_iter_with_offsets() {
+                    let (offset, wp::Naming { index: data_index, name }) = elem?;
+                    module
+                        .data
+                        .get_mut(u32_to_usize(data_index))
+                        .ok_or_else(|| ParseIssue::index(offset, data_index, "data"))?
+                        .name = Some(name.to_string());
+                }
+            }
+            // Function types are not separate entities in our AST (see `Function::type_`), so
+            // there is nothing to attach their names to.
+            Name::Type(name_map) => {
                 warnings.push(ParseIssue::unsupported(name_map.range().start, WasmExtension::ExtendedNameSection))
             }
             | Name::Unknown {
@@ -1262,9 +1624,123 @@ fn parse_name_custom_section(
     Ok(())
 }
 
+/// Parses the text format by converting it to the binary format with `wast` first, which does all
+/// the heavy lifting of resolving identifiers, unfolding expressions, expanding abbreviations, etc.
+/// Identifiers end up in the name section of the binary and thus as names in our AST.
+pub fn parse_module_text(text: &str) -> Result<(Module, ParseWarnings), ParseError> {
+    let text_error = |err: wast::Error| {
+        let (line, column) = err.span().linecol_in(text);
+        ParseIssue::Text {
+            line: line + 1,
+            column: column + 1,
+            message: err.message(),
+        }
+    };
+    let buffer = wast::parser::ParseBuffer::new(text).map_err(text_error)?;
+    let mut wat = wast::parser::parse::<wast::Wat>(&buffer).map_err(text_error)?;
+    if let wast::Wat::Component(component) = &wat {
+        let message = "components are not supported in the text format, only core modules".to_string();
+        Err(text_error(wast::Error::new(component.span, message)))?
+    }
+    let bytes = wat.encode().map_err(text_error)?;
+
+    // Offsets into the intermediate binary are meaningless to the caller, so drop them.
+    let (module, _offsets, warnings) = parse_module(&bytes)?;
+    Ok((module, warnings))
+}
+
+pub fn parse_component(bytes: &[u8]) -> Result<(Component, ParseWarnings), ParseError> {
+    let mut warnings = Vec::new();
+    let component = parse_component_at_offset(bytes, 0, &mut warnings)?;
+    Ok((component, warnings))
+}
+
+// We do not use `wp::Parser` here, because it would descend into the nested core modules, which we
+// want to parse as a whole with `parse_module` instead.
+// Also, it only accepts a single (by now outdated) version of the component binary format.
+fn parse_component_at_offset(
+    bytes: &[u8],
+    offset: usize,
+    warnings: &mut Vec<ParseIssue>,
+) -> Result<Component, ParseError> {
+    let mut reader = wp::BinaryReader::new_with_offset(bytes, offset);
+
+    // Preamble: magic number, version, and layer (which is 0 for core modules, 1 for components).
+    if reader.read_bytes(4)? != b"\0asm" {
+        Err(ParseIssue::message(offset, "magic header not detected", None))?
+    }
+    let version = u16::from_le_bytes(reader.read_bytes(2)?.try_into().expect("read exactly 2 bytes"));
+    let layer_offset = reader.original_position();
+    let layer = u16::from_le_bytes(reader.read_bytes(2)?.try_into().expect("read exactly 2 bytes"));
+    if layer != 1 {
+        Err(ParseIssue::message(layer_offset, "not a component (but maybe a core module), expected layer 1", None))?
+    }
+
+    let mut sections = Vec::new();
+    while !reader.eof() {
+        let id = reader.read_u8()?;
+        let size = reader.read_var_u32()?;
+        let content_offset = reader.original_position();
+        let content = reader.read_bytes(u32_to_usize(size))?;
+
+        sections.push(match id {
+            ComponentSection::MODULE_ID => {
+                // Offsets in the module (and its warnings) are relative to the start of the module.
+                let (module, _offsets, module_warnings) = parse_module(content).map_err(|err| {
+                    ParseIssue::message(content_offset, "could not parse core module of component", Some(err.into_issue()))
+                })?;
+                warnings.extend(module_warnings);
+                ComponentSection::Module(Box::new(module))
+            }
+            ComponentSection::COMPONENT_ID => {
+                ComponentSection::Component(parse_component_at_offset(content, content_offset, warnings)?)
+            }
+            id => ComponentSection::Raw {
+                id,
+                content: content.to_vec(),
+            },
+        });
+    }
+
+    Ok(Component { version, sections })
+}
+
+/// Everything needed to parse function bodies on demand, shared by all bodies of a module.
+pub(crate) struct LazyContext {
+    // Only the contents of the code section (after the count of entries), but ranges of bodies
+    // are relative to the whole binary, such that offsets in errors are the same as for eager parsing.
+    code_section: Vec<u8>,
+    code_section_offset: usize,
+    types: Types,
+    // Extensions used in lazily parsed bodies end up here, not in the metadata of the module.
+    metadata: RwLock<ModuleMetadata>,
+    // Bodies can only refer to data segments if there is a data count section, so the encoder
+    // must also add one if it copies such bodies verbatim.
+    pub has_data_count_section: bool,
+}
+
+impl LazyContext {
+    /// The types in the order of the original type section, i.e., what type indices refer to.
+    pub fn types(&self) -> &[FunctionType] {
+        self.types.0.as_deref().unwrap_or(&[])
+    }
+
+    pub fn bytes(&self, range: Range<usize>) -> &[u8] {
+        &self.code_section[range.start - self.code_section_offset..range.end - self.code_section_offset]
+    }
+}
+
+pub(crate) fn parse_lazy_body(context: &LazyContext, range: Range<usize>) -> Result<Code, ParseError> {
+    let mut body = wp::FunctionBody::new(range.start, context.bytes(range));
+    // See `wp::Payload::CodeSectionEntry` in `parse_module_with`.
+    body.allow_memarg64(true);
+    parse_body(body, &context.types, &context.metadata, None)
+}
+
 // Wrapper for type map, to offer some convenience like:
 // - u32 indices (which we get from wasmparser) instead of usize (which Vec expects)
 // - checking that type section exists only a single time and type index is valid
+#[derive(Clone)]
 struct Types(Option<Vec<FunctionType>>);
 
 impl Types {
@@ -0,0 +1,540 @@
+//! Printing our AST in the WebAssembly text format, mostly for debugging (e.g., of instrumented
+//! modules). Unlike external tools (e.g., `wasm2wat`), this does not require a valid module:
+//! out-of-bounds indices are printed as-is and unbalanced blocks just mess up the indentation.
+//!
+//! Entities are printed in the order of the binary, i.e., imports first. Indices in the text
+//! (as well as in the `(;0;)` comments) are thus the same as in the encoded binary, which differ
+//! from our AST indices if imports were added after other functions, e.g., by `add_function_import`.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Write;
+
+use crate::*;
+
+/// How many instructions to look ahead when trying to find the next original instruction in an
+/// instrumented body, before considering the original instruction as removed.
+const RESYNC_WINDOW: usize = 256;
+
+/// Pad instructions to this width before the comment with their index, for readability.
+const INSTR_COMMENT_COLUMN: usize = 48;
+
+pub fn print_module(module: &Module, original: Option<&Module>) -> String {
+    let mut printer = Printer::new(module);
+    printer.module(original).expect("writing to a String cannot fail");
+    printer.out
+}
+
+struct Printer<'a> {
+    module: &'a Module,
+    out: String,
+    functions: IndexSpace,
+    globals: IndexSpace,
+    tables: IndexSpace,
+    memories: IndexSpace,
+    tags: IndexSpace,
+    elements: IndexSpace,
+    data: IndexSpace,
+}
+
+/// How the entities of one index space are referred to in the text format.
+struct IndexSpace {
+    /// AST indices in the order of the binary, i.e., imports first.
+    order: Vec<usize>,
+    /// Per AST index, the identifier (if the entity has a name) or the index in the binary.
+    references: Vec<String>,
+    /// Per AST index, the index in the binary.
+    binary_indices: Vec<usize>,
+}
+
+impl IndexSpace {
+    fn new<'a>(entities: impl Iterator<Item = (Option<&'a str>, bool)>) -> Self {
+        let entities: Vec<_> = entities.collect();
+        let (imports, definitions): (Vec<usize>, Vec<usize>) = (0..entities.len()).partition(|&idx| entities[idx].1);
+        let order: Vec<usize> = imports.into_iter().chain(definitions).collect();
+
+        let mut binary_indices = vec![0; entities.len()];
+        for (binary_idx, &idx) in order.iter().enumerate() {
+            binary_indices[idx] = binary_idx;
+        }
+
+        let mut identifiers = Identifiers::default();
+        let references = entities
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, _))| match name {
+                Some(name) => identifiers.unique(name),
+                None => binary_indices[idx].to_string(),
+            })
+            .collect();
+
+        IndexSpace { order, references, binary_indices }
+    }
+
+    fn reference(&self, idx: usize) -> String {
+        match self.references.get(idx) {
+            Some(reference) => reference.clone(),
+            // Invalid module, print at least something.
+            None => idx.to_string(),
+        }
+    }
+
+    /// The identifier (if any) and the binary index as a comment, as in `wasm2wat`.
+    fn definition(&self, idx: usize) -> String {
+        let reference = &self.references[idx];
+        let binary_idx = self.binary_indices[idx];
+        if reference.starts_with('$') {
+            format!("{reference} (;{binary_idx};)")
+        } else {
+            format!("(;{binary_idx};)")
+        }
+    }
+
+    fn is_first(&self, idx: usize) -> bool {
+        self.binary_indices.get(idx) == Some(&0)
+    }
+}
+
+/// Names in our AST can be arbitrary strings, but identifiers in the text format are restricted
+/// to some characters and must be unique (per index space or function), so fix that up.
+#[derive(Default)]
+struct Identifiers(HashSet<String>);
+
+impl Identifiers {
+    fn unique(&mut self, name: &str) -> String {
+        let base = sanitize_identifier(name);
+        let mut identifier = base.clone();
+        let mut suffix = 0;
+        while !self.0.insert(identifier.clone()) {
+            suffix += 1;
+            identifier = format!("{base}.{suffix}");
+        }
+        identifier
+    }
+}
+
+fn sanitize_identifier(name: &str) -> String {
+    // See https://webassembly.github.io/spec/core/text/values.html#text-idchar
+    let is_idchar = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c);
+    let mut identifier = String::with_capacity(name.len() + 1);
+    identifier.push('$');
+    identifier.extend(name.chars().map(|c| if is_idchar(c) { c } else { '_' }));
+    if name.is_empty() {
+        identifier.push('_');
+    }
+    identifier
+}
+
+fn string(bytes: &[u8]) -> String {
+    let mut string = String::with_capacity(bytes.len() + 2);
+    string.push('"');
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(string, "\\{}", byte as char).unwrap(),
+            0x20..=0x7e => string.push(byte as char),
+            _ => write!(string, "\\{byte:02x}").unwrap(),
+        }
+    }
+    string.push('"');
+    string
+}
+
+fn function_type(type_: FunctionType, param_names: &[String]) -> String {
+    let mut text = String::new();
+    for (i, input) in type_.inputs().iter().enumerate() {
+        match param_names.get(i) {
+            Some(name) if name.starts_with('$') => write!(text, " (param {name} {input})").unwrap(),
+            _ => write!(text, " (param {input})").unwrap(),
+        }
+    }
+    if !type_.results().is_empty() {
+        text.push_str(" (result");
+        for result in type_.results() {
+            write!(text, " {result}").unwrap();
+        }
+        text.push(')');
+    }
+    text
+}
+
+fn val(val: Val) -> String {
+    fn float(bits: u64, is_nan: bool, is_negative: bool, mantissa_mask: u64, display: String) -> String {
+        if is_nan {
+            // Keep the payload, which Rust's `Display` would drop.
+            let sign = if is_negative { "-" } else { "" };
+            format!("{sign}nan:0x{:x}", bits & mantissa_mask)
+        } else {
+            // Rust's `Display` is exact (i.e., roundtrips), but prints `inf` as in the text format.
+            display
+        }
+    }
+    match val {
+        Val::F32(v) => {
+            let v = v.into_inner();
+            float(v.to_bits().into(), v.is_nan(), v.is_sign_negative(), (1 << 23) - 1, v.to_string())
+        }
+        Val::F64(v) => {
+            let v = v.into_inner();
+            float(v.to_bits(), v.is_nan(), v.is_sign_negative(), (1 << 52) - 1, v.to_string())
+        }
+        Val::Ref { type_, is_null: _ } => heap_type(type_).to_string(),
+        val => val.to_string(),
+    }
+}
+
+fn heap_type(type_: ValType) -> &'static str {
+    match type_ {
+        ValType::ExternRef => "extern",
+        _ => "func",
+    }
+}
+
+struct MemargText<Op>(Memarg, Op);
+
+impl<Op: MemoryOp> fmt::Display for MemargText<Op> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f, self.1)
+    }
+}
+
+impl<'a> Printer<'a> {
+    fn new(module: &'a Module) -> Self {
+        Printer {
+            module,
+            out: String::new(),
+            functions: IndexSpace::new(module.functions.iter().map(|f| (f.name.as_deref(), f.import().is_some()))),
+            globals: IndexSpace::new(module.globals.iter().map(|g| (g.name.as_deref(), g.import().is_some()))),
+            tables: IndexSpace::new(module.tables.iter().map(|t| (t.name.as_deref(), t.import.is_some()))),
+            memories: IndexSpace::new(module.memories.iter().map(|m| (m.name.as_deref(), m.import.is_some()))),
+            tags: IndexSpace::new(module.tags.iter().map(|t| (None, t.import.is_some()))),
+            elements: IndexSpace::new(module.elements.iter().map(|e| (e.name.as_deref(), false))),
+            data: IndexSpace::new(module.data.iter().map(|d| (d.name.as_deref(), false))),
+        }
+    }
+
+    fn module(&mut self, original: Option<&Module>) -> fmt::Result {
+        let module = self.module;
+        match &module.name {
+            Some(name) => writeln!(self.out, "(module {}", sanitize_identifier(name))?,
+            None => writeln!(self.out, "(module")?,
+        }
+
+        // All imports must come before all definitions in the text format, so go over the index
+        // spaces twice. In each, imports come first anyway, see `IndexSpace::order`.
+        for imports in [true, false] {
+            for idx in self.functions.order.clone() {
+                let function = &module.functions[idx];
+                if function.import().is_some() == imports {
+                    let original = original.and_then(|original| original.functions.get(idx));
+                    self.function(idx, function, original)?;
+                }
+            }
+            for &idx in &self.tables.order {
+                let table = &module.tables[idx];
+                if table.import.is_some() == imports {
+                    let limits = self.limits(table.limits);
+                    let header = self.header(self.tables.definition(idx), &table.export, table.import.as_ref());
+                    writeln!(self.out, "  (table {header}{limits} {})", table.elem_type)?;
+                }
+            }
+            for &idx in &self.memories.order {
+                let memory = &module.memories[idx];
+                if memory.import.is_some() == imports {
+                    let header = self.header(self.memories.definition(idx), &memory.export, memory.import.as_ref());
+                    let index_type = if memory.memory64 { " i64" } else { "" };
+                    let limits = self.limits(memory.limits);
+                    let shared = if memory.shared { " shared" } else { "" };
+                    writeln!(self.out, "  (memory {header}{index_type}{limits}{shared})")?;
+                }
+            }
+            for &idx in &self.globals.order {
+                let global = &module.globals[idx];
+                let import = match &global.init {
+                    ImportOrPresent::Import(module, name) => Some((module.clone(), name.clone())),
+                    ImportOrPresent::Present(_) => None,
+                };
+                if import.is_some() == imports {
+                    let header = self.header(self.globals.definition(idx), &global.export, import.as_ref());
+                    let type_ = match global.type_.1 {
+                        Mutability::Const => global.type_.0.to_string(),
+                        Mutability::Mut => format!("(mut {})", global.type_.0),
+                    };
+                    match &global.init {
+                        ImportOrPresent::Import(_, _) => writeln!(self.out, "  (global {header} {type_})")?,
+                        ImportOrPresent::Present(init) => {
+                            writeln!(self.out, "  (global {header} {type_} {})", self.const_expr(init))?
+                        }
+                    }
+                }
+            }
+            for &idx in &self.tags.order {
+                let tag = &module.tags[idx];
+                if tag.import.is_some() == imports {
+                    let header = self.header(self.tags.definition(idx), &tag.export, tag.import.as_ref());
+                    writeln!(self.out, "  (tag {header}{})", function_type(tag.type_, &[]))?;
+                }
+            }
+        }
+
+        if let Some(start) = module.start {
+            writeln!(self.out, "  (start {})", self.functions.reference(start.to_usize()))?;
+        }
+
+        for (idx, element) in module.elements() {
+            let mut text = format!("  (elem {}", self.elements.definition(idx.to_usize()));
+            match &element.mode {
+                ElementMode::Active { table, offset } => write!(
+                    text,
+                    " (table {}) (offset {})",
+                    self.tables.reference(table.to_usize()),
+                    self.const_expr(offset)
+                )?,
+                ElementMode::Passive => {}
+                ElementMode::Declared => text.push_str(" declare"),
+            }
+            match &element.items {
+                ElementItems::Functions(functions) => {
+                    text.push_str(" func");
+                    for function in functions {
+                        write!(text, " {}", self.functions.reference(function.to_usize()))?;
+                    }
+                }
+                ElementItems::Expressions(type_, items) => {
+                    write!(text, " {type_}")?;
+                    for item in items {
+                        write!(text, " (item {})", self.const_expr(item))?;
+                    }
+                }
+            }
+            writeln!(self.out, "{text})")?;
+        }
+
+        for (idx, data) in module.data() {
+            let mut text = format!("  (data {}", self.data.definition(idx.to_usize()));
+            if let DataMode::Active { memory, offset } = &data.mode {
+                write!(
+                    text,
+                    " (memory {}) (offset {})",
+                    self.memories.reference(memory.to_usize()),
+                    self.const_expr(offset)
+                )?;
+            }
+            writeln!(self.out, "{text} {})", string(&data.bytes))?;
+        }
+
+        for custom in &module.custom_sections {
+            writeln!(self.out, "  ;; custom section {} with {} bytes", string(custom.name.as_bytes()), custom.content.len())?;
+        }
+
+        writeln!(self.out, ")")
+    }
+
+    /// Identifier, inline exports, and inline import of a module field.
+    fn header<S: AsRef<str>>(&self, definition: String, export: &[String], import: Option<&(S, S)>) -> String {
+        let mut text = definition;
+        for name in export {
+            write!(text, " (export {})", string(name.as_bytes())).unwrap();
+        }
+        if let Some((module, name)) = import {
+            write!(text, " (import {} {})", string(module.as_ref().as_bytes()), string(name.as_ref().as_bytes())).unwrap();
+        }
+        text
+    }
+
+    fn limits(&self, limits: Limits) -> String {
+        match limits.max_size {
+            Some(max_size) => format!(" {} {max_size}", limits.initial_size),
+            None => format!(" {}", limits.initial_size),
+        }
+    }
+
+    fn const_expr(&self, expr: &[Instr]) -> String {
+        let instrs = match expr {
+            [instrs @ .., Instr::End] => instrs,
+            instrs => instrs,
+        };
+        let locals = IndexSpace::new(std::iter::empty());
+        let texts: Vec<String> = instrs.iter().map(|instr| self.instr(instr, &locals, None)).collect();
+        texts.join(" ")
+    }
+
+    fn function(&mut self, idx: usize, function: &Function, original: Option<&Function>) -> fmt::Result {
+        let locals = IndexSpace::new(function.param_or_locals().map(|(_, local)| (local.name(), false)));
+        let header = self.header(self.functions.definition(idx), &function.export, function.import().as_ref());
+        let params = &locals.references[..function.type_.inputs().len()];
+        write!(self.out, "  (func {header}{}", function_type(function.type_, params))?;
+
+        let code = match function.code() {
+            Some(code) => code,
+            None => return writeln!(self.out, ")"),
+        };
+        writeln!(self.out)?;
+
+        let num_params = function.type_.inputs().len();
+        for (i, local) in code.locals.iter().enumerate() {
+            let reference = &locals.references[num_params + i];
+            if reference.starts_with('$') {
+                writeln!(self.out, "    (local {reference} {})", local.type_)?;
+            } else {
+                writeln!(self.out, "    (local {})", local.type_)?;
+            }
+        }
+
+        // The final `end` is implicit in the text format.
+        let body = match code.body.as_slice() {
+            [body @ .., Instr::End] => body,
+            body => body,
+        };
+        let original_indices = original.map(|original| match original.code() {
+            Some(original) => match_original(&original.body, &code.body),
+            None => vec![None; code.body.len()],
+        });
+
+        let mut depth = 2;
+        let mut label_count = 0;
+        for (instr_idx, instr) in body.iter().enumerate() {
+            let label_name = match instr {
+                Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Try(_) => {
+                    label_count += 1;
+                    code.label_name(label_count - 1)
+                }
+                _ => None,
+            };
+
+            let depth_before = match instr {
+                Instr::Else | Instr::Catch(_) | Instr::CatchAll | Instr::End | Instr::Delegate(_) => depth - 1,
+                _ => depth,
+            };
+            depth = match instr {
+                Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Try(_) => depth + 1,
+                Instr::End | Instr::Delegate(_) => depth - 1,
+                _ => depth,
+            }
+            .max(2);
+
+            let line = format!("{:indent$}{}", "", self.instr(instr, &locals, label_name), indent = 2 * depth_before.max(2));
+            write!(self.out, "{line:INSTR_COMMENT_COLUMN$} ;; @{instr_idx}")?;
+            match original_indices.as_ref().map(|indices| indices[instr_idx]) {
+                Some(Some(original_idx)) => write!(self.out, " (original @{})", original_idx.to_usize())?,
+                Some(None) => write!(self.out, " (inserted)")?,
+                None => {}
+            }
+            writeln!(self.out)?;
+        }
+        writeln!(self.out, "  )")
+    }
+
+    fn instr(&self, instr: &Instr, locals: &IndexSpace, label_name: Option<&str>) -> String {
+        use Instr::*;
+        let name = instr.to_name();
+        match instr {
+            Block(type_) | Loop(type_) | If(type_) | Try(type_) => match label_name {
+                Some(label) => format!("{name} {}{}", sanitize_identifier(label), function_type(*type_, &[])),
+                None => format!("{name}{}", function_type(*type_, &[])),
+            },
+            Call(function) | ReturnCall(function) | RefFunc(function) => {
+                format!("{name} {}", self.functions.reference(function.to_usize()))
+            }
+            CallIndirect(type_, table) | ReturnCallIndirect(type_, table) => {
+                format!("{name} {}{}", self.tables.reference(table.to_usize()), function_type(*type_, &[]))
+            }
+            Catch(tag) | Throw(tag) => format!("{name} {}", self.tags.reference(tag.to_usize())),
+            TypedSelect(type_) => format!("{name} (result {type_})"),
+            RefNull(type_) => format!("{name} {}", heap_type(*type_)),
+            Local(_, local) => format!("{name} {}", locals.reference(local.to_usize())),
+            Global(_, global) => format!("{name} {}", self.globals.reference(global.to_usize())),
+
+            Load(op, memarg) => self.memarg_instr(name, *memarg, *op),
+            Store(op, memarg) => self.memarg_instr(name, *memarg, *op),
+            AtomicLoad(op, memarg) => self.memarg_instr(name, *memarg, *op),
+            AtomicStore(op, memarg) => self.memarg_instr(name, *memarg, *op),
+            Atomic(op, memarg) => self.memarg_instr(name, *memarg, *op),
+            LoadLane(op, memarg, lane) => {
+                format!("{} {lane}", self.memarg_instr(name, *memarg, *op))
+            }
+            StoreLane(op, memarg, lane) => {
+                format!("{} {lane}", self.memarg_instr(name, *memarg, *op))
+            }
+
+            MemorySize(memory) | MemoryGrow(memory) | MemoryFill(memory) => {
+                format!("{name} {}", self.memories.reference(memory.to_usize()))
+            }
+            MemoryCopy { dst, src } => format!(
+                "{name} {} {}",
+                self.memories.reference(dst.to_usize()),
+                self.memories.reference(src.to_usize())
+            ),
+            MemoryInit(data, memory) => format!(
+                "{name} {} {}",
+                self.memories.reference(memory.to_usize()),
+                self.data.reference(data.to_usize())
+            ),
+            DataDrop(data) => format!("{name} {}", self.data.reference(data.to_usize())),
+            TableInit(element, table) => format!(
+                "{name} {} {}",
+                self.tables.reference(table.to_usize()),
+                self.elements.reference(element.to_usize())
+            ),
+            ElemDrop(element) => format!("{name} {}", self.elements.reference(element.to_usize())),
+            TableCopy { dst, src } => format!(
+                "{name} {} {}",
+                self.tables.reference(dst.to_usize()),
+                self.tables.reference(src.to_usize())
+            ),
+            TableGet(table) | TableSet(table) | TableSize(table) | TableGrow(table) | TableFill(table) => {
+                format!("{name} {}", self.tables.reference(table.to_usize()))
+            }
+
+            Const(value) => format!("{name} {}", val(*value)),
+
+            // All other instructions have no indices or types, so their `Display` is already valid.
+            instr => instr.to_string(),
+        }
+    }
+
+    fn memarg_instr(&self, name: &str, memarg: Memarg, op: impl MemoryOp) -> String {
+        let mut text = name.to_string();
+        // `Memarg::fmt` would print the AST index of the memory, so print the reference instead.
+        if !self.memories.is_first(memarg.memory.to_usize()) {
+            write!(text, " {}", self.memories.reference(memarg.memory.to_usize())).unwrap();
+        }
+        let offset_align = MemargText(Memarg { memory: Idx::from(0u32), ..memarg }, op).to_string();
+        if !offset_align.is_empty() {
+            write!(text, " {offset_align}").unwrap();
+        }
+        text
+    }
+}
+
+/// Heuristically matches the instructions of an instrumented body with the original body, i.e.,
+/// returns for each instrumented instruction the index of the same original instruction, or `None`
+/// if it was inserted. Because instrumentation mostly inserts (and only rarely replaces or removes)
+/// instructions, we match greedily in order, which is linear and not quadratic like a full diff.
+/// The downside is that an inserted instruction that happens to be equal to the next original
+/// instruction is attributed to the original (and the original then counts as inserted).
+fn match_original(original: &[Instr], instrumented: &[Instr]) -> Vec<Option<Idx<Instr>>> {
+    let mut result = Vec::with_capacity(instrumented.len());
+    let mut original_idx = 0;
+    for (i, instr) in instrumented.iter().enumerate() {
+        // Skip over original instructions that were removed or replaced, i.e., that do not come
+        // up soon in the instrumented body.
+        while let Some(original_instr) = original.get(original_idx) {
+            if original_instr == instr {
+                break;
+            }
+            let window = &instrumented[i..instrumented.len().min(i + RESYNC_WINDOW)];
+            if window.contains(original_instr) {
+                break;
+            }
+            original_idx += 1;
+        }
+
+        if original.get(original_idx) == Some(instr) {
+            result.push(Some(original_idx.into()));
+            original_idx += 1;
+        } else {
+            result.push(None);
+        }
+    }
+    result
+}
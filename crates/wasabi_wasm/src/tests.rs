@@ -14,9 +14,72 @@ use crate::*;
 const NAME_SECTION_TEST_BINARY: &str = "../../test-inputs/wasm-feature-tests/name-section/wabt-tests/names.wasm";
 const BANANABREAD_REAL_WORLD_TEST_BINARY: &str = "../../test-inputs/real-world-binaries/bananabread/bb.wasm";
 
-// Removed this test, because when changing to wasmparser,
-// we did not port over the low-level parsing of the extended name section.
-// const WASM_TEST_INPUT_EXTENDED_NAMES_SECTION: &str = "../../test-inputs/wasm-feature-tests/name-section/extended-name-section/vuln.wasm";
+/// Spec tests that exercise extensions beyond the MVP, which we support in the AST.
+const SPEC_TEST_BINARIES_WITH_EXTENSIONS: &[&str] = &[
+    // Sign-extension operators.
+    "../../test-inputs/wasm-spec-tests/build/i32.wasm",
+    "../../test-inputs/wasm-spec-tests/build/i64.wasm",
+    // Non-trapping float-to-int conversions.
+    "../../test-inputs/wasm-spec-tests/build/conversions.wasm",
+    "../../test-inputs/wasm-spec-tests/build/binary-leb128.wasm",
+    // Bulk memory operations and passive segments.
+    "../../test-inputs/wasm-spec-tests/build/bulk.wasm",
+    "../../test-inputs/wasm-spec-tests/build/memory_copy.wasm",
+    "../../test-inputs/wasm-spec-tests/build/memory_fill.wasm",
+    "../../test-inputs/wasm-spec-tests/build/memory_init.wasm",
+    "../../test-inputs/wasm-spec-tests/build/table_copy.wasm",
+    "../../test-inputs/wasm-spec-tests/build/table_init.wasm",
+    // Reference types, typed select, and multiple tables.
+    "../../test-inputs/wasm-spec-tests/build/elem.wasm",
+    "../../test-inputs/wasm-spec-tests/build/ref_is_null.wasm",
+    "../../test-inputs/wasm-spec-tests/build/select.wasm",
+    "../../test-inputs/wasm-spec-tests/build/table_fill.wasm",
+    "../../test-inputs/wasm-spec-tests/build/table_get.wasm",
+    "../../test-inputs/wasm-spec-tests/build/table_grow.wasm",
+    // SIMD, not covered by the spec tests at the time of building them.
+    "../../test-inputs/wasm-feature-tests/small-manually-written/build/simd.wat.wasm",
+    // Threads and atomics, including a shared memory.
+    "../../test-inputs/wasm-feature-tests/small-manually-written/build/atomics.wat.wasm",
+    // Multiple memories, also imported ones. (Was once rejected, hence the `invalid` directory.)
+    "../../test-inputs/wasm-feature-tests/small-manually-written/build/multi-memory.wat.wasm",
+    "../../test-inputs/invalid/multiple-memories.wasm",
+    // 64-bit memories, including an offset that does not fit in 32 bits.
+    "../../test-inputs/wasm-feature-tests/small-manually-written/build/memory64.wat.wasm",
+    // Tail calls, direct and indirect.
+    "../../test-inputs/wasm-feature-tests/small-manually-written/build/tail-calls.wat.wasm",
+    // Exception handling: tags (also imported and exported), try/catch/catch_all, throw, rethrow, and delegate.
+    "../../test-inputs/wasm-feature-tests/small-manually-written/build/exceptions.wat.wasm",
+];
+
+const COMPONENT_TEST_BINARY: &str = "../../test-inputs/wasm-feature-tests/small-manually-written/build/component.wat.wasm";
+
+const EXTENDED_NAME_SECTION_TEST_BINARIES: &[&str] = &[
+    // Emscripten output with global and data segment names.
+    "../../test-inputs/wasm-feature-tests/name-section/extended-name-section/vuln.wasm",
+    // Also table, memory, element, and label names.
+    "../../test-inputs/wasm-feature-tests/small-manually-written/build/extended-names.wat.wasm",
+];
+
+/// For comparing modules that went through tools that do not preserve (all) names.
+fn without_names(mut module: Module) -> Module {
+    module.name = None;
+    for function in &mut module.functions {
+        function.name = None;
+        let locals = function.param_or_locals().map(|(idx, _)| idx).collect::<Vec<_>>();
+        for idx in locals {
+            *function.param_or_local_name_mut(idx) = None;
+        }
+        if let Some(code) = function.code_mut() {
+            code.label_names.clear();
+        }
+    }
+    module.globals.iter_mut().for_each(|global| global.name = None);
+    module.tables.iter_mut().for_each(|table| table.name = None);
+    module.memories.iter_mut().for_each(|memory| memory.name = None);
+    module.elements.iter_mut().for_each(|element| element.name = None);
+    module.data.iter_mut().for_each(|data| data.name = None);
+    module
+}
 
 #[test]
 fn collect_all_function_types_in_test_set() {
@@ -81,6 +144,319 @@ fn roundtrip_produces_same_module_ast() {
     });
 }
 
+#[test]
+fn streaming_parse_and_encode_produce_same_result() {
+    /// Returns only few bytes per read, to exercise the buffering when parsing from a stream.
+    struct SlowReader<'a>(&'a [u8]);
+    impl std::io::Read for SlowReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(7);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    for_each_valid_wasm_binary_in_test_set(|path| {
+        let bytes = fs::read(path).unwrap();
+        let (module, offsets, _) = Module::from_bytes(&bytes)
+            .unwrap_or_else(|err| panic!("Could not parse valid binary '{}': {err}", path.display()));
+        let (module_streamed, offsets_streamed, _) = Module::from_reader(SlowReader(&bytes))
+            .unwrap_or_else(|err| panic!("Could not parse valid binary '{}' from reader: {err}", path.display()));
+        assert_eq!(module, module_streamed, "Streaming parse differs for binary '{}'", path.display());
+        assert_eq!(offsets.sections, offsets_streamed.sections);
+        assert_eq!(offsets.functions_code, offsets_streamed.functions_code);
+
+        let mut written = Vec::new();
+        let len = module.write_to(&mut written).unwrap();
+        assert_eq!(len, written.len());
+        assert_eq!(written, module.to_bytes().unwrap(), "Streaming encode differs for binary '{}'", path.display());
+    });
+
+    // Truncated binaries must produce an error, not hang or panic.
+    let bytes = fs::read(BANANABREAD_REAL_WORLD_TEST_BINARY).unwrap();
+    for len in [0, 4, 9, bytes.len() / 2, bytes.len() - 1] {
+        assert!(Module::from_reader(SlowReader(&bytes[..len])).is_err(), "truncated to {len} bytes");
+    }
+}
+
+#[test]
+fn text_format_produces_same_module_ast_as_binary() {
+    // The binaries were produced by external tools, partially without the name section.
+    let dir = "../../test-inputs/wasm-feature-tests/small-manually-written";
+    for entry in fs::read_dir(format!("{dir}/src")).unwrap() {
+        let path = entry.unwrap().path();
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        if file_name == "component.wat" {
+            assert!(Module::from_wat(&text).is_err(), "components are not modules");
+            continue;
+        }
+
+        let (module, _) = Module::from_wat(&text)
+            .unwrap_or_else(|err| panic!("Could not parse text format '{}': {err}", path.display()));
+        let (module_binary, _, _) = Module::from_file(format!("{dir}/build/{file_name}.wasm")).unwrap();
+        assert_eq!(without_names(module), without_names(module_binary), "text and binary differ for '{}'", path.display());
+    }
+}
+
+#[test]
+fn text_format_identifiers_and_errors() {
+    let (module, warnings) = Module::from_wat(r#"(module
+        (global $counter (mut i32) (i32.const 0))
+        (func $inc (param $by i32) (local $tmp i32)
+            (global.set $counter (i32.add (global.get $counter) (local.get $by)))))"#).unwrap();
+    assert!(warnings.is_empty());
+    let function = module.function(0u32.into());
+    assert_eq!(function.name.as_deref(), Some("inc"));
+    assert_eq!(function.param_or_local_name(0u32.into()), Some("by"));
+    assert_eq!(function.param_or_local_name(1u32.into()), Some("tmp"));
+    assert_eq!(module.global(0u32.into()).name.as_deref(), Some("counter"));
+    // Folded expressions are flattened in the order of evaluation.
+    assert_eq!(function.code().unwrap().body, vec![
+        Instr::Global(GlobalOp::Get, 0u32.into()),
+        Instr::Local(LocalOp::Get, 0u32.into()),
+        Instr::Binary(BinaryOp::I32Add),
+        Instr::Global(GlobalOp::Set, 0u32.into()),
+        Instr::End,
+    ]);
+
+    let err = Module::from_wat("(module\n  (func\n    call $missing))").unwrap_err();
+    assert!(err.to_string().contains("line 3, column 10"), "{err}");
+    let err = Module::from_wat("(module\n  (func (i32.frob)))").unwrap_err();
+    assert!(err.to_string().contains("line 2, column 10"), "{err}");
+}
+
+#[test]
+fn printed_text_format_parses_to_same_module_ast() {
+    let check = |path: &std::path::Path| {
+        let (module, _, _) = Module::from_file(path)
+            .unwrap_or_else(|err| panic!("Could not parse valid binary '{}': {err}", path.display()));
+        let text = module.to_wat();
+        let (module_roundtrip, _) = Module::from_wat(&text)
+            .unwrap_or_else(|err| panic!("Could not parse printed text format of '{}': {err}", path.display()));
+
+        // Custom sections are only printed as comments. Names may have been changed to be valid
+        // identifiers, so ignore them.
+        let mut module = without_names(module);
+        module.custom_sections.clear();
+        assert_eq!(module, without_names(module_roundtrip), "Text roundtrip failed for binary '{}'", path.display());
+    };
+    for_each_valid_wasm_binary_in_test_set(check);
+    for path in SPEC_TEST_BINARIES_WITH_EXTENSIONS.iter().chain(EXTENDED_NAME_SECTION_TEST_BINARIES) {
+        check(std::path::Path::new(path));
+    }
+
+    // Names that are valid identifiers are kept.
+    let (module, _, _) = Module::from_file(EXTENDED_NAME_SECTION_TEST_BINARIES[1]).unwrap();
+    assert_eq!(module, Module::from_wat(&module.to_wat()).unwrap().0);
+}
+
+#[test]
+fn printed_text_format_marks_inserted_instructions() {
+    let (original, _) = Module::from_wat(r#"(module
+        (func $f (param $x i32) (result i32)
+            (block $exit
+                (br_if $exit (local.get $x)))
+            (i32.const 0)))"#).unwrap();
+
+    let mut instrumented = original.clone();
+    let hook = instrumented.add_function_import(FunctionType::new(&[ValType::I32], &[]), "hooks".to_string(), "hook".to_string());
+    let body = &mut instrumented.function_mut(0u32.into()).code_mut().unwrap().body;
+    body.insert(2, Instr::Local(LocalOp::Get, 0u32.into()));
+    body.insert(3, Instr::Call(hook));
+
+    let text = instrumented.to_wat_marking_inserted(&original);
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    // The import added last is printed first, as in the binary.
+    assert!(lines[1].starts_with(r#"(func (;0;) (import "hooks" "hook") (param i32))"#), "{text}");
+    assert!(lines[2].starts_with("(func $f (;1;) (param $x i32) (result i32)"), "{text}");
+    let instrs: Vec<(&str, &str)> = lines[3..8].iter().map(|line| line.split_once(" ;; ").unwrap()).map(|(instr, comment)| (instr.trim(), comment)).collect();
+    assert_eq!(instrs, vec![
+        ("block $exit", "@0 (original @0)"),
+        ("local.get $x", "@1 (original @1)"),
+        ("local.get $x", "@2 (inserted)"),
+        ("call 0", "@3 (inserted)"),
+        ("br_if 0", "@4 (original @2)"),
+    ]);
+}
+
+#[test]
+fn lazy_parsing_produces_same_module_ast() {
+    for_each_valid_wasm_binary_in_test_set(|path| {
+        let (mut module, _, _) = Module::from_file(path)
+            .unwrap_or_else(|err| panic!("Could not parse valid binary '{}': {err}", path.display()));
+        let (mut lazy_module, _, _) = Module::from_file_lazy(path)
+            .unwrap_or_else(|err| panic!("Could not lazily parse valid binary '{}': {err}", path.display()));
+
+        // Modify only the first function with a body, such that all other bodies are copied verbatim.
+        let modify = |module: &mut Module| {
+            if let Some((_, function)) = module.functions_mut().find(|(_, function)| function.code().is_some()) {
+                function.code_mut().unwrap().body.insert(0, Instr::Nop);
+            }
+        };
+        modify(&mut module);
+        modify(&mut lazy_module);
+
+        // Encode before comparing, since comparing forces parsing of all bodies.
+        let bytes = lazy_module.to_bytes()
+            .unwrap_or_else(|err| panic!("Could not encode lazily parsed binary '{}': {err}", path.display()));
+        let (module_roundtrip, _, _) = Module::from_bytes(&bytes).unwrap();
+        assert_eq!(module, module_roundtrip, "Lazy roundtrip failed for binary '{}'", path.display());
+        // Extensions used only in unparsed bodies are missing from the metadata, see `Module::from_bytes_lazy`.
+        assert!(lazy_module.metadata.used_extensions().all(|lazy| module.metadata.used_extensions().any(|eager| eager == lazy)));
+        lazy_module.metadata = module.metadata.clone();
+        assert_eq!(module, lazy_module, "Lazy parsing differs for binary '{}'", path.display());
+    });
+}
+
+#[test]
+fn encoding_malformed_lazy_body_is_an_error() {
+    let binary = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic and version
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section: [] -> []
+        0x03, 0x02, 0x01, 0x00, // function section: one function of type 0
+        0x0a, 0x05, 0x01, 0x03, 0x00, 0xff, 0x0b, // code section: one body with an invalid opcode
+    ];
+    let (mut module, _, _) = Module::from_bytes_lazy(&binary).unwrap();
+    // Unmodified bodies are copied verbatim, without parsing them.
+    assert_eq!(module.to_bytes().unwrap(), binary);
+
+    // A new type changes the type indices, so the body must be parsed and re-encoded.
+    module.add_function_import(FunctionType::new(&[ValType::I32], &[]), "env".to_string(), "f".to_string());
+    let err = module.to_bytes().unwrap_err();
+    assert!(err.to_string().starts_with("could not parse function body on demand"), "{err}");
+}
+
+#[test]
+fn encoding_too_large_table_limits_is_an_error() {
+    let too_large = u32::MAX as u64 + 1;
+    let defined = Table::new(ValType::FuncRef, Limits { initial_size: too_large, max_size: None });
+    let imported = Table::new_imported(ValType::FuncRef, Limits { initial_size: 0, max_size: Some(too_large) }, "env".to_string(), "table".to_string());
+    for table in [defined, imported] {
+        let mut module = Module::new();
+        module.tables.push(table);
+        let err = module.to_bytes().unwrap_err();
+        assert!(err.to_string().ends_with("table limit 4294967296 does not fit in u32"), "{err}");
+    }
+}
+
+#[test]
+fn nested_body_conversion_roundtrips_and_type_checks() {
+    for_each_valid_wasm_binary_in_test_set(|path| {
+        let (mut module, _, _) = Module::from_file(path)
+            .unwrap_or_else(|err| panic!("Could not parse valid binary '{}': {err}", path.display()));
+
+        for (function_idx, function) in module.functions_mut() {
+            if let Some(code) = function.code_mut() {
+                let nested = nested::Body::from_flat(&code.body)
+                    .unwrap_or_else(|err| panic!("Could not nest function #{} in '{}': {err}", function_idx.to_usize(), path.display()));
+                let flat = nested.to_flat();
+                // Only dead code can get lost during the conversion.
+                if flat.len() == code.body.len() {
+                    assert_eq!(flat, code.body, "function #{} in '{}'", function_idx.to_usize(), path.display());
+                }
+                assert_eq!(nested::Body::from_flat(&flat).unwrap(), nested);
+                code.body = flat;
+            }
+        }
+
+        // Removing dead code must not make the module invalid.
+        TypeChecker::check_module(&module)
+            .unwrap_or_else(|err| panic!("Binary '{}' without dead code should type check: {err}", path.display()));
+    });
+}
+
+#[test]
+fn nested_body_drops_dead_code() {
+    use crate::Instr::*;
+    let flat = vec![
+        Block(FunctionType::new(&[], &[])),
+        Br(Label::from(0u32)),
+        // Dead code, including a nested block.
+        Block(FunctionType::new(&[], &[])),
+        Nop,
+        End,
+        End,
+        Return,
+        Unreachable,
+        End,
+    ];
+    let nested = nested::Body::from_flat(&flat).unwrap();
+    assert_eq!(nested, nested::Body {
+        instrs: vec![nested::Instr::Block(FunctionType::new(&[], &[]), nested::Body {
+            instrs: vec![],
+            terminator: Some(nested::Terminator::Br(Label::from(0u32))),
+        })],
+        terminator: Some(nested::Terminator::Return),
+    });
+    assert_eq!(nested.to_flat(), vec![Block(FunctionType::new(&[], &[])), Br(Label::from(0u32)), End, Return, End]);
+
+    assert!(nested::Body::from_flat(&[Block(FunctionType::new(&[], &[])), End]).is_err(), "missing end");
+    assert!(nested::Body::from_flat(&[Else, End]).is_err(), "else without if");
+}
+
+#[test]
+fn spec_tests_with_extensions_roundtrip_and_type_check() {
+    for path in SPEC_TEST_BINARIES_WITH_EXTENSIONS {
+        let (module, _, _) = Module::from_file(path)
+            .unwrap_or_else(|err| panic!("Could not parse spec test binary '{path}': {err}"));
+        TypeChecker::check_module(&module)
+            .unwrap_or_else(|err| panic!("Spec test binary '{path}' should type check: {err}"));
+
+        let bytes = module.to_bytes()
+            .unwrap_or_else(|err| panic!("Could not encode spec test binary '{path}': {err}"));
+        let (module_roundtrip, _, _) = Module::from_bytes(&bytes).unwrap();
+        assert_eq!(module, module_roundtrip, "Roundtrip failed for spec test binary '{path}'");
+    }
+}
+
+#[test]
+fn spec_tests_with_extensions_record_used_extensions() {
+    use WasmExtension::*;
+    // The extensions each binary is expected to use, by file name.
+    let expected_extensions: &[(&str, &[WasmExtension])] = &[
+        ("i32.wasm", &[SignExtensionOps]),
+        ("i64.wasm", &[SignExtensionOps]),
+        ("conversions.wasm", &[NontrappingFloatToInt]),
+        ("binary-leb128.wasm", &[NontrappingFloatToInt]),
+        ("bulk.wasm", &[BulkMemoryOperations]),
+        ("memory_copy.wasm", &[BulkMemoryOperations]),
+        ("memory_fill.wasm", &[BulkMemoryOperations]),
+        ("memory_init.wasm", &[BulkMemoryOperations]),
+        ("table_copy.wasm", &[BulkMemoryOperations]),
+        ("table_init.wasm", &[BulkMemoryOperations]),
+        ("elem.wasm", &[ReferenceTypes]),
+        ("ref_is_null.wasm", &[ReferenceTypes]),
+        ("select.wasm", &[ReferenceTypes]),
+        ("table_fill.wasm", &[ReferenceTypes]),
+        ("table_get.wasm", &[ReferenceTypes]),
+        ("table_grow.wasm", &[ReferenceTypes]),
+        ("simd.wat.wasm", &[Simd]),
+        ("atomics.wat.wasm", &[ThreadsAtomics]),
+        ("multi-memory.wat.wasm", &[BulkMemoryOperations, MultiMemory]),
+        ("multiple-memories.wasm", &[MultiMemory]),
+        ("memory64.wat.wasm", &[BulkMemoryOperations, Memory64]),
+        ("tail-calls.wat.wasm", &[TailCalls]),
+        ("exceptions.wat.wasm", &[ExceptionHandling]),
+    ];
+    for path in SPEC_TEST_BINARIES_WITH_EXTENSIONS {
+        let file_name = std::path::Path::new(path).file_name().unwrap().to_str().unwrap();
+        let (_, expected) = expected_extensions
+            .iter()
+            .find(|(name, _)| *name == file_name)
+            .unwrap_or_else(|| panic!("no expected extensions for '{path}'"));
+        let (module, _, _) = Module::from_file(path).unwrap();
+        let mut used: Vec<_> = module.metadata.used_extensions().collect();
+        used.sort();
+        assert_eq!(&used, expected, "wrong used extensions for '{path}'");
+    }
+
+    // No spec binary uses extended constant expressions, so check it with a hand-written one.
+    let (module, _) = Module::from_wat("(module (global i32 (i32.add (i32.const 1) (i32.const 2))))").unwrap();
+    assert_eq!(module.metadata.used_extensions().collect::<Vec<_>>(), vec![ExtendedConst]);
+}
+
 #[test]
 fn type_checking_valid_files() {
     for_each_valid_wasm_binary_in_test_set(|path| {
@@ -102,11 +478,203 @@ fn decode_encode_is_valid_wasm() {
         module.to_file(output_path)
             .unwrap_or_else(|err| panic!("Could not encode valid binary to file '{}': {err}", output_path.display()));
 
-        wasm_validate(output_path)
+        // Validate with an independent implementation, such that encoder bugs that `Module::validate`
+        // also misses are still caught. (Our own validator is tested in `validating_valid_files`.)
+        let features = wasmparser::WasmFeatures {
+            threads: true,
+            tail_call: true,
+            multi_memory: true,
+            exceptions: true,
+            memory64: true,
+            extended_const: true,
+            ..Default::default()
+        };
+        let encoded = fs::read(output_path).unwrap();
+        wasmparser::Validator::new_with_features(features)
+            .validate_all(&encoded)
             .unwrap_or_else(|err| panic!("Written binary did not validate '{}': {err}", output_path.display()));
     });
 }
 
+#[test]
+fn validating_valid_files() {
+    for path in SPEC_TEST_BINARIES_WITH_EXTENSIONS.iter().chain(EXTENDED_NAME_SECTION_TEST_BINARIES) {
+        let (module, _, _) = Module::from_file(path).unwrap();
+        module.validate()
+            .unwrap_or_else(|err| panic!("Binary '{path}' should validate: {err}"));
+    }
+    for_each_valid_wasm_binary_in_test_set(|path| {
+        let (module, _, _) = Module::from_file(path).unwrap();
+        module.validate()
+            .unwrap_or_else(|err| panic!("Valid binary '{}' should validate: {err}", path.display()));
+    });
+}
+
+#[test]
+fn validation_errors_are_located() {
+    use crate::validate::Location;
+    use crate::validate::ValidationErrorKind::*;
+
+    let validate = |text: &str| Module::from_wat(text).unwrap().0.validate().unwrap_err();
+
+    let text = fs::read_to_string("../../test-inputs/invalid/global-init-cycle.wat").unwrap();
+    let err = validate(&text);
+    assert_eq!(err.0.kind, GlobalInitCycle(vec![0u32.into()]));
+    assert_eq!((&err.0.location, err.0.instruction_idx), (&Location::Global(0u32.into()), Some(0u32.into())));
+    let err = validate("(module (global $a i32 (global.get $b)) (global $b i32 (global.get $a)))");
+    assert_eq!(err.to_string(), "invalid module, global #0, instruction #0 (global.get 1): cycle between global initializers: global #0 -> global #1 -> global #0");
+    let err = validate("(module (global $a i32 (global.get $b)) (global $b i32 (i32.const 0)))");
+    assert_eq!(err.0.kind, GlobalInitOrder(1u32.into()));
+    // Imported globals can be read regardless of their position in the AST.
+    let (mut module, _) = Module::from_wat("(module (global i32 (global.get 1)) (global i32 (i32.const 0)))").unwrap();
+    module.globals[1] = Global::new_imported(GlobalType(ValType::I32, Mutability::Const), "m".to_string(), "g".to_string());
+    module.validate().unwrap();
+
+    let err = validate("(module (global $g (mut i32) (i32.const 0)) (global i32 (global.get $g)))");
+    assert_eq!(err.0.kind, MutableGlobalInConstExpr(0u32.into()));
+    let err = validate("(module (global i32 (i32.const 1) (i32.const 2) (i32.div_s)))");
+    assert_eq!((err.0.kind, err.0.instruction_idx), (NonConstantInstruction, Some(2u32.into())));
+    let err = validate("(module (memory 1) (data (i64.const 0) \"\"))");
+    assert_eq!(err.0.location, Location::DataOffset(0u32.into()));
+    assert_eq!(err.0.kind, ConstExprType { expected: vec![ValType::I32], actual: vec![ValType::I64] });
+
+    let err = validate(r#"(module (func (export "f")) (memory (export "f") 1))"#);
+    assert_eq!((err.0.kind, err.0.location), (DuplicateExport("f".to_string()), Location::Memory(0u32.into())));
+    let err = validate("(module (memory 2 1))");
+    assert_eq!(err.0.kind, LimitsMinGreaterThanMax { initial: 2, max: 1 });
+    let err = validate("(module (memory 65537))");
+    assert_eq!(err.0.kind, LimitsTooLarge { size: 65537, bound: 65536 });
+    let err = validate("(module (func $s (param i32)) (start $s))");
+    assert_eq!((err.0.kind, err.0.location), (StartFunctionType(FunctionType::new(&[ValType::I32], &[])), Location::Start));
+    let err = validate("(module (global i32 (i32.const 0)) (func (global.set 0 (i32.const 1))))");
+    assert_eq!(err.0.kind, ImmutableGlobalSet(0u32.into()));
+    let err = validate("(module (func (drop (ref.func 0))))");
+    assert_eq!(err.0.kind, UndeclaredFunctionReference(0u32.into()));
+
+    // Out-of-bounds indices cannot be written in the text format, so insert them in the AST.
+    let (mut module, _) = Module::from_wat("(module (func))").unwrap();
+    module.functions[0].instrs_mut().unwrap().insert(0, Instr::Call(1u32.into()));
+    let err = module.validate().unwrap_err();
+    assert_eq!(err.0.kind, IndexOutOfBounds { index_space: "function", index: 1, count: 1 });
+    assert_eq!((err.0.location, err.0.instruction), (Location::Function(0u32.into()), Some(Instr::Call(1u32.into()))));
+
+    // Type errors are reported with the same kind of location.
+    let err = validate("(module (func (result i32)))");
+    assert!(matches!(err.0.kind, Type(_)));
+    assert_eq!((err.0.location, err.0.instruction), (Location::Function(0u32.into()), Some(Instr::End)));
+}
+
+#[test]
+fn type_errors_are_collected_and_rendered() {
+    let (module, _) = Module::from_wat(r#"(module
+        (func $ok (result i32) i32.const 0)
+        (func $mismatch (param f32) (result i32) i32.const 1 local.get 0 i32.add)
+        (func $underflow drop)
+        (global $g i32 i32.const 0))"#).unwrap();
+    // Go through the binary to get the offsets of function bodies.
+    let bytes = module.to_bytes().unwrap();
+    let (mut module, offsets, _) = Module::from_bytes(&bytes).unwrap();
+    // Init expressions with more than one instruction cannot be encoded, so break it in the AST.
+    module.globals[0].init = ImportOrPresent::Present(vec![Instr::Const(Val::I32(0)), Instr::Binary(BinaryOp::I32Add), Instr::End]);
+
+    let errors = TypeChecker::check_module_collect_errors(&module, Some(&offsets));
+    let locations: Vec<_> = errors.iter().map(|e| (e.0.function_idx, e.0.global_idx, e.0.instruction_idx)).collect();
+    assert_eq!(locations, vec![
+        (Some(1u32.into()), None, Some(2u32.into())),
+        (Some(2u32.into()), None, Some(0u32.into())),
+        (None, Some(0u32.into()), Some(1u32.into())),
+    ]);
+    assert_eq!(errors[0].0.function_offset, offsets.function_idx_to_offset(1u32.into()));
+    assert_eq!(errors[0].0.expected_stack, Some(vec![ValType::I32, ValType::I32]));
+    assert_eq!(errors[0].0.actual_stack, Some(vec![ValType::I32, ValType::F32].into()));
+    assert_eq!(errors[1].0.expected_stack, None);
+    assert_eq!(errors[1].0.actual_stack, Some(vec![].into()));
+    assert_eq!(errors[2].0.global_name.as_deref(), Some("g"));
+
+    // The fail-fast API reports the first of them.
+    assert_eq!(TypeChecker::check_module(&module).unwrap_err().0.instruction_idx, errors[0].0.instruction_idx);
+
+    let offset = errors[0].0.function_offset.unwrap();
+    assert_eq!(errors[0].render(&module), format!("\
+error: expected type i32, but got f32
+ --> function #1 (mismatch), instruction #2, code offset 0x{offset:x}
+  |
+0 | i32.const 1
+1 | local.get 0
+2 | i32.add
+  | ^^^^^^^ expected [i32, i32] on top of the stack, but found [i32, f32]
+3 | end
+  |
+"));
+
+    // With instruction offsets, errors point to the instruction itself.
+    let (module, offsets, _) = Module::from_bytes_with_instr_offsets(&bytes).unwrap();
+    let errors = TypeChecker::check_module_collect_errors(&module, Some(&offsets));
+    assert_eq!(errors[0].0.instruction_offset, offsets.instr_offset(1u32.into(), 2u32.into()));
+    assert!(errors[0].render(&module).contains(&format!("instruction #2, offset 0x{:x}\n", errors[0].0.instruction_offset.unwrap())));
+
+    // After modifying the function, instruction indices no longer match the original offsets.
+    let (mut module, offsets, _) = Module::from_bytes_with_instr_offsets(&bytes).unwrap();
+    module.functions[1].instrs_mut().unwrap().insert(0, Instr::Nop);
+    let errors = TypeChecker::check_module_collect_errors(&module, Some(&offsets));
+    assert_eq!(errors[0].0.instruction_idx, Some(3u32.into()));
+    assert_eq!(errors[0].0.instruction_offset, None);
+    assert!(errors[0].0.function_offset.is_some());
+}
+
+#[test]
+fn extended_name_section_is_parsed_and_roundtrips() {
+    for path in EXTENDED_NAME_SECTION_TEST_BINARIES {
+        let (module, _, warnings) = Module::from_file(path)
+            .unwrap_or_else(|err| panic!("Could not parse binary '{path}': {err}"));
+        assert!(warnings.is_empty(), "Unexpected warnings parsing '{path}': {warnings:#?}");
+        assert_eq!(module.globals[0].name.as_deref(), Some("__stack_pointer"), "in binary '{path}'");
+
+        let bytes = module.to_bytes()
+            .unwrap_or_else(|err| panic!("Could not encode binary '{path}': {err}"));
+        let (module_roundtrip, _, _) = Module::from_bytes(&bytes).unwrap();
+        assert_eq!(module, module_roundtrip, "Roundtrip failed for binary '{path}'");
+    }
+
+    let (module, _, _) = Module::from_file(EXTENDED_NAME_SECTION_TEST_BINARIES[1]).unwrap();
+    assert_eq!(module.globals[1].name.as_deref(), Some("counter"));
+    assert_eq!(module.tables[0].name.as_deref(), Some("functions"));
+    assert_eq!(module.memories[0].name.as_deref(), Some("heap"));
+    assert_eq!(module.elements[0].name.as_deref(), Some("pointers"));
+    assert_eq!(module.data[0].name.as_deref(), Some("greeting"));
+    let code = module.functions[0].code().unwrap();
+    assert_eq!(code.label_name(0), Some("exit"));
+    assert_eq!(code.label_name(1), Some("next"));
+    // The third block is unnamed.
+    assert_eq!(code.label_name(2), None);
+    assert_eq!(code.label_name(3), Some("nonzero"));
+}
+
+#[test]
+fn component_core_modules_are_parsed_and_roundtrip() {
+    let bytes = fs::read(COMPONENT_TEST_BINARY).unwrap();
+    assert!(Module::from_bytes(&bytes).is_err(), "A component is not a core module");
+
+    let (mut component, warnings) = Component::from_bytes(&bytes).unwrap();
+    assert!(warnings.is_empty(), "Unexpected warnings: {warnings:#?}");
+
+    // One core module at the top-level, one in the nested component.
+    let modules = component.modules();
+    assert_eq!(modules.len(), 2);
+    assert_eq!(modules[0].functions[0].export, vec!["add".to_string()]);
+    assert_eq!(modules[1].globals.len(), 1);
+
+    // Modify a core module, then re-assemble the component.
+    component.modules_mut()[0].functions[0].name = Some("add".to_string());
+    let bytes_roundtrip = component.to_bytes().unwrap();
+    let (component_roundtrip, _) = Component::from_bytes(&bytes_roundtrip).unwrap();
+    assert_eq!(component, component_roundtrip);
+    assert_eq!(component_roundtrip.modules()[0].functions[0].name.as_deref(), Some("add"));
+
+    // Component-level sections are passed through unmodified.
+    assert!(component.sections.iter().any(|section| matches!(section, ComponentSection::Raw { .. })));
+}
+
 // TODO: Also ensure that used_wasm_extensions(encode(decode(wasm))) <= used_wasm_extensions(wasm), i.e., that our
 // encoding does not introduce new extensions.
 
@@ -143,6 +711,57 @@ fn code_offsets_like_objdump() {
     assert_eq!(offsets.function_offset_to_idx(0x1e38d2), Some(Idx::from(3642u32)));
 }
 
+#[test]
+fn instr_offsets_like_objdump() {
+    let (module, offsets, _warnings) = Module::from_file_with_instr_offsets(NAME_SECTION_TEST_BINARY).unwrap();
+    assert_eq!(module, Module::from_file(NAME_SECTION_TEST_BINARY).unwrap().0);
+
+    // Expected values are taken from wasm-objdump output.
+    let func = Idx::from(0u32);
+    assert_eq!(offsets.instr_offset(func, Idx::from(0u32)), Some(0x1a));
+    assert_eq!(offsets.instr_offset(func, Idx::from(1u32)), Some(0x1c));
+    assert_eq!(offsets.instr_offset(func, Idx::from(2u32)), None);
+    // Offsets inside an instruction's immediates also belong to the instruction.
+    assert_eq!(offsets.offset_to_instr(0x1a), Some((func, Idx::from(0u32))));
+    assert_eq!(offsets.offset_to_instr(0x1b), Some((func, Idx::from(0u32))));
+    assert_eq!(offsets.offset_to_instr(0x1c), Some((func, Idx::from(1u32))));
+    // Locals and the name section after the code are not part of any instruction.
+    assert_eq!(offsets.offset_to_instr(0x19), None);
+    assert_eq!(offsets.offset_to_instr(0x1d), None);
+
+    // Instruction offsets are only recorded on request.
+    let (_module, offsets, _warnings) = Module::from_file(NAME_SECTION_TEST_BINARY).unwrap();
+    assert_eq!(offsets.instrs, None);
+    assert_eq!(offsets.offset_to_instr(0x1a), None);
+
+    // A truncated body without any instructions (not even the final `end`) must not panic.
+    let binary = [
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // magic and version
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section: [] -> []
+        0x03, 0x02, 0x01, 0x00, // function section: one function of type 0
+        0x0a, 0x03, 0x01, 0x01, 0x00, // code section: one body with no locals and no instructions
+    ];
+    let (_module, offsets, _warnings) = Module::from_bytes_with_instr_offsets(&binary).unwrap();
+    for offset in 0..=binary.len() {
+        assert_eq!(offsets.offset_to_instr(offset), None);
+    }
+}
+
+#[test]
+fn instr_offsets_roundtrip_in_real_world_binary() {
+    let (module, offsets, _warnings) = Module::from_file_with_instr_offsets(BANANABREAD_REAL_WORLD_TEST_BINARY).unwrap();
+    for (func_idx, function) in module.functions() {
+        for instr_idx in 0..function.instrs().len() {
+            let offset = offsets.instr_offset(func_idx, instr_idx.into()).unwrap();
+            assert_eq!(offsets.offset_to_instr(offset), Some((func_idx, instr_idx.into())));
+        }
+        // Bodies start with the local declarations, so the code offset is not an instruction.
+        if let Some(code_offset) = offsets.function_idx_to_offset(func_idx) {
+            assert_eq!(offsets.offset_to_instr(code_offset), None);
+        }
+    }
+}
+
 #[test]
 // Unfortunately, when switching from my own low-level parser to wasmparser, this fails
 // because it is not quite as strict as my error reporting was.
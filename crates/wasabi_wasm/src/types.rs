@@ -182,6 +182,7 @@ use crate::ImportOrPresent;
 use crate::Instr;
 use crate::Label;
 use crate::Module;
+use crate::Offsets;
 use crate::ValType;
 
 /// Value type inferred by the type checker.
@@ -400,6 +401,16 @@ pub struct TypeErrorRepr {
 
     pub global_idx: Option<Idx<Global>>,
     pub global_name: Option<String>,
+
+    /// Byte offsets of the function's code and the instruction in the original binary, see
+    /// `Offsets`. Only filled in by `TypeChecker::check_module_collect_errors()`, when given offsets.
+    pub function_offset: Option<usize>,
+    pub instruction_offset: Option<usize>,
+
+    // Optional stack information, if the error is about the values on the stack: which types the
+    // instruction expected on top, and the stack of the current block before the instruction.
+    pub expected_stack: Option<Vec<ValType>>,
+    pub actual_stack: Option<StackType>,
 }
 
 impl fmt::Display for TypeError {
@@ -437,12 +448,118 @@ impl<T: AsRef<str>> From<T> for TypeError {
             function_name: None,
             global_idx: None,
             global_name: None,
+            function_offset: None,
+            instruction_offset: None,
+            expected_stack: None,
+            actual_stack: None,
         }))
     }
 }
 
 impl std::error::Error for TypeError {}
 
+/// How many instructions before and after the erroneous one `TypeError::render()` shows.
+const RENDER_CONTEXT_INSTRS: usize = 3;
+
+impl TypeError {
+    /// Renders the error similar to rustc diagnostics, i.e., with the instructions surrounding the
+    /// erroneous one, and the expected vs. actual stack (if known) pointing at the instruction.
+    /// The `module` must be the one that was type checked, for looking up those instructions.
+    ///
+    /// Example:
+    /// ```text
+    /// error: expected type i32, but got f32
+    ///  --> function #0 (foo), instruction #2, code offset 0x2a
+    ///   |
+    /// 0 | local.get 0
+    /// 1 | f32.const 1
+    /// 2 | i32.add
+    ///   | ^^^^^^^ expected [i32, i32] on top of the stack, but found [i32, f32]
+    /// 3 | end
+    ///   |
+    /// ```
+    pub fn render(&self, module: &Module) -> String {
+        use std::fmt::Write;
+
+        let error = &self.0;
+        let mut out = format!("error: {}\n", error.message);
+
+        let mut location = String::new();
+        if let Some(function_idx) = error.function_idx {
+            write!(location, "function #{}", function_idx.to_usize()).unwrap();
+            if let Some(function_name) = &error.function_name {
+                write!(location, " ({function_name})").unwrap();
+            }
+        }
+        if let Some(global_idx) = error.global_idx {
+            write!(location, "global init expression #{}", global_idx.to_usize()).unwrap();
+            if let Some(global_name) = &error.global_name {
+                write!(location, " ({global_name})").unwrap();
+            }
+        }
+        if let Some(instruction_idx) = error.instruction_idx {
+            write!(location, ", instruction #{}", instruction_idx.to_usize()).unwrap();
+        }
+        match (error.instruction_offset, error.function_offset) {
+            (Some(offset), _) => write!(location, ", offset 0x{offset:x}").unwrap(),
+            (None, Some(offset)) => write!(location, ", code offset 0x{offset:x}").unwrap(),
+            (None, None) => {}
+        }
+        writeln!(out, " --> {}", location.trim_start_matches(", ")).unwrap();
+
+        let instrs = match (error.function_idx, error.global_idx) {
+            (Some(function_idx), _) => module.functions.get(function_idx.to_usize()).map(Function::instrs),
+            (None, Some(global_idx)) => match module.globals.get(global_idx.to_usize()).map(|global| &global.init) {
+                Some(ImportOrPresent::Present(init)) => Some(init.as_slice()),
+                _ => None,
+            },
+            (None, None) => None,
+        };
+        let (instrs, error_idx) = match (instrs, error.instruction_idx) {
+            (Some(instrs), Some(instruction_idx)) if instruction_idx.to_usize() < instrs.len() => (instrs, instruction_idx.to_usize()),
+            // Without the instruction, we can show only the (shorter) header.
+            _ => return out,
+        };
+
+        let first = error_idx.saturating_sub(RENDER_CONTEXT_INSTRS);
+        let last = (error_idx + RENDER_CONTEXT_INSTRS).min(instrs.len() - 1);
+        let gutter = last.to_string().len();
+
+        // The nesting depth must be counted from the beginning, not only for the shown instructions.
+        let mut depth: usize = 0;
+        writeln!(out, "{:gutter$} |", "").unwrap();
+        for (instr_idx, instr) in instrs.iter().enumerate().take(last + 1) {
+            let depth_before = match instr {
+                Instr::Else | Instr::Catch(_) | Instr::CatchAll | Instr::End | Instr::Delegate(_) => depth.saturating_sub(1),
+                _ => depth,
+            };
+            depth = match instr {
+                Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Try(_) => depth + 1,
+                Instr::End | Instr::Delegate(_) => depth.saturating_sub(1),
+                _ => depth,
+            };
+            if instr_idx < first {
+                continue;
+            }
+
+            let indent = 2 * depth_before;
+            writeln!(out, "{instr_idx:>gutter$} | {:indent$}{instr}", "").unwrap();
+            if instr_idx == error_idx {
+                let marker = "^".repeat(instr.to_string().len());
+                write!(out, "{:gutter$} | {:indent$}{marker}", "", "").unwrap();
+                match (&error.expected_stack, &error.actual_stack) {
+                    (Some(expected), Some(actual)) => write!(out, " expected {} on top of the stack, but found {actual}", StackType::Reachable(expected.clone())).unwrap(),
+                    (None, Some(actual)) => write!(out, " found stack {actual}").unwrap(),
+                    _ => {}
+                }
+                out.push('\n');
+            }
+        }
+        writeln!(out, "{:gutter$} |", "").unwrap();
+        out
+    }
+}
+
 /// Holds the state during type checking and type inference.
 ///
 /// See the module comment for general notes on WebAssembly type checking.
@@ -505,6 +622,17 @@ struct BlockFrame {
     /// block receive?
     // TODO: Switch to a "small vector" since there will usually be very few elements.
     if_inputs: Option<Vec<ValType>>,
+
+    /// Needed for type-checking exception handling instructions: `catch` and `delegate` must
+    /// follow a `try` block, and `rethrow` must refer to a `catch` or `catch_all` handler.
+    try_kind: Option<TryKind>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum TryKind {
+    Try,
+    Catch,
+    CatchAll,
 }
 
 impl<'module> TypeChecker<'module> {
@@ -538,24 +666,53 @@ impl<'module> TypeChecker<'module> {
         Ok(())
     }
 
-    /// Type checks all instructions in a `function`.
-    pub fn check_function(function: &Function, module: &Module) -> Result<(), TypeError> {
-        if let Some(code) = function.code() {
-            let mut type_checker = TypeChecker::begin_function(function, module);
-            for (instr_idx, instr) in code.body.iter().enumerate() {
-                let _instr_type_ignored = type_checker
-                    .check_next_instr(instr)
-                    // Add type error location information.
-                    .map_err(|mut e| {
-                        e.0.instruction_idx = Some(instr_idx.into());
-                        e.0.instruction = Some(instr.clone());
+    /// Like `check_module`, but does not stop at the first type error. Instead, returns the errors
+    /// of all functions and globals in `module` (empty if the module is well-typed).
+    ///
+    /// Each function and global init expression contributes at most its first error, since the
+    /// state of the type checker afterwards is meaningless and later errors would be mostly noise.
+    /// If the `offsets` of the original binary are given, errors in functions also carry the byte
+    /// offset of the function's code, and of the instruction if `Offsets::instrs` was recorded.
+    /// Because functions are only ever appended to a module, the former works also after
+    /// instrumentation (just that added functions have no offset), the latter only for functions
+    /// that were not modified.
+    pub fn check_module_collect_errors(module: &Module, offsets: Option<&Offsets>) -> Vec<TypeError> {
+        let mut errors = Vec::new();
 
-                        e.0.function_name = function.name.clone();
+        for (func_idx, function) in module.functions() {
+            if let Err(mut e) = Self::check_function(function, module) {
+                // Add type error location information.
+                e.0.function_idx = Some(func_idx);
+                if let Some(offsets) = offsets {
+                    e.0.function_offset = offsets.function_idx_to_offset(func_idx);
+                    // Instruction indices only match the original binary if the body was not modified
+                    // (e.g., by instrumentation), which we approximate by an unchanged instruction count.
+                    if offsets.instr_count(func_idx) == Some(function.instrs().len()) {
+                        e.0.instruction_offset = e.0.instruction_idx.and_then(|instr_idx| offsets.instr_offset(func_idx, instr_idx));
+                    }
+                }
+                errors.push(e);
+            }
+        }
 
-                        e
-                    })?;
+        for (global_idx, global) in module.globals() {
+            if let Err(mut e) = Self::check_global_init(global, module) {
+                // Add type error location information.
+                e.0.global_idx = Some(global_idx);
+                e.0.global_name = global.name.clone();
+                errors.push(e);
             }
         }
+
+        errors
+    }
+
+    /// Type checks all instructions in a `function`.
+    pub fn check_function(function: &Function, module: &Module) -> Result<(), TypeError> {
+        // `instrs_with_types` also adds the error location, the types themselves are ignored.
+        for result in function.instrs_with_types(module) {
+            result?;
+        }
         Ok(())
     }
 
@@ -567,6 +724,7 @@ impl<'module> TypeChecker<'module> {
                 Code {
                     locals: Vec::new(),
                     body: init.clone(),
+                    label_names: Vec::new(),
                 },
                 Vec::new(),
             );
@@ -634,6 +792,14 @@ impl<'module> TypeChecker<'module> {
     // First, value stack operations, i.e., about pushing, popping, and checking individual value
     // types on the stack.
 
+    /// Type error about the values on the stack, with the current (not yet popped) block stack.
+    fn stack_error(&self, message: impl AsRef<str>, expected: Option<&[ValType]>) -> TypeError {
+        let mut error = TypeError::from(message);
+        error.0.expected_stack = expected.map(<[ValType]>::to_vec);
+        error.0.actual_stack = self.current_block_type_stack().ok();
+        error
+    }
+
     fn pop_val(&mut self) -> Result<InferredValType, TypeError> {
         let frame = self.top_block_mut()?;
         if frame.unreachable {
@@ -646,25 +812,35 @@ impl<'module> TypeChecker<'module> {
             // first (i.e., proceed with the following, "regular" logic).
         }
 
-        frame
-            .value_stack
-            .pop()
-            .ok_or_else(|| "expected a value, but value stack was empty".into())
+        match frame.value_stack.pop() {
+            Some(ty) => Ok(ty),
+            None => Err(self.stack_error("expected a value, but value stack was empty", None)),
+        }
     }
 
     fn pop_val_expected(&mut self, expected: ValType) -> Result<(), TypeError> {
-        let actual = self.pop_val()?;
-        expected.join(actual).ok_or_else(|| {
-            TypeError::from(format!("expected type {expected}, but got {actual}"))
-        })?;
-        Ok(())
+        self.pop_vals_expected(&[expected])
     }
 
     fn pop_vals_expected(&mut self, expected: &[ValType]) -> Result<(), TypeError> {
+        // Check all values before popping any, such that errors can report the unmodified stack.
+        let frame = self.top_block()?;
         // The expected types must be checked in reverse order.
-        for &expected in expected.iter().rev() {
-            self.pop_val_expected(expected)?;
+        for (depth, &expected_ty) in expected.iter().rev().enumerate() {
+            let actual = match frame.value_stack.len().checked_sub(depth + 1) {
+                Some(i) => frame.value_stack[i],
+                // In unreachable code, missing values are produced "out of thin air", see `pop_val`.
+                None if frame.unreachable => break,
+                None => return Err(self.stack_error("expected a value, but value stack was empty", Some(expected))),
+            };
+            if expected_ty.join(actual).is_none() {
+                return Err(self.stack_error(format!("expected type {expected_ty}, but got {actual}"), Some(expected)));
+            }
         }
+
+        let frame = self.top_block_mut()?;
+        let new_len = frame.value_stack.len().saturating_sub(expected.len());
+        frame.value_stack.truncate(new_len);
         Ok(())
     }
 
@@ -690,6 +866,7 @@ impl<'module> TypeChecker<'module> {
             expected_results: results.clone(),
             label_inputs: results,
             if_inputs: None,
+            try_kind: None,
         })
     }
 
@@ -697,18 +874,26 @@ impl<'module> TypeChecker<'module> {
         let label_inputs = match instr {
             Instr::Loop(_) => inputs,
             Instr::Block(_) | Instr::If(_) | Instr::Else => results,
+            Instr::Try(_) | Instr::Catch(_) | Instr::CatchAll => results,
             _ => unreachable!("push_block() should never be called with non-block instruction {:?}", instr),
         };
         let if_inputs = match instr {
             Instr::If(_) => Some(inputs.to_vec()),
             _ => None,
         };
+        let try_kind = match instr {
+            Instr::Try(_) => Some(TryKind::Try),
+            Instr::Catch(_) => Some(TryKind::Catch),
+            Instr::CatchAll => Some(TryKind::CatchAll),
+            _ => None,
+        };
         self.block_stack.push(BlockFrame {
             value_stack: inputs.iter().cloned().map(InferredValType::from).collect(),
             unreachable: false,
             expected_results: results.to_vec(),
             label_inputs: label_inputs.to_vec(),
             if_inputs,
+            try_kind,
         });
     }
 
@@ -791,8 +976,9 @@ fn check_instr(
         }
     };
 
-    // In the simple cases, we know the type from the instruction alone.
-    if let Some(ty) = instr.simple_type() {
+    // In the simple cases, we know the type from the instruction alone (and for memory
+    // instructions, whether the accessed memory is 64-bit).
+    if let Some(ty) = instr.simple_type_with_memories(&module.memories) {
         state.pop_vals_expected(ty.inputs())?;
         state.push_vals(ty.results())?;
         return Ok(to_inferred_type(ty));
@@ -823,6 +1009,21 @@ fn check_instr(
             state.push_vals(function_ty.results())?;
             to_inferred_type(function_ty)
         }
+        TableGet(idx) | TableSet(idx) | TableGrow(idx) | TableFill(idx) => {
+            let elem_ty = module.table(*idx).elem_type;
+            let instr_ty = match instr {
+                TableGet(_) => FunctionType::new(&[ValType::I32], &[elem_ty]),
+                TableSet(_) => FunctionType::new(&[ValType::I32, elem_ty], &[]),
+                // Initial value for the new elements and the number of elements to grow by.
+                TableGrow(_) => FunctionType::new(&[elem_ty, ValType::I32], &[ValType::I32]),
+                // Destination index, value, and number of elements to fill.
+                TableFill(_) => FunctionType::new(&[ValType::I32, elem_ty, ValType::I32], &[]),
+                _ => unreachable!(),
+            };
+            state.pop_vals_expected(instr_ty.inputs())?;
+            state.push_vals(instr_ty.results())?;
+            to_inferred_type(instr_ty)
+        }
 
         // Value-polymorphic instructions:
         Drop => {
@@ -839,10 +1040,29 @@ fn check_instr(
             let ty2 = state.pop_val()?;
             let ty = ty1.join(ty2)
                 .ok_or_else(|| TypeError::from(format!("incompatible types {ty1} and {ty2} for select arguments")))?;
+            if let Ok(ty) = ValType::try_from(ty) {
+                if ty.is_ref() {
+                    return Err(TypeError::from(format!("select without type annotation cannot be used for reference type {ty}")));
+                }
+            }
             state.push_val(ty)?;
             match (ValType::try_from(ty), was_unreachable) {
                 (_, true) => InferredInstructionType::Unreachable,
-                (Ok(ty), false) => InferredInstructionType::Reachable(FunctionType::new(&[ValType::I32, ty, ty], &[ty])),
+                (Ok(ty), false) => InferredInstructionType::Reachable(FunctionType::new(&[ty, ty, ValType::I32], &[ty])),
+                (Err(UnconstrainedTypeError), false) => unreachable!("unconstrained value type should never appear in reachable code"),
+            }
+        }
+        RefIsNull => {
+            let ty = state.pop_val()?;
+            if let Ok(ty) = ValType::try_from(ty) {
+                if !ty.is_ref() {
+                    return Err(TypeError::from(format!("expected a reference type, but got {ty}")));
+                }
+            }
+            state.push_val(ValType::I32)?;
+            match (ValType::try_from(ty), was_unreachable) {
+                (_, true) => InferredInstructionType::Unreachable,
+                (Ok(ty), false) => InferredInstructionType::Reachable(FunctionType::new(&[ty], &[ValType::I32])),
                 (Err(UnconstrainedTypeError), false) => unreachable!("unconstrained value type should never appear in reachable code"),
             }
         }
@@ -855,7 +1075,7 @@ fn check_instr(
         // nested in the AST already, so this hack is not necessary.
         // See https://github.com/WebAssembly/spec/blob/master/interpreter/valid/valid.ml
         // and https://github.com/WasmCert/WasmCert-Isabelle/blob/master/WebAssembly/Wasm_Checker_Types.thy
-        Block(block_ty) | Loop(block_ty) => {
+        Block(block_ty) | Loop(block_ty) | Try(block_ty) => {
             state.push_block(instr, block_ty.inputs(), block_ty.results());
             to_inferred_type(FunctionType::new(block_ty.inputs(), &[]))
         }
@@ -888,6 +1108,33 @@ fn check_instr(
             to_inferred_type(FunctionType::new(&if_inputs, &if_frame.expected_results))
         }
 
+        // Exception handling: Handlers are typed like else blocks, with the values carried by the
+        // caught exception (if any) on their child stack.
+        Catch(_) | CatchAll => {
+            let try_frame = state.pop_block()?;
+            match try_frame.try_kind {
+                Some(TryKind::Try | TryKind::Catch) => {}
+                Some(TryKind::CatchAll) => return Err(TypeError::from(format!("{} after catch_all handler", instr.to_name()))),
+                None => return Err(TypeError::from(format!("{} instruction not matching try", instr.to_name()))),
+            }
+            let tag_params = match instr {
+                Catch(idx) => module.tag(*idx).type_.inputs().to_vec(),
+                _ => Vec::new(),
+            };
+            state.push_block(instr, &tag_params, &try_frame.expected_results);
+            to_inferred_type(FunctionType::new(&tag_params, &try_frame.expected_results))
+        }
+        // Like an end of the try block, but the label must refer to an outer block as well.
+        Delegate(label) => {
+            let try_frame = state.pop_block()?;
+            if try_frame.try_kind != Some(TryKind::Try) {
+                return Err(TypeError::from("delegate instruction not matching try"));
+            }
+            state.get_block(*label)?;
+            state.push_vals(&try_frame.expected_results)?;
+            to_inferred_type(FunctionType::new(&[], &try_frame.expected_results))
+        }
+
         // Branches: br_if is the only branch that is not followed by dead code.
         BrIf(label) => {
             // Condition.
@@ -941,6 +1188,44 @@ fn check_instr(
             state.unreachable()?;
             to_inferred_type(FunctionType::new(tys, &[]))
         }
+        // Like a call followed by a return, except that the callee results are returned directly.
+        ReturnCall(_) | ReturnCallIndirect(_, _) => {
+            let callee_ty = match instr {
+                ReturnCall(idx) => module.function(*idx).type_,
+                ReturnCallIndirect(func_ty, _) => *func_ty,
+                _ => unreachable!(),
+            };
+            if callee_ty.results() != function.type_.results() {
+                return Err(TypeError::from(format!(
+                    "tail call to function of type {callee_ty}, but results of current function of type {} differ",
+                    function.type_
+                )));
+            }
+            let mut input_tys = callee_ty.inputs().to_vec();
+            if let ReturnCallIndirect(_, _) = instr {
+                // The index into the table.
+                input_tys.push(ValType::I32);
+            }
+            state.pop_vals_expected(&input_tys)?;
+            state.unreachable()?;
+            to_inferred_type(FunctionType::new(&input_tys, &[]))
+        }
+
+        // Throwing is stack-polymorphic like branches, since it also leaves the current block.
+        Throw(idx) => {
+            let tag_ty = module.tag(*idx).type_;
+            state.pop_vals_expected(tag_ty.inputs())?;
+            state.unreachable()?;
+            to_inferred_type(FunctionType::new(tag_ty.inputs(), &[]))
+        }
+        Rethrow(label) => {
+            match state.get_block(*label)?.try_kind {
+                Some(TryKind::Catch | TryKind::CatchAll) => {}
+                _ => return Err(TypeError::from(format!("rethrow label {} does not refer to a catch or catch_all handler", label.to_u32()))),
+            }
+            state.unreachable()?;
+            to_inferred_type(FunctionType::new(&[], &[]))
+        }
 
         // The prototypical stack-polymorphic instruction.
         // Here you can see our type simplification: We return a concrete type
@@ -964,40 +1249,61 @@ fn check_instr(
     })
 }
 
-// /// Extension trait for adding types to instruction sequences.
-// pub trait WithTypes: Sized {
-//     /// Checks and infers types for instructions from `function`.
-//     fn with_types<'a>(self, function: &'a Function, module: &'a Module) -> WithTypesIter<'a, Self>;
-// }
-
-// impl<Iter, I> WithTypes for Iter
-// where
-//     Iter: Iterator<Item = I>,
-//     I: Borrow<Instr>
-// {
-//     fn with_types<'a>(self, function: &'a Function, module: &'a Module) -> WithTypesIter<'a, Self> {
-//         let mut type_checker = TypeChecker::new();
-//         type_checker.push_ctrl_func(function.type_.results.to_vec());
-//         WithTypesIter(self, TypeChecker::new(), function, module)
-//     }
-// }
-
-// pub struct WithTypesIter<'a, I>(I, TypeChecker, &'a Function, &'a Module);
-
-// impl<'a, Iter, I> Iterator for WithTypesIter<'a, Iter>
-// where
-//     Iter: Iterator<Item = I>,
-//     I: Borrow<Instr>
-// {
-//     type Item = (I, Result<InstructionType, TypeError>);
-
-//     fn next(&mut self) -> Option<Self::Item> {
-//         // TODO add location information to error
-//         let instr = self.0.next()?;
-//         let ty = self.1.instr(instr.borrow(), self.2, self.3);
-//         Some((instr, ty))
-//     }
-// }
+/// Iterator over the instructions of a function, together with their inferred type and the type
+/// stack of the current block after each instruction, see `Function::instrs_with_types()`.
+///
+/// Instructions in dead code are also yielded, but with `InferredInstructionType::Unreachable`
+/// and `StackType::Unreachable` (see their documentation for why).
+/// Stops after the first type error, since the state of the type checker is meaningless afterwards.
+#[derive(Debug)]
+pub struct InstrsWithTypes<'module> {
+    instrs: std::iter::Enumerate<std::slice::Iter<'module, Instr>>,
+    type_checker: TypeChecker<'module>,
+    failed: bool,
+}
+
+impl<'module> InstrsWithTypes<'module> {
+    pub(crate) fn new(function: &'module Function, module: &'module Module) -> Self {
+        InstrsWithTypes {
+            instrs: function.instrs().iter().enumerate(),
+            type_checker: TypeChecker::begin_function(function, module),
+            failed: false,
+        }
+    }
+
+    fn type_stack_after_instr(&self) -> Result<StackType, TypeError> {
+        if self.type_checker.block_stack.is_empty() {
+            // After the final end, only the results of the function remain.
+            Ok(StackType::Reachable(self.type_checker.function.type_.results().to_vec()))
+        } else {
+            self.type_checker.current_block_type_stack()
+        }
+    }
+}
+
+impl<'module> Iterator for InstrsWithTypes<'module> {
+    type Item = Result<(Idx<Instr>, &'module Instr, InferredInstructionType, StackType), TypeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let (instr_idx, instr) = self.instrs.next()?;
+        let result = self.type_checker.check_next_instr(instr)
+            .and_then(|instr_type| Ok((instr_idx.into(), instr, instr_type, self.type_stack_after_instr()?)))
+            // Add type error location information.
+            .map_err(|mut e| {
+                e.0.instruction_idx = Some(instr_idx.into());
+                e.0.instruction = Some(instr.clone());
+
+                e.0.function_name = self.type_checker.function.name.clone();
+
+                e
+            });
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -1009,7 +1315,15 @@ mod tests {
     use crate::Instr;
     use crate::Instr::*;
     use crate::Label;
+    use crate::Limits;
+    use crate::LoadOp::*;
     use crate::LocalOp;
+    use crate::Memarg;
+    use crate::Memory;
+    use crate::Module;
+    use crate::StoreOp::*;
+    use crate::Table;
+    use crate::Tag;
     use crate::UnaryOp::*;
     use crate::Val;
     use crate::ValType;
@@ -1028,6 +1342,7 @@ mod tests {
             Code {
                 locals: vec![crate::Local::new(F32)],
                 body: Vec::new(),
+                label_names: Vec::new(),
             },
             Vec::new(),
         )));
@@ -1092,6 +1407,15 @@ mod tests {
         assert_reachable_type(&mut type_checker, Drop, &[F32], &[]);
     }
 
+    #[test]
+    pub fn value_polymorphic_select_has_condition_last() {
+        let mut type_checker = init_function_module_type_checker();
+        assert_reachable_type(&mut type_checker, Const(Val::F32(0.0.into())), &[], &[F32]);
+        assert_reachable_type(&mut type_checker, Const(Val::F32(1.0.into())), &[], &[F32]);
+        assert_reachable_type(&mut type_checker, Const(Val::I32(0)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, Select, &[F32, F32, I32], &[F32]);
+    }
+
     #[test]
     pub fn can_type_check_but_not_infer_type_in_dead_code() {
         let mut type_checker = init_function_module_type_checker();
@@ -1123,6 +1447,94 @@ mod tests {
         assert_unreachable_type(&mut type_checker, Binary(I32Add));
     }
 
+    #[test]
+    pub fn return_call_with_dead_code_following() {
+        let mut type_checker = init_function_module_type_checker();
+        assert_reachable_type(&mut type_checker, Const(Val::I32(0)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, Const(Val::I32(0)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, ReturnCallIndirect(FunctionType::new(&[I32], &[F64]), Idx::from(0u32)), &[I32, I32], &[]);
+        assert_unreachable_type(&mut type_checker, Binary(I32Add));
+    }
+
+    #[test]
+    pub fn return_call_must_have_same_results() {
+        let mut type_checker = init_function_module_type_checker();
+        assert!(type_checker.check_next_instr(&ReturnCallIndirect(FunctionType::new(&[], &[F32]), Idx::from(0u32))).is_err());
+    }
+
+    #[test]
+    pub fn sign_extension_ops_preserve_type() {
+        let mut type_checker = init_function_module_type_checker();
+        assert_reachable_type(&mut type_checker, Const(Val::I32(0xff)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, Unary(I32Extend8S), &[I32], &[I32]);
+        assert_reachable_type(&mut type_checker, Unary(I64ExtendI32S), &[I32], &[I64]);
+        assert_reachable_type(&mut type_checker, Unary(I64Extend32S), &[I64], &[I64]);
+        assert!(type_checker.check_next_instr(&Unary(I32Extend16S)).is_err());
+    }
+
+    #[test]
+    pub fn saturating_truncation_converts_float_to_int() {
+        let mut type_checker = init_function_module_type_checker();
+        assert_reachable_type(&mut type_checker, Const(Val::F64(f64::NAN.into())), &[], &[F64]);
+        assert_reachable_type(&mut type_checker, Unary(I32TruncSatF64U), &[F64], &[I32]);
+        assert!(type_checker.check_next_instr(&Unary(I64TruncSatF32S)).is_err());
+    }
+
+    #[test]
+    pub fn references_need_typed_select() {
+        let mut type_checker = init_function_module_type_checker();
+        assert_reachable_type(&mut type_checker, RefNull(ExternRef), &[], &[ExternRef]);
+        assert_reachable_type(&mut type_checker, RefNull(ExternRef), &[], &[ExternRef]);
+        assert_reachable_type(&mut type_checker, Const(Val::I32(1)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, TypedSelect(ExternRef), &[ExternRef, ExternRef, I32], &[ExternRef]);
+        assert_reachable_type(&mut type_checker, RefIsNull, &[ExternRef], &[I32]);
+        assert!(type_checker.check_next_instr(&RefIsNull).is_err());
+
+        let mut type_checker = init_function_module_type_checker();
+        assert_reachable_type(&mut type_checker, RefFunc(Idx::from(0u32)), &[], &[FuncRef]);
+        assert_reachable_type(&mut type_checker, RefFunc(Idx::from(0u32)), &[], &[FuncRef]);
+        assert_reachable_type(&mut type_checker, Const(Val::I32(1)), &[], &[I32]);
+        assert!(type_checker.check_next_instr(&Select).is_err());
+    }
+
+    #[test]
+    pub fn table_instructions_use_element_type_of_their_table() {
+        let function = Box::leak(Box::new(Function::new(FunctionType::empty(), Code::new(), Vec::new())));
+        let module = Box::leak(Box::<Module>::default());
+        module.tables.push(Table::new(FuncRef, Limits { initial_size: 0, max_size: None }));
+        module.tables.push(Table::new(ExternRef, Limits { initial_size: 0, max_size: None }));
+        let mut type_checker = TypeChecker::begin_function(function, module);
+        assert_reachable_type(&mut type_checker, Const(Val::I32(0)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, TableGet(Idx::from(1u32)), &[I32], &[ExternRef]);
+        assert_reachable_type(&mut type_checker, TableSize(Idx::from(0u32)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, TableGrow(Idx::from(1u32)), &[ExternRef, I32], &[I32]);
+        assert_reachable_type(&mut type_checker, RefNull(ExternRef), &[], &[ExternRef]);
+        assert!(type_checker.check_next_instr(&TableSet(Idx::from(0u32))).is_err());
+    }
+
+    #[test]
+    pub fn memory_instructions_use_index_type_of_their_memory() {
+        let function = Box::leak(Box::new(Function::new(FunctionType::empty(), Code::new(), Vec::new())));
+        let module = Box::leak(Box::<Module>::default());
+        module.memories.push(Memory::new(Limits { initial_size: 1, max_size: None }, false, false));
+        module.memories.push(Memory::new(Limits { initial_size: 1, max_size: None }, false, true));
+        let mut type_checker = TypeChecker::begin_function(function, module);
+        let memarg = |memory: u32| Memarg { memory: Idx::from(memory), ..Memarg::default(I64Load) };
+        assert_reachable_type(&mut type_checker, Const(Val::I64(0)), &[], &[I64]);
+        assert_reachable_type(&mut type_checker, Load(I64Load, memarg(1)), &[I64], &[I64]);
+        assert_reachable_type(&mut type_checker, MemoryGrow(Idx::from(1u32)), &[I64], &[I64]);
+        assert_reachable_type(&mut type_checker, Unary(I32WrapI64), &[I64], &[I32]);
+        assert_reachable_type(&mut type_checker, Load(I64Load, memarg(0)), &[I32], &[I64]);
+        assert_reachable_type(&mut type_checker, MemorySize(Idx::from(0u32)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, MemorySize(Idx::from(0u32)), &[], &[I32]);
+        // Copying from a 32-bit memory only needs a 32-bit length.
+        assert_reachable_type(&mut type_checker, MemoryCopy { dst: Idx::from(1u32), src: Idx::from(0u32) }, &[I64, I32, I32], &[]);
+        // A 32-bit address is invalid for the 64-bit memory.
+        assert_reachable_type(&mut type_checker, MemorySize(Idx::from(0u32)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, Const(Val::I64(0)), &[], &[I64]);
+        assert!(type_checker.check_next_instr(&Store(I64Store, memarg(1))).is_err());
+    }
+
     #[test]
     pub fn block_result_type_is_attached_to_end() {
         let mut type_checker = init_function_module_type_checker();
@@ -1180,6 +1592,44 @@ mod tests {
         assert_reachable_type(&mut type_checker, End, &[], &[I64]);
     }
 
+    #[test]
+    pub fn try_catch_handlers_receive_exception_values() {
+        let mut type_checker = init_function_module_type_checker();
+        let module = Box::leak(Box::<Module>::default());
+        module.tags.push(Tag::new(FunctionType::new(&[I32, F32], &[])));
+        type_checker.module = module;
+        assert_reachable_type(&mut type_checker, Try(FunctionType::new(&[], &[I32])), &[], &[]);
+        assert_reachable_type(&mut type_checker, Const(Val::I32(0)), &[], &[I32]);
+        assert_reachable_type(&mut type_checker, Const(Val::F32(0.0.into())), &[], &[F32]);
+        assert_reachable_type(&mut type_checker, Throw(Idx::from(0u32)), &[I32, F32], &[]);
+        assert_unreachable_type(&mut type_checker, Catch(Idx::from(0u32)));
+        assert_reachable_type(&mut type_checker, Drop, &[F32], &[]);
+        // NOTE: Like for else, the input of a catch are the values pushed on its child stack.
+        assert_reachable_type(&mut type_checker, Catch(Idx::from(0u32)), &[I32, F32], &[I32]);
+        assert_reachable_type(&mut type_checker, Drop, &[F32], &[]);
+        assert_reachable_type(&mut type_checker, CatchAll, &[], &[I32]);
+        assert_reachable_type(&mut type_checker, Rethrow(Label::from(0u32)), &[], &[]);
+        assert_unreachable_type(&mut type_checker, End);
+        assert!(type_checker.check_next_instr(&CatchAll).is_err());
+
+        let mut type_checker = init_function_module_type_checker();
+        type_checker.module = module;
+        assert_reachable_type(&mut type_checker, Const(Val::F32(0.0.into())), &[], &[F32]);
+        assert!(type_checker.check_next_instr(&Throw(Idx::from(0u32))).is_err());
+    }
+
+    #[test]
+    pub fn delegate_ends_try_block_and_rethrow_needs_handler() {
+        let mut type_checker = init_function_module_type_checker();
+        assert_reachable_type(&mut type_checker, Block(FunctionType::empty()), &[], &[]);
+        assert_reachable_type(&mut type_checker, Try(FunctionType::new(&[], &[I64])), &[], &[]);
+        assert_reachable_type(&mut type_checker, Const(Val::I64(0)), &[], &[I64]);
+        assert_reachable_type(&mut type_checker, Delegate(Label::from(1u32)), &[], &[I64]);
+        assert_reachable_type(&mut type_checker, Drop, &[I64], &[]);
+        assert!(type_checker.check_next_instr(&Rethrow(Label::from(0u32))).is_err());
+        assert!(type_checker.check_next_instr(&Delegate(Label::from(0u32))).is_err());
+    }
+
     #[test]
     pub fn unconditional_branch_leaves_end_unreachable() {
         let mut type_checker = init_function_module_type_checker();
@@ -1226,4 +1676,52 @@ mod tests {
         assert_reachable_type(&mut type_checker, Const(Val::I64(0)), &[], &[I64]);
         assert_reachable_type(&mut type_checker, End, &[], &[I64]);
     }
+
+    #[test]
+    pub fn instrs_with_types_include_dead_code_and_stack() {
+        use super::InferredInstructionType;
+        use super::StackType;
+        let function = Function::new(
+            FunctionType::new(&[], &[I32]),
+            Code {
+                locals: Vec::new(),
+                body: vec![Const(Val::I32(1)), Const(Val::I64(2)), Drop, Return, Drop, End],
+                label_names: Vec::new(),
+            },
+            Vec::new(),
+        );
+        let module = Module::default();
+        let typed_instrs = function.instrs_with_types(&module).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(typed_instrs.len(), 6);
+
+        let (instr_idx, instr, instr_type, stack_type) = &typed_instrs[2];
+        assert_eq!((*instr_idx, *instr), (Idx::from(2u32), &Drop));
+        assert_eq!(instr_type, &InferredInstructionType::Reachable(FunctionType::new(&[I64], &[])));
+        assert_eq!(stack_type, &StackType::Reachable(vec![I32]));
+
+        let (_, _, instr_type, stack_type) = &typed_instrs[4];
+        assert_eq!(instr_type, &InferredInstructionType::Unreachable);
+        assert!(matches!(stack_type, StackType::Unreachable(_)));
+
+        // After the final end, only the function results remain.
+        assert_eq!(typed_instrs[5].3, StackType::Reachable(vec![I32]));
+    }
+
+    #[test]
+    pub fn instrs_with_types_stop_after_type_error() {
+        let function = Function::new(
+            FunctionType::new(&[], &[]),
+            Code {
+                locals: Vec::new(),
+                body: vec![Unary(F32Abs), Nop, End],
+                label_names: Vec::new(),
+            },
+            Vec::new(),
+        );
+        let module = Module::default();
+        let mut typed_instrs = function.instrs_with_types(&module);
+        let err = typed_instrs.next().unwrap().unwrap_err();
+        assert_eq!(err.0.instruction_idx, Some(Idx::from(0u32)));
+        assert!(typed_instrs.next().is_none());
+    }
 }
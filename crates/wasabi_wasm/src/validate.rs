@@ -0,0 +1,533 @@
+//! Validation of whole modules, see `Module::validate()`.
+//!
+//! The type checker in `types` only checks function bodies and global initializers. This module
+//! covers the remaining rules of the WebAssembly validation algorithm that matter for our AST:
+//! index bounds, unique export names, limits, the start function signature, and the restrictions
+//! on constant expressions, including cycles between global initializers.
+//! Some rules are already enforced by the AST representation (e.g., inlined function types) or by
+//! the encoder (e.g., the data count section), so they are not checked here.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::types::TypeChecker;
+use crate::types::TypeError;
+use crate::*;
+
+/// Error when validating a module, with the location of the offending entity (and instruction).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError(pub Box<ValidationErrorRepr>);
+
+// Behind a pointer for the same reason as `TypeError`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrorRepr {
+    pub kind: ValidationErrorKind,
+    pub location: Location,
+
+    // Optional location inside the function body or constant expression at `location`.
+    pub instruction_idx: Option<Idx<Instr>>,
+    pub instruction: Option<Instr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ValidationErrorKind {
+    #[error("{index_space} index {index} out of bounds ({count} {index_space}s in index space)")]
+    IndexOutOfBounds {
+        index_space: &'static str,
+        index: u32,
+        count: usize,
+    },
+
+    #[error("export name \"{0}\" is not unique")]
+    DuplicateExport(String),
+
+    #[error("minimum size {initial} is larger than maximum size {max}")]
+    LimitsMinGreaterThanMax { initial: u64, max: u64 },
+    #[error("size {size} is larger than the bound {bound}")]
+    LimitsTooLarge { size: u64, bound: u64 },
+    #[error("shared memory must have a maximum size")]
+    SharedMemoryWithoutMax,
+
+    #[error("start function must have type [] -> [], but has type {0}")]
+    StartFunctionType(FunctionType),
+
+    #[error("global.set of immutable global #{}", .0.to_usize())]
+    ImmutableGlobalSet(Idx<Global>),
+    #[error("ref.func of function #{}, which is not declared in an element segment, export, or global initializer", .0.to_usize())]
+    UndeclaredFunctionReference(Idx<Function>),
+    #[error("element segment of type {actual} cannot initialize table of type {expected}")]
+    ElementType { expected: ValType, actual: ValType },
+
+    #[error("instruction is not allowed in a constant expression")]
+    NonConstantInstruction,
+    #[error("constant expression reads mutable global #{}", .0.to_usize())]
+    MutableGlobalInConstExpr(Idx<Global>),
+    #[error("constant expression expects {} on the stack, but found {}", val_types_to_string(expected), val_types_to_string(actual))]
+    ConstExprType {
+        expected: Vec<ValType>,
+        actual: Vec<ValType>,
+    },
+    #[error("global initializer reads global #{}, which is only initialized afterwards", .0.to_usize())]
+    GlobalInitOrder(Idx<Global>),
+    #[error("cycle between global initializers: {}", cycle_to_string(.0))]
+    GlobalInitCycle(Vec<Idx<Global>>),
+
+    #[error("type error: {0}")]
+    Type(String),
+}
+
+/// Entity of the module that is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Function(Idx<Function>),
+    Global(Idx<Global>),
+    Table(Idx<Table>),
+    Memory(Idx<Memory>),
+    Tag(Idx<Tag>),
+    Start,
+    /// The offset expression of an active element segment.
+    ElementOffset(Idx<Element>),
+    /// The n-th item (function index or constant expression) of an element segment.
+    ElementItem(Idx<Element>, usize),
+    /// The offset expression of an active data segment.
+    DataOffset(Idx<Data>),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Function(idx) => write!(f, "function #{}", idx.to_usize()),
+            Location::Global(idx) => write!(f, "global #{}", idx.to_usize()),
+            Location::Table(idx) => write!(f, "table #{}", idx.to_usize()),
+            Location::Memory(idx) => write!(f, "memory #{}", idx.to_usize()),
+            Location::Tag(idx) => write!(f, "tag #{}", idx.to_usize()),
+            Location::Start => f.write_str("start function"),
+            Location::ElementOffset(idx) => write!(f, "offset of element segment #{}", idx.to_usize()),
+            Location::ElementItem(idx, item) => write!(f, "element segment #{}, item #{item}", idx.to_usize()),
+            Location::DataOffset(idx) => write!(f, "offset of data segment #{}", idx.to_usize()),
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid module, {}", self.0.location)?;
+        if let Some(instruction_idx) = self.0.instruction_idx {
+            write!(f, ", instruction #{}", instruction_idx.to_usize())?;
+            if let Some(instruction) = &self.0.instruction {
+                write!(f, " ({instruction})")?;
+            }
+        }
+        write!(f, ": {}", self.0.kind)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl ValidationError {
+    fn new(kind: ValidationErrorKind, location: Location) -> Self {
+        Self(Box::new(ValidationErrorRepr {
+            kind,
+            location,
+            instruction_idx: None,
+            instruction: None,
+        }))
+    }
+
+    fn at_instruction(mut self, instruction_idx: usize, instruction: &Instr) -> Self {
+        self.0.instruction_idx = Some(instruction_idx.into());
+        self.0.instruction = Some(instruction.clone());
+        self
+    }
+
+    fn from_type_error(err: TypeError) -> Self {
+        let TypeError(err) = err;
+        let location = match (err.function_idx, err.global_idx) {
+            (Some(function_idx), _) => Location::Function(function_idx),
+            (None, Some(global_idx)) => Location::Global(global_idx),
+            (None, None) => unreachable!("type errors from TypeChecker::check_module are always located"),
+        };
+        Self(Box::new(ValidationErrorRepr {
+            kind: ValidationErrorKind::Type(err.message),
+            location,
+            instruction_idx: err.instruction_idx,
+            instruction: err.instruction,
+        }))
+    }
+}
+
+fn val_types_to_string(types: &[ValType]) -> String {
+    format!("{types:?}").to_lowercase()
+}
+
+fn cycle_to_string(cycle: &[Idx<Global>]) -> String {
+    cycle.iter()
+        .chain(cycle.first())
+        .map(|idx| format!("global #{}", idx.to_usize()))
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Maximum number of pages of a 32-bit and 64-bit memory, respectively.
+const MAX_MEMORY32_PAGES: u64 = 1 << 16;
+const MAX_MEMORY64_PAGES: u64 = 1 << 48;
+/// Maximum number of table elements (tables are always indexed with `i32`).
+const MAX_TABLE_SIZE: u64 = u32::MAX as u64;
+
+/// Extended constant expressions extension: integer arithmetic in constant expressions.
+const CONST_BINARY_OPS: &[BinaryOp] = &[
+    BinaryOp::I32Add,
+    BinaryOp::I32Sub,
+    BinaryOp::I32Mul,
+    BinaryOp::I64Add,
+    BinaryOp::I64Sub,
+    BinaryOp::I64Mul,
+];
+
+/// Validates the whole `module` and returns the first error found.
+pub fn validate_module(module: &Module) -> Result<(), ValidationError> {
+    let validator = Validator::new(module);
+    validator.check_limits()?;
+    validator.check_exports()?;
+    validator.check_start()?;
+    validator.check_global_inits()?;
+    validator.check_elements()?;
+    validator.check_data()?;
+    validator.check_function_bodies()?;
+
+    // The type checker looks up the types of referenced functions, globals etc. without bounds
+    // checks, so it must only run after all indices are known to be valid.
+    TypeChecker::check_module(module).map_err(ValidationError::from_type_error)
+}
+
+struct Validator<'module> {
+    module: &'module Module,
+    /// Functions that may be referenced with `ref.func` in function bodies, which the spec calls
+    /// `C.refs` (reference types extension).
+    declared_functions: HashSet<Idx<Function>>,
+}
+
+impl<'module> Validator<'module> {
+    fn new(module: &'module Module) -> Self {
+        let global_inits = module.globals().filter_map(|(_, global)| global.init());
+        let element_exprs = module.elements().flat_map(|(_, element)| match &element.items {
+            ElementItems::Functions(_) => [].iter(),
+            ElementItems::Expressions(_, exprs) => exprs.iter(),
+        });
+        let mut declared_functions: HashSet<Idx<Function>> = global_inits.chain(element_exprs)
+            .flatten()
+            .filter_map(|instr| match instr {
+                Instr::RefFunc(func) => Some(*func),
+                _ => None,
+            })
+            .collect();
+        for (_, element) in module.elements() {
+            if let ElementItems::Functions(funcs) = &element.items {
+                declared_functions.extend(funcs.iter().copied());
+            }
+        }
+        for (idx, function) in module.functions() {
+            if !function.export.is_empty() {
+                declared_functions.insert(idx);
+            }
+        }
+
+        Validator { module, declared_functions }
+    }
+
+    // Index bounds checks that return the referenced entity, if valid.
+
+    fn function(&self, idx: Idx<Function>) -> Result<&'module Function, ValidationErrorKind> {
+        check_index(idx, self.module.functions.len(), "function")?;
+        Ok(self.module.function(idx))
+    }
+
+    fn global(&self, idx: Idx<Global>) -> Result<&'module Global, ValidationErrorKind> {
+        check_index(idx, self.module.globals.len(), "global")?;
+        Ok(self.module.global(idx))
+    }
+
+    fn table(&self, idx: Idx<Table>) -> Result<&'module Table, ValidationErrorKind> {
+        check_index(idx, self.module.tables.len(), "table")?;
+        Ok(self.module.table(idx))
+    }
+
+    fn memory(&self, idx: Idx<Memory>) -> Result<&'module Memory, ValidationErrorKind> {
+        check_index(idx, self.module.memories.len(), "memory")?;
+        Ok(self.module.memory(idx))
+    }
+
+    fn tag(&self, idx: Idx<Tag>) -> Result<(), ValidationErrorKind> {
+        check_index(idx, self.module.tags.len(), "tag")
+    }
+
+    fn element(&self, idx: Idx<Element>) -> Result<(), ValidationErrorKind> {
+        check_index(idx, self.module.elements.len(), "element")
+    }
+
+    fn data(&self, idx: Idx<Data>) -> Result<(), ValidationErrorKind> {
+        check_index(idx, self.module.data.len(), "data")
+    }
+
+    fn check_limits(&self) -> Result<(), ValidationError> {
+        for (idx, table) in self.module.tables() {
+            check_limits(&table.limits, MAX_TABLE_SIZE)
+                .map_err(|kind| ValidationError::new(kind, Location::Table(idx)))?;
+        }
+        for (idx, memory) in self.module.memories() {
+            let bound = if memory.memory64 { MAX_MEMORY64_PAGES } else { MAX_MEMORY32_PAGES };
+            check_limits(&memory.limits, bound)
+                .map_err(|kind| ValidationError::new(kind, Location::Memory(idx)))?;
+            if memory.shared && memory.limits.max_size.is_none() {
+                return Err(ValidationError::new(ValidationErrorKind::SharedMemoryWithoutMax, Location::Memory(idx)));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_exports(&self) -> Result<(), ValidationError> {
+        let module = self.module;
+        let exports = module.functions().map(|(idx, function)| (Location::Function(idx), &function.export))
+            .chain(module.tables().map(|(idx, table)| (Location::Table(idx), &table.export)))
+            .chain(module.memories().map(|(idx, memory)| (Location::Memory(idx), &memory.export)))
+            .chain(module.globals().map(|(idx, global)| (Location::Global(idx), &global.export)))
+            .chain(module.tags().map(|(idx, tag)| (Location::Tag(idx), &tag.export)));
+
+        let mut names = HashSet::new();
+        for (location, export_names) in exports {
+            for name in export_names {
+                if !names.insert(name.as_str()) {
+                    return Err(ValidationError::new(ValidationErrorKind::DuplicateExport(name.clone()), location));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_start(&self) -> Result<(), ValidationError> {
+        if let Some(start) = self.module.start {
+            let function = self.function(start)
+                .map_err(|kind| ValidationError::new(kind, Location::Start))?;
+            if function.type_ != FunctionType::empty() {
+                return Err(ValidationError::new(ValidationErrorKind::StartFunctionType(function.type_), Location::Start));
+            }
+        }
+        Ok(())
+    }
+
+    fn check_global_inits(&self) -> Result<(), ValidationError> {
+        for (idx, global) in self.module.globals() {
+            if let Some(init) = global.init() {
+                self.check_const_expr(init, global.type_.0)
+                    .map_err(|(instr_idx, kind)| ValidationError::new(kind, Location::Global(idx)).at_instruction(instr_idx, &init[instr_idx]))?;
+            }
+        }
+
+        // Imported globals are initialized before all others, and the defined ones in order.
+        // So a global initializer may read any imported global, but only defined globals before it.
+        for (idx, global) in self.module.globals() {
+            if let Some(init) = global.init() {
+                for (instr_idx, instr) in init.iter().enumerate() {
+                    if let &Instr::Global(GlobalOp::Get, read) = instr {
+                        if read >= idx && self.module.global(read).init().is_some() {
+                            let kind = match self.global_init_path(read, idx) {
+                                Some(path) => ValidationErrorKind::GlobalInitCycle(
+                                    std::iter::once(idx).chain(path.into_iter().filter(|&global| global != idx)).collect()),
+                                None => ValidationErrorKind::GlobalInitOrder(read),
+                            };
+                            return Err(ValidationError::new(kind, Location::Global(idx)).at_instruction(instr_idx, instr));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the globals on a path of initializer reads from `from` to `to`, if there is one.
+    fn global_init_path(&self, from: Idx<Global>, to: Idx<Global>) -> Option<Vec<Idx<Global>>> {
+        let mut visited = HashSet::new();
+        let mut worklist = vec![vec![from]];
+        while let Some(path) = worklist.pop() {
+            let last = *path.last().expect("paths are never empty");
+            if last == to {
+                return Some(path);
+            }
+            if !visited.insert(last) {
+                continue;
+            }
+            let reads = self.module.global(last).init().into_iter().flatten()
+                .filter_map(|instr| match instr {
+                    &Instr::Global(GlobalOp::Get, read) => Some(read),
+                    _ => None,
+                });
+            for read in reads {
+                let mut path = path.clone();
+                path.push(read);
+                worklist.push(path);
+            }
+        }
+        None
+    }
+
+    fn check_elements(&self) -> Result<(), ValidationError> {
+        for (idx, element) in self.module.elements() {
+            let elem_type = match &element.items {
+                ElementItems::Functions(_) => ValType::FuncRef,
+                ElementItems::Expressions(elem_type, _) => *elem_type,
+            };
+
+            if let ElementMode::Active { table, offset } = &element.mode {
+                let location = || Location::ElementOffset(idx);
+                let table = self.table(*table)
+                    .map_err(|kind| ValidationError::new(kind, location()))?;
+                if table.elem_type != elem_type {
+                    let kind = ValidationErrorKind::ElementType { expected: table.elem_type, actual: elem_type };
+                    return Err(ValidationError::new(kind, location()));
+                }
+                self.check_const_expr(offset, ValType::I32)
+                    .map_err(|(instr_idx, kind)| ValidationError::new(kind, location()).at_instruction(instr_idx, &offset[instr_idx]))?;
+            }
+
+            match &element.items {
+                ElementItems::Functions(funcs) => {
+                    for (item, &func) in funcs.iter().enumerate() {
+                        self.function(func)
+                            .map_err(|kind| ValidationError::new(kind, Location::ElementItem(idx, item)))?;
+                    }
+                }
+                ElementItems::Expressions(elem_type, exprs) => {
+                    for (item, expr) in exprs.iter().enumerate() {
+                        self.check_const_expr(expr, *elem_type)
+                            .map_err(|(instr_idx, kind)| ValidationError::new(kind, Location::ElementItem(idx, item)).at_instruction(instr_idx, &expr[instr_idx]))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_data(&self) -> Result<(), ValidationError> {
+        for (idx, data) in self.module.data() {
+            if let DataMode::Active { memory, offset } = &data.mode {
+                let memory = self.memory(*memory)
+                    .map_err(|kind| ValidationError::new(kind, Location::DataOffset(idx)))?;
+                self.check_const_expr(offset, memory.index_type())
+                    .map_err(|(instr_idx, kind)| ValidationError::new(kind, Location::DataOffset(idx)).at_instruction(instr_idx, &offset[instr_idx]))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `expr` only contains constant instructions and produces a single value of type
+    /// `expected`. On error, also returns the index of the offending instruction.
+    fn check_const_expr(&self, expr: &[Instr], expected: ValType) -> Result<(), (usize, ValidationErrorKind)> {
+        let mut stack = Vec::new();
+        for (instr_idx, instr) in expr.iter().enumerate() {
+            let error = |kind| (instr_idx, kind);
+            match instr {
+                Instr::Const(val) => stack.push(val.to_type()),
+                Instr::RefNull(ty) => stack.push(*ty),
+                Instr::RefFunc(func) => {
+                    self.function(*func).map_err(error)?;
+                    stack.push(ValType::FuncRef);
+                }
+                Instr::Global(GlobalOp::Get, idx) => {
+                    let global = self.global(*idx).map_err(error)?;
+                    if global.type_.1 == Mutability::Mut {
+                        return Err(error(ValidationErrorKind::MutableGlobalInConstExpr(*idx)));
+                    }
+                    stack.push(global.type_.0);
+                }
+                Instr::Binary(op) if CONST_BINARY_OPS.contains(op) => {
+                    let op_type = op.to_type();
+                    if !stack.ends_with(op_type.inputs()) {
+                        return Err(error(ValidationErrorKind::ConstExprType { expected: op_type.inputs().to_vec(), actual: stack }));
+                    }
+                    stack.truncate(stack.len() - op_type.inputs().len());
+                    stack.extend_from_slice(op_type.results());
+                }
+                Instr::End if instr_idx == expr.len() - 1 => {
+                    if stack != [expected] {
+                        return Err(error(ValidationErrorKind::ConstExprType { expected: vec![expected], actual: stack }));
+                    }
+                }
+                _ => return Err(error(ValidationErrorKind::NonConstantInstruction)),
+            }
+        }
+        Ok(())
+    }
+
+    fn check_function_bodies(&self) -> Result<(), ValidationError> {
+        for (idx, function) in self.module.functions() {
+            if let Some(code) = function.code() {
+                for (instr_idx, instr) in code.body.iter().enumerate() {
+                    self.check_instr(instr, function)
+                        .map_err(|kind| ValidationError::new(kind, Location::Function(idx)).at_instruction(instr_idx, instr))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the indices in a single instruction of a function body (except for labels, which are
+    /// checked by the type checker).
+    fn check_instr(&self, instr: &Instr, function: &Function) -> Result<(), ValidationErrorKind> {
+        use Instr::*;
+        match instr {
+            Call(func) | ReturnCall(func) => self.function(*func).map(drop),
+            RefFunc(func) => {
+                self.function(*func)?;
+                if !self.declared_functions.contains(func) {
+                    return Err(ValidationErrorKind::UndeclaredFunctionReference(*func));
+                }
+                Ok(())
+            }
+            CallIndirect(_, table) | ReturnCallIndirect(_, table)
+            | TableGet(table) | TableSet(table) | TableSize(table) | TableGrow(table) | TableFill(table) => self.table(*table).map(drop),
+            TableCopy { dst, src } => self.table(*dst).and(self.table(*src)).map(drop),
+            TableInit(elem, table) => self.element(*elem).and(self.table(*table).map(drop)),
+            ElemDrop(elem) => self.element(*elem),
+
+            Throw(tag) | Catch(tag) => self.tag(*tag),
+
+            Local(_, local) => check_index(*local, function.param_count() + function.local_count(), "local"),
+            Global(op, idx) => {
+                let global = self.global(*idx)?;
+                if *op == GlobalOp::Set && global.type_.1 == Mutability::Const {
+                    return Err(ValidationErrorKind::ImmutableGlobalSet(*idx));
+                }
+                Ok(())
+            }
+
+            Load(_, memarg) | Store(_, memarg) | LoadLane(_, memarg, _) | StoreLane(_, memarg, _)
+            | AtomicLoad(_, memarg) | AtomicStore(_, memarg) | Atomic(_, memarg) => self.memory(memarg.memory).map(drop),
+            MemorySize(memory) | MemoryGrow(memory) | MemoryFill(memory) => self.memory(*memory).map(drop),
+            MemoryCopy { dst, src } => self.memory(*dst).and(self.memory(*src)).map(drop),
+            MemoryInit(data, memory) => self.data(*data).and(self.memory(*memory).map(drop)),
+            DataDrop(data) => self.data(*data),
+
+            _ => Ok(()),
+        }
+    }
+}
+
+fn check_index<T>(idx: Idx<T>, count: usize, index_space: &'static str) -> Result<(), ValidationErrorKind> {
+    if idx.to_usize() < count {
+        Ok(())
+    } else {
+        Err(ValidationErrorKind::IndexOutOfBounds { index_space, index: idx.to_u32(), count })
+    }
+}
+
+fn check_limits(limits: &Limits, bound: u64) -> Result<(), ValidationErrorKind> {
+    for size in std::iter::once(limits.initial_size).chain(limits.max_size) {
+        if size > bound {
+            return Err(ValidationErrorKind::LimitsTooLarge { size, bound });
+        }
+    }
+    match limits.max_size {
+        Some(max) if limits.initial_size > max => Err(ValidationErrorKind::LimitsMinGreaterThanMax { initial: limits.initial_size, max }),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,164 @@
+/*
+ * Wasabi runtime: program-independent part of the generated <name>.wasabi.js file.
+ * Contains the loader (which supplies the low-level hooks as imports when the instrumented module
+ * is instantiated) and helper functions that are called from the generated low-level hooks.
+ */
+
+const Wasabi = {
+    module: {
+        // filled at instantiation time by the loader below
+        exports: undefined,
+        table: undefined,
+        // filled by the generated, program-dependent part of this file
+        info: undefined,
+        lowlevelHooks: undefined,
+    },
+
+    // set by the user, e.g., Wasabi.analysis = { binary(location, op, first, second, result) { ... } };
+    analysis: {},
+
+    HOOK_NAMES: [
+        "start",
+        "nop",
+        "unreachable",
+        "if_",
+        "br",
+        "br_if",
+        "br_table",
+        "begin",
+        "end",
+        "drop",
+        "select",
+        "call_pre",
+        "call_post",
+        "return_",
+        "throw_",
+        "rethrow",
+        "catch_",
+        "const_",
+        "unary",
+        "binary",
+        "load",
+        "store",
+        "atomic_load",
+        "atomic_store",
+        "atomic_rmw",
+        "atomic_cmpxchg",
+        "memory_size",
+        "memory_grow",
+        "memory_copy",
+        "memory_fill",
+        "table_set",
+        "table_grow",
+        "table_fill",
+        "table_copy",
+        "table_init",
+        "local",
+        "global",
+    ],
+
+    // i64 values are passed to the low-level hooks as two i32 halves (see convert_i64.rs)
+    i64: function (low, high) {
+        return (BigInt(high) << 32n) | BigInt(low >>> 0);
+    },
+
+    // v128 values are passed as four i32 lanes, lowest first, and given to analyses as the
+    // 16 bytes of the vector in little-endian order (i.e., the same order as in memory)
+    v128: function (lane0, lane1, lane2, lane3) {
+        return new Uint8Array(new Int32Array([lane0, lane1, lane2, lane3]).buffer);
+    },
+
+    // call end() hooks for all blocks that are left by the taken br_table target,
+    // which is only known at runtime
+    endBrTableBlocks: function (brTablesInfoIdx, tableIdx, func) {
+        const brTableInfo = Wasabi.module.info.brTables[brTablesInfoIdx];
+        const target = (tableIdx < brTableInfo.table.length) ? brTableInfo.table[tableIdx] : brTableInfo.default;
+        // the fourth element is only present for else and catch blocks (the matching if or try)
+        for (const [blockType, begin, end, ifOrTryBegin] of target.ends) {
+            Wasabi.analysis.end(
+                {func, instr: end},
+                blockType,
+                {func, instr: begin},
+                (ifOrTryBegin === undefined) ? undefined : {func, instr: ifOrTryBegin},
+            );
+        }
+    },
+
+    // map an index into the table to the (original, i.e., not instrumented) function index
+    resolveTableIdx: function (tableIdx) {
+        if (Wasabi.module.exports === undefined || Wasabi.module.table === undefined) {
+            console.warn("Wasabi: cannot resolve table index without module exports or table, was the module instantiated via the Wasabi loader?");
+            return undefined;
+        }
+        // out of bounds, the call_indirect will trap right after the hook
+        if (tableIdx >= Wasabi.module.table.length) {
+            return undefined;
+        }
+        const resolvedFunction = Wasabi.module.table.get(tableIdx);
+        if (resolvedFunction === null) {
+            return undefined;
+        }
+        // the name property of an exported function is its index in the instrumented module, see
+        // https://webassembly.github.io/spec/js-api/#exported-function-exotic-objects
+        // The low-level hooks are imported functions in the instrumented module and thus shift
+        // the indices of all non-imported functions.
+        const instrumentedIdx = parseInt(resolvedFunction.name);
+        const lowlevelHookCount = Object.keys(Wasabi.module.lowlevelHooks).length;
+        return (instrumentedIdx < Wasabi.module.info.originalFunctionImportsCount)
+            ? instrumentedIdx
+            : instrumentedIdx - lowlevelHookCount;
+    },
+};
+
+/*
+ * Loader: patch the WebAssembly JS API, such that the low-level hooks are added to the imports
+ * and the exports (in particular the table) are available for resolving indirect calls.
+ */
+(function () {
+    function addHooks(importObject) {
+        // the low-level hooks call all high-level hooks unconditionally, so fill unimplemented ones
+        for (const hook of Wasabi.HOOK_NAMES) {
+            if (!(hook in Wasabi.analysis)) {
+                Wasabi.analysis[hook] = function () {};
+            }
+        }
+        const importObjectWithHooks = Object.assign({}, importObject);
+        importObjectWithHooks.__wasabi_hooks = Wasabi.module.lowlevelHooks;
+        return importObjectWithHooks;
+    }
+
+    function onInstance(instance) {
+        Wasabi.module.exports = instance.exports;
+        Wasabi.module.table = instance.exports[Wasabi.module.info.tableExportName];
+    }
+
+    const oldInstantiate = WebAssembly.instantiate;
+    WebAssembly.instantiate = function (source, importObject) {
+        return oldInstantiate(source, addHooks(importObject)).then(result => {
+            // returns either an Instance (if given a Module) or {module, instance} (if given bytes)
+            onInstance(result instanceof WebAssembly.Instance ? result : result.instance);
+            return result;
+        });
+    };
+
+    if (WebAssembly.instantiateStreaming !== undefined) {
+        const oldInstantiateStreaming = WebAssembly.instantiateStreaming;
+        WebAssembly.instantiateStreaming = function (source, importObject) {
+            return oldInstantiateStreaming(source, addHooks(importObject)).then(result => {
+                onInstance(result.instance);
+                return result;
+            });
+        };
+    }
+
+    const OldInstance = WebAssembly.Instance;
+    WebAssembly.Instance = function (module, importObject) {
+        const instance = new OldInstance(module, addHooks(importObject));
+        onInstance(instance);
+        return instance;
+    };
+    WebAssembly.Instance.prototype = OldInstance.prototype;
+})();
+
+// analyses are written against the global Wasabi object
+globalThis.Wasabi = Wasabi;
@@ -13,14 +13,15 @@ use self::BlockStackElement::*;
  * Data structure for representing the "control stack", i.e., the implicit nested block structure
  * of control-flow instructions.
  * Needed for:
- *  - resolving End instructions to their corresponding begins (i.e., Block, Loop, If, Else)
+ *  - resolving End instructions to their corresponding begins (i.e., Block, Loop, If, Else, Try, Catch)
  *  - resolving labels of branches to actual instruction indices (which requires the previous as a first step)
  */
 
 #[derive(Debug)]
 pub struct BlockStack {
     block_stack: SmallVec<[BlockStackElement; 8]>,
-    /// Maps the beginning of a block to its end (or else, for if) instruction. Pre-computed on new().
+    /// Maps the beginning of a block to its end (or else, for if; or the next catch/catch_all or
+    /// delegate, for try and catch blocks) instruction. Pre-computed on new().
     begin_end_map: IntMap<Idx<Instr>, Idx<Instr>>,
 }
 
@@ -47,6 +48,18 @@ pub enum BlockStackElement {
         begin_if: Idx<Instr>,
         end: Idx<Instr>,
     },
+    /// `end` is the end of the whole try-catch construct (or the delegate ending the try block),
+    /// since that is where branches out of the try block (and any of its handlers) continue.
+    Try {
+        begin: Idx<Instr>,
+        end: Idx<Instr>,
+    },
+    /// Exception handler started by either a catch or catch_all instruction.
+    Catch {
+        begin_catch: Idx<Instr>,
+        begin_try: Idx<Instr>,
+        end: Idx<Instr>,
+    },
 }
 
 impl BlockStack {
@@ -60,14 +73,14 @@ impl BlockStack {
         for (iidx, instr) in instrs[..instrs.len() - 1].iter().enumerate() {
             let iidx = iidx.into();
             match *instr {
-                Instr::Block(_) | Instr::Loop(_) | Instr::If(_) => begin_stack.push(iidx),
-                Instr::Else | Instr::End => {
+                Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Try(_) => begin_stack.push(iidx),
+                Instr::Else | Instr::End | Instr::Catch(_) | Instr::CatchAll | Instr::Delegate(_) => {
                     let begin_iidx = begin_stack
                         .pop()
                         .expect("invalid block nesting: could not end block, stack was empty");
                     begin_end_map.insert(begin_iidx, iidx);
-                    // special case: Else also starts its own block
-                    if let Instr::Else = instr {
+                    // special case: Else (and exception handlers) also start their own block
+                    if let Instr::Else | Instr::Catch(_) | Instr::CatchAll = instr {
                         begin_stack.push(iidx);
                     }
                 }
@@ -126,6 +139,24 @@ impl BlockStack {
         self.block_stack.push(if_);
     }
 
+    pub fn begin_try(&mut self, begin: Idx<Instr>) {
+        self.block_stack.push(Try {
+            begin,
+            end: self.try_end(begin),
+        });
+    }
+
+    /// follows the chain of handlers after a try (or catch) block to the end of the whole construct
+    fn try_end(&self, begin: Idx<Instr>) -> Idx<Instr> {
+        let mut end = *self.begin_end_map.get(&begin).unwrap_or_else(|| panic!(
+            "invalid block nesting: could not find end/catch/delegate for try or catch begin at {begin:?}"
+        ));
+        while let Some(&next) = self.begin_end_map.get(&end) {
+            end = next;
+        }
+        end
+    }
+
     /// returns matching If block (of which this else is a "sibling")
     pub fn else_(&mut self) -> BlockStackElement {
         match self.block_stack.pop() {
@@ -150,6 +181,36 @@ impl BlockStack {
         }
     }
 
+    /// begins an exception handler (for catch and catch_all instructions)
+    /// returns the preceding Try or Catch block, which is ended by this handler
+    pub fn catch(&mut self, begin_catch: Idx<Instr>) -> BlockStackElement {
+        match self.block_stack.pop() {
+            Some(block_element) => match block_element {
+                Try { begin: begin_try, end } | Catch { begin_try, end, .. } => {
+                    self.block_stack.push(Catch {
+                        begin_catch,
+                        begin_try,
+                        end,
+                    });
+                    block_element
+                }
+                block => panic!(
+                    "invalid block nesting: expected try or catch on block stack, but got {block:?}"
+                ),
+            },
+            None => panic!("invalid block nesting: expected try or catch, but stack was empty"),
+        }
+    }
+
+    /// returns the block with the given relative label, e.g., the handler targeted by a rethrow
+    pub fn get(&self, label: Label) -> &BlockStackElement {
+        self.block_stack
+            .iter()
+            .rev()
+            .nth(label.to_usize())
+            .unwrap_or_else(|| panic!("invalid label: cannot find block for {label:?}"))
+    }
+
     pub fn end(&mut self) -> BlockStackElement {
         self.block_stack
             .pop()
@@ -178,7 +239,7 @@ impl BlockStack {
 
             match *target_block {
                 Loop { begin, .. } => begin,
-                Function { end } | Block { end, .. } | If { end, .. } | Else { end, .. } => end,
+                Function { end } | Block { end, .. } | If { end, .. } | Else { end, .. } | Try { end, .. } | Catch { end, .. } => end,
             }
         };
 
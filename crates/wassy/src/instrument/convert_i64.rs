@@ -1,21 +1,26 @@
 use wasabi_wasm::BinaryOp::I64ShrS;
+use wasabi_wasm::ExtractLaneOp::I32x4ExtractLane;
 use wasabi_wasm::Instr;
 use wasabi_wasm::Instr::Binary;
 use wasabi_wasm::Instr::Const;
+use wasabi_wasm::Instr::ExtractLane;
 use wasabi_wasm::Instr::Unary;
 use wasabi_wasm::UnaryOp::I32WrapI64;
 use wasabi_wasm::Val;
 use wasabi_wasm::ValType;
 use wasabi_wasm::ValType::I32;
 use wasabi_wasm::ValType::I64;
+use wasabi_wasm::ValType::V128;
 
 /*
  * Helper functions for turning i64's into two i32's so that we can pass them to JavaScript
+ * (and similarly, v128's into four i32's, which JavaScript cannot receive at all)
  */
 
 pub fn convert_i64_type(ty: &ValType) -> &[ValType] {
     match ty {
         &I64 => &[I32, I32],
+        &V128 => &[I32, I32, I32, I32],
         ty => std::slice::from_ref(ty),
     }
 }
@@ -33,6 +38,12 @@ pub fn convert_i64_instr(append_to: &mut Vec<Instr>, instr: Instr, ty: ValType)
             Binary(I64ShrS),
             Unary(I32WrapI64), // high bits
         ]),
+        // Lanes in little-endian order, i.e., lowest bits first, like for i64.
+        V128 => {
+            for lane in 0..4 {
+                append_to.extend_from_slice(&[instr.clone(), ExtractLane(I32x4ExtractLane, lane)]);
+            }
+        }
         _ => append_to.push(instr),
     }
 }
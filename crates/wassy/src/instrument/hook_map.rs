@@ -34,14 +34,17 @@ impl Arg {
     fn to_lowlevel_param_name(&self) -> String {
         match self.ty {
             I64 => self.name.clone() + "_low, " + &self.name + "_high",
+            V128 => (0..4).map(|lane| format!("{}_{lane}", self.name)).collect::<Vec<_>>().join(", "),
             _ => self.name.clone(),
         }
     }
 
-    /// for the actual argument when forwarding to the high-level hook
+    /// for the actual argument when forwarding to the high-level hook (i64 are joined into a BigInt,
+    /// v128 into a Uint8Array)
     fn to_lowlevel_long_expr(&self) -> String {
         match self.ty {
-            I64 => format!("new Long({})", self.to_lowlevel_param_name()),
+            I64 => format!("Wasabi.i64({})", self.to_lowlevel_param_name()),
+            V128 => format!("Wasabi.v128({})", self.to_lowlevel_param_name()),
             _ => self.name.clone(),
         }
     }
@@ -52,6 +55,16 @@ macro_rules! args {
     ($($name:ident: $ty:expr),*) => (vec![ $(Arg { name: stringify!($name).into(), ty: $ty }),* ]);
 }
 
+/// index type of the memory accessed by a memory instruction, given as the (only) polymorphic type
+/// for 64-bit memories and omitted for 32-bit memories
+fn memory_index_ty(polymorphic_tys: &[ValType]) -> ValType {
+    match *polymorphic_tys {
+        [] => I32,
+        [index_ty] => index_ty,
+        _ => panic!("memory instructions have at most one polymorphic index type"),
+    }
+}
+
 pub struct Hook {
     pub idx: Idx<Function>,
     pub wasm: Function,
@@ -84,6 +97,7 @@ impl Hook {
             lowlevel_args.extend(
                 args.iter()
                     // and expand i64 to a tuple of (i32, i32) since there is no JS interop for i64
+                    // (and v128 to four i32, see convert_i64.rs)
                     .flat_map(
                         |Arg {
                              name: _name,
@@ -148,29 +162,69 @@ impl HookMap {
 
             Nop | Unreachable => Hook::new(&ll_name, args!(), &ll_name, ""),
 
+            // catch_all carries no values and belongs to no tag
+            CatchAll => Hook::new(&ll_name, args!(), "catch_", "undefined, []"),
+            Rethrow(_) => Hook::new(&ll_name, args!(catchInstr: I32), &ll_name, "{func, instr: catchInstr}"),
+
             If(_) => Hook::new(&ll_name, args!(condition: I32), "if_", "condition === 1"),
             Br(_) => Hook::new(&ll_name, args!(targetLabel: I32, targetInstr: I32), &ll_name, "{label: targetLabel, location: {func, instr: targetInstr}}"),
             BrIf(_) => Hook::new(&ll_name, args!(condition: I32, targetLabel: I32, targetInstr: I32), &ll_name, "{label: targetLabel, location: {func, instr: targetInstr}}, condition === 1"),
             // NOTE js_args is very hacky! We rely on the Hook constructor to close the parenthesis and insert the call statement to endBrTableBlock() here
             BrTable { .. } => Hook::new(&ll_name, args!(tableIdx: I32, brTablesInfoIdx: I32), &ll_name, "Wasabi.module.info.brTables[brTablesInfoIdx].table, Wasabi.module.info.brTables[brTablesInfoIdx].default, tableIdx); Wasabi.endBrTableBlocks(brTablesInfoIdx, tableIdx, func"),
 
-            MemorySize(_) => Hook::new(&ll_name, args!(currentSizePages: I32), &ll_name, "currentSizePages"),
-            MemoryGrow(_) => Hook::new(&ll_name, args!(deltaPages: I32, previousSizePages: I32), &ll_name, "deltaPages, previousSizePages"),
+            TableCopy { .. } => Hook::new(&ll_name, args!(dstTable: I32, srcTable: I32, dst: I32, src: I32, length: I32), &ll_name, "dstTable, srcTable, dst, src, length"),
+            TableInit(_, _) => Hook::new(&ll_name, args!(table: I32, element: I32, dst: I32, src: I32, length: I32), &ll_name, "table, element, dst, src, length"),
 
             Load(op, _) => {
                 let ty = op.to_type().results()[0];
-                let args = args!(offset: I32, align: I32, addr: I32, value: ty);
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(offset: index_ty, align: I32, memory: I32, addr: index_ty, value: ty);
                 let instr_name = instr.to_name();
-                let js_args = &format!("\"{}\", {{addr, offset, align}}, {}", instr_name, &args[3].to_lowlevel_long_expr());
+                let js_args = &format!("\"{}\", {{addr: {}, offset: {}, align, memory}}, {}", instr_name, args[3].to_lowlevel_long_expr(), args[0].to_lowlevel_long_expr(), args[4].to_lowlevel_long_expr());
                 Hook::new(ll_name, args, "load", js_args)
             }
             Store(op, _) => {
                 let ty = op.to_type().inputs()[1];
-                let args = args!(offset: I32, align: I32, addr: I32, value: ty);
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(offset: index_ty, align: I32, memory: I32, addr: index_ty, value: ty);
                 let instr_name = instr.to_name();
-                let js_args = &format!("\"{}\", {{addr, offset, align}}, {}", instr_name, &args[3].to_lowlevel_long_expr());
+                let js_args = &format!("\"{}\", {{addr: {}, offset: {}, align, memory}}, {}", instr_name, args[3].to_lowlevel_long_expr(), args[0].to_lowlevel_long_expr(), args[4].to_lowlevel_long_expr());
                 Hook::new(ll_name, args, "store", js_args)
             }
+            // all atomic accesses of the threads proposal are sequentially consistent
+            AtomicLoad(op, _) => {
+                let ty = op.to_type().results()[0];
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(offset: index_ty, align: I32, memory: I32, addr: index_ty, value: ty);
+                let instr_name = instr.to_name();
+                let js_args = &format!("\"{}\", {{addr: {}, offset: {}, align, memory}}, {}, \"seq_cst\"", instr_name, args[3].to_lowlevel_long_expr(), args[0].to_lowlevel_long_expr(), args[4].to_lowlevel_long_expr());
+                Hook::new(ll_name, args, "atomic_load", js_args)
+            }
+            AtomicStore(op, _) => {
+                let ty = op.to_type().inputs()[1];
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(offset: index_ty, align: I32, memory: I32, addr: index_ty, value: ty);
+                let instr_name = instr.to_name();
+                let js_args = &format!("\"{}\", {{addr: {}, offset: {}, align, memory}}, {}, \"seq_cst\"", instr_name, args[3].to_lowlevel_long_expr(), args[0].to_lowlevel_long_expr(), args[4].to_lowlevel_long_expr());
+                Hook::new(ll_name, args, "atomic_store", js_args)
+            }
+            // read-modify-write operations also return the old value, which was read from memory
+            Atomic(op, _) if op.is_cmpxchg() => {
+                let ty = op.to_type();
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(offset: index_ty, align: I32, memory: I32, addr: index_ty, expected: ty.inputs()[1], replacement: ty.inputs()[2], oldValue: ty.results()[0]);
+                let instr_name = instr.to_name();
+                let js_args = &format!("\"{}\", {{addr: {}, offset: {}, align, memory}}, {}, \"seq_cst\"", instr_name, args[3].to_lowlevel_long_expr(), args[0].to_lowlevel_long_expr(), args[4..].iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", "));
+                Hook::new(ll_name, args, "atomic_cmpxchg", js_args)
+            }
+            Atomic(op, _) if !op.is_wait_or_notify() => {
+                let ty = op.to_type();
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(offset: index_ty, align: I32, memory: I32, addr: index_ty, value: ty.inputs()[1], oldValue: ty.results()[0]);
+                let instr_name = instr.to_name();
+                let js_args = &format!("\"{}\", {{addr: {}, offset: {}, align, memory}}, {}, \"seq_cst\"", instr_name, args[3].to_lowlevel_long_expr(), args[0].to_lowlevel_long_expr(), args[4..].iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", "));
+                Hook::new(ll_name, args, "atomic_rmw", js_args)
+            }
 
             Const(val) => {
                 let ty = val.to_type();
@@ -213,7 +267,7 @@ impl HookMap {
                 let js_args = &args[0].to_lowlevel_long_expr();
                 Hook::new(ll_name, args, "drop", js_args)
             }
-            Select => {
+            Select | TypedSelect(_) => {
                 assert_eq!(polymorphic_tys.len(), 2, "select has two polymorphic arguments");
                 assert_eq!(polymorphic_tys[0], polymorphic_tys[1], "select arguments must be equal");
                 let args = args!(condition: I32, input0: polymorphic_tys[0], input1: polymorphic_tys[1]);
@@ -234,19 +288,76 @@ impl HookMap {
                 let js_args = &format!("\"{}\", {}", instr_name, args.iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", "));
                 Hook::new(ll_name, args, "global", js_args)
             }
+            // the table element type is only known from the module, so it is given as polymorphic type
+            TableSet(_) => {
+                assert_eq!(polymorphic_tys.len(), 1, "table.set has only one reference argument");
+                let args = args!(table: I32, index: I32, value: polymorphic_tys[0]);
+                Hook::new(&ll_name, args, "table_set", "table, index, value")
+            }
+            TableGrow(_) => {
+                assert_eq!(polymorphic_tys.len(), 1, "table.grow has only one reference argument");
+                let args = args!(table: I32, initValue: polymorphic_tys[0], delta: I32, previousSize: I32);
+                Hook::new(&ll_name, args, "table_grow", "table, initValue, delta, previousSize")
+            }
+            TableFill(_) => {
+                assert_eq!(polymorphic_tys.len(), 1, "table.fill has only one reference argument");
+                let args = args!(table: I32, dst: I32, value: polymorphic_tys[0], length: I32);
+                Hook::new(&ll_name, args, "table_fill", "table, dst, value, length")
+            }
+            // the index type of the memory is only known from the module, and only given as
+            // polymorphic type for 64-bit memories, such that hooks for 32-bit memories keep their name
+            MemorySize(_) => {
+                let args = args!(currentSizePages: memory_index_ty(polymorphic_tys));
+                let js_args = &args[0].to_lowlevel_long_expr();
+                Hook::new(&ll_name, args, "memory_size", js_args)
+            }
+            MemoryGrow(_) => {
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(deltaPages: index_ty, previousSizePages: index_ty);
+                let js_args = &args.iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", ");
+                Hook::new(&ll_name, args, "memory_grow", js_args)
+            }
+            MemoryCopy { .. } => {
+                let (dst_ty, src_ty) = match *polymorphic_tys {
+                    [] => (I32, I32),
+                    [dst_ty, src_ty] => (dst_ty, src_ty),
+                    _ => panic!("memory.copy has either no or two polymorphic index types"),
+                };
+                // the length is only 64-bit if both memories are
+                let length_ty = if dst_ty == src_ty { dst_ty } else { I32 };
+                let args = args!(dst: dst_ty, src: src_ty, length: length_ty);
+                let js_args = &args.iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", ");
+                Hook::new(&ll_name, args, "memory_copy", js_args)
+            }
+            // only the lowest byte of the value is written to memory
+            MemoryFill(_) => {
+                let index_ty = memory_index_ty(polymorphic_tys);
+                let args = args!(dst: index_ty, value: I32, length: index_ty);
+                let js_args = &format!("{}, value & 0xff, {}", args[0].to_lowlevel_long_expr(), args[2].to_lowlevel_long_expr());
+                Hook::new(&ll_name, args, "memory_fill", js_args)
+            }
             Return => {
                 let args = polymorphic_tys.iter().enumerate().map(|(i, &ty)| Arg { name: format!("result{i}"), ty }).collect::<Vec<_>>();
                 let js_args = &format!("[{}]", args.iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", "));
                 Hook::new(ll_name, args, "return_", js_args)
             }
-            Call(_) => {
+            // tail calls are reported to the same high-level hook as normal calls
+            Call(_) | ReturnCall(_) => {
                 let mut args = args!(targetFunc: I32);
                 args.extend(polymorphic_tys.iter().enumerate().map(|(i, &ty)| Arg { name: format!("arg{i}"), ty }));
                 // NOTE calls the high-level call_pre hook with one argument less than call_indirect, thus tableIdx === undefined since this is a direct call
                 let js_args = &format!("targetFunc, [{}]", args[1..].iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", "));
                 Hook::new(ll_name, args, "call_pre", js_args)
             }
-            CallIndirect(_, _) => {
+            // the values carried by an exception depend on the type of its tag
+            Throw(_) | Catch(_) => {
+                let mut args = args!(tag: I32);
+                args.extend(polymorphic_tys.iter().enumerate().map(|(i, &ty)| Arg { name: format!("value{i}"), ty }));
+                let js_args = &format!("tag, [{}]", args[1..].iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", "));
+                let highlevel_name = if let Throw(_) = instr { "throw_" } else { "catch_" };
+                Hook::new(ll_name, args, highlevel_name, js_args)
+            }
+            CallIndirect(_, _) | ReturnCallIndirect(_, _) => {
                 let mut args = args!(tableIndex: I32);
                 args.extend(polymorphic_tys.iter().enumerate().map(|(i, &ty)| Arg { name: format!("arg{i}"), ty }));
                 let js_args = &format!("Wasabi.resolveTableIdx(tableIndex), [{}], tableIndex", args[1..].iter().map(Arg::to_lowlevel_long_expr).collect::<Vec<_>>().join(", "));
@@ -256,7 +367,14 @@ impl HookMap {
 
             /* instructions that need additional information and thus have own method */
 
-            Block(_) | Loop(_) | Else | End => panic!("cannot get hook for block-type instruction with this method, please use the other methods specialized to the block type"),
+            Block(_) | Loop(_) | Else | End | Try(_) | Delegate(_) => panic!("cannot get hook for block-type instruction with this method, please use the other methods specialized to the block type"),
+
+            MemoryInit(_, _) | DataDrop(_) | ElemDrop(_)
+            | RefNull(_) | RefIsNull | RefFunc(_) | TableGet(_) | TableSize(_)
+            | LoadLane(_, _, _) | StoreLane(_, _, _) | ExtractLane(_, _) | ReplaceLane(_, _)
+            | I8x16Shuffle(_) | V128Bitselect
+            // memory.atomic.wait/notify
+            | Atomic(_, _) | AtomicFence => panic!("there is no hook for instruction {instr}"),
         };
 
         self.get_or_insert(ll_name, generate_hook)
@@ -315,6 +433,18 @@ impl HookMap {
         ))
     }
 
+    pub fn begin_try(&self) -> Instr {
+        self.get_or_insert(LowLevelHookName::monomorphic("begin_try"), |ll_name| Hook::new(ll_name, vec![], "begin", "\"try\""))
+    }
+
+    pub fn begin_catch(&self) -> Instr {
+        self.get_or_insert(LowLevelHookName::monomorphic("begin_catch"), |ll_name| Hook::new(ll_name,
+            args!(tryInstr: I32),
+            "begin",
+            "\"catch\", {func, instr: tryInstr}",
+        ))
+    }
+
     pub fn end(&self, block: &BlockStackElement) -> Instr {
         let (ll_name, generate_hook): (_, fn(String) -> Hook) = match *block {
             BlockStackElement::Function { .. } => (
@@ -362,6 +492,24 @@ impl HookMap {
                     "\"else\", {func, instr: elseInstr}, {func, instr: ifInstr}",
                 )
             ),
+            BlockStackElement::Try { .. } => (
+                LowLevelHookName::monomorphic("end_try"),
+                |ll_name| Hook::new(
+                    ll_name,
+                    args!(beginInstr: I32),
+                    "end",
+                    "\"try\", {func, instr: beginInstr}",
+                )
+            ),
+            BlockStackElement::Catch { .. } => (
+                LowLevelHookName::monomorphic("end_catch"),
+                |ll_name| Hook::new(
+                    ll_name,
+                    args!(catchInstr: I32, tryInstr: I32),
+                    "end",
+                    "\"catch\", {func, instr: catchInstr}, {func, instr: tryInstr}",
+                )
+            ),
         };
         self.get_or_insert(ll_name, generate_hook)
     }
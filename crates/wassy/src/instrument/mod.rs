@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::convert::TryInto;
 
 use parking_lot::RwLock;
@@ -10,10 +11,11 @@ use wasabi_wasm::Instr;
 use wasabi_wasm::Instr::*;
 use wasabi_wasm::Label;
 use wasabi_wasm::LocalOp::*;
-use wasabi_wasm::MemoryOp;
 use wasabi_wasm::Module;
 use wasabi_wasm::Mutability;
+use wasabi_wasm::types::InferredInstructionType;
 use wasabi_wasm::Val;
+use wasabi_wasm::ValType;
 use wasabi_wasm::ValType::*;
 
 use crate::options::Hook;
@@ -26,7 +28,6 @@ use self::duplicate_stack::*;
 use self::hook_map::HookMap;
 use self::pointer_hardening::harden_module;
 use self::static_info::*;
-use self::type_stack::TypeStack;
 use self::write_protection::write_protect_range;
 use self::monitor_inst::monitor_test;
 
@@ -35,17 +36,23 @@ mod convert_i64;
 mod duplicate_stack;
 mod hook_map;
 mod pointer_hardening;
-mod static_info;
-pub mod type_stack;
+pub mod static_info;
 mod write_protection;
 mod monitor_inst;
 
+#[cfg(test)]
+mod tests;
+
 /// Instruments every instruction in Jalangi-style with a callback that takes inputs, outputs, and
 /// other relevant information.
+/// Returns the generated JavaScript (Node.js CommonJS module if `node_js`, ES module for the browser otherwise),
+/// the static information about the module, needed by the runtime, and the number of inserted low-level hooks.
 #[allow(clippy::cognitive_complexity)]
-pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
+pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet, node_js: bool) -> Option<(String, ModuleInfo, usize)> {
     // make sure table is exported, needed for Wasabi runtime to resolve table indices to function indices.
-    for table in &mut module.tables {
+    // NOTE only the first table, because export names must be unique and the runtime resolves
+    // only call_indirect's on that table anyway.
+    if let Some(table) = module.tables.first_mut() {
         if table.export.is_empty() {
             table.export.push("__wasabi_table".into());
         }
@@ -69,8 +76,25 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
         None
     };
 
+    // for the index type of memory instructions, i.e., whether they access a 64-bit memory
+    let memories = &module.memories;
+    // for the values carried by exceptions
+    let tags = &module.tags;
+
+    // Drop and select are value-polymorphic, so get their types from the type checker. This needs
+    // the whole module, so do it for all functions before they are borrowed mutably below.
+    let drop_select_types: Vec<_> = {
+        let module = &*module;
+        module.functions.par_iter()
+            .map(|function| drop_select_types(function, module))
+            .collect()
+    };
+
+    // static info for br_tables per function, see `BrTable` below
+    let br_table_infos = RwLock::new(Vec::new());
+
     module.functions.par_iter_mut().enumerate().for_each(|(fidx, function): (usize, &mut Function)| {
-        let fidx = fidx.into();
+        let fidx: Idx<Function> = fidx.into();
         // only instrument non-imported functions
         if function.code().is_none() {
             return;
@@ -90,7 +114,11 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
         // for branch target resolution (i.e., relative labels -> instruction locations)
         let mut block_stack = BlockStack::new(&original_body);
         // for drop/select monomorphization (cannot determine their input types only from instruction, but need this additional type information)
-        let mut type_stack = TypeStack::new();
+        let drop_select_types = &drop_select_types[fidx.to_usize()];
+        // static info for the br_tables in this function and where their index is passed to the
+        // hook, for making the index absolute after instrumenting all functions
+        let mut function_br_tables = Vec::new();
+        let mut br_table_info_idx_consts = Vec::new();
 
         // execute start hook before anything else
         if module_info.read().start == Some(fidx)
@@ -119,7 +147,8 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
         }
 
         // remember implicit return for instrumentation: add "synthetic" return hook call to last end
-        let implicit_return = !original_body.ends_with(&[Return, End]);
+        // (tail calls also return explicitly, see below)
+        let implicit_return = !matches!(original_body.as_slice(), [.., Return | ReturnCall(_) | ReturnCallIndirect(_, _), End]);
 
         // WebAssembly's type rules are weird with unreachable code (i.e., code after an
         // "unreachable" instruction or after unconditional branches like return/br/br_table):
@@ -137,11 +166,9 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
         // but to me it looks like it makes type checking more _complicated_!?
         // Since we can't change the spec now, I guess we are stuck with it though...)
         //
-        // Unfortunately, Wasabi's type checking (in type_stack) is not powerful enough
-        // to "produce" the missing types for unreachable code.
-        // (TODO I think this needs some form of primitive unification. E.g., checking
-        // [unreachable, f32] against [i32, f32, f32]
-        // should "expand" or unify the type of the unreachable instruction to/with [i32, f32].)
+        // The type checker in wasabi_wasm handles these "produced" types, but it cannot assign
+        // concrete types to instructions in unreachable code (see `InferredInstructionType`), and
+        // the hooks need concrete types for their arguments.
         //
         // Since I don't want to introduce this kind of complexity, and because unreachable code
         // is by definition never executed, a workaround is to simply not instrument unreachable
@@ -160,10 +187,10 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
 
         for (iidx, instr) in original_body.into_iter().enumerate() {
 
-            // End or Else could end the current "unreachable" block.
+            // End or Else (or a handler or delegate ending a try block) could end the current "unreachable" block.
             if unreachable_depth > 0 {
                 match instr {
-                    Else | End => unreachable_depth -= 1,
+                    Else | End | Catch(_) | CatchAll | Delegate(_) => unreachable_depth -= 1,
                     _ => {}
                 };
             }
@@ -174,10 +201,11 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                 // 2. If the unreachable code itself contains even deeper blocks, increase the "unreachable depth".
                 match instr {
                     // NOTE Else can also open a "deeper" unreachable block, but only if we were unreachable to begin with.
-                    Block(_) | Loop(_) | If(_) | Else => unreachable_depth += 1,
+                    // Same for exception handlers.
+                    Block(_) | Loop(_) | If(_) | Else | Try(_) | Catch(_) | CatchAll => unreachable_depth += 1,
                     _ => {}
                 };
-                // 3. DO NOT instrument unreachable code, since type_stack will throw an exception on
+                // 3. DO NOT instrument unreachable code, since there are no concrete types for
                 // instructions that pop types that are "magically produced" by unreachable code.
                 continue;
             }
@@ -222,9 +250,8 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
 
                 /* Control Instructions: Blocks */
 
-                Block(block_ty) => {
+                Block(_) => {
                     block_stack.begin_block(iidx);
-                    type_stack.begin(block_ty);
 
                     instrumented_body.push(instr);
 
@@ -236,9 +263,8 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                         ])
                     }
                 }
-                Loop(block_ty) => {
+                Loop(_) => {
                     block_stack.begin_loop(iidx);
-                    type_stack.begin(block_ty);
 
                     instrumented_body.push(instr);
 
@@ -250,10 +276,8 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                         ])
                     }
                 }
-                If(block_ty) => {
+                If(_) => {
                     block_stack.begin_if(iidx);
-                    type_stack.instr(&FunctionType::new(&[I32], &[]));
-                    type_stack.begin(block_ty);
 
                     // if_ hook for the condition (always executed on either branch)
                     if enabled_hooks.contains(Hook::If) {
@@ -288,8 +312,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                         unreachable!()
                     };
 
-                    type_stack.else_();
-
                     if enabled_hooks.contains(Hook::End) {
                         instrumented_body.extend_from_slice(&[
                             location.0.clone(),
@@ -313,7 +335,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                 End => {
                     let block = block_stack.end();
                     assert_eq!(iidx, block.end());
-                    type_stack.end();
 
                     // add "synthetic" return hook call for implicit returns
                     if implicit_return
@@ -344,6 +365,130 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                 }
 
 
+                /* Control Instructions: Exceptions */
+
+                Try(_) => {
+                    block_stack.begin_try(iidx);
+
+                    instrumented_body.push(instr);
+
+                    if enabled_hooks.contains(Hook::Begin) {
+                        instrumented_body.extend_from_slice(&[
+                            location.0,
+                            location.1,
+                            hooks.begin_try(),
+                        ])
+                    }
+                }
+                // Handlers are like else blocks: the preceding try block (or handler) is ended here,
+                // if it completes normally, and a new block is started, which is only entered if
+                // an exception was caught.
+                Catch(_) | CatchAll => {
+                    let ended_block = block_stack.catch(iidx);
+                    let begin_try = match ended_block {
+                        BlockStackElement::Try { begin, .. } | BlockStackElement::Catch { begin_try: begin, .. } => begin,
+                        _ => unreachable!(),
+                    };
+
+                    if enabled_hooks.contains(Hook::End) {
+                        instrumented_body.extend_from_slice(&[
+                            location.0.clone(),
+                            location.1.clone(),
+                        ]);
+                        if let BlockStackElement::Catch { begin_catch, .. } = ended_block {
+                            instrumented_body.push(begin_catch.to_const());
+                        }
+                        instrumented_body.extend_from_slice(&[
+                            begin_try.to_const(),
+                            hooks.end(&ended_block),
+                        ]);
+                    }
+
+                    instrumented_body.push(instr.clone());
+
+                    // values of the caught exception (none for catch_all)
+                    let value_tys = match instr {
+                        Catch(tag_idx) => tags[tag_idx.to_usize()].type_.inputs(),
+                        _ => &[],
+                    };
+                    if enabled_hooks.contains(Hook::Catch) {
+                        let value_tmps = function.add_fresh_locals(value_tys);
+
+                        save_stack_to_locals(&mut instrumented_body, &value_tmps);
+                        instrumented_body.extend_from_slice(&[
+                            location.0.clone(),
+                            location.1.clone(),
+                        ]);
+                        if let Catch(tag_idx) = instr {
+                            instrumented_body.push(tag_idx.to_const());
+                        }
+                        restore_locals_with_i64_handling(&mut instrumented_body, value_tmps, function);
+                        instrumented_body.push(hooks.instr(&instr, value_tys));
+                    }
+
+                    if enabled_hooks.contains(Hook::Begin) {
+                        instrumented_body.extend_from_slice(&[
+                            location.0,
+                            location.1,
+                            begin_try.to_const(),
+                            hooks.begin_catch(),
+                        ])
+                    }
+                }
+                // Ends the try block like an end, only exceptions are forwarded to an outer handler.
+                Delegate(_) => {
+                    let block = block_stack.end();
+                    assert_eq!(iidx, block.end());
+
+                    if enabled_hooks.contains(Hook::End) {
+                        block.append_end_hook_args(&mut instrumented_body, fidx);
+                        instrumented_body.push(hooks.end(&block))
+                    }
+
+                    instrumented_body.push(instr);
+                }
+                // NOTE hooks must come before the instruction, and unlike for branches, no end hooks
+                // are called for the blocks that are left, since the handler is only known at runtime.
+                Throw(tag_idx) => {
+                    let tag_ty = tags[tag_idx.to_usize()].type_;
+
+                    if enabled_hooks.contains(Hook::Throw) {
+                        let value_tmps = function.add_fresh_locals(tag_ty.inputs());
+
+                        save_stack_to_locals(&mut instrumented_body, &value_tmps);
+                        instrumented_body.extend_from_slice(&[
+                            location.0,
+                            location.1,
+                            tag_idx.to_const(),
+                        ]);
+                        restore_locals_with_i64_handling(&mut instrumented_body, value_tmps, function);
+                        instrumented_body.push(hooks.instr(&instr, tag_ty.inputs()));
+                    }
+
+                    instrumented_body.push(instr);
+
+                    unreachable_depth = 1;
+                }
+                Rethrow(label) => {
+                    if enabled_hooks.contains(Hook::Throw) {
+                        let begin_catch = match *block_stack.get(label) {
+                            BlockStackElement::Catch { begin_catch, .. } => begin_catch,
+                            ref block => panic!("rethrow label must refer to a catch or catch_all block, but got {block:?}"),
+                        };
+                        instrumented_body.extend_from_slice(&[
+                            location.0,
+                            location.1,
+                            begin_catch.to_const(),
+                            hooks.instr(&instr, &[]),
+                        ])
+                    }
+
+                    instrumented_body.push(instr);
+
+                    unreachable_depth = 1;
+                }
+
+
                 /* Control Instructions: Branches/Breaks */
                 // NOTE hooks must come before instr
 
@@ -374,8 +519,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                     unreachable_depth = 1;
                 }
                 BrIf(target_label) => {
-                    type_stack.instr(&FunctionType::new(&[I32], &[]));
-
                     let br_target = block_stack.br_target(target_label);
 
                     if enabled_hooks.contains(Hook::BrIf)
@@ -418,16 +561,17 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                     instrumented_body.push(instr)
                 }
                 BrTable { ref table, default } => {
-                    type_stack.instr(&FunctionType::new(&[I32], &[]));
+                    // each br_table instruction gets its own entry in the static info object
+                    // that maps table index to label and location
+                    // NOTE the index is relative to this function for now, since other functions
+                    // are instrumented in parallel, see below the loop over all functions
+                    let br_table_info_idx = function_br_tables.len();
+                    function_br_tables.push(BrTableInfo::from_br_table(table, default, &block_stack, fidx, iidx));
 
                     if enabled_hooks.contains(Hook::BrTable)
                         // because end hooks are called at runtime, we need to instrument even if br_table is not enabled
                         || enabled_hooks.contains(Hook::End) {
 
-                        // each br_table instruction gets its own entry in the static info object
-                        // that maps table index to label and location
-                        module_info.write().br_tables.push(BrTableInfo::from_br_table(table, default, &block_stack, fidx));
-
                         // NOTE calling the end() hooks for the intermediate blocks is done at runtime
                         // by the br_table low-level hook
 
@@ -438,7 +582,10 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                             location.0,
                             location.1,
                             Local(Get, target_idx_tmp),
-                            Const(Val::I32((module_info.read().br_tables.len() - 1) as i32)),
+                        ]);
+                        br_table_info_idx_consts.push(instrumented_body.len());
+                        instrumented_body.extend_from_slice(&[
+                            Const(Val::I32(br_table_info_idx as i32)),
                             hooks.instr(&instr, &[])
                         ])
                     }
@@ -452,8 +599,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                 /* Control Instructions: Calls & Returns */
 
                 Return => {
-                    type_stack.instr(&FunctionType::new(&[], function.type_.results()));
-
                     // return hook
                     if enabled_hooks.contains(Hook::Return) {
                         let result_tys = function.type_.results();
@@ -482,7 +627,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                 }
                 Call(target_func_idx) => {
                     let func_ty = &module_info.read().functions[target_func_idx.to_usize()].type_;
-                    type_stack.instr(func_ty);
 
                     if enabled_hooks.contains(Hook::Call) {
                         /* pre call hook */
@@ -517,8 +661,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                     }
                 }
                 CallIndirect(ref func_ty, _ /* table idx == 0 in WASM version 1 */) => {
-                    type_stack.instr(&instr.simple_type().unwrap());
-
                     if enabled_hooks.contains(Hook::Call) {
                         /* pre call hook */
 
@@ -554,12 +696,88 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                         instrumented_body.push(instr.clone());
                     }
                 }
+                // Tail calls leave the current function before the callee is executed and the callee
+                // returns directly to our caller. So after the call hook, we call a "synthetic" return
+                // hook and the end hooks like for a return. The return hook has no results (they are
+                // not known yet, they are reported by the return hook of the callee) and there is no
+                // call_post hook.
+                ReturnCall(target_func_idx) => {
+                    let func_ty = module_info.read().functions[target_func_idx.to_usize()].type_;
+
+                    if enabled_hooks.contains(Hook::Call) {
+                        let arg_tmps = function.add_fresh_locals(func_ty.inputs());
+
+                        save_stack_to_locals(&mut instrumented_body, &arg_tmps);
+                        instrumented_body.extend_from_slice(&[
+                            location.0.clone(),
+                            location.1.clone(),
+                            target_func_idx.to_const(),
+                        ]);
+                        restore_locals_with_i64_handling(&mut instrumented_body, arg_tmps, function);
+                        instrumented_body.push(hooks.instr(&instr, func_ty.inputs()));
+                    }
+
+                    if enabled_hooks.contains(Hook::Return) {
+                        instrumented_body.extend_from_slice(&[
+                            location.0,
+                            location.1,
+                            hooks.instr(&Return, &[]),
+                        ]);
+                    }
+
+                    if enabled_hooks.contains(Hook::End) {
+                        for block in block_stack.return_target().ended_blocks {
+                            block.append_end_hook_args(&mut instrumented_body, fidx);
+                            instrumented_body.push(hooks.end(&block));
+                        }
+                    }
+
+                    instrumented_body.push(instr);
+
+                    unreachable_depth = 1;
+                }
+                ReturnCallIndirect(ref func_ty, _) => {
+                    if enabled_hooks.contains(Hook::Call) {
+                        let target_table_idx_tmp = function.add_fresh_local(I32);
+                        let arg_tmps = function.add_fresh_locals(func_ty.inputs());
+
+                        instrumented_body.push(Local(Set, target_table_idx_tmp));
+                        save_stack_to_locals(&mut instrumented_body, &arg_tmps);
+                        instrumented_body.extend_from_slice(&[
+                            Local(Get, target_table_idx_tmp),
+                            location.0.clone(),
+                            location.1.clone(),
+                            Local(Get, target_table_idx_tmp),
+                        ]);
+                        restore_locals_with_i64_handling(&mut instrumented_body, arg_tmps, function);
+                        instrumented_body.push(hooks.instr(&instr, func_ty.inputs()));
+                    }
+
+                    if enabled_hooks.contains(Hook::Return) {
+                        instrumented_body.extend_from_slice(&[
+                            location.0,
+                            location.1,
+                            hooks.instr(&Return, &[]),
+                        ]);
+                    }
+
+                    if enabled_hooks.contains(Hook::End) {
+                        for block in block_stack.return_target().ended_blocks {
+                            block.append_end_hook_args(&mut instrumented_body, fidx);
+                            instrumented_body.push(hooks.end(&block));
+                        }
+                    }
+
+                    instrumented_body.push(instr.clone());
+
+                    unreachable_depth = 1;
+                }
 
 
                 /* Parametric Instructions */
 
                 Drop => {
-                    let ty = type_stack.pop_val();
+                    let ty = drop_select_types[&iidx];
 
                     if enabled_hooks.contains(Hook::Drop) {
                         let tmp = function.add_fresh_local(ty);
@@ -576,11 +794,8 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                         instrumented_body.push(instr);
                     }
                 }
-                Select => {
-                    assert_eq!(type_stack.pop_val(), I32, "select condition should be i32");
-                    let ty = type_stack.pop_val();
-                    assert_eq!(type_stack.pop_val(), ty, "select arguments should have same type");
-                    type_stack.push_val(ty);
+                Select | TypedSelect(_) => {
+                    let ty = drop_select_types[&iidx];
 
                     if enabled_hooks.contains(Hook::Drop) {
                         let condition_tmp = function.add_fresh_local(I32);
@@ -604,11 +819,9 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
 
                 /* Variable Instructions */
 
-                Local(op, local_idx) => {
+                Local(_, local_idx) => {
                     let local_ty = function.param_or_local_type(local_idx);
 
-                    type_stack.instr(&op.to_type(local_ty));
-
                     instrumented_body.push(instr.clone());
 
                     // insert hook AFTER instruction, so that we can use local.get instead of duplicating the value through a new local
@@ -622,11 +835,9 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                         instrumented_body.push(hooks.instr(&instr, &[local_ty]));
                     }
                 }
-                Global(op, global_idx) => {
+                Global(_, global_idx) => {
                     let global_ty = module_info.read().globals[global_idx.to_usize()];
 
-                    type_stack.instr(&op.to_type(global_ty));
-
                     instrumented_body.push(instr.clone());
 
                     // insert hook AFTER instruction, so that we can use global.get instead of duplicating the value through a new local
@@ -644,38 +855,160 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
 
                 /* Memory Instructions */
 
-                MemorySize(_ /* memory idx == 0 in WASM version 1 */) => {
-                    type_stack.instr(&instr.simple_type().unwrap());
-
+                MemorySize(memory_idx) => {
                     instrumented_body.push(instr.clone());
 
                     if enabled_hooks.contains(Hook::MemorySize) {
+                        let index_ty = memories[memory_idx.to_usize()].index_type();
                         instrumented_body.extend_from_slice(&[
                             location.0,
                             location.1,
-                            // optimization: just call memory_size again instead of duplicating result into local
+                        ]);
+                        // optimization: just call memory_size again instead of duplicating result into local
+                        convert_i64_instr(&mut instrumented_body, instr.clone(), index_ty);
+                        instrumented_body.push(hooks.instr(&instr, &memory_index_tys([index_ty])));
+                    }
+                }
+                MemoryGrow(memory_idx) => {
+                    if enabled_hooks.contains(Hook::MemoryGrow) {
+                        let index_ty = memories[memory_idx.to_usize()].index_type();
+                        let input_tmp = function.add_fresh_local(index_ty);
+                        let result_tmp = function.add_fresh_local(index_ty);
+
+                        instrumented_body.extend_from_slice(&[
+                            Local(Tee, input_tmp),
                             instr.clone(),
-                            hooks.instr(&instr, &[])
+                            Local(Tee, result_tmp),
+                            location.0,
+                            location.1,
                         ]);
+                        restore_locals_with_i64_handling(&mut instrumented_body, [input_tmp, result_tmp], function);
+                        instrumented_body.push(hooks.instr(&instr, &memory_index_tys([index_ty])));
+                    } else {
+                        instrumented_body.push(instr);
                     }
                 }
-                MemoryGrow(_ /* memory idx == 0 in WASM version 1 */) => {
-                    type_stack.instr(&instr.simple_type().unwrap());
 
-                    if enabled_hooks.contains(Hook::MemoryGrow) {
-                        let input_tmp = function.add_fresh_local(I32);
+                MemoryCopy { .. } | MemoryFill(_) => {
+                    let ty = instr.simple_type_with_memories(memories).unwrap();
+
+                    if (enabled_hooks.contains(Hook::MemoryCopy) && matches!(instr, MemoryCopy { .. }))
+                        || (enabled_hooks.contains(Hook::MemoryFill) && matches!(instr, MemoryFill(_))) {
+                        let input_tmps = function.add_fresh_locals(ty.inputs());
+
+                        save_stack_to_locals(&mut instrumented_body, &input_tmps);
+                        instrumented_body.extend_from_slice(&[
+                            instr.clone(),
+                            location.0,
+                            location.1,
+                        ]);
+                        restore_locals_with_i64_handling(&mut instrumented_body, input_tmps, function);
+                        let index_tys = match instr {
+                            MemoryCopy { dst, src } => memory_index_tys([memories[dst.to_usize()].index_type(), memories[src.to_usize()].index_type()]),
+                            MemoryFill(memory_idx) => memory_index_tys([memories[memory_idx.to_usize()].index_type()]),
+                            _ => unreachable!(),
+                        };
+                        instrumented_body.push(hooks.instr(&instr, &index_tys));
+                    } else {
+                        instrumented_body.push(instr);
+                    }
+                }
+                // The remaining bulk memory instructions only initialize memory from data segments
+                // or drop segments, so there are no hooks for them (yet).
+                MemoryInit(_, _) | DataDrop(_) | ElemDrop(_) => {
+                    instrumented_body.push(instr);
+                }
+
+
+                /* Reference and Table Instructions */
+
+                RefNull(_) | RefFunc(_) | TableSize(_) | RefIsNull | TableGet(_) => {
+                    instrumented_body.push(instr);
+                }
+                // NOTE table hooks are inserted after the instruction, i.e., only called if it did not trap
+                TableSet(table_idx) => {
+                    let elem_ty = module_info.read().tables[table_idx.to_usize()];
+
+                    if enabled_hooks.contains(Hook::Table) {
+                        let index_tmp = function.add_fresh_local(I32);
+                        let value_tmp = function.add_fresh_local(elem_ty);
+
+                        save_stack_to_locals(&mut instrumented_body, &[index_tmp, value_tmp]);
+                        instrumented_body.extend_from_slice(&[
+                            instr.clone(),
+                            location.0,
+                            location.1,
+                            table_idx.to_const(),
+                            Local(Get, index_tmp),
+                            Local(Get, value_tmp),
+                            hooks.instr(&instr, &[elem_ty]),
+                        ]);
+                    } else {
+                        instrumented_body.push(instr);
+                    }
+                }
+                TableGrow(table_idx) => {
+                    let elem_ty = module_info.read().tables[table_idx.to_usize()];
+
+                    if enabled_hooks.contains(Hook::Table) {
+                        let init_value_tmp = function.add_fresh_local(elem_ty);
+                        let delta_tmp = function.add_fresh_local(I32);
                         let result_tmp = function.add_fresh_local(I32);
 
+                        save_stack_to_locals(&mut instrumented_body, &[init_value_tmp, delta_tmp]);
                         instrumented_body.extend_from_slice(&[
-                            Local(Tee, input_tmp),
                             instr.clone(),
                             Local(Tee, result_tmp),
                             location.0,
                             location.1,
-                            Local(Get, input_tmp),
+                            table_idx.to_const(),
+                            Local(Get, init_value_tmp),
+                            Local(Get, delta_tmp),
                             Local(Get, result_tmp),
-                            hooks.instr(&instr, &[])
+                            hooks.instr(&instr, &[elem_ty]),
+                        ]);
+                    } else {
+                        instrumented_body.push(instr);
+                    }
+                }
+                TableFill(table_idx) => {
+                    let elem_ty = module_info.read().tables[table_idx.to_usize()];
+
+                    if enabled_hooks.contains(Hook::Table) {
+                        let input_tmps = function.add_fresh_locals(&[I32, elem_ty, I32]);
+
+                        save_stack_to_locals(&mut instrumented_body, &input_tmps);
+                        instrumented_body.extend_from_slice(&[
+                            instr.clone(),
+                            location.0,
+                            location.1,
+                            table_idx.to_const(),
                         ]);
+                        restore_locals_with_i64_handling(&mut instrumented_body, input_tmps, function);
+                        instrumented_body.push(hooks.instr(&instr, &[elem_ty]));
+                    } else {
+                        instrumented_body.push(instr);
+                    }
+                }
+                TableCopy { .. } | TableInit(_, _) => {
+                    let ty = instr.simple_type().unwrap();
+
+                    if enabled_hooks.contains(Hook::Table) {
+                        let input_tmps = function.add_fresh_locals(ty.inputs());
+
+                        save_stack_to_locals(&mut instrumented_body, &input_tmps);
+                        instrumented_body.extend_from_slice(&[
+                            instr.clone(),
+                            location.0,
+                            location.1,
+                        ]);
+                        match instr {
+                            TableCopy { dst, src } => instrumented_body.extend_from_slice(&[dst.to_const(), src.to_const()]),
+                            TableInit(element_idx, table_idx) => instrumented_body.extend_from_slice(&[table_idx.to_const(), element_idx.to_const()]),
+                            _ => unreachable!(),
+                        }
+                        restore_locals_with_i64_handling(&mut instrumented_body, input_tmps, function);
+                        instrumented_body.push(hooks.instr(&instr, &[]));
                     } else {
                         instrumented_body.push(instr);
                     }
@@ -683,47 +1016,56 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
 
                 /* rest are "grouped instructions", i.e., where many instructions can be handled in a similar manner */
 
-                Load(op, memarg) => {
-                    let ty = op.to_type();
-                    type_stack.instr(&ty);
+                // atomic loads and stores are reported to the atomic variants of the load and store hooks
+                Load(_, memarg) | AtomicLoad(_, memarg) => {
+                    let ty = instr.simple_type_with_memories(memories).unwrap();
 
                     if enabled_hooks.contains(Hook::Load) {
                         let addr_tmp = function.add_fresh_local(ty.inputs()[0]);
                         let value_tmp = function.add_fresh_local(ty.results()[0]);
 
+                        let index_ty = ty.inputs()[0];
+
                         instrumented_body.extend_from_slice(&[
                             Local(Tee, addr_tmp),
                             instr.clone(),
                             Local(Tee, value_tmp),
                             location.0,
                             location.1,
-                            Const(Val::I32(memarg.offset as i32)),
+                        ]);
+                        convert_i64_instr(&mut instrumented_body, memarg_offset_const(memarg.offset, index_ty), index_ty);
+                        instrumented_body.extend_from_slice(&[
                             Const(Val::I32(memarg.alignment_exp as i32)),
+                            memarg.memory.to_const(),
                         ]);
                         restore_locals_with_i64_handling(&mut instrumented_body, [addr_tmp, value_tmp], function);
-                        instrumented_body.push(hooks.instr(&instr, &[]));
+                        instrumented_body.push(hooks.instr(&instr, &memory_index_tys([index_ty])));
                     } else {
                         instrumented_body.push(instr);
                     }
                 }
-                Store(op, memarg) => {
-                    let ty = op.to_type();
-                    type_stack.instr(&ty);
+                Store(_, memarg) | AtomicStore(_, memarg) => {
+                    let ty = instr.simple_type_with_memories(memories).unwrap();
 
                     if enabled_hooks.contains(Hook::Store) {
                         let addr_tmp = function.add_fresh_local(ty.inputs()[0]);
                         let value_tmp = function.add_fresh_local(ty.inputs()[1]);
 
+                        let index_ty = ty.inputs()[0];
+
                         save_stack_to_locals(&mut instrumented_body, &[addr_tmp, value_tmp]);
                         instrumented_body.extend_from_slice(&[
                             instr.clone(),
                             location.0,
                             location.1,
-                            Const(Val::I32(memarg.offset as i32)),
+                        ]);
+                        convert_i64_instr(&mut instrumented_body, memarg_offset_const(memarg.offset, index_ty), index_ty);
+                        instrumented_body.extend_from_slice(&[
                             Const(Val::I32(memarg.alignment_exp as i32)),
+                            memarg.memory.to_const(),
                         ]);
                         restore_locals_with_i64_handling(&mut instrumented_body, [addr_tmp, value_tmp], function);
-                        instrumented_body.push(hooks.instr(&instr, &[]));
+                        instrumented_body.push(hooks.instr(&instr, &memory_index_tys([index_ty])));
                     } else {
                         instrumented_body.push(instr);
                     }
@@ -733,8 +1075,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                 /* Numeric Instructions */
 
                 Const(val) => {
-                    type_stack.instr(&instr.simple_type().unwrap());
-
                     instrumented_body.push(instr.clone());
 
                     if enabled_hooks.contains(Hook::Const) {
@@ -749,7 +1089,6 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                 }
                 Unary(_) | Binary(_) => {
                     let ty = instr.simple_type().unwrap();
-                    type_stack.instr(&ty);
 
                     if (enabled_hooks.contains(Hook::Unary) && ty.inputs().len() == 1)
                         || (enabled_hooks.contains(Hook::Binary) && ty.inputs().len() == 2) {
@@ -769,13 +1108,75 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
                         instrumented_body.push(instr);
                     }
                 }
+                // SIMD instructions that do not fit any of the hooks above (vector loads, stores,
+                // and lane-wise arithmetic are handled as Load, Store, Unary, and Binary).
+                LoadLane(_, _, _) | StoreLane(_, _, _) | ExtractLane(_, _) | ReplaceLane(_, _)
+                | I8x16Shuffle(_) | V128Bitselect => {
+                    instrumented_body.push(instr);
+                }
+                // read-modify-write operations write to memory, so they are hooked when stores are
+                Atomic(op, memarg) if !op.is_wait_or_notify() && enabled_hooks.contains(Hook::Store) => {
+                    let ty = instr.simple_type_with_memories(memories).unwrap();
+                    let input_tmps: Vec<_> = ty.inputs().iter().map(|&ty| function.add_fresh_local(ty)).collect();
+                    let old_value_tmp = function.add_fresh_local(ty.results()[0]);
+
+                    let index_ty = ty.inputs()[0];
+
+                    save_stack_to_locals(&mut instrumented_body, &input_tmps);
+                    instrumented_body.extend_from_slice(&[
+                        instr.clone(),
+                        Local(Tee, old_value_tmp),
+                        location.0,
+                        location.1,
+                    ]);
+                    convert_i64_instr(&mut instrumented_body, memarg_offset_const(memarg.offset, index_ty), index_ty);
+                    instrumented_body.extend_from_slice(&[
+                        Const(Val::I32(memarg.alignment_exp as i32)),
+                        memarg.memory.to_const(),
+                    ]);
+                    restore_locals_with_i64_handling(&mut instrumented_body, input_tmps.into_iter().chain([old_value_tmp]), function);
+                    instrumented_body.push(hooks.instr(&instr, &memory_index_tys([index_ty])));
+                }
+                // Wait, notify, and fence instructions of the threads extension are not hooked (yet).
+                Atomic(_, _) | AtomicFence => {
+                    instrumented_body.push(instr);
+                }
             }
         }
 
         // finally, switch dummy body out against instrumented body
         function.code_mut().unwrap().body = instrumented_body;
+        br_table_infos.write().push((fidx, function_br_tables, br_table_info_idx_consts));
     });
 
+    // collect br_table infos in order of location, not in the (non-deterministic) order in which
+    // functions were instrumented, and make the indices passed to the br_table hook absolute
+    let mut br_table_infos = br_table_infos.into_inner();
+    br_table_infos.sort_unstable_by_key(|(fidx, _, _)| *fidx);
+    for (fidx, function_br_tables, br_table_info_idx_consts) in br_table_infos {
+        let start_idx = module_info.read().br_tables.len() as i32;
+        let body = module.functions[fidx.to_usize()].instrs_mut().expect("only instrumented functions have br_tables");
+        for const_idx in br_table_info_idx_consts {
+            match &mut body[const_idx] {
+                Const(Val::I32(br_table_info_idx)) => *br_table_info_idx += start_idx,
+                instr => unreachable!("expected br_table info index, but got {instr}"),
+            }
+        }
+        module_info.write().br_tables.extend(function_br_tables);
+    }
+
+    // actually add the hooks to module and check that inserted Idx is the one on the Hook struct
+    // NOTE must happen before the passes below, since those add their own (non-imported) functions
+    // and would otherwise shift the function indices the hook calls were generated with
+    let hooks = hooks.finish();
+    let hook_count = hooks.len();
+    let mut js_hooks = Vec::with_capacity(hook_count);
+    for hook in hooks {
+        js_hooks.push(hook.js);
+        assert_eq!(hook.idx, module.functions.len().into(), "have other functions been inserted into the module since starting collection of hooks?");
+        module.functions.push(hook.wasm);
+    }
+
     if enabled_hooks.contains(Hook::PointerHardening) {
         harden_module(module);
     }
@@ -786,30 +1187,18 @@ pub fn add_hooks(module: &mut Module, enabled_hooks: HookSet) -> Option<usize> {
     }
 
     // Test the write protection here.
-    if enabled_hooks.contains(Hook::WriteProtection) {
+    // Not every module has a memory to protect, e.g., the small adapter modules in components.
+    if enabled_hooks.contains(Hook::WriteProtection) && !module.memories.is_empty() {
         let start_add = 0x404;
         let end_add = 0xFFFF;
-        write_protect_range(module, start_add, end_add);
+        write_protect_range(module, Idx::from(0u32), start_add, end_add);
     }
 
-    // actually add the hooks to module and check that inserted Idx is the one on the Hook struct
-    let hooks = hooks.finish();
-    let hook_count = hooks.len();
-    //    let mut hook_list: Vec<(String, FunctionType)> = hooks.iter().map(|hook| (hook.wasm.import.as_ref().map(|opt| opt.1.clone()).unwrap(), hook.wasm.type_.clone())).collect();
-    //    hook_list.sort_by_key(|h| h.0.clone());
-    //    for hook in hook_list {
-    //        println!("{}: {:?}", hook.0, hook.1);
-    //    }
-    //    println!("{:?}", hook_list.iter().max_by_key(|hook| hook.1.params.len()));
-
-    // let mut js_hooks = Vec::new();
-    // for hook in hooks {
-    //     js_hooks.push(hook.js);
-    //     assert_eq!(hook.idx, module.functions.len().into(), "have other functions been inserted into the module since starting collection of hooks?");
-    //     module.functions.push(hook.wasm);
-    // }
+    let mut module_info = module_info.into_inner();
+    module_info.set_instrumented_function_idx(module);
+    let js = generate_js(&module_info, &js_hooks, node_js);
 
-    Some(hook_count)
+    Some((js, module_info, hook_count))
 }
 
 /// convenience to hand (function/instr/local/global) indices to hooks
@@ -853,6 +1242,19 @@ impl BlockStackElement {
                 begin_else.to_const(),
                 begin_if.to_const(),
             ]),
+            BlockStackElement::Try { begin, end } => {
+                append_to.extend_from_slice(&[fidx.to_const(), end.to_const(), begin.to_const()])
+            }
+            BlockStackElement::Catch {
+                begin_catch,
+                begin_try,
+                end,
+            } => append_to.extend_from_slice(&[
+                fidx.to_const(),
+                end.to_const(),
+                begin_catch.to_const(),
+                begin_try.to_const(),
+            ]),
         }
     }
     fn end(&self) -> Idx<Instr> {
@@ -862,60 +1264,74 @@ impl BlockStackElement {
             | Block { end, .. }
             | Loop { end, .. }
             | If { end, .. }
-            | Else { end, .. } => *end,
+            | Else { end, .. }
+            | Try { end, .. }
+            | Catch { end, .. } => *end,
         }
     }
 }
 
-// fn generate_js(module_info: ModuleInfo, hooks: &[String], node_js: bool) -> String {
-//     let mut result = r#"/*
-// * Generated by Wasabi. DO NOT EDIT.
-// * Contains:
-// *   - independent of program-to-instrument: long.js dependency, Wasabi loader and runtime
-// *   - generated from program-to-instrument: static information and low-level hooks
-// */
-// "#
-//     .to_string();
-
-//     if node_js {
-//         // For Node.js, write the long.js dependency to a separate file (in main) and
-//         // only `require()` it here.
-//         result.push_str("const Long = require('./long.js');");
-//     } else {
-//         // Browser case (default):
-//         // FIXME super hacky: just cat together long.js dependency, program-independent, and
-//         // program-dependent JavaScript into one big file.
-//         // * Alternative A: use webpack or other bundler, drawbacks:
-//         //    - users need to install another tool
-//         //    - needs to be run after every instrumentation
-//         // * Alternative B: compile Wasabi itself to WebAssembly, instrument at runtime
-//         result.push_str("// long.js\n");
-//         result.push_str(
-//             include_str!("../../../js/long.js/long.js")
-//                 .lines()
-//                 .next()
-//                 .expect("could not include long.js dependency"),
-//         );
-//     }
-//     result.push_str("\n\n");
-
-//     result.push_str(include_str!("../../../js/runtime.js"));
-//     result.push('\n');
-
-//     result.push_str("Wasabi.module.info = ");
-//     result.push_str(&serde_json::to_string(&module_info).unwrap());
-//     result.push_str(";\n\n");
-
-//     result.push_str("Wasabi.module.lowlevelHooks = {\n");
-//     for hook in hooks {
-//         result.push_str(hook);
-//         result.push('\n');
-//     }
-//     result.push_str("};\n");
-
-//     if node_js {
-//         result.push_str("\nmodule.exports = Wasabi;\n");
-//     }
-
-//     result
-// }
+/// polymorphic types of memory hooks: the index types of the accessed memories, but only if one of
+/// them is 64-bit, such that the hooks for 32-bit memories are not renamed
+fn memory_index_tys<const N: usize>(index_tys: [ValType; N]) -> Vec<ValType> {
+    if index_tys.contains(&I64) {
+        index_tys.to_vec()
+    } else {
+        Vec::new()
+    }
+}
+
+/// the static offset of a load or store, as a constant of the index type of its memory
+fn memarg_offset_const(offset: u64, index_ty: ValType) -> Instr {
+    match index_ty {
+        I64 => Const(Val::I64(offset as i64)),
+        _ => Const(Val::I32(offset as u32 as i32)),
+    }
+}
+
+fn generate_js(module_info: &ModuleInfo, hooks: &[String], node_js: bool) -> String {
+    let mut result = r#"/*
+ * Generated by Wasabi. DO NOT EDIT.
+ * Contains:
+ *   - independent of program-to-instrument: Wasabi loader and runtime
+ *   - generated from program-to-instrument: static information and low-level hooks
+ */
+"#
+    .to_string();
+    result.push('\n');
+
+    result.push_str(include_str!("../../js/runtime.js"));
+    result.push('\n');
+
+    result.push_str("Wasabi.module.info = ");
+    result.push_str(&serde_json::to_string(module_info).unwrap());
+    result.push_str(";\n\n");
+
+    result.push_str("Wasabi.module.lowlevelHooks = {\n");
+    for hook in hooks {
+        result.push_str(hook);
+        result.push('\n');
+    }
+    result.push_str("};\n");
+
+    if node_js {
+        result.push_str("\nmodule.exports = Wasabi;\n");
+    } else {
+        result.push_str("\nexport default Wasabi;\n");
+    }
+
+    result
+}
+
+/// input types of the value-polymorphic drop and select instructions in reachable code, which
+/// (unlike, e.g., i32.add) cannot be determined from the instruction alone
+fn drop_select_types(function: &Function, module: &Module) -> HashMap<Idx<Instr>, ValType> {
+    function.instrs_with_types(module)
+        .map(|typed_instr| typed_instr.unwrap_or_else(|err| panic!("cannot instrument function that does not type check: {err}")))
+        .filter_map(|(iidx, instr, instr_ty, _)| match (instr, instr_ty) {
+            // select's inputs are [t, t, i32]
+            (Drop | Select | TypedSelect(_), InferredInstructionType::Reachable(ty)) => Some((iidx, ty.inputs()[0])),
+            _ => None,
+        })
+        .collect()
+}
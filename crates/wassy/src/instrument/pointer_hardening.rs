@@ -4,9 +4,9 @@ use std::time::*;
 
 use wasabi_wasm::BinaryOp::I32Xor;
 use wasabi_wasm::Data;
+use wasabi_wasm::DataMode;
 use wasabi_wasm::ImportOrPresent::Present;
 use wasabi_wasm::LoadOp::I32Load;
-use wasabi_wasm::Memory;
 use wasabi_wasm::Module;
 use wasabi_wasm::Val;
 use wasabi_wasm::Val::I32;
@@ -15,7 +15,7 @@ use wasabi_wasm::{Code, Instr::*};
 pub fn harden_module(module: &mut Module) {
     let canary = generate_le_canary();
     let func_ptr_addresses = find_and_crypt_func_ptrs(module, canary);
-    encrypt_func_ptrs_in_memory(&mut module.memories, &func_ptr_addresses, canary);
+    encrypt_func_ptrs_in_memory(&mut module.data, &func_ptr_addresses, canary);
 }
 
 fn generate_le_canary() -> u32 {
@@ -39,11 +39,13 @@ fn insert_xor_instrs(idx: usize, code: &mut Code, canary: u32) {
 
 // Just testing something for now - RPW.
 fn find_stack_ptr(module: &Module) -> u32 {
-    // Is stack pointer always first global?
+    // LLVM names the stack pointer global in the name section, if present.
+    // Otherwise, assume it is the first global.
     // How often does this end up being 65536?
     let test = module
         .globals()
-        .nth(0)
+        .find(|(_, global)| global.name.as_deref() == Some("__stack_pointer"))
+        .or_else(|| module.globals().next())
         .expect("[Pointer Hardening] Could not find stack pointer")
         .1;
 
@@ -120,7 +122,11 @@ fn find_and_crypt_func_ptrs(module: &mut Module, canary: u32) -> Vec<u32> {
                 loop {
                     match func_instrs_rev_iter.peek() {
                         Some(Load(I32Load, mem_arg)) => {
-                            func_ptr_addr = mem_arg.offset;
+                            // Offsets only exceed u32 in 64-bit memories, which are not hardened.
+                            func_ptr_addr = match u32::try_from(mem_arg.offset) {
+                                Ok(offset) => offset,
+                                Err(_) => break 'l_next_func,
+                            };
                             break;
                         }
                         None => {
@@ -145,7 +151,7 @@ fn find_and_crypt_func_ptrs(module: &mut Module, canary: u32) -> Vec<u32> {
                             let const_val = *i32 as u32;
                             let mut func_ptr_addr = const_val + func_ptr_addr;
                             // println!("[Pointer Hardening] Found function pointer address: {func_ptr_addr}");
-                            if !is_func_ptr_addr_in_memory(&module.memories, func_ptr_addr) {
+                            if !is_func_ptr_addr_in_memory(&module.data, func_ptr_addr) {
                                 println!("[Pointer Hardening] Could not find function pointer address ({func_ptr_addr}) in memory; checking other methods...");
                                 // Try using base ptr + offset instead?
                                 func_ptr_addr = func_ptr_via_stack(stack_ptr_idx, func_ptr_addr);
@@ -185,10 +191,10 @@ fn round_up_data_section_4_bytes(data_section: &mut Data) -> usize {
     bytes_appended
 }
 
-fn is_func_ptr_addr_in_memory(memories: &[Memory], func_ptr_addr: u32) -> bool {
-    for memory in memories.iter() {
-        for data_section in memory.data.iter() {
-            if let Const(Val::I32(i32)) = data_section.offset[0] {
+fn is_func_ptr_addr_in_memory(data: &[Data], func_ptr_addr: u32) -> bool {
+    for data_section in data.iter() {
+        if let DataMode::Active { offset, .. } = &data_section.mode {
+            if let Const(Val::I32(i32)) = offset[0] {
                 if data_section.bytes.is_empty() {
                     continue;
                 }
@@ -211,16 +217,16 @@ fn is_func_ptr_addr_in_memory(memories: &[Memory], func_ptr_addr: u32) -> bool {
 }
 
 fn encrypt_func_ptrs_in_memory(
-    memories: &mut [Memory],
+    data: &mut [Data],
     func_ptr_addresses: &Vec<u32>,
     canary: u32,
 ) {
     for func_ptr_addr in func_ptr_addresses {
         let mut found_func_ptr = false;
 
-        'l_encrypted_func_ptr: for memory in memories.iter_mut() {
-            for data_section in memory.data.iter_mut() {
-                if let Const(Val::I32(i32)) = data_section.offset[0] {
+        'l_encrypted_func_ptr: for data_section in data.iter_mut() {
+            if let DataMode::Active { offset, .. } = &data_section.mode {
+                if let Const(Val::I32(i32)) = offset[0] {
                     if data_section.bytes.is_empty() {
                         continue;
                     }
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::Serialize;
 use serde::Serializer;
 use wasabi_wasm::Function;
@@ -5,6 +7,7 @@ use wasabi_wasm::FunctionType;
 use wasabi_wasm::Idx;
 use wasabi_wasm::Instr;
 use wasabi_wasm::Label;
+use wasabi_wasm::Local;
 use wasabi_wasm::Module;
 use wasabi_wasm::ValType;
 
@@ -22,12 +25,19 @@ pub struct ModuleInfo {
     pub functions: Vec<FunctionInfo>,
     #[serde(serialize_with = "serialize_types")]
     pub globals: Vec<ValType>,
+    /// element type of each table
+    #[serde(serialize_with = "serialize_types")]
+    pub tables: Vec<ValType>,
     pub start: Option<Idx<Function>>,
     pub table_export_name: Option<String>,
     pub br_tables: Vec<BrTableInfo>,
     // For mapping indices of indirectly called functions to the original indices, see
     // `resolveTableIdx` in `runtime.js`.
     pub original_function_imports_count: usize,
+    /// Index of each original function in the instrumented binary, which differs from its index
+    /// in the original binary if it is not imported, since the low-level hooks are imported
+    /// functions and the binary format puts all imported functions first.
+    pub instrumented_function_idx: Vec<Idx<Function>>,
 }
 
 impl<'a> From<&'a Module> for ModuleInfo {
@@ -35,6 +45,7 @@ impl<'a> From<&'a Module> for ModuleInfo {
         ModuleInfo {
             functions: module.functions.iter().map(Into::into).collect(),
             globals: module.globals.iter().map(|g| g.type_.0).collect(),
+            tables: module.tables.iter().map(|table| table.elem_type).collect(),
             start: module.start,
             // if the module has no table, there cannot be a call_indirect, so this null will never be read from JS runtime
             table_export_name: module
@@ -47,10 +58,29 @@ impl<'a> From<&'a Module> for ModuleInfo {
                 .iter()
                 .filter_map(Function::import)
                 .count(),
+            // not known yet before instrumentation, see set_instrumented_function_idx()
+            instrumented_function_idx: Vec::new(),
         }
     }
 }
 
+impl ModuleInfo {
+    /// must be called after all functions have been added to the instrumented `module`
+    pub fn set_instrumented_function_idx(&mut self, module: &Module) {
+        let import_count = module.functions.iter().filter_map(Function::import).count();
+        let mut next_import_idx = 0;
+        let mut next_non_import_idx = import_count;
+        self.instrumented_function_idx = module.functions[..self.functions.len()]
+            .iter()
+            .map(|function| {
+                let counter = if function.import().is_some() { &mut next_import_idx } else { &mut next_non_import_idx };
+                *counter += 1;
+                (*counter - 1).into()
+            })
+            .collect();
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FunctionInfo {
@@ -62,8 +92,13 @@ pub struct FunctionInfo {
     #[serde(serialize_with = "serialize_types")]
     pub locals: Vec<ValType>,
     pub instr_count: usize,
-    // TODO we could add name (from debug info) here, but it would be added for all functions,
-    // in the JSON as `"name": null`, which is a lot of overhead...
+    // names from the name section (i.e., debug info) are omitted if not present, to avoid the
+    // overhead of `"name": null` for every function in the JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// names of parameters and locals, by local index
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub local_names: BTreeMap<Idx<Local>, String>,
 }
 
 impl<'a> From<&'a Function> for FunctionInfo {
@@ -81,6 +116,11 @@ impl<'a> From<&'a Function> for FunctionInfo {
                 .map(|local| local.type_)
                 .collect(),
             instr_count: function.instr_count(),
+            name: function.name.clone(),
+            local_names: function
+                .param_or_locals()
+                .filter_map(|(idx, param_or_local)| Some((idx, param_or_local.name()?.to_string())))
+                .collect(),
         }
     }
 }
@@ -114,6 +154,8 @@ where
 #[derive(Serialize)]
 /// for resolving br_table instruction targets at runtime
 pub struct BrTableInfo {
+    /// of the br_table instruction itself
+    pub location: Location,
     pub table: Vec<ResolvedLabel>,
     pub default: ResolvedLabel,
 }
@@ -125,6 +167,7 @@ impl BrTableInfo {
         default: Label,
         block_stack: &BlockStack,
         func: Idx<Function>,
+        instr: Idx<Instr>,
     ) -> Self {
         let resolve = |label: Label| {
             let target = block_stack.br_target(label);
@@ -135,6 +178,7 @@ impl BrTableInfo {
             }
         };
         BrTableInfo {
+            location: Location(func, instr),
             table: table.iter().cloned().map(resolve).collect(),
             default: resolve(default),
         }
@@ -172,6 +216,12 @@ impl Serialize for BlockStackElement {
                 end,
                 begin_if,
             } => ("else", begin_else, end, begin_if).serialize(serializer),
+            Try { begin, end } => ("try", begin, end).serialize(serializer),
+            Catch {
+                begin_catch,
+                end,
+                begin_try,
+            } => ("catch", begin_catch, end, begin_try).serialize(serializer),
         }
     }
 }
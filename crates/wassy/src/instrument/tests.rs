@@ -0,0 +1,154 @@
+use std::str::FromStr;
+
+use wasabi_wasm::AtomicOp;
+use wasabi_wasm::Instr::*;
+use wasabi_wasm::LocalOp;
+use wasabi_wasm::Module;
+use wasabi_wasm::Val;
+use wasabi_wasm::ValType;
+
+use crate::options::Hook;
+use crate::options::HookSet;
+use crate::runtime::lowlevel::LowLevelHook;
+
+use super::add_hooks;
+use super::static_info::ModuleInfo;
+use super::write_protection::write_protect_range;
+
+/// Instruments the module in the text format with only the given hooks enabled.
+fn instrument(wat: &str, enabled_hooks: HookSet) -> (Module, ModuleInfo) {
+    let (mut module, _warnings) = Module::from_wat(wat).unwrap();
+    let (_js, module_info, _hook_count) = add_hooks(&mut module, enabled_hooks, false).unwrap();
+    (module, module_info)
+}
+
+#[test]
+fn br_table_infos_are_ordered_by_location() {
+    let wat = r#"(module
+        (func $a (param i32)
+            (block (block (br_table 0 1 (local.get 0))))
+            (block (br_table 0 (local.get 0))))
+        (func $b (param i32)
+            (block (br_table 0 (local.get 0)))))"#;
+
+    // Functions are instrumented in parallel, so repeat to make a different order likely.
+    for _ in 0..10 {
+        let (module, module_info) = instrument(wat, HookSet::only(Hook::BrTable));
+        let locations: Vec<_> = module_info.br_tables.iter()
+            .map(|info| (info.location.0.to_usize(), info.location.1.to_usize()))
+            .collect();
+        assert_eq!(locations, vec![(0, 3), (0, 8), (1, 2)]);
+
+        // The index passed to the hook must point to the info for the same location.
+        let mut hook_calls = 0;
+        for function in &module.functions {
+            for window in function.instrs().windows(7) {
+                if let [Local(LocalOp::Tee, _), Const(Val::I32(func)), Const(Val::I32(instr)), Local(LocalOp::Get, _), Const(Val::I32(info_idx)), Call(_), BrTable { .. }] = window {
+                    assert_eq!(locations[*info_idx as usize], (*func as usize, *instr as usize));
+                    hook_calls += 1;
+                }
+            }
+        }
+        assert_eq!(hook_calls, 3);
+    }
+}
+
+/// Names of the low-level hooks imported by the instrumented module.
+fn hook_names(module: &Module) -> Vec<&str> {
+    module.functions.iter().filter_map(|function| function.import()).map(|(_module, name)| name).collect()
+}
+
+#[test]
+fn atomic_rmw_is_hooked_like_a_store() {
+    let wat = r#"(module
+        (memory 1 1 shared)
+        (func (param i32 i32) (result i32)
+            (i32.atomic.rmw.add offset=4 (local.get 0) (local.get 1))))"#;
+    let (module, _) = instrument(wat, HookSet::only(Hook::Store));
+    module.validate().unwrap();
+
+    assert_eq!(hook_names(&module), vec!["i32_atomic_rmw_add"]);
+    assert_eq!(LowLevelHook::from_str("i32_atomic_rmw_add"), Ok(LowLevelHook::AtomicRmw(AtomicOp::I32AtomicRmwAdd, ValType::I32)));
+    // location, offset, alignment, memory, address, operand, and the old value read from memory
+    let (hook_idx, hook) = module.functions().find(|(_, function)| function.import().is_some()).unwrap();
+    assert_eq!(hook.type_.inputs(), &[ValType::I32; 8]);
+    let body = module.functions[0].instrs();
+    let rmw_idx = body.iter().position(|instr| matches!(instr, Atomic(AtomicOp::I32AtomicRmwAdd, _))).unwrap();
+    assert!(matches!(body[rmw_idx + 1..rmw_idx + 6], [Local(LocalOp::Tee, _), Const(Val::I32(0)), Const(Val::I32(2)), Const(Val::I32(4)), Const(Val::I32(2))]), "{body:?}");
+    assert_eq!(body.iter().filter(|instr| matches!(instr, Call(func) if *func == hook_idx)).count(), 1);
+
+    // Without the store hook, read-modify-write operations are not instrumented.
+    let (module, _) = instrument(wat, HookSet::only(Hook::Load));
+    assert!(hook_names(&module).is_empty());
+}
+
+#[test]
+fn return_call_is_followed_by_a_return_hook() {
+    let wat = r#"(module
+        (func $callee (param i32) (result i32) (local.get 0))
+        (func (param i32) (result i32)
+            (return_call $callee (local.get 0))))"#;
+    let (module, _) = instrument(wat, Hook::Call | Hook::Return | Hook::End);
+    module.validate().unwrap();
+
+    // Like a return, but without results, since they are only known after the callee returned.
+    assert_eq!(LowLevelHook::from_str("return"), Ok(LowLevelHook::Return(vec![])));
+    let hooks: Vec<_> = module.functions()
+        .filter_map(|(idx, function)| function.import().map(|(_module, name)| (idx, name)))
+        .collect();
+    let hook_name = |instr: &wasabi_wasm::Instr| match instr {
+        Call(func) => hooks.iter().find(|(idx, _)| idx == func).map(|(_, name)| *name),
+        _ => None,
+    };
+    let hook_calls: Vec<_> = module.functions[1].instrs().iter().filter_map(hook_name).collect();
+    // The last end hook is for the implicit function end, which is unreachable after the tail call.
+    assert_eq!(hook_calls, vec!["return_call_i", "return", "end_function", "end_function"]);
+}
+
+/// Runs the exported function "write" of `module` with the given address and returns whether it trapped.
+fn write_traps(module: &Module, addr: i32) -> bool {
+    let mut store = wasmer::Store::default();
+    let module = wasmer::Module::new(&store, module.to_bytes().unwrap()).unwrap();
+    let instance = wasmer::Instance::new(&mut store, &module, &wasmer::imports! {}).unwrap();
+    let write = instance.exports.get_function("write").unwrap();
+    write.call(&mut store, &[wasmer::Value::I32(addr)]).is_err()
+}
+
+#[test]
+fn write_protection_checks_all_writes() {
+    let write_protected = |write_instr: &str| {
+        let wat = format!(r#"(module
+            (memory 1)
+            (data "\00\00")
+            (func (export "write") (param $addr i32)
+                {write_instr}))"#);
+        let (mut module, _warnings) = Module::from_wat(&wat).unwrap();
+        write_protect_range(&mut module, 0u32.into(), 16, 32);
+        module.validate().unwrap();
+        module
+    };
+
+    // Writes [addr, addr+8), the protected range is [16, 32).
+    let module = write_protected("(memory.fill (local.get $addr) (i32.const 0) (i32.const 8))");
+    for (addr, traps) in [(0, false), (8, false), (9, true), (20, true), (31, true), (32, false)] {
+        assert_eq!(write_traps(&module, addr), traps, "memory.fill at {addr}");
+    }
+    let module = write_protected("(memory.copy (local.get $addr) (i32.const 0) (i32.const 8))");
+    assert_eq!((write_traps(&module, 8), write_traps(&module, 9)), (false, true));
+    let module = write_protected("(memory.init 0 (local.get $addr) (i32.const 0) (i32.const 2))");
+    assert_eq!((write_traps(&module, 14), write_traps(&module, 15)), (false, true));
+
+    // Writes [addr+4, addr+8), i.e., the static offset counts as well.
+    let module = write_protected("(i32.atomic.store offset=4 (local.get $addr) (i32.const 1))");
+    for (addr, traps) in [(8, false), (12, true), (24, true), (28, false)] {
+        assert_eq!(write_traps(&module, addr), traps, "i32.atomic.store at {addr}");
+    }
+    let module = write_protected("(drop (i32.atomic.rmw.add offset=4 (local.get $addr) (i32.const 1)))");
+    assert_eq!((write_traps(&module, 8), write_traps(&module, 12)), (false, true));
+    let module = write_protected("(drop (i64.atomic.rmw8.cmpxchg_u (local.get $addr) (i64.const 0) (i64.const 1)))");
+    assert_eq!((write_traps(&module, 15), write_traps(&module, 16)), (false, true));
+    let module = write_protected("(v128.store32_lane 1 (local.get $addr) (v128.const i64x2 0 0))");
+    assert_eq!((write_traps(&module, 12), write_traps(&module, 13)), (false, true));
+    let module = write_protected("(i64.store (local.get $addr) (i64.const 0))");
+    assert_eq!((write_traps(&module, 8), write_traps(&module, 9)), (false, true));
+}
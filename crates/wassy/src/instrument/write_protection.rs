@@ -1,143 +1,149 @@
+use std::collections::HashMap;
+
 use wasabi_wasm::BinaryOp::*;
 use wasabi_wasm::Function;
-use wasabi_wasm::FunctionType;
 use wasabi_wasm::FunctionType::GoedelNumber;
 use wasabi_wasm::Idx;
 use wasabi_wasm::Instr;
 use wasabi_wasm::Instr::*;
 use wasabi_wasm::Label;
 use wasabi_wasm::LocalOp;
-use wasabi_wasm::StoreOp::*;
+use wasabi_wasm::Memory;
+use wasabi_wasm::MemoryOp;
+use wasabi_wasm::UnaryOp::I64ExtendI32U;
 use wasabi_wasm::Val;
 use wasabi_wasm::ValType::*;
-use wasabi_wasm::{MemoryOp, Module};
+use wasabi_wasm::Module;
 
-struct ValidateWritePatch {
-    original_instr: Instr,
-    func_idx: Idx<Function>,
+/// Number of bytes written by an instruction, starting at its effective address.
+enum WriteLength {
+    /// Stores (including atomic and lane stores) and read-modify-write operations.
+    Static(u64),
+    /// Bulk memory instructions, which take the length as the operand with this index.
+    Operand(u32),
 }
 
-static VALIDATE_WRITE_PATCHES: Vec<ValidateWritePatch> = Vec::new();
+/// Returns the memory, static offset, and length of the write of `instr`, or `None` if `instr`
+/// does not write to memory. The address is always the first operand.
+fn memory_write(instr: &Instr) -> Option<(Idx<Memory>, u64, WriteLength)> {
+    match *instr {
+        Store(op, memarg) => Some((memarg.memory, memarg.offset, WriteLength::Static(op.natural_alignment().into()))),
+        StoreLane(op, memarg, _) => Some((memarg.memory, memarg.offset, WriteLength::Static(op.natural_alignment().into()))),
+        AtomicStore(op, memarg) => Some((memarg.memory, memarg.offset, WriteLength::Static(op.natural_alignment().into()))),
+        Atomic(op, memarg) if !op.is_wait_or_notify() => Some((memarg.memory, memarg.offset, WriteLength::Static(op.natural_alignment().into()))),
+        MemoryFill(memory) | MemoryInit(_, memory) => Some((memory, 0, WriteLength::Operand(2))),
+        // only the destination memory is written
+        MemoryCopy { dst, .. } => Some((dst, 0, WriteLength::Operand(2))),
+        _ => None,
+    }
+}
 
-pub fn write_protect_range(module: &mut Module, start_address: u32, end_address: u32) {
+/// Only writes to `memory` are checked, since the range is only protected in that memory.
+/// A write traps if any of the bytes it writes (from its effective address, i.e., including the
+/// static offset, up to its length) is in the range, or if it is a bulk write of length zero
+/// to an address in the range.
+/// The addresses are u64 to also cover 64-bit memories, otherwise they must fit in u32.
+pub fn write_protect_range(module: &mut Module, memory: Idx<Memory>, start_address: u64, end_address: u64) {
     assert!(
         start_address < end_address,
         "start_address ({start_address:#010X}) < end_address ({end_address:#010X})"
     );
+    let addr_type = module.memories[memory.to_usize()].index_type();
+    assert!(
+        addr_type == I64 || end_address <= u32::MAX as u64,
+        "end_address ({end_address:#010X}) is out of range for a 32-bit memory"
+    );
+    // Comparisons and constants in the check must match the address type.
+    let (add, ge_u, le_u) = match addr_type {
+        I64 => (I64Add, I64GeU, I64LeU),
+        _ => (I32Add, I32GeU, I32LeU),
+    };
+    let addr_const = |address: u64| match addr_type {
+        I64 => Const(Val::I64(address as i64)),
+        _ => Const(Val::I32(address as u32 as i32)),
+    };
 
-    // println!("start_address ({start_address:#010X}) < end_address ({end_address:#010X})");
+    // Identical write instructions share the same checking function.
+    let mut validate_write_funcs: HashMap<Instr, Idx<Function>> = HashMap::new();
 
-    let mut patched_store_instrs = 0;
+    let mut patched_write_instrs = 0;
     for (func_idx, func) in module.clone().functions() {
         if let Some(func_code) = func.code() {
             for (instr_idx, instr) in func_code.body.clone().into_iter().enumerate() {
-                if let Store(store_op, _) = instr {
-                    let func_type = store_op.to_type();
-                    let stack_size = func_type.inputs().len();
-                    if stack_size != 2 {
-                        println!("[Write Protection] Encountered a 'store' instruction with {stack_size} value{0} on the stack, skipping !", if stack_size == 1 { "" } else { "s" });
-                        continue;
-                    }
-                    let store_addr_type = func_type.inputs()[0];
-                    let store_val_type = func_type.inputs()[1];
+                let Some((write_memory, offset, length)) = memory_write(&instr) else {
+                    continue;
+                };
+                if write_memory != memory {
+                    continue;
+                }
 
-                    if store_addr_type != I32 {
-                        println!("[Write Protection] Encountered a 'store' instruction where the address is of type '{store_addr_type}', skipping !");
-                        continue;
-                    }
-                    match store_op {
-                        I32Store8 | I32Store16 | I32Store => {
-                            if store_val_type != I32 {
-                                println!("[Write Protection] Encountered a 'i32.store' instruction where the value being stored is of type '{store_val_type}', skipping !");
-                                continue;
-                            }
+                let validate_write_func_idx = match validate_write_funcs.get(&instr) {
+                    Some(&func_idx) => func_idx,
+                    None => {
+                        let func_type = instr.simple_type_with_memories(&module.memories).unwrap();
+                        // The effective address is stored in the only non-parameter local.
+                        let addr_local = Idx::from(func_type.inputs().len() as u32);
+
+                        let mut body = vec![
+                            Block(GoedelNumber {
+                                inputs: 0,
+                                results: 0,
+                            }),
+                            Local(LocalOp::Get, Idx::from(0u32)),
+                        ];
+                        if offset != 0 {
+                            body.extend_from_slice(&[addr_const(offset), Binary(add)]);
                         }
-                        I64Store8 | I64Store16 | I64Store32 | I64Store => {
-                            if store_val_type != I64 {
-                                println!("[Write Protection] Encountered a 'i64.store' instruction where the value being stored is of type '{store_val_type}', skipping !");
-                                continue;
+                        // Check if the written bytes overlap with the range that we are protecting,
+                        // i.e., the write neither begins after the range nor ends before it.
+                        body.extend_from_slice(&[
+                            Local(LocalOp::Tee, addr_local),
+                            addr_const(end_address),
+                            Binary(ge_u),
+                            BrIf(Label::from(0u32)),
+                            Local(LocalOp::Get, addr_local),
+                        ]);
+                        match length {
+                            WriteLength::Static(length) => body.push(addr_const(length)),
+                            WriteLength::Operand(operand) => {
+                                body.push(Local(LocalOp::Get, Idx::from(operand)));
+                                // e.g., the length of memory.init is always i32
+                                if func_type.inputs()[operand as usize] != addr_type {
+                                    body.push(Unary(I64ExtendI32U));
+                                }
                             }
                         }
-                        F32Store => {
-                            if store_val_type != F32 {
-                                println!("[Write Protection] Encountered a 'f32.store' instruction where the value being stored is of type '{store_val_type}', skipping !");
-                                continue;
-                            }
+                        body.extend_from_slice(&[
+                            Binary(add),
+                            addr_const(start_address),
+                            Binary(le_u),
+                            BrIf(Label::from(0u32)),
+                            Unreachable,
+                            End,
+                        ]);
+                        // Then perform the original write with all of its operands.
+                        for param in 0..func_type.inputs().len() {
+                            body.push(Local(LocalOp::Get, Idx::from(param as u32)));
                         }
-                        F64Store => {
-                            if store_val_type != F64 {
-                                println!("[Write Protection] Encountered a 'f64.store' instruction where the value being stored is of type '{store_val_type}', skipping !");
-                                continue;
-                            }
-                        }
-                    };
-
-                    // Check if we have already inserted a patch that corresponds to this instruction
-                    let validate_write_patch = VALIDATE_WRITE_PATCHES
-                        .iter()
-                        .find(|validate_write_patch| validate_write_patch.original_instr == instr);
+                        body.extend_from_slice(&[instr.clone(), End]);
 
-                    let validate_write_func_idx = match validate_write_patch {
-                        Some(validate_write_patch) => validate_write_patch.func_idx,
-                        None => {
-                            let num_additional_bytes_modified = match store_op {
-                                I32Store8 | I64Store8 => 0,
-                                I32Store16 | I64Store16 => 1,
-                                I32Store | F32Store | I64Store32 => 3,
-                                I64Store | F64Store => 7,
-                            };
-
-                            // Mike: Can this function be better optimised ?
-                            module.add_function(
-                                FunctionType::new(&[store_addr_type, store_val_type], &[]),
-                                vec![
-                                    I32, // Temporary variable
-                                ],
-                                // Check if the address being written to is in the range that we are protecting
-                                vec![
-                                    Block(GoedelNumber {
-                                        inputs: 0,
-                                        results: 0,
-                                    }),
-                                    Local(LocalOp::Get, Idx::from(0u32)),
-                                    Const(Val::I32(end_address as i32)),
-                                    Binary(I32GeU),
-                                    BrIf(Label::from(0u32)),
-                                    Const(Val::I32(start_address as i32)),
-                                    Local(LocalOp::Get, Idx::from(0u32)),
-                                    Binary(I32Sub),
-                                    Local(LocalOp::Tee, Idx::from(2u32)),
-                                    Const(Val::I32(0)),
-                                    Local(LocalOp::Get, Idx::from(2u32)),
-                                    Const(Val::I32(start_address as i32)),
-                                    Binary(I32LeU),
-                                    Select,
-                                    Const(Val::I32(num_additional_bytes_modified)),
-                                    Binary(I32GtU),
-                                    BrIf(Label::from(0u32)),
-                                    Unreachable,
-                                    End,
-                                    Local(LocalOp::Get, Idx::from(0u32)),
-                                    Local(LocalOp::Get, Idx::from(1u32)),
-                                    instr,
-                                    End,
-                                ],
-                            )
-                        }
-                    };
-
-                    if let Some(module_code) = module.functions[func_idx.to_usize()].code_mut() {
-                        module_code.body[instr_idx] = Call(validate_write_func_idx);
-                        patched_store_instrs += 1;
-                    } else {
-                        println!("[Write Protection] Failed to patch instruction #{0} in function #{1} !", instr_idx + 1, func_idx.to_usize());
+                        let validate_write_func_idx = module.add_function(func_type, vec![addr_type], body);
+                        validate_write_funcs.insert(instr.clone(), validate_write_func_idx);
+                        validate_write_func_idx
                     }
+                };
+
+                if let Some(module_code) = module.functions[func_idx.to_usize()].code_mut() {
+                    module_code.body[instr_idx] = Call(validate_write_func_idx);
+                    patched_write_instrs += 1;
+                } else {
+                    println!("[Write Protection] Failed to patch instruction #{0} in function #{1} !", instr_idx + 1, func_idx.to_usize());
                 }
             }
         }
     }
     println!(
-        "[Write Protection] Patched {patched_store_instrs} 'store' instruction{0} !",
-        if patched_store_instrs == 1 { "" } else { "s" }
+        "[Write Protection] Patched {patched_write_instrs} write instruction{0} !",
+        if patched_write_instrs == 1 { "" } else { "s" }
     );
 }
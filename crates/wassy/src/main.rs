@@ -3,6 +3,7 @@ use std::io;
 
 use main_error::MainError;
 use wasabi_wasm::Module;
+use wasabi_wasm::WasmExtension;
 
 use clap::Parser;
 
@@ -12,6 +13,9 @@ use wassy::options::Options;
 
 use wassy::runtime::create_runtime;
 
+/// Extensions whose instructions or types the instrumentation cannot handle yet.
+const UNSUPPORTED_EXTENSIONS: &[WasmExtension] = &[WasmExtension::MultiValue];
+
 fn main() -> Result<(), MainError> {
     // TODO: use clap as our CLI since we're moving away from Wasabi.
     let args = Options::parse();
@@ -36,26 +40,36 @@ fn main() -> Result<(), MainError> {
         .file_name()
         .ok_or_else(|| io_err("invalid input file, has no filename"))?;
     let output_file_wasm = args.output_dir.join(input_filename);
-    // let output_file_wasabi_js = output_file_wasm.with_extension("wasabi.js");
+    let output_file_wasabi_js = output_file_wasm.with_extension("wasabi.js");
+    let output_file_info_json = output_file_wasm.with_extension("info.json");
+    let output_file_wat = output_file_wasm.with_extension("wat");
 
     // instrument Wasm and generate JavaScript
     let (mut module, _offsets, _warnings) = Module::from_file(&args.input_file)?;
-    if module.metadata.used_extensions().next().is_some() {
-        return Err(io_err(
-            "input file uses Wasm extensions, which are not supported yet by Wasabi",
-        )
+    if let Some(extension) = module.metadata.used_extensions().find(|extension| UNSUPPORTED_EXTENSIONS.contains(extension)) {
+        return Err(io_err(&format!(
+            "input file uses the Wasm extension '{}', which is not supported yet by Wasabi",
+            extension.name()
+        ))
         .into());
     }
-    // let (_js, hook_count) = add_hooks(&mut module, enabled_hooks, args.node_js).unwrap();
-    let hook_count = add_hooks(&mut module, enabled_hooks).unwrap();
+    let original_module = args.emit_wat.then(|| module.clone());
+    let (js, module_info, hook_count) = add_hooks(&mut module, enabled_hooks, args.node_js).unwrap();
     println!("inserted {hook_count} low-level hooks");
 
     // write output files
     fs::create_dir_all(&args.output_dir)?;
-    module.to_file(output_file_wasm)?;
+    module.to_file(&output_file_wasm)?;
+    fs::write(output_file_wasabi_js, js)?;
+    if args.emit_info {
+        fs::write(output_file_info_json, serde_json::to_string_pretty(&module_info)?)?;
+    }
+    if let Some(original_module) = original_module {
+        fs::write(output_file_wat, module.to_wat_marking_inserted(&original_module))?;
+    }
 
-    // TODO: use runtime from wasmer to provide host functions.
-    let _test = create_runtime(&args.input_file)?;
+    // Run the instrumented module headless, without an analysis for now.
+    let _runtime = create_runtime(&output_file_wasm, module_info, ())?;
 
     Ok(())
 }
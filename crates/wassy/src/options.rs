@@ -23,6 +23,14 @@ pub struct Options {
     #[arg(short = 'o', long = "output-dir", default_value = "./out/")]
     pub output_dir: PathBuf,
 
+    /// Also write the static module information (functions, br_table targets, etc.) to <input>.info.json
+    #[arg(long = "emit-info")]
+    pub emit_info: bool,
+
+    /// Also write the instrumented module in the text format to <input>.wat, marking inserted instructions
+    #[arg(long = "emit-wat")]
+    pub emit_wat: bool,
+
     /// Instrumentations to apply
     #[arg(long = "hooks", num_args(0..))]
     pub hooks: Vec<Hook>,
@@ -52,6 +60,10 @@ pub enum Hook {
     PointerHardening,
     Return,
 
+    // Exception handling extension, throw together for throw and rethrow.
+    Throw,
+    Catch,
+
     Drop,
     Select,
 
@@ -67,6 +79,11 @@ pub enum Hook {
 
     MemorySize,
     MemoryGrow,
+    // Bulk memory extension, only the instructions that write to memory.
+    MemoryCopy,
+    MemoryFill,
+    // Reference types and bulk memory extensions, all instructions that write to a table.
+    Table,
 
     Local,
     Global,
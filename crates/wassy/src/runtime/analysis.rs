@@ -0,0 +1,169 @@
+use wasabi_wasm::AtomicLoadOp;
+use wasabi_wasm::AtomicOp;
+use wasabi_wasm::AtomicStoreOp;
+use wasabi_wasm::BinaryOp;
+use wasabi_wasm::Element;
+use wasabi_wasm::Function;
+use wasabi_wasm::Global;
+use wasabi_wasm::GlobalOp;
+use wasabi_wasm::Idx;
+use wasabi_wasm::Instr;
+use wasabi_wasm::Label;
+use wasabi_wasm::LoadOp;
+use wasabi_wasm::Local;
+use wasabi_wasm::LocalOp;
+use wasabi_wasm::Memory;
+use wasabi_wasm::StoreOp;
+use wasabi_wasm::Table;
+use wasabi_wasm::Tag;
+use wasabi_wasm::UnaryOp;
+use wasabi_wasm::Val;
+
+/*
+ * High-level analysis API for writing dynamic analyses in Rust, i.e., the counterpart to the
+ * JavaScript `Wasabi.analysis` object. The runtime decodes the arguments of the low-level hooks
+ * (instruction locations, i64 halves, etc.) before calling the methods of this trait.
+ */
+
+/// Location of an instruction in the original (i.e., not instrumented) module.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Location {
+    pub func: Idx<Function>,
+    /// `None` for events that do not correspond to an instruction, e.g., the beginning of a
+    /// function or the implicit return at its end.
+    pub instr: Option<Idx<Instr>>,
+}
+
+impl Location {
+    /// from the (i32, i32) pair that is passed as the first two arguments to every low-level hook
+    pub fn from_raw(func: i32, instr: i32) -> Self {
+        Location {
+            func: (func as u32).into(),
+            instr: u32::try_from(instr).ok().map(Into::into),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum BlockType {
+    Function,
+    Block,
+    Loop,
+    If,
+    Else,
+    Try,
+    /// Handler of a try block, started by either a catch or catch_all instruction.
+    Catch,
+}
+
+/// Relative label of a branch, resolved to the absolute instruction it jumps to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct BranchTarget {
+    pub label: Label,
+    pub location: Location,
+}
+
+/// Static immediates and dynamic effective address of a load or store.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct MemArg {
+    /// 64-bit for memories of the memory64 extension, otherwise always fits in 32 bits.
+    pub addr: u64,
+    pub offset: u64,
+    pub align: u32,
+    /// Always memory 0, unless the module uses the multi-memory extension.
+    pub memory: Idx<Memory>,
+}
+
+/// Memory ordering of an atomic access. The threads proposal only has sequentially consistent
+/// atomics, but relaxed or acquire-release orderings are likely to follow.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum MemoryOrdering {
+    SeqCst,
+}
+
+/// One method per high-level hook in `options::Hook`, all with an empty default implementation,
+/// such that an analysis only needs to implement the hooks it is interested in.
+/// Only hooks that were enabled during instrumentation are ever called.
+#[allow(unused_variables)]
+pub trait Analysis: Send + 'static {
+    fn start(&mut self, location: Location) {}
+
+    fn nop(&mut self, location: Location) {}
+    fn unreachable(&mut self, location: Location) {}
+
+    fn if_(&mut self, location: Location, condition: bool) {}
+    fn br(&mut self, location: Location, target: BranchTarget) {}
+    fn br_if(&mut self, location: Location, target: BranchTarget, condition: bool) {}
+    fn br_table(&mut self, location: Location, table: &[BranchTarget], default: BranchTarget, table_idx: u32) {}
+
+    /// `if_location` is only given for else and catch blocks and points to the matching if or try.
+    fn begin(&mut self, location: Location, block_type: BlockType, if_location: Option<Location>) {}
+    /// `begin_location` is the block, loop, if, else, try, catch, or catch_all instruction that
+    /// began the block, `if_location` is as for `begin`.
+    /// Not called for blocks (and functions) that are left because an exception was thrown.
+    fn end(&mut self, location: Location, block_type: BlockType, begin_location: Location, if_location: Option<Location>) {}
+
+    /// `table_idx` is only given for indirect calls, in which case `target_func` is the function
+    /// in the table at that index, if it could be resolved statically from the element section.
+    /// Also called for tail calls (`return_call` and `return_call_indirect`), which are followed by
+    /// a `return_` without results and the `end` hooks of the calling function, but not by `call_post`.
+    fn call_pre(&mut self, location: Location, target_func: Option<Idx<Function>>, args: &[Val], table_idx: Option<u32>) {}
+    fn call_post(&mut self, location: Location, results: &[Val]) {}
+    fn return_(&mut self, location: Location, results: &[Val]) {}
+
+    /// Called before throwing a new exception with the given `tag` and the `values` it carries.
+    fn throw_(&mut self, location: Location, tag: Idx<Tag>, values: &[Val]) {}
+    /// `catch_location` is the catch or catch_all of the handler whose exception is rethrown.
+    fn rethrow(&mut self, location: Location, catch_location: Location) {}
+    /// Called at the beginning of a handler, with the caught exception's values, i.e., after all
+    /// blocks between the throw and the handler have been left (without calling `end` for them).
+    /// `tag` is `None` for catch_all, which does not have access to the values of the exception.
+    fn catch_(&mut self, location: Location, tag: Option<Idx<Tag>>, values: &[Val]) {}
+
+    fn drop(&mut self, location: Location, value: Val) {}
+    fn select(&mut self, location: Location, condition: bool, first: Val, second: Val) {}
+
+    fn const_(&mut self, location: Location, value: Val) {}
+    fn unary(&mut self, location: Location, op: UnaryOp, input: Val, result: Val) {}
+    fn binary(&mut self, location: Location, op: BinaryOp, first: Val, second: Val, result: Val) {}
+
+    fn load(&mut self, location: Location, op: LoadOp, memarg: MemArg, value: Val) {}
+    fn store(&mut self, location: Location, op: StoreOp, memarg: MemArg, value: Val) {}
+    /// Atomic variants of `load` and `store`, enabled together with them.
+    fn atomic_load(&mut self, location: Location, op: AtomicLoadOp, memarg: MemArg, value: Val, ordering: MemoryOrdering) {}
+    fn atomic_store(&mut self, location: Location, op: AtomicStoreOp, memarg: MemArg, value: Val, ordering: MemoryOrdering) {}
+    /// Atomic read-modify-write operations (e.g., `i32.atomic.rmw.add`), enabled together with `store`.
+    /// `old_value` was read from memory (and is returned by the instruction), the result of
+    /// combining it with `value` was written back.
+    fn atomic_rmw(&mut self, location: Location, op: AtomicOp, memarg: MemArg, value: Val, old_value: Val, ordering: MemoryOrdering) {}
+    /// Atomic compare-exchange, enabled together with `store`. `replacement` was only written if
+    /// `old_value` (read from memory and returned by the instruction) equals `expected`.
+    #[allow(clippy::too_many_arguments)]
+    fn atomic_cmpxchg(&mut self, location: Location, op: AtomicOp, memarg: MemArg, expected: Val, replacement: Val, old_value: Val, ordering: MemoryOrdering) {}
+
+    /// Sizes, addresses, and lengths of memory instructions are u64 to also cover 64-bit memories.
+    fn memory_size(&mut self, location: Location, current_size_pages: u64) {}
+    /// If growing failed, `previous_size_pages` is -1, i.e., `u32::MAX` for 32-bit memories and
+    /// `u64::MAX` for 64-bit memories.
+    fn memory_grow(&mut self, location: Location, delta_pages: u64, previous_size_pages: u64) {}
+    /// Called after the copy, i.e., only if it did not trap.
+    fn memory_copy(&mut self, location: Location, dst: u64, src: u64, length: u64) {}
+    /// Called after the fill, i.e., only if it did not trap.
+    /// `value` is the byte written to every address, even though the instruction takes an i32.
+    fn memory_fill(&mut self, location: Location, dst: u64, value: u8, length: u64) {}
+
+    /// The table hooks are called after the instruction, i.e., only if it did not trap.
+    /// References are opaque, so `value` only tells whether a null reference was written.
+    fn table_set(&mut self, location: Location, table: Idx<Table>, index: u32, value: Val) {}
+    /// `previous_size` is -1 (i.e., `u32::MAX`) if the table could not be grown.
+    fn table_grow(&mut self, location: Location, table: Idx<Table>, init_value: Val, delta: u32, previous_size: u32) {}
+    fn table_fill(&mut self, location: Location, table: Idx<Table>, dst: u32, value: Val, length: u32) {}
+    fn table_copy(&mut self, location: Location, dst_table: Idx<Table>, src_table: Idx<Table>, dst: u32, src: u32, length: u32) {}
+    fn table_init(&mut self, location: Location, table: Idx<Table>, element: Idx<Element>, dst: u32, src: u32, length: u32) {}
+
+    fn local(&mut self, location: Location, op: LocalOp, index: Idx<Local>, value: Val) {}
+    fn global(&mut self, location: Location, op: GlobalOp, index: Idx<Global>, value: Val) {}
+}
+
+/// Does nothing, e.g., for just running an instrumented module.
+impl Analysis for () {}
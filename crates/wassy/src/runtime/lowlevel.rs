@@ -0,0 +1,543 @@
+use std::str::FromStr;
+
+use wasabi_wasm::AtomicLoadOp;
+use wasabi_wasm::AtomicOp;
+use wasabi_wasm::AtomicStoreOp;
+use wasabi_wasm::BinaryOp;
+use wasabi_wasm::Function;
+use wasabi_wasm::GlobalOp;
+use wasabi_wasm::Idx;
+use wasabi_wasm::LoadOp;
+use wasabi_wasm::LocalOp;
+use wasabi_wasm::MemoryOp;
+use wasabi_wasm::StoreOp;
+use wasabi_wasm::UnaryOp;
+use wasabi_wasm::Val;
+use wasabi_wasm::ValType;
+use wasmer::Value;
+
+use crate::instrument::block_stack::BlockStackElement;
+use crate::instrument::static_info::BrTableInfo;
+use crate::instrument::static_info::ResolvedLabel;
+
+use super::analysis::*;
+
+/*
+ * Decoding of the low-level hooks that the instrumented module imports from `__wasabi_hooks`
+ * (see `instrument::hook_map`), i.e., the Rust counterpart of the generated low-level JavaScript hooks.
+ * The hook name determines which high-level hook to call and how to interpret the Wasm arguments
+ * (in particular, which pairs of i32 arguments are actually the low and high half of an i64).
+ */
+
+/// State shared by all low-level hooks of one instance.
+pub struct HookEnv<A> {
+    pub analysis: A,
+    pub br_tables: Vec<BrTableInfo>,
+    /// contents of the (first) table, for resolving call_indirect targets
+    pub table: Vec<Option<Idx<Function>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LowLevelHook {
+    Start,
+    Nop,
+    Unreachable,
+    If,
+    Br,
+    BrIf,
+    BrTable,
+    Begin(BlockType),
+    End(BlockType),
+    // memory hooks are monomorphized by the index type of the memory (i64 only for memory64),
+    // memory.copy by that of both the destination and source memory
+    MemorySize(ValType),
+    MemoryGrow(ValType),
+    MemoryCopy(ValType, ValType),
+    MemoryFill(ValType),
+    TableCopy,
+    TableInit,
+    Load(LoadOp, ValType),
+    Store(StoreOp, ValType),
+    AtomicLoad(AtomicLoadOp, ValType),
+    AtomicStore(AtomicStoreOp, ValType),
+    AtomicRmw(AtomicOp, ValType),
+    AtomicCmpxchg(AtomicOp, ValType),
+    Const(ValType),
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    // polymorphic hooks, monomorphized by the type(s) in the mangled name
+    Drop(ValType),
+    Select(ValType),
+    // monomorphized by the element type of the table
+    TableSet(ValType),
+    TableGrow(ValType),
+    TableFill(ValType),
+    Local(LocalOp, ValType),
+    Global(GlobalOp, ValType),
+    Return(Vec<ValType>),
+    Call(Vec<ValType>),
+    CallIndirect(Vec<ValType>),
+    CallPost(Vec<ValType>),
+    // monomorphized by the parameter types of the tag
+    Throw(Vec<ValType>),
+    Rethrow,
+    Catch(Vec<ValType>),
+    CatchAll,
+}
+
+impl FromStr for LowLevelHook {
+    type Err = ();
+
+    /// inverse of `LowLevelHookName::to_mangled_string()` in `instrument::hook_map`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        use LowLevelHook::*;
+
+        // monomorphic hooks first, since some of their names (e.g., "br_if") would otherwise be
+        // mistaken for a hook stem ("br") followed by mangled types ("if" == [i32, f32]).
+        match name {
+            "start" => return Ok(Start),
+            "nop" => return Ok(Nop),
+            "unreachable" => return Ok(Unreachable),
+            "if" => return Ok(If),
+            "br" => return Ok(Br),
+            "br_if" => return Ok(BrIf),
+            "br_table" => return Ok(BrTable),
+            "begin_function" => return Ok(Begin(BlockType::Function)),
+            "begin_block" => return Ok(Begin(BlockType::Block)),
+            "begin_loop" => return Ok(Begin(BlockType::Loop)),
+            "begin_if" => return Ok(Begin(BlockType::If)),
+            "begin_else" => return Ok(Begin(BlockType::Else)),
+            "begin_try" => return Ok(Begin(BlockType::Try)),
+            "begin_catch" => return Ok(Begin(BlockType::Catch)),
+            "end_function" => return Ok(End(BlockType::Function)),
+            "end_block" => return Ok(End(BlockType::Block)),
+            "end_loop" => return Ok(End(BlockType::Loop)),
+            "end_if" => return Ok(End(BlockType::If)),
+            "end_else" => return Ok(End(BlockType::Else)),
+            "end_try" => return Ok(End(BlockType::Try)),
+            "end_catch" => return Ok(End(BlockType::Catch)),
+            "rethrow" => return Ok(Rethrow),
+            "catch_all" => return Ok(CatchAll),
+            "memory_size" => return Ok(MemorySize(ValType::I32)),
+            "memory_grow" => return Ok(MemoryGrow(ValType::I32)),
+            "memory_copy" => return Ok(MemoryCopy(ValType::I32, ValType::I32)),
+            "memory_fill" => return Ok(MemoryFill(ValType::I32)),
+            "table_copy" => return Ok(TableCopy),
+            "table_init" => return Ok(TableInit),
+            _ => {}
+        }
+
+        // numeric and memory instructions: the first '.' of the instruction name became '_'
+        // (the prefix is either a value type or, for SIMD instructions, a vector shape like "i8x16"),
+        // except for atomic instructions, where also the '.' after "atomic" (and after "rmw<N>" for
+        // read-modify-write instructions) became '_'.
+        // Loads and stores on 64-bit memories additionally have the mangled suffix "_I".
+        let (unmangled, index_ty) = match name.strip_suffix("_I") {
+            Some(unmangled) => (unmangled, ValType::I64),
+            None => (name, ValType::I32),
+        };
+        if let Some((prefix, op)) = unmangled.split_once('_') {
+            let ty = ValType::from_str(prefix);
+            let instr_name = match op.strip_prefix("atomic_") {
+                Some(op) if op.starts_with("rmw") => format!("{prefix}.atomic.{}", op.replacen('_', ".", 1)),
+                Some(op) => format!("{prefix}.atomic.{op}"),
+                None => format!("{prefix}.{op}"),
+            };
+            if let (Ok(ty), "const") = (ty, op) {
+                return Ok(Const(ty));
+            } else if let Ok(op) = UnaryOp::from_str(&instr_name) {
+                return Ok(Unary(op));
+            } else if let Ok(op) = BinaryOp::from_str(&instr_name) {
+                return Ok(Binary(op));
+            } else if let Ok(op) = LoadOp::from_str(&instr_name) {
+                return Ok(Load(op, index_ty));
+            } else if let Ok(op) = StoreOp::from_str(&instr_name) {
+                return Ok(Store(op, index_ty));
+            } else if let Ok(op) = AtomicLoadOp::from_str(&instr_name) {
+                return Ok(AtomicLoad(op, index_ty));
+            } else if let Ok(op) = AtomicStoreOp::from_str(&instr_name) {
+                return Ok(AtomicStore(op, index_ty));
+            } else if let Ok(op) = AtomicOp::from_str(&instr_name) {
+                return match op {
+                    op if op.is_cmpxchg() => Ok(AtomicCmpxchg(op, index_ty)),
+                    op if op.is_wait_or_notify() => Err(()),
+                    op => Ok(AtomicRmw(op, index_ty)),
+                };
+            } else if ty.is_ok() {
+                return Err(());
+            }
+        }
+
+        // polymorphic hooks: "<stem>" if there are no types, otherwise "<stem>_<type chars>"
+        let (stem, types) = match name {
+            "return" | "call" | "call_indirect" | "call_post" | "return_call" | "return_call_indirect" | "throw" | "catch" => (name, Vec::new()),
+            _ => {
+                let (stem, types) = name.rsplit_once('_').ok_or(())?;
+                let types = types.chars().map(ValType::from_char).collect::<Option<Vec<_>>>().ok_or(())?;
+                (stem, types)
+            }
+        };
+        let single_type = || match types.as_slice() {
+            &[ty] => Ok(ty),
+            _ => Err(()),
+        };
+        Ok(match stem {
+            "drop" => Drop(single_type()?),
+            "select" => match types.as_slice() {
+                &[ty1, ty2] if ty1 == ty2 => Select(ty1),
+                _ => return Err(()),
+            },
+            "local_get" => Local(LocalOp::Get, single_type()?),
+            "local_set" => Local(LocalOp::Set, single_type()?),
+            "local_tee" => Local(LocalOp::Tee, single_type()?),
+            "global_get" => Global(GlobalOp::Get, single_type()?),
+            "global_set" => Global(GlobalOp::Set, single_type()?),
+            "table_set" => TableSet(single_type()?),
+            "table_grow" => TableGrow(single_type()?),
+            "table_fill" => TableFill(single_type()?),
+            "memory_size" => MemorySize(single_type()?),
+            "memory_grow" => MemoryGrow(single_type()?),
+            "memory_fill" => MemoryFill(single_type()?),
+            "memory_copy" => match types.as_slice() {
+                &[dst_ty, src_ty] => MemoryCopy(dst_ty, src_ty),
+                _ => return Err(()),
+            },
+            "return" => Return(types),
+            // tail calls have the same arguments and high-level hook as normal calls
+            "call" | "return_call" => Call(types),
+            "call_indirect" | "return_call_indirect" => CallIndirect(types),
+            "call_post" => CallPost(types),
+            "throw" => Throw(types),
+            "catch" => Catch(types),
+            _ => return Err(()),
+        })
+    }
+}
+
+impl LowLevelHook {
+    /// decodes the Wasm arguments and forwards them to the corresponding high-level hook
+    pub fn call<A: Analysis>(&self, env: &mut HookEnv<A>, args: &[Value]) {
+        let mut args = Args(args.iter());
+        let location = args.location();
+        let func = location.func;
+        let analysis = &mut env.analysis;
+
+        match *self {
+            LowLevelHook::Start => analysis.start(location),
+            LowLevelHook::Nop => analysis.nop(location),
+            LowLevelHook::Unreachable => analysis.unreachable(location),
+
+            LowLevelHook::If => analysis.if_(location, args.bool()),
+            LowLevelHook::Br => {
+                let target = args.branch_target(func);
+                analysis.br(location, target)
+            }
+            LowLevelHook::BrIf => {
+                let condition = args.bool();
+                let target = args.branch_target(func);
+                analysis.br_if(location, target, condition)
+            }
+            LowLevelHook::BrTable => {
+                let table_idx = args.u32();
+                let info = &env.br_tables[args.u32() as usize];
+                let table: Vec<_> = info.table.iter().map(ResolvedLabel::to_branch_target).collect();
+                analysis.br_table(location, &table, info.default.to_branch_target(), table_idx);
+
+                // end() hooks of the blocks that are left by the taken branch can only be called
+                // now, since the branch target is only known at runtime
+                let taken = info.table.get(table_idx as usize).unwrap_or(&info.default);
+                for block in &taken.end_blocks {
+                    call_end_hook(analysis, func, block);
+                }
+            }
+
+            LowLevelHook::Begin(block_type @ (BlockType::Else | BlockType::Catch)) => {
+                let if_or_try_location = args.location_in(func);
+                analysis.begin(location, block_type, Some(if_or_try_location))
+            }
+            LowLevelHook::Begin(block_type) => analysis.begin(location, block_type, None),
+            LowLevelHook::End(BlockType::Function) => {
+                analysis.end(location, BlockType::Function, Location { func, instr: None }, None)
+            }
+            LowLevelHook::End(block_type @ (BlockType::Else | BlockType::Catch)) => {
+                let begin_location = args.location_in(func);
+                let if_or_try_location = args.location_in(func);
+                analysis.end(location, block_type, begin_location, Some(if_or_try_location))
+            }
+            LowLevelHook::End(block_type) => {
+                let begin_location = args.location_in(func);
+                analysis.end(location, block_type, begin_location, None)
+            }
+
+            LowLevelHook::MemorySize(index_ty) => analysis.memory_size(location, args.index(index_ty)),
+            LowLevelHook::MemoryGrow(index_ty) => {
+                let delta_pages = args.index(index_ty);
+                let previous_size_pages = args.index(index_ty);
+                analysis.memory_grow(location, delta_pages, previous_size_pages)
+            }
+            LowLevelHook::MemoryCopy(dst_ty, src_ty) => {
+                let dst = args.index(dst_ty);
+                let src = args.index(src_ty);
+                // the length is only 64-bit if both memories are
+                let length_ty = if dst_ty == src_ty { dst_ty } else { ValType::I32 };
+                let length = args.index(length_ty);
+                analysis.memory_copy(location, dst, src, length)
+            }
+            LowLevelHook::MemoryFill(index_ty) => {
+                let dst = args.index(index_ty);
+                let value = args.u32() as u8;
+                let length = args.index(index_ty);
+                analysis.memory_fill(location, dst, value, length)
+            }
+            LowLevelHook::TableCopy => {
+                let dst_table = args.u32().into();
+                let src_table = args.u32().into();
+                let dst = args.u32();
+                let src = args.u32();
+                let length = args.u32();
+                analysis.table_copy(location, dst_table, src_table, dst, src, length)
+            }
+            LowLevelHook::TableInit => {
+                let table = args.u32().into();
+                let element = args.u32().into();
+                let dst = args.u32();
+                let src = args.u32();
+                let length = args.u32();
+                analysis.table_init(location, table, element, dst, src, length)
+            }
+            LowLevelHook::Load(op, index_ty) => {
+                let memarg = args.memarg(index_ty);
+                let value = args.val(op.to_type().results()[0]);
+                analysis.load(location, op, memarg, value)
+            }
+            LowLevelHook::Store(op, index_ty) => {
+                let memarg = args.memarg(index_ty);
+                let value = args.val(op.to_type().inputs()[1]);
+                analysis.store(location, op, memarg, value)
+            }
+            // all atomic accesses of the threads proposal are sequentially consistent
+            LowLevelHook::AtomicLoad(op, index_ty) => {
+                let memarg = args.memarg(index_ty);
+                let value = args.val(op.to_type().results()[0]);
+                analysis.atomic_load(location, op, memarg, value, MemoryOrdering::SeqCst)
+            }
+            LowLevelHook::AtomicStore(op, index_ty) => {
+                let memarg = args.memarg(index_ty);
+                let value = args.val(op.to_type().inputs()[1]);
+                analysis.atomic_store(location, op, memarg, value, MemoryOrdering::SeqCst)
+            }
+            LowLevelHook::AtomicRmw(op, index_ty) => {
+                let ty = op.to_type();
+                let memarg = args.memarg(index_ty);
+                let value = args.val(ty.inputs()[1]);
+                let old_value = args.val(ty.results()[0]);
+                analysis.atomic_rmw(location, op, memarg, value, old_value, MemoryOrdering::SeqCst)
+            }
+            LowLevelHook::AtomicCmpxchg(op, index_ty) => {
+                let ty = op.to_type();
+                let memarg = args.memarg(index_ty);
+                let expected = args.val(ty.inputs()[1]);
+                let replacement = args.val(ty.inputs()[2]);
+                let old_value = args.val(ty.results()[0]);
+                analysis.atomic_cmpxchg(location, op, memarg, expected, replacement, old_value, MemoryOrdering::SeqCst)
+            }
+
+            LowLevelHook::Const(ty) => analysis.const_(location, args.val(ty)),
+            LowLevelHook::Unary(op) => {
+                let ty = op.to_type();
+                let input = args.val(ty.inputs()[0]);
+                let result = args.val(ty.results()[0]);
+                analysis.unary(location, op, input, result)
+            }
+            LowLevelHook::Binary(op) => {
+                let ty = op.to_type();
+                let first = args.val(ty.inputs()[0]);
+                let second = args.val(ty.inputs()[1]);
+                let result = args.val(ty.results()[0]);
+                analysis.binary(location, op, first, second, result)
+            }
+
+            LowLevelHook::Drop(ty) => analysis.drop(location, args.val(ty)),
+            LowLevelHook::Select(ty) => {
+                let condition = args.bool();
+                let first = args.val(ty);
+                let second = args.val(ty);
+                analysis.select(location, condition, first, second)
+            }
+            LowLevelHook::TableSet(ty) => {
+                let table = args.u32().into();
+                let index = args.u32();
+                analysis.table_set(location, table, index, args.val(ty))
+            }
+            LowLevelHook::TableGrow(ty) => {
+                let table = args.u32().into();
+                let init_value = args.val(ty);
+                let delta = args.u32();
+                let previous_size = args.u32();
+                analysis.table_grow(location, table, init_value, delta, previous_size)
+            }
+            LowLevelHook::TableFill(ty) => {
+                let table = args.u32().into();
+                let dst = args.u32();
+                let value = args.val(ty);
+                let length = args.u32();
+                analysis.table_fill(location, table, dst, value, length)
+            }
+            LowLevelHook::Local(op, ty) => {
+                let index = args.u32().into();
+                analysis.local(location, op, index, args.val(ty))
+            }
+            LowLevelHook::Global(op, ty) => {
+                let index = args.u32().into();
+                analysis.global(location, op, index, args.val(ty))
+            }
+
+            LowLevelHook::Return(ref tys) => analysis.return_(location, &args.vals(tys)),
+            LowLevelHook::Call(ref tys) => {
+                let target_func = args.u32().into();
+                analysis.call_pre(location, Some(target_func), &args.vals(tys), None)
+            }
+            LowLevelHook::CallIndirect(ref tys) => {
+                let table_idx = args.u32();
+                let target_func = env.table.get(table_idx as usize).copied().flatten();
+                analysis.call_pre(location, target_func, &args.vals(tys), Some(table_idx))
+            }
+            LowLevelHook::CallPost(ref tys) => analysis.call_post(location, &args.vals(tys)),
+
+            LowLevelHook::Throw(ref tys) => {
+                let tag = args.u32().into();
+                analysis.throw_(location, tag, &args.vals(tys))
+            }
+            LowLevelHook::Rethrow => {
+                let catch_location = args.location_in(func);
+                analysis.rethrow(location, catch_location)
+            }
+            LowLevelHook::Catch(ref tys) => {
+                let tag = args.u32().into();
+                analysis.catch_(location, Some(tag), &args.vals(tys))
+            }
+            LowLevelHook::CatchAll => analysis.catch_(location, None, &[]),
+        }
+    }
+}
+
+fn call_end_hook(analysis: &mut impl Analysis, func: Idx<Function>, block: &BlockStackElement) {
+    let location = |instr| Location { func, instr: Some(instr) };
+    match *block {
+        BlockStackElement::Function { end } => analysis.end(location(end), BlockType::Function, Location { func, instr: None }, None),
+        BlockStackElement::Block { begin, end } => analysis.end(location(end), BlockType::Block, location(begin), None),
+        BlockStackElement::Loop { begin, end } => analysis.end(location(end), BlockType::Loop, location(begin), None),
+        BlockStackElement::If { begin_if, end, .. } => analysis.end(location(end), BlockType::If, location(begin_if), None),
+        BlockStackElement::Else { begin_else, begin_if, end } => analysis.end(location(end), BlockType::Else, location(begin_else), Some(location(begin_if))),
+        BlockStackElement::Try { begin, end } => analysis.end(location(end), BlockType::Try, location(begin), None),
+        BlockStackElement::Catch { begin_catch, begin_try, end } => analysis.end(location(end), BlockType::Catch, location(begin_catch), Some(location(begin_try))),
+    }
+}
+
+impl ResolvedLabel {
+    fn to_branch_target(&self) -> BranchTarget {
+        BranchTarget {
+            label: self.label,
+            location: Location {
+                func: self.location.0,
+                instr: Some(self.location.1),
+            },
+        }
+    }
+}
+
+/// Cursor over the arguments of a low-level hook.
+/// Panics if the arguments do not match the hook name, which can only happen if the hook was
+/// not generated by `instrument::hook_map`.
+struct Args<'a>(std::slice::Iter<'a, Value>);
+
+impl Args<'_> {
+    fn next(&mut self) -> &Value {
+        self.0.next().expect("low-level hook was called with too few arguments")
+    }
+
+    fn i32(&mut self) -> i32 {
+        self.next().unwrap_i32()
+    }
+
+    fn u32(&mut self) -> u32 {
+        self.i32() as u32
+    }
+
+    fn bool(&mut self) -> bool {
+        self.i32() != 0
+    }
+
+    /// inverse of `convert_i64_instr()` in `instrument::convert_i64`
+    fn val(&mut self, ty: ValType) -> Val {
+        match ty {
+            ValType::I32 => Val::I32(self.i32()),
+            ValType::I64 => {
+                let low = self.u32() as i64;
+                let high = self.i32() as i64;
+                Val::I64((high << 32) | low)
+            }
+            ValType::F32 => Val::F32(self.next().unwrap_f32().into()),
+            ValType::F64 => Val::F64(self.next().unwrap_f64().into()),
+            // references are opaque to the analysis, only whether they are null is observable
+            ValType::FuncRef => match self.next() {
+                Value::FuncRef(func) => Val::Ref { type_: ty, is_null: func.is_none() },
+                value => panic!("expected funcref argument, got {value:?}"),
+            },
+            ValType::ExternRef => match self.next() {
+                Value::ExternRef(extern_ref) => Val::Ref { type_: ty, is_null: extern_ref.is_none() },
+                value => panic!("expected externref argument, got {value:?}"),
+            },
+            ValType::V128 => {
+                let mut bytes = [0; 16];
+                for lane in bytes.chunks_exact_mut(4) {
+                    lane.copy_from_slice(&self.i32().to_le_bytes());
+                }
+                Val::V128(bytes)
+            }
+        }
+    }
+
+    fn vals(&mut self, tys: &[ValType]) -> Vec<Val> {
+        tys.iter().map(|&ty| self.val(ty)).collect()
+    }
+
+    fn location(&mut self) -> Location {
+        let func = self.i32();
+        let instr = self.i32();
+        Location::from_raw(func, instr)
+    }
+
+    /// for hook arguments that only carry an instruction index, relative to the current function
+    fn location_in(&mut self, func: Idx<Function>) -> Location {
+        Location {
+            func,
+            instr: Some(self.u32().into()),
+        }
+    }
+
+    fn branch_target(&mut self, func: Idx<Function>) -> BranchTarget {
+        let label = self.u32().into();
+        BranchTarget {
+            label,
+            location: self.location_in(func),
+        }
+    }
+
+    /// for addresses, sizes, and lengths, which are i64 for 64-bit memories and i32 otherwise
+    fn index(&mut self, index_ty: ValType) -> u64 {
+        match self.val(index_ty) {
+            Val::I32(i32) => i32 as u32 as u64,
+            Val::I64(i64) => i64 as u64,
+            val => panic!("expected i32 or i64 index, got {val:?}"),
+        }
+    }
+
+    fn memarg(&mut self, index_ty: ValType) -> MemArg {
+        let offset = self.index(index_ty);
+        let align = self.u32();
+        let memory = self.u32().into();
+        let addr = self.index(index_ty);
+        MemArg { addr, offset, align, memory }
+    }
+}
@@ -1,16 +1,65 @@
-use wasmer::{imports, Function, FunctionType, Instance, Module, Store, Type, Value};
+use wasabi_wasm::ElementItems;
+use wasabi_wasm::ElementMode;
+use wasabi_wasm::Idx;
+use wasabi_wasm::Instr;
+use wasabi_wasm::Val;
+use wasmer::{imports, Extern, ExternType, Function, FunctionEnv, FunctionType, Instance, Module, Store, Type};
 // use wasmer_compiler_cranelift::Cranelift;
 // use wasmer_engine_jit::JIT;
+use std::collections::HashMap;
 use std::path::Path;
 
+use crate::instrument::static_info::ModuleInfo;
+
+use self::lowlevel::HookEnv;
+use self::lowlevel::LowLevelHook;
+
+pub use self::analysis::*;
+
+mod analysis;
+pub(crate) mod lowlevel;
+#[cfg(test)]
+mod tests;
+
 // fn host_print(i: i32) {
 //     println!("{}", i);
 // }
 
-pub fn create_runtime(wasm_bin: &Path) -> Result<Instance, Box<dyn std::error::Error>> {
+/// An instantiated, instrumented module, whose low-level hooks call into the analysis `A`.
+pub struct Runtime<A: Analysis> {
+    pub store: Store,
+    pub instance: Instance,
+    env: FunctionEnv<HookEnv<A>>,
+}
+
+impl<A: Analysis> Runtime<A> {
+    pub fn analysis(&self) -> &A {
+        &self.env.as_ref(&self.store).analysis
+    }
+
+    pub fn analysis_mut(&mut self) -> &mut A {
+        &mut self.env.as_mut(&mut self.store).analysis
+    }
+}
+
+/// Instantiates the instrumented module `wasm_bin` and provides all of its `__wasabi_hooks`
+/// imports, such that they call the high-level hooks of `analysis`.
+/// `module_info` must be the static information returned by `add_hooks` for this module.
+pub fn create_runtime<A: Analysis>(wasm_bin: &Path, module_info: ModuleInfo, analysis: A) -> Result<Runtime<A>, Box<dyn std::error::Error>> {
     println!("[+] Creating runtime from module: {:?}", wasm_bin.display());
+    let bytes = std::fs::read(wasm_bin)?;
     let mut store = Store::default();
-    let module = Module::from_file(&store, &wasm_bin)?;
+    let module = Module::new(&store, &bytes)?;
+
+    let table = {
+        let (module, _offsets, _warnings) = wasabi_wasm::Module::from_bytes(&bytes)?;
+        resolve_table(&module, &module_info.instrumented_function_idx)
+    };
+    let env = FunctionEnv::new(&mut store, HookEnv {
+        analysis,
+        br_tables: module_info.br_tables,
+        table,
+    });
 
     let host_print_sig = FunctionType::new(vec![Type::I32], vec![]);
     let host_print_func = Function::new(&mut store, &host_print_sig, |args| {
@@ -23,15 +72,68 @@ pub fn create_runtime(wasm_bin: &Path) -> Result<Instance, Box<dyn std::error::E
 
     println!("[+] Creating imports");
 
-    let import_obj = imports! {
+    let mut import_obj = imports! {
         "env" => {
             "print" => host_print_func,
         },
     };
 
+    for import in module.imports().filter(|import| import.module() == "__wasabi_hooks") {
+        let hook: LowLevelHook = import.name().parse().map_err(|()| format!("unknown low-level hook '{}'", import.name()))?;
+        let ty = match import.ty() {
+            ExternType::Function(ty) => ty.clone(),
+            ty => return Err(format!("low-level hook '{}' must be a function, but is {:?}", import.name(), ty).into()),
+        };
+        let hook_func = Function::new_with_env(&mut store, &env, ty, move |mut env, args| {
+            hook.call(env.data_mut(), args);
+            Ok(vec![])
+        });
+        import_obj.define("__wasabi_hooks", import.name(), Extern::from(hook_func));
+    }
+
     println!("[+] Instantiating module");
 
     let instance = Instance::new(&mut store, &module, &import_obj)?;
 
-    Ok(instance)
+    Ok(Runtime { store, instance, env })
+}
+
+/// Statically resolves the contents of the first table from its active element segments, for
+/// mapping call_indirect table indices to the called function.
+/// Segments with non-constant offsets (e.g., from an imported global) are ignored, as are passive
+/// segments, which are only copied into the table at runtime by `table.init`.
+/// `module` is the instrumented binary, so its function indices are mapped back to the original
+/// ones via the inverse of `instrumented_function_idx`, like `resolveTableIdx` in `runtime.js`.
+fn resolve_table(module: &wasabi_wasm::Module, instrumented_function_idx: &[Idx<wasabi_wasm::Function>]) -> Vec<Option<Idx<wasabi_wasm::Function>>> {
+    let original_function_idx: HashMap<_, _> = instrumented_function_idx
+        .iter()
+        .enumerate()
+        .map(|(original_idx, &instrumented_idx)| (instrumented_idx, Idx::from(original_idx)))
+        .collect();
+    let mut table = Vec::new();
+    for element in &module.elements {
+        if let ElementMode::Active { table: table_idx, offset } = &element.mode {
+            if let (0, [Instr::Const(Val::I32(offset)), Instr::End]) = (table_idx.to_usize(), offset.as_slice()) {
+                let offset = *offset as u32 as usize;
+                let functions: Vec<_> = match &element.items {
+                    ElementItems::Functions(functions) => functions.iter().copied().map(Some).collect(),
+                    ElementItems::Expressions(_, exprs) => exprs
+                        .iter()
+                        .map(|expr| match expr.as_slice() {
+                            [Instr::RefFunc(func), Instr::End] => Some(*func),
+                            _ => None,
+                        })
+                        .collect(),
+                };
+                if table.len() < offset + functions.len() {
+                    table.resize(offset + functions.len(), None);
+                }
+                for (i, func) in functions.into_iter().enumerate() {
+                    // functions added during instrumentation have no original index
+                    table[offset + i] = func.and_then(|func| original_function_idx.get(&func).copied());
+                }
+            }
+        }
+    }
+    table
 }
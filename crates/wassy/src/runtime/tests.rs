@@ -0,0 +1,50 @@
+use wasabi_wasm::Function;
+use wasabi_wasm::Idx;
+use wasabi_wasm::Module;
+use wasabi_wasm::Val;
+
+use crate::instrument::add_hooks;
+use crate::options::Hook;
+use crate::options::HookSet;
+
+use super::create_runtime;
+use super::Analysis;
+use super::Location;
+
+/// Records the target function of every call, in the order they were executed.
+#[derive(Default)]
+struct CallTargets(Vec<Option<Idx<Function>>>);
+
+impl Analysis for CallTargets {
+    fn call_pre(&mut self, _location: Location, target_func: Option<Idx<Function>>, _args: &[Val], _table_idx: Option<u32>) {
+        self.0.push(target_func);
+    }
+}
+
+#[test]
+fn direct_and_indirect_calls_report_the_same_target() {
+    let wat = r#"(module
+        (type $t (func))
+        (import "env" "print" (func (param i32)))
+        (table 1 funcref)
+        (elem (i32.const 0) $callee)
+        (func $callee)
+        (func (export "main")
+            (call $callee)
+            (call_indirect (type $t) (i32.const 0))))"#;
+    let (mut module, _warnings) = Module::from_wat(wat).unwrap();
+    let (_js, module_info, _hook_count) = add_hooks(&mut module, HookSet::only(Hook::Call), false).unwrap();
+
+    let wasm_file = std::env::temp_dir().join(format!("wassy-call-targets-{}.wasm", std::process::id()));
+    module.to_file(&wasm_file).unwrap();
+    let runtime = create_runtime(&wasm_file, module_info, CallTargets::default());
+    std::fs::remove_file(&wasm_file).unwrap();
+    let mut runtime = runtime.unwrap();
+
+    let main = runtime.instance.exports.get_function("main").unwrap().clone();
+    main.call(&mut runtime.store, &[]).unwrap();
+
+    // $callee has index 1 in the original module, even though the low-level hooks are imported
+    // before it in the instrumented binary.
+    assert_eq!(runtime.analysis().0, vec![Some(Idx::from(1u32)); 2]);
+}
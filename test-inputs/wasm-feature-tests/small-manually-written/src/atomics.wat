@@ -0,0 +1,52 @@
+(module
+  (memory (export "memory") 1 1 shared)
+
+  ;; Atomic loads and stores.
+  (func (export "load_store") (param $addr i32) (result i64)
+    local.get $addr
+    i32.const 42
+    i32.atomic.store
+    local.get $addr
+    i64.const 7
+    i64.atomic.store16 offset=8
+    local.get $addr
+    i32.atomic.load8_u
+    i64.extend_i32_u
+    local.get $addr
+    i64.atomic.load offset=8
+    i64.add)
+
+  ;; Read-modify-write operations, including compare-exchange.
+  (func (export "rmw") (param $addr i32) (result i32)
+    local.get $addr
+    i32.const 1
+    i32.atomic.rmw.add
+    drop
+    local.get $addr
+    i64.const 3
+    i64.atomic.rmw32.xchg_u offset=8
+    i32.wrap_i64
+    local.get $addr
+    i32.const 0
+    i32.const 5
+    i32.atomic.rmw8.cmpxchg_u
+    i32.add
+    local.get $addr
+    i64.const 0
+    i64.const 1
+    i64.atomic.rmw.cmpxchg offset=8
+    i32.wrap_i64
+    i32.add)
+
+  ;; Waiting, notifying, and fences.
+  (func (export "sync") (param $addr i32) (result i32)
+    atomic.fence
+    local.get $addr
+    i32.const 1
+    i64.const 0
+    memory.atomic.wait32
+    local.get $addr
+    i32.const 1
+    memory.atomic.notify
+    i32.add)
+)
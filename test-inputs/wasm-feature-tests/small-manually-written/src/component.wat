@@ -0,0 +1,25 @@
+(component
+  ;; Core module, which is instantiated and whose function is lifted to a component export.
+  (core module $adder
+    (func (export "add") (param i32 i32) (result i32)
+      local.get 0
+      local.get 1
+      i32.add))
+  (core instance $adder_instance (instantiate $adder))
+  (func $add (param "a" s32) (param "b" s32) (result s32)
+    (canon lift (core func $adder_instance "add")))
+  (export "add" (func $add))
+
+  ;; Nested component with another core module.
+  (component $nested
+    (core module $counter
+      (global $count (mut i32) (i32.const 0))
+      (func (export "increment") (result i32)
+        global.get $count
+        i32.const 1
+        i32.add
+        global.set $count
+        global.get $count))
+    (core instance $counter_instance (instantiate $counter))
+    (func (export "increment") (result s32)
+      (canon lift (core func $counter_instance "increment")))))
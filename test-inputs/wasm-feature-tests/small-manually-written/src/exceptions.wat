@@ -0,0 +1,49 @@
+(module
+  (import "env" "imported_tag" (tag $imported))
+  (tag $error (param i32 i64))
+  (tag $empty)
+  (export "error" (tag $error))
+
+  (func $may_throw (param $x i32)
+    local.get $x
+    i32.eqz
+    if
+      return
+    end
+    local.get $x
+    i64.const 42
+    throw $error)
+
+  ;; Catches the error and returns its payload, or -1 if nothing was thrown.
+  (func (export "catch") (param $x i32) (result i32)
+    try (result i32)
+      local.get $x
+      call $may_throw
+      i32.const -1
+    catch $error
+      drop
+    catch_all
+      i32.const -2
+    end)
+
+  ;; Nested handlers, rethrow, and a try block that delegates to the outer handler.
+  (func (export "rethrow") (param $x i32) (result i32)
+    try (result i32)
+      try
+        local.get $x
+        call $may_throw
+      catch $error
+        drop
+        drop
+        rethrow 0
+      end
+      try
+        throw $empty
+      delegate 0
+      i32.const 0
+    catch $empty
+      i32.const 1
+    catch_all
+      i32.const 2
+    end)
+)
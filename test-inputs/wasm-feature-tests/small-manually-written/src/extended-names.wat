@@ -0,0 +1,36 @@
+(module
+  ;; Names of globals, tables, memories, element and data segments, and labels, which are
+  ;; emitted into the extended name section.
+  (global $__stack_pointer (mut i32) (i32.const 65536))
+  (global $counter (mut i32) (i32.const 0))
+  (table $functions 1 funcref)
+  (memory $heap 1)
+  (elem $pointers (i32.const 0) $count)
+  (data $greeting (i32.const 16) "hello")
+
+  (func $count (export "count") (param $n i32) (result i32)
+    block $exit
+      loop $next
+        local.get $n
+        i32.eqz
+        br_if $exit
+        global.get $counter
+        i32.const 1
+        i32.add
+        global.set $counter
+        local.get $n
+        i32.const 1
+        i32.sub
+        local.set $n
+        br $next
+      end
+    end
+    ;; Unnamed, so that the label index of the following block is not equal to its position
+    ;; in the label names.
+    block
+      global.get $counter
+      if $nonzero
+        nop
+      end
+    end
+    global.get $counter))
@@ -0,0 +1,36 @@
+(module
+  (memory i64 1 2)
+  (data (i64.const 0) "\01\02\03\04")
+
+  ;; Loads and stores with i64 addresses.
+  (func (export "load_store") (param $addr i64) (result i64)
+    local.get $addr
+    local.get $addr
+    i32.load offset=4
+    i32.store
+    local.get $addr
+    i64.load8_u
+    local.get $addr
+    i64.const 0
+    i64.store offset=8 align=4)
+
+  ;; 64-bit memories allow offsets beyond u32 (this one traps, since it is out of bounds).
+  (func (export "large_offset") (result i32)
+    i64.const 0
+    i32.load8_u offset=0x100000000)
+
+  ;; Sizes, deltas, and lengths of memory instructions are i64 as well.
+  (func (export "bulk") (result i64)
+    i64.const 0
+    i64.const 16
+    i64.const 4
+    memory.copy
+    i64.const 32
+    i32.const 0xff
+    i64.const 8
+    memory.fill
+    i64.const 1
+    memory.grow
+    memory.size
+    i64.add)
+)
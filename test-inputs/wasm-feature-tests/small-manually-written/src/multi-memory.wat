@@ -0,0 +1,41 @@
+(module
+  (import "env" "memory" (memory $imported 1))
+  (memory $shadow 1 2)
+  (memory $data 1)
+  (data (memory $data) (i32.const 0) "\01\02\03\04")
+  (data $passive "\ff\ff")
+
+  ;; Loads and stores with explicit memory indices.
+  (func (export "copy_to_shadow") (param $addr i32) (result i32)
+    local.get $addr
+    local.get $addr
+    i32.load $imported offset=4
+    i32.store $shadow
+    local.get $addr
+    i64.load8_u $data
+    i64.const 1
+    i64.add
+    local.get $addr
+    i32.load16_s $shadow align=1
+    drop
+    i32.wrap_i64)
+
+  ;; Memory-indexed memory instructions.
+  (func (export "bulk") (result i32)
+    i32.const 0
+    i32.const 0
+    i32.const 4
+    memory.copy $shadow $data
+    i32.const 8
+    i32.const 0
+    i32.const 2
+    memory.init $data $passive
+    i32.const 0
+    i32.const 0
+    i32.const 16
+    memory.fill $shadow
+    i32.const 1
+    memory.grow $shadow
+    memory.size $data
+    i32.add)
+)
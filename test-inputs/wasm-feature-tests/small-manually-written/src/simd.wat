@@ -0,0 +1,109 @@
+(module
+  (memory 1)
+  (global $mask (mut v128) (v128.const i64x2 -1 0))
+
+  ;; Vector parameters, results, and locals.
+  (func $add (export "add") (param v128 v128) (result v128)
+    (local $tmp v128)
+    local.get 0
+    local.get 1
+    i32x4.add
+    local.tee $tmp
+    local.get $tmp
+    global.get $mask
+    v128.bitselect)
+
+  ;; Loads and stores, including the extending, splatting, zeroing, and lane variants.
+  (func (export "memory") (param $addr i32) (result v128)
+    (local $v v128)
+    local.get $addr
+    v128.const i32x4 1 2 3 4
+    v128.store offset=16
+    local.get $addr
+    v128.load offset=16 align=8
+    local.get $addr
+    v128.load8x8_s
+    i16x8.add
+    local.get $addr
+    v128.load32_splat
+    local.get $addr
+    v128.load64_zero
+    i64x2.sub
+    i8x16.sub
+    local.set $v
+    local.get $addr
+    local.get $v
+    v128.load16_lane 3
+    local.set $v
+    local.get $addr
+    local.get $v
+    v128.store32_lane offset=4 1
+    local.get $addr
+    local.get $v
+    v128.load8_lane offset=1 align=1 15)
+
+  ;; Splats, lane accesses, and shuffles.
+  (func (export "lanes") (param i32 i64 f32 f64) (result i32)
+    local.get 0
+    i8x16.splat
+    local.get 1
+    i64x2.splat
+    i8x16.shuffle 0 17 2 19 4 21 6 23 8 25 10 27 12 29 14 31
+    local.get 2
+    f32x4.replace_lane 2
+    local.get 3
+    f64x2.replace_lane 1
+    v128.const i8x16 15 14 13 12 11 10 9 8 7 6 5 4 3 2 1 0
+    i8x16.swizzle
+    i8x16.extract_lane_s 0
+    v128.const i16x8 0 1 2 3 4 5 6 7
+    i16x8.extract_lane_u 7
+    i32.add)
+
+  ;; Lane-wise arithmetic, comparisons, and tests.
+  (func (export "arithmetic") (param v128 v128) (result i32)
+    local.get 0
+    local.get 1
+    f32x4.mul
+    f32x4.sqrt
+    local.get 1
+    f32x4.pmin
+    local.get 0
+    f32x4.lt
+    v128.any_true
+    local.get 0
+    i8x16.abs
+    local.get 1
+    i8x16.avgr_u
+    i8x16.popcnt
+    i32.const 3
+    i8x16.shl
+    local.get 1
+    i8x16.add_sat_s
+    i8x16.all_true
+    i32.add
+    local.get 0
+    i16x8.extadd_pairwise_i8x16_u
+    local.get 1
+    i16x8.q15mulr_sat_s
+    local.get 0
+    i32x4.dot_i16x8_s
+    local.get 1
+    i64x2.extmul_high_i32x4_u
+    i64x2.bitmask
+    i32.add)
+
+  ;; Conversions between shapes.
+  (func (export "convert") (param v128) (result v128)
+    local.get 0
+    f32x4.convert_i32x4_u
+    f64x2.promote_low_f32x4
+    f64x2.nearest
+    i32x4.trunc_sat_f64x2_s_zero
+    i16x8.extend_low_i8x16_s
+    local.get 0
+    i8x16.narrow_i16x8_u
+    v128.not
+    local.get 0
+    v128.andnot)
+)
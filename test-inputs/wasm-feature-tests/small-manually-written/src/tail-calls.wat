@@ -0,0 +1,26 @@
+(module
+  (type $i64_to_i64 (func (param i64 i64) (result i64)))
+  (table funcref (elem $factorial_acc))
+
+  (func $factorial (export "factorial") (param $n i64) (result i64)
+    local.get $n
+    i64.const 1
+    return_call $factorial_acc)
+
+  ;; Tail-recursive, with the tail calls nested in blocks.
+  (func $factorial_acc (param $n i64) (param $acc i64) (result i64)
+    block $done
+      local.get $n
+      i64.eqz
+      br_if $done
+      local.get $n
+      i64.const 1
+      i64.sub
+      local.get $n
+      local.get $acc
+      i64.mul
+      i32.const 0
+      return_call_indirect (type $i64_to_i64)
+    end
+    local.get $acc)
+)